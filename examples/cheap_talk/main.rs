@@ -0,0 +1,261 @@
+mod options;
+
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+use clap::Parser;
+use log::{debug, info};
+use plotters::style::{colors, RGBColor};
+use serde::Serialize;
+use amfiteatr_core::agent::{AgentGen, PolicyAgent, StatefulAgent, TracingAgentGen};
+use amfiteatr_core::comm::{AgentMpscAdapter, EnvironmentMpscPort};
+use amfiteatr_core::env::TracingBasicEnvironment;
+use amfiteatr_classic::SymmetricRewardTable;
+use amfiteatr_classic::domain::{AgentNum, ClassicAction};
+use amfiteatr_rl::policy::{ActorCriticPolicy, LearningNetworkPolicy, TrainConfig};
+use amfiteatr_rl::tch::Device;
+use amfiteatr_rl::tch::nn::Adam;
+use amfiteatr_rl::tensor_data::ConversionToTensor;
+use amfiteatr_examples::games::GamePreset;
+use amfiteatr_examples::error::{create_dir_all, ExperimentError, write_json};
+use amfiteatr_examples::plots::{Plot, PlotSeries};
+use amfiteatr_examples::policy::a2c_network;
+use amfiteatr_examples::cheap_talk::{CheapTalkConversion, CheapTalkDomainNumbered, CheapTalkError, CheapTalkInfoSet, CheapTalkState, split_by_stage, StagePolicy, TalkPolicy, TalkStrategy};
+use amfiteatr_examples::pairing::new_game_state_with_tables;
+use amfiteatr_examples::pairing::heterogeneous::RewardTables;
+use amfiteatr_examples::pairing::noise::Noise;
+use amfiteatr_examples::series::{GroupPayoffHistory, GroupPayoffs, PayoffGroupSeries};
+use amfiteatr_examples::sync::{ScriptedGroup, SynchronousModel};
+use crate::options::CheapTalkOptions;
+
+type D = CheapTalkDomainNumbered;
+type InfoSet = CheapTalkInfoSet<AgentNum>;
+type AgentComm = AgentMpscAdapter<D>;
+type Learner = TracingAgentGen<D, StagePolicy<ActorCriticPolicy<D, InfoSet, CheapTalkConversion>>, AgentComm>;
+type Scripted = AgentGen<D, TalkPolicy<AgentNum>, AgentComm>;
+
+const GROUP_COLORS: [RGBColor; 5] = [colors::BLACK, colors::GREEN, colors::RED, colors::BLUE, colors::MAGENTA];
+
+/// Learners' use of one symbol in test episodes after every epoch.
+#[derive(Serialize, Clone, Debug, Default)]
+struct SymbolSeries{
+    symbol: u8,
+    /// Fraction of learners' messages with symbol
+    frequencies: Vec<f32>,
+    /// Cooperation of learners after sending symbol
+    sender_cooperation: Vec<f32>,
+    /// Cooperation of learners after receiving symbol
+    receiver_cooperation: Vec<f32>,
+}
+
+#[derive(Serialize, Clone, Debug)]
+struct CheapTalkResults{
+    payoffs: Vec<PayoffGroupSeries>,
+    learning_cooperation: Vec<f32>,
+    symbols: Vec<SymbolSeries>,
+}
+
+pub fn setup_logger(options: &CheapTalkOptions) -> Result<(), fern::InitError> {
+    let dispatch  = fern::Dispatch::new()
+
+        .format(|out, message, record| {
+            out.finish(format_args!(
+                "{}[{}][{}] {}",
+                chrono::Local::now().format("[%H:%M:%S]"),
+                record.target(),
+                record.level(),
+                message
+            ))
+        })
+        .level(options.log_level)
+        .level_for("amfiteatr_core", options.log_level_amfi);
+
+        match &options.log_file{
+            None => dispatch.chain(std::io::stdout()),
+            Some(f) => dispatch.chain(fern::log_file(f)?)
+        }
+
+        .apply()?;
+    Ok(())
+}
+
+type Model = SynchronousModel<D, TracingBasicEnvironment<D, CheapTalkState<AgentNum>, EnvironmentMpscPort<D>>, Learner, Scripted>;
+
+/// Averages of test episodes.
+struct Evaluation{
+    payoffs: GroupPayoffs,
+    learning_cooperation: f32,
+    /// For every symbol fraction of learners' messages, cooperation after sending it and after receiving it
+    symbols: Vec<(f32, Option<f32>, Option<f32>)>,
+}
+
+/// Counts of encounters and cooperation in them.
+#[derive(Copy, Clone, Default)]
+struct Cooperation{
+    encounters: usize,
+    cooperated: usize,
+}
+
+impl Cooperation{
+    fn note(&mut self, action: ClassicAction){
+        self.encounters += 1;
+        self.cooperated += (action == ClassicAction::Down) as usize;
+    }
+
+    fn rate(&self) -> Option<f32>{
+        (self.encounters > 0).then(|| self.cooperated as f32 / self.encounters as f32)
+    }
+}
+
+fn evaluate(model: &mut Model, episodes: usize, vocabulary: usize) -> Evaluation{
+    let mut cooperation = Cooperation::default();
+    let mut sent = vec![Cooperation::default(); vocabulary];
+    let mut received = vec![Cooperation::default(); vocabulary];
+    let payoffs = model.evaluate(episodes, |agent|{
+        for round in agent.info_set().history(){
+            cooperation.note(round.own_action);
+            if let Some(s) = round.own_message.and_then(|m| sent.get_mut(m as usize)){
+                s.note(round.own_action);
+            }
+            if let Some(r) = round.partner_message.and_then(|m| received.get_mut(m as usize)){
+                r.note(round.own_action);
+            }
+        }
+    });
+    let messages = sent.iter().map(|s| s.encounters).sum::<usize>().max(1) as f32;
+    Evaluation{
+        payoffs,
+        learning_cooperation: cooperation.rate().unwrap_or(0.0),
+        symbols: sent.iter().zip(received.iter())
+            .map(|(s, r)| (s.encounters as f32 / messages, s.rate(), r.rate()))
+            .collect(),
+    }
+}
+
+fn update_policies(model: &mut Model) -> Result<(), ExperimentError<D>>{
+    model.update_policies(|agent|{
+        let trajectories = agent.take_episodes();
+        let (messaging, acting) = split_by_stage(&trajectories[..]);
+        let policy = agent.policy_mut();
+        // silent game has no message steps and batch of them can not be built
+        if messaging.iter().any(|t| !t.is_empty()){
+            policy.messaging.train_on_trajectories_env_reward(&messaging[..])?;
+        }
+        policy.acting.train_on_trajectories_env_reward(&acting[..])
+    })?;
+    Ok(())
+}
+
+fn main() -> Result<(), ExperimentError<D>>{
+    let args = CheapTalkOptions::parse();
+    setup_logger(&args)?;
+    let device = Device::Cpu;
+    let reward_table = GamePreset::table_or(args.game, SymmetricRewardTable::new(4, 0, 3, 2));
+    debug!("Reward table: {reward_table:?}");
+    let vocabulary = (!args.silent).then_some(args.vocabulary);
+    let symbols = vocabulary.unwrap_or(0) as usize;
+
+    let tensor_repr = CheapTalkConversion::new(vocabulary, args.number_of_rounds);
+    let input_size = tensor_repr.desired_shape_flatten();
+
+    let players = args.number_of_learning + args.number_of_cooperators + args.number_of_defectors
+        + args.number_of_liars + args.number_of_trusters;
+    let tables = match &args.reward_tables{
+        Some(path) => RewardTables::from_file(path, players, reward_table)?,
+        None => RewardTables::uniform(players, reward_table),
+    };
+
+    let mut env_adapter = EnvironmentMpscPort::new();
+    let mut learning_agents = Vec::with_capacity(args.number_of_learning);
+    let mut id: AgentNum = 0;
+    for _ in 0..args.number_of_learning{
+        let comm = env_adapter.register_agent(id)?;
+        let [messaging, acting] = [args.vocabulary as i64, 2].map(|outputs|{
+            let net = a2c_network(input_size, outputs, device);
+            let opt = net.build_optimizer(Adam::default(), 1e-4)?;
+            Ok::<_, ExperimentError<D>>(ActorCriticPolicy::new(net, opt, tensor_repr, TrainConfig {gamma: 0.99}))
+        });
+        let policy = StagePolicy{messaging: messaging?, acting: acting?};
+        let state = CheapTalkInfoSet::new(id, tables.table_of(id as usize), vocabulary);
+        learning_agents.push(Arc::new(Mutex::new(TracingAgentGen::new(state, comm, policy))));
+        id += 1;
+    }
+    let mut scripted_groups = Vec::new();
+    for (name, number, strategy) in [
+        ("Cooperators", args.number_of_cooperators, TalkStrategy::Cooperator),
+        ("Defectors", args.number_of_defectors, TalkStrategy::Defector),
+        ("Liars", args.number_of_liars, TalkStrategy::Liar),
+        ("Trusters", args.number_of_trusters, TalkStrategy::Truster),
+    ]{
+        let mut agents = Vec::with_capacity(number);
+        for _ in 0..number{
+            let comm = env_adapter.register_agent(id)?;
+            let state = CheapTalkInfoSet::new(id, tables.table_of(id as usize), vocabulary);
+            agents.push(Arc::new(Mutex::new(AgentGen::new(state, comm, TalkPolicy::new(strategy)))));
+            id += 1;
+        }
+        scripted_groups.push(ScriptedGroup{name, agents});
+    }
+    let game_state = new_game_state_with_tables(args.number_of_rounds, tables,
+        Noise::new(args.action_noise, args.observation_noise), args.continuation, None)
+        .map_err(CheapTalkError::from)?;
+    let env_state = CheapTalkState::new(game_state, vocabulary);
+    let mut model = Model::new(TracingBasicEnvironment::new(env_state, env_adapter), learning_agents, scripted_groups);
+
+    let names: Vec<&str> = std::iter::once("Learners").chain(model.scripted_groups.iter().map(|g| g.name)).collect();
+    let mut payoffs = GroupPayoffHistory::new(names);
+    let mut learning_cooperation = Vec::with_capacity(args.epochs + 1);
+    let mut symbol_series: Vec<SymbolSeries> = (0..symbols)
+        .map(|s| SymbolSeries{symbol: s as u8, ..Default::default()})
+        .collect();
+    let mut record = |epoch: usize, evaluation: Evaluation|{
+        info!("Epoch {epoch}: learning agents' payoff: {:?}, scripted agents' payoffs: {:?}, learning agents' cooperation: {:.02}, symbols (frequency, cooperation of sender, cooperation of receiver): {:.02?}",
+            evaluation.payoffs.learning, evaluation.payoffs.scripted, evaluation.learning_cooperation, evaluation.symbols);
+        payoffs.push(&evaluation.payoffs);
+        learning_cooperation.push(evaluation.learning_cooperation);
+        for (series, (frequency, sender, receiver)) in symbol_series.iter_mut().zip(evaluation.symbols){
+            series.frequencies.push(frequency);
+            series.sender_cooperation.extend(sender);
+            series.receiver_cooperation.extend(receiver);
+        }
+    };
+
+    info!("Starting initial evaluation");
+    record(0, evaluate(&mut model, args.evaluation_episodes, symbols));
+    for e in 0..args.epochs{
+        info!("Running training epoch: {}", e);
+        for _ in 0..args.batch_size{
+            model.run_episode();
+        }
+        update_policies(&mut model)?;
+        record(e + 1, evaluate(&mut model, args.evaluation_episodes, symbols));
+    }
+
+    let stamp = chrono::Local::now().format("[%Y-%m-%d][%H:%M:%S]");
+    let base_path = "results/cheap_talk/";
+    create_dir_all(base_path)?;
+    let name = format!("{}_{}_{}-{}-{}-{}-{}_{}", symbols, args.number_of_rounds,
+        args.number_of_learning, args.number_of_cooperators, args.number_of_defectors,
+        args.number_of_liars, args.number_of_trusters, stamp);
+
+    let plot = Plot::new()
+        .size(args.plot_width, args.plot_height)
+        .x_desc("Epoch");
+    let mut action_series = vec![PlotSeries::new(learning_cooperation.clone(), "Cooperation", colors::BLACK)];
+    action_series.extend(symbol_series.iter().zip(GROUP_COLORS.iter().skip(1).cycle())
+        .map(|(s, color)| PlotSeries::new(s.frequencies.clone(), &format!("Symbol {}", s.symbol), *color)));
+    let path = format!("{base_path}/learning_actions-{name}.{}", args.plot_format.extension());
+    plot.clone().y_desc("Frequency").draw(Path::new(&path), &action_series[..])
+        .map_err(|e| ExperimentError::plot(Path::new(&path), e))?;
+
+    let payoff_series = payoffs.plot_series(&GROUP_COLORS);
+    let path = format!("{base_path}/payoffs-{name}.{}", args.plot_format.extension());
+    plot.y_desc("Payoff").draw(Path::new(&path), &payoff_series[..])
+        .map_err(|e| ExperimentError::plot(Path::new(&path), e))?;
+
+    let results = CheapTalkResults{
+        payoffs: payoffs.into_series(),
+        learning_cooperation, symbols: symbol_series,
+    };
+    write_json(format!("{base_path}/series-{name}.json"), &results, true)?;
+    Ok(())
+}
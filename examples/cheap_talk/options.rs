@@ -0,0 +1,91 @@
+use std::path::PathBuf;
+use log::LevelFilter;
+use clap::Parser;
+use amfiteatr_examples::games::GamePreset;
+use amfiteatr_examples::pairing::noise::parse_probability;
+use amfiteatr_examples::plots::PlotFormat;
+
+#[derive(Parser)]
+#[command(author, version, about, long_about = None)]
+pub struct CheapTalkOptions{
+
+    #[arg(short = 'v', long = "log_level", value_enum, default_value = "info")]
+    pub log_level: LevelFilter,
+
+    #[arg(short = 'a', long = "log_level_amfi", value_enum, default_value = "OFF")]
+    pub log_level_amfi: LevelFilter,
+
+    #[arg(short = 'o', long = "logfile")]
+    pub log_file: Option<PathBuf>,
+
+    #[arg(short = 'e', long = "epochs", default_value = "100")]
+    pub epochs: usize,
+
+    #[arg(short = 'b', long = "batch", default_value = "64")]
+    pub batch_size: usize,
+
+    /// Test episodes played before training and after every epoch
+    #[arg(long = "evaluation-episodes", default_value = "100")]
+    pub evaluation_episodes: usize,
+
+    #[arg(short = 'n', long = "rounds", default_value = "10")]
+    pub number_of_rounds: usize,
+
+    /// Probability that environment executes action opposite to chosen one (trembling hand)
+    #[arg(long = "action-noise", default_value = "0", value_parser = parse_probability)]
+    pub action_noise: f64,
+
+    /// Probability that agent observes opposite action of its opponent than was executed
+    #[arg(long = "observation-noise", default_value = "0", value_parser = parse_probability)]
+    pub observation_noise: f64,
+
+    /// Probability of playing next round after every round (shadow of the future), with it
+    /// number of rounds is limit of game length
+    #[arg(short = 'w', long = "continuation", value_parser = parse_probability)]
+    pub continuation: Option<f64>,
+
+    /// Game played, without it agents play stag hunt
+    #[arg(long = "game", value_enum)]
+    pub game: Option<GamePreset>,
+
+    /// Json file assigning reward tables or payoff multipliers to agents, agents not listed
+    /// there use table of game
+    #[arg(long = "reward-tables")]
+    pub reward_tables: Option<PathBuf>,
+
+    /// Number of symbols agents can send, learners' messaging networks have output for each of them
+    #[arg(short = 's', long = "vocabulary", default_value = "2", value_parser = clap::value_parser!(u8).range(2..))]
+    pub vocabulary: u8,
+
+    /// Plays game without message stage
+    #[arg(long = "silent")]
+    pub silent: bool,
+
+    #[arg(short = 'l', long = "learners", default_value = "4")]
+    pub number_of_learning: usize,
+
+    /// Agents announcing and playing cooperation
+    #[arg(short = 'c', long = "cooperators", default_value = "0")]
+    pub number_of_cooperators: usize,
+
+    /// Agents announcing and playing defection
+    #[arg(short = 'd', long = "defectors", default_value = "0")]
+    pub number_of_defectors: usize,
+
+    /// Agents announcing cooperation and defecting
+    #[arg(short = 'L', long = "liars", default_value = "0")]
+    pub number_of_liars: usize,
+
+    /// Agents cooperating with partners announcing cooperation
+    #[arg(short = 't', long = "trusters", default_value = "2")]
+    pub number_of_trusters: usize,
+
+    #[arg(long = "plot-format", value_enum, default_value = "svg")]
+    pub plot_format: PlotFormat,
+
+    #[arg(long = "plot-width", default_value = "400")]
+    pub plot_width: u32,
+
+    #[arg(long = "plot-height", default_value = "300")]
+    pub plot_height: u32,
+}
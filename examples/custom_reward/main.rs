@@ -3,10 +3,9 @@ mod options;
 use std::{thread};
 use std::path::{Path};
 use log::{debug, info};
-use amfiteatr_rl::tch::{Device, nn, Tensor};
-use amfiteatr_rl::tch::nn::{Adam, VarStore};
-use amfiteatr_rl::tensor_data::{ConversionToTensor};
-use amfiteatr_rl::torch_net::{A2CNet, NeuralNetTemplate, TensorA2C};
+use amfiteatr_rl::tch::{Device, Tensor};
+use amfiteatr_rl::tch::nn::Adam;
+use amfiteatr_rl::tensor_data::{ConversionToTensor, FloatTensorReward};
 use clap::{Parser};
 use plotters::style::colors;
 use amfiteatr_core::agent::*;
@@ -16,12 +15,17 @@ use amfiteatr_core::error::AmfiError;
 use amfiteatr_classic::agent::{LocalHistoryInfoSet, LocalHistoryConversionToTensor, AgentAssessmentClassic};
 use amfiteatr_classic::domain::{AgentNum, ClassicGameDomain, ClassicGameDomainNumbered};
 use amfiteatr_classic::domain::ClassicAction::Down;
-use amfiteatr_classic::env::PairingState;
+use amfiteatr_examples::pairing::new_game_state;
+use amfiteatr_examples::pairing::noise::Noise;
 use amfiteatr_classic::SymmetricRewardTableInt;
+use amfiteatr_examples::games::GamePreset;
 use amfiteatr_rl::policy::*;
 use crate::options::EducatorOptions;
 use crate::options::SecondPolicy;
-use amfiteatr_examples::plots::{plot_many_series, PlotSeries};
+use amfiteatr_examples::error::{create_dir_all, EpisodeReport, ExperimentError, Participant, write_json};
+use amfiteatr_examples::diagnostics::{DiagnosticsSeries, train_a2c};
+use amfiteatr_examples::policy::a2c_network;
+use amfiteatr_examples::plots::{Axis, AxisConfig, Dashboard, LineStyle, Panel, Plot, PlotSeries};
 use amfiteatr_examples::series::{MultiAgentPayoffSeries, PayoffSeries};
 
 
@@ -54,35 +58,44 @@ type Domain = ClassicGameDomain<AgentNum>;
 
 
 
+/// Runs one game in threads of environment and agents, `episode` is number of game reported
+/// in case of failure.
 pub fn run_game(
+    episode: usize,
     env: &mut (impl AutoEnvironmentWithScores<Domain> + Send + ReseedEnvironment<Domain, ()>),
     agent0: &mut (impl EpisodeMemoryAgent<Domain, ()> + AutomaticAgentRewarded<Domain> + Send + ReseedAgent<Domain, ()>),
     agent1: &mut (impl EpisodeMemoryAgent<Domain, ()> + AutomaticAgentRewarded<Domain> + Send + ReseedAgent<Domain, ()>))
-    -> Result<(), AmfiError<Domain>>{
+    -> Result<(), ExperimentError<Domain>>{
 
+    let mut report = EpisodeReport::default();
+    let id0 = *agent0.id();
+    let id1 = *agent1.id();
     thread::scope(|s|{
-        s.spawn(||{
+        let env_handle = s.spawn(||{
             env.reseed(());
-            env.run_with_scores().unwrap();
+            env.run_with_scores()
         });
-        s.spawn(||{
-            agent0.run_episode_rewarded(()).unwrap()
+        let handle0 = s.spawn(||{
+            agent0.run_episode_rewarded(())
         });
-        s.spawn(||{
-            agent1.run_episode_rewarded(()).unwrap()
+        let handle1 = s.spawn(||{
+            agent1.run_episode_rewarded(())
         });
+        report.join(Participant::Environment, env_handle);
+        report.join(Participant::Agent(id0), handle0);
+        report.join(Participant::Agent(id1), handle1);
     });
-    Ok(())
+    report.into_result(episode)
 
 }
 
 type D = ClassicGameDomainNumbered;
 
 
-fn main() -> Result<(), AmfiError<ClassicGameDomain<AgentNum>>>{
+fn main() -> Result<(), ExperimentError<ClassicGameDomain<AgentNum>>>{
 
     let args = EducatorOptions::parse();
-    setup_logger(&args).unwrap();
+    setup_logger(&args)?;
     let device = Device::Cpu;
     //type Domain = ClassicGameDomainNumbered;
     let number_of_players = 2;
@@ -110,37 +123,21 @@ fn main() -> Result<(), AmfiError<ClassicGameDomain<AgentNum>>>{
     let mut custom_payoffs_1 = Vec::with_capacity(args.epochs + 1);
     let mut agent_0_coops = Vec::with_capacity(args.epochs + 1);
     let mut agent_1_coops = Vec::with_capacity(args.epochs + 1);
+    let mut training_diagnostics = [DiagnosticsSeries::default(), DiagnosticsSeries::default()];
     //let mut opti_payoffs_1 = Vec::with_capacity(args.epochs + 1);
 
 
 
     let mut env_adapter = EnvironmentMpscPort::new();
-    let comm0 = env_adapter.register_agent(0).unwrap();
-    let comm1 = env_adapter.register_agent(1).unwrap();
+    let comm0 = env_adapter.register_agent(0)?;
+    let comm1 = env_adapter.register_agent(1)?;
 
-    let reward_table = SymmetricRewardTableInt::new(
+    let reward_table = GamePreset::table_or(args.game, SymmetricRewardTableInt::new(
         args.coop_versus_coop,
         args.coop_versus_defect,
         args.defect_versus_coop,
-        args.defect_versus_defect);
-
-    let net_template = NeuralNetTemplate::new(|path|{
-        let seq = nn::seq()
-            .add(nn::linear(path / "input", input_size, 512, Default::default()))
-            //.add(nn::linear(path / "h1", 256, 256, Default::default()))
-            .add(nn::linear(path / "hidden1", 512, 512, Default::default()))
-            .add_fn(|xs| xs.tanh())
-            .add(nn::linear(path / "hidden2", 512, 256, Default::default()))
-            .add_fn(|xs| xs.tanh())
-            .add_fn(|xs|xs.relu());
-            //.add(nn::linear(path / "h2", 512, 512, Default::default()));
-        let actor = nn::linear(path / "al", 256, 2, Default::default());
-        let critic =  nn::linear(path / "ac", 256, 1, Default::default());
-        {move |input: &Tensor|{
-            let xs = input.to_device(device).apply(&seq);
-            TensorA2C{critic: xs.apply(&critic), actor: xs.apply(&actor)}
-        }}
-    });
+        args.defect_versus_defect));
+
 
 
 
@@ -148,33 +145,40 @@ fn main() -> Result<(), AmfiError<ClassicGameDomain<AgentNum>>>{
 
 
 
-    let env_state_template = PairingState::new_even(number_of_players, args.number_of_rounds, reward_table.into()).unwrap();
+    let env_state_template = new_game_state(number_of_players, args.number_of_rounds, reward_table,
+        Noise::new(args.action_noise, args.observation_noise), args.continuation)?;
     let mut environment = TracingBasicEnvironment::new(env_state_template.clone(), env_adapter);
 
 
-    let net0 = A2CNet::new(VarStore::new(device), net_template.get_net_closure());
-    let opt0 = net0.build_optimizer(Adam::default(), 1e-4).unwrap();
+    let net0 = a2c_network(input_size, 2, device);
+    let opt0 = net0.build_optimizer(Adam::default(), 1e-4)?;
     let normal_policy = ActorCriticPolicy::new(net0, opt0, tensor_repr, TrainConfig {gamma: 0.99});
-    let state0 = LocalHistoryInfoSet::new(0, reward_table.into());
+    let state0 = LocalHistoryInfoSet::new(0, reward_table);
     let mut agent_0 = TracingAgentGen::new(state0, comm0, normal_policy);
 
 
-    let state1 = LocalHistoryInfoSet::new(1, reward_table.into());
+    let state1 = LocalHistoryInfoSet::new(1, reward_table);
     //let test_policy = ClassicPureStrategy::new(ClassicAction::Defect);
 
-    let net1 = A2CNet::new(VarStore::new(device), net_template.get_net_closure());
-    let opt1 = net1.build_optimizer(Adam::default(), 1e-4).unwrap();
+    let mut net1 = a2c_network(input_size, 2, device);
+    if let Some(load_file) = &args.load_file{
+        net1.var_store_mut().load(load_file)
+            .map_err(|e| AmfiError::<Domain>::Custom(format!("Failed loading network from {load_file:?}: {e}")))?;
+    }
+    let opt1 = net1.build_optimizer(Adam::default(), 1e-4)?;
     let policy1 = ActorCriticPolicy::new(net1, opt1, tensor_repr, TrainConfig {gamma: 0.99});
     //let mut agent_1 = AgentGenT::new(state1, comm1, Arc::new(Mutex::new(policy1)));
     let mut agent_1 = TracingAgentGen::new(state1, comm1, policy1);
 
 
     //evaluate on start
+    let mut episode = 0;
     let mut coops = [Vec::new(), Vec::new()];
     let mut scores = [Vec::new(), Vec::new(), Vec::new()];
     for i in 0..100{
         debug!("Plaing round: {i:} of initial simulation");
-        run_game(&mut environment, &mut agent_0, &mut agent_1)?;
+        run_game(episode, &mut environment, &mut agent_0, &mut agent_1)?;
+        episode += 1;
         scores[0].push(agent_0.current_universal_score()) ;
         scores[1].push(agent_1.current_universal_score());
         scores[2].push(reward_f(agent_1.current_assessment_total()) as i64);
@@ -204,15 +208,18 @@ fn main() -> Result<(), AmfiError<ClassicGameDomain<AgentNum>>>{
         agent_1.clear_episodes();
         info!("Starting epoch {e:}");
         for _g in 0..args.batch_size{
-            run_game(&mut environment, &mut agent_0, &mut agent_1)?;
+            run_game(episode, &mut environment, &mut agent_0, &mut agent_1)?;
+            episode += 1;
         }
         let trajectories_0 = agent_0.take_episodes();
         let trajectories_1 = agent_1.take_episodes();
-        agent_0.policy_mut().train_on_trajectories_env_reward(&trajectories_0[..])?;
-        match args.policy{
-            SecondPolicy::Std => agent_1.policy_mut().train_on_trajectories_env_reward(&trajectories_1[..]),
+        let diagnostics_0 = train_a2c(agent_0.policy_mut(), &tensor_repr, &trajectories_0[..],
+            |step| step.step_universal_reward().to_tensor(), args.diagnostics)?;
+        let diagnostics_1 = match args.policy{
+            SecondPolicy::Std => train_a2c(agent_1.policy_mut(), &tensor_repr, &trajectories_1[..],
+                |step| step.step_universal_reward().to_tensor(), args.diagnostics),
             SecondPolicy::MinDefects => {
-                agent_1.policy_mut().train_on_trajectories(&trajectories_1[..], |step| {
+                train_a2c(agent_1.policy_mut(), &tensor_repr, &trajectories_1[..], |step| {
                     //let own_defects = step.step_info_set().count_actions_self(Defect) as i64;
                     //let custom_reward = step.step_subjective_reward().count_other_actions(Cooperate);
                     let custom_reward = reward_f(step.step_subjective_reward());
@@ -221,11 +228,11 @@ fn main() -> Result<(), AmfiError<ClassicGameDomain<AgentNum>>>{
                     //    step.step_info_set(), step.step_subjective_reward());
                     //trace!("Custom reward calculated: {}", &custom_reward);
                     Tensor::from_slice(&v_custom_reward[..])
-                })
+                }, args.diagnostics)
             },
 
             SecondPolicy::StdMinDefects => {
-                agent_1.policy_mut().train_on_trajectories(&trajectories_1[..], |step| {
+                train_a2c(agent_1.policy_mut(), &tensor_repr, &trajectories_1[..], |step| {
                     //let own_defects = step.step_info_set().count_actions_self(Defect) as i64;
                     //let custom_reward = step.step_subjective_reward().f_combine_table_with_other_coop(100.0);
                     let custom_reward = reward_f(step.step_subjective_reward());
@@ -234,33 +241,40 @@ fn main() -> Result<(), AmfiError<ClassicGameDomain<AgentNum>>>{
                     //    step.step_info_set(), step.step_subjective_reward());
                     //trace!("Custom reward calculated: {}", &custom_reward);
                     Tensor::from_slice(&v_custom_reward[..])
-                })
+                }, args.diagnostics)
             },
 
             SecondPolicy::StdMinDefectsBoth => {
-                agent_1.policy_mut().train_on_trajectories(&trajectories_1[..], |step| {
+                train_a2c(agent_1.policy_mut(), &tensor_repr, &trajectories_1[..], |step| {
                     let custom_reward = reward_f(step.step_subjective_reward());
                     let v_custom_reward = vec![custom_reward];
 
                     Tensor::from_slice(&v_custom_reward[..])
-                })
+                }, args.diagnostics)
             },
             SecondPolicy::Edu => {
-                agent_1.policy_mut().train_on_trajectories(&trajectories_1[..], |step| {
+                train_a2c(agent_1.policy_mut(), &tensor_repr, &trajectories_1[..], |step| {
                     let custom_reward = reward_f(step.step_subjective_reward());
                     let v_custom_reward = vec![custom_reward];
 
                     Tensor::from_slice(&v_custom_reward[..])
-                })
+                }, args.diagnostics)
             }
         }?;
+        if let (Some(diagnostics_0), Some(diagnostics_1)) = (diagnostics_0, diagnostics_1){
+            info!("Training diagnostics of agent 0: {}", diagnostics_0);
+            info!("Training diagnostics of agent 1: {}", diagnostics_1);
+            training_diagnostics[0].push(&diagnostics_0);
+            training_diagnostics[1].push(&diagnostics_1);
+        }
 
 
         let mut scores = [Vec::new(), Vec::new(), Vec::new()];
         let mut coops = [Vec::new(), Vec::new()];
         for i in 0..100{
             debug!("Plaing round: {i:} of initial simulation");
-            run_game(&mut environment, &mut agent_0, &mut agent_1)?;
+            run_game(episode, &mut environment, &mut agent_0, &mut agent_1)?;
+            episode += 1;
             scores[0].push(agent_0.current_universal_score());
             scores[1].push(agent_1.current_universal_score());
             scores[2].push(reward_f(agent_1.current_assessment_total()) as i64);
@@ -286,12 +300,16 @@ fn main() -> Result<(), AmfiError<ClassicGameDomain<AgentNum>>>{
         agent_1_coops.push(coops_a[1] as f32);
     }
 
-    run_game(&mut environment, &mut agent_0, &mut agent_1)?;
-    println!("{:?}", agent_0.take_episodes().last().unwrap().list().last().unwrap());
+    run_game(episode, &mut environment, &mut agent_0, &mut agent_1)?;
+    if let Some(step) = agent_0.take_episodes().last().and_then(|t| t.list().last().cloned()){
+        println!("{:?}", step);
+    }
 
 
 
-    println!("{}", environment.trajectory().list().last().unwrap());
+    if let Some(step) = environment.trajectory().list().last(){
+        println!("{}", step);
+    }
 
     println!("Scores: 0: {},\t1: {}", environment.actual_score_of_player(&0), environment.actual_score_of_player(&1));
 
@@ -299,33 +317,15 @@ fn main() -> Result<(), AmfiError<ClassicGameDomain<AgentNum>>>{
     //plot_payoffs(Path::new(format!("agent_0-{:?}-{:?}.svg", args.policy, args.number_of_rounds).as_str()), &payoffs_0[..]).unwrap();
     //plot_payoffs(Path::new(format!("agent_1-{:?}-{:?}.svg", args.policy, args.number_of_rounds).as_str()), &payoffs_1[..]).unwrap();
 
-    let agent0_data = PlotSeries {
-        data: payoffs_0,
-        description: "Agent 0".to_string(),
-        color: colors::RED,
-    };
-    let agent1_data = PlotSeries {
-        data: payoffs_1,
-        description: "Agent 1".to_string(),
-        color: colors::BLUE,
-    };
+    let agent0_data = PlotSeries::new(payoffs_0, "Agent 0", colors::RED);
+    let agent1_data = PlotSeries::new(payoffs_1, "Agent 1", colors::BLUE);
 
-    let agent1_custom_data = PlotSeries {
-        data: custom_payoffs_1,
-        description: "Agent 1 - self assessment".to_string(),
-        color: colors::GREEN,
-    };
+    let agent1_custom_data = PlotSeries::new(custom_payoffs_1, "Agent 1 - self assessment", colors::GREEN)
+        .line_style(LineStyle::Dashed)
+        .axis(Axis::Secondary);
 
-    let agent1_coops = PlotSeries {
-        data: agent_1_coops,
-        description: "Agent 1 cooperations".to_string(),
-        color: colors::BLUE,
-    };
-    let agent0_coops = PlotSeries {
-        data: agent_0_coops,
-        description: "Agent 0 cooperations".to_string(),
-        color: colors::RED
-    };
+    let agent1_coops = PlotSeries::new(agent_1_coops, "Agent 1 cooperations", colors::BLUE);
+    let agent0_coops = PlotSeries::new(agent_0_coops, "Agent 0 cooperations", colors::RED);
 
     let s_policy = match args.policy{
         SecondPolicy::StdMinDefects => {
@@ -336,6 +336,7 @@ fn main() -> Result<(), AmfiError<ClassicGameDomain<AgentNum>>>{
     };
     let stamp = chrono::Local::now().format("[%Y-%m-%d][%H:%M:%S]");
     let base_path = "results/custom_assessment/";
+    create_dir_all(base_path)?;
 
     let mut series = MultiAgentPayoffSeries::<D>{
         agent_series: vec![],
@@ -356,29 +357,70 @@ fn main() -> Result<(), AmfiError<ClassicGameDomain<AgentNum>>>{
         _ => vec![agent0_data, agent1_data, agent1_custom_data]
     };
 
-    plot_many_series(Path::new(
-        format!("{}/payoffs-{}-{:?}_{}.svg",
+    let coop_series = vec![agent0_coops, agent1_coops];
+
+    let plot = Plot::new()
+        .size(args.plot_width, args.plot_height)
+        .x_desc("Epoch");
+
+    let payoff_plot = plot.clone().y_desc("Payoff")
+        .secondary_y(AxisConfig{description: "Self assessment".to_string(), ..Default::default()});
+    let path = format!("{}/payoffs-{}-{:?}_{}.{}",
+                base_path,
+                &s_policy.as_str(),
+                args.number_of_rounds,
+                stamp,
+                args.plot_format.extension());
+    payoff_plot.draw(Path::new(&path), &plot_series[..])
+        .map_err(|e| ExperimentError::plot(Path::new(&path), e))?;
+
+    let path = format!("{}/actions-1l-{}-{:?}_{}.{}",
                 base_path,
                 &s_policy.as_str(),
                 args.number_of_rounds,
-                chrono::Local::now().format("[%Y-%m-%d][%H:%M:%S]"))
-            .as_str(), ),  "",&plot_series[..],
-        "Epoch",
-        "Payoff"
-    ).unwrap();
-
-    plot_many_series(Path::new(
-        format!("{}/actions-1l-{}-{:?}_{}.svg",
+                stamp,
+                args.plot_format.extension());
+    plot.clone().y_desc("Cooperations").draw(Path::new(&path), &coop_series[..])
+        .map_err(|e| ExperimentError::plot(Path::new(&path), e))?;
+
+    let path = format!("{}/dashboard-{}-{:?}_{}.{}",
                 base_path,
                 &s_policy.as_str(),
                 args.number_of_rounds,
-                stamp)
-            .as_str(), ), "",&[agent0_coops, agent1_coops,],
-            "Epoch",
-            "Cooperations"
-            ).unwrap();
+                stamp,
+                args.plot_format.extension());
+    let mut dashboard = Dashboard::new(&format!("Custom assessment: {}", s_policy))
+        .panel_size(args.plot_width, args.plot_height)
+        .panel(Panel::lines(payoff_plot.title("Payoffs"), plot_series))
+        .panel(Panel::lines(plot.clone().title("Cooperations").y_desc("Cooperations"), coop_series));
+    if args.diagnostics{
+        let [diagnostics_0, diagnostics_1] = &training_diagnostics;
+        let [losses_0, statistics_0] = diagnostics_0.panels(&plot, "Agent 0");
+        let [losses_1, statistics_1] = diagnostics_1.panels(&plot, "Agent 1");
+        dashboard = dashboard
+            .panel(losses_0)
+            .panel(losses_1)
+            .panel(statistics_0)
+            .panel(statistics_1);
+    }
+    dashboard.draw(Path::new(&path))
+        .map_err(|e| ExperimentError::plot(Path::new(&path), e))?;
     //plot_payoffs(Path::new(format!("custom-payoffs-{:?}-{:?}.svg", args.policy, args.number_of_rounds).as_str()), &agent1_custom_data ).unwrap();
 
+    if args.diagnostics{
+        write_json(format!("{}/diagnostics-{}-{:?}_{}.json",
+                    base_path,
+                    &s_policy.as_str(),
+                    args.number_of_rounds,
+                    stamp), &training_diagnostics, false)?;
+    }
+
+    if let Some(save_file) = &args.save_file{
+        agent_1.policy().var_store().save(save_file)
+            .map_err(|e| AmfiError::<Domain>::Custom(format!("Failed saving network to {save_file:?}: {e}")))?;
+        info!("Saved network of agent 1 to {save_file:?}");
+    }
+
     Ok(())
     //let standard_strategy =
 }
\ No newline at end of file
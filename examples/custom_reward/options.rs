@@ -1,6 +1,9 @@
 use std::path::PathBuf;
 use log::LevelFilter;
+use amfiteatr_examples::plots::PlotFormat;
 use clap::{ValueEnum, Parser};
+use amfiteatr_examples::pairing::noise::parse_probability;
+use amfiteatr_examples::games::GamePreset;
 
 #[derive(ValueEnum, Debug, Copy,  Clone)]
 pub enum SecondPolicy{
@@ -25,9 +28,11 @@ pub struct EducatorOptions{
     #[arg(short = 'o', long = "logfile")]
     pub log_file: Option<PathBuf>,
 
+    /// Save network of agent 1 after training (checkpoint can be loaded by tournament client)
     #[arg(short = 's', long = "save")]
     pub save_file: Option<PathBuf>,
 
+    /// Initialise network of agent 1 from checkpoint
     #[arg(short = 'l', long = "load")]
     pub load_file: Option<PathBuf>,
 
@@ -37,15 +42,37 @@ pub struct EducatorOptions{
     #[arg(short = 'b', long = "batch", default_value = "64")]
     pub batch_size: usize,
 
+    /// Compute training diagnostics (losses, entropy, gradient norm) of every update, costs
+    /// additional forward and backward pass per update
+    #[arg(long = "diagnostics")]
+    pub diagnostics: bool,
+
     #[arg(short = 'n', long = "rounds", default_value = "10")]
     pub number_of_rounds: usize,
 
+    /// Probability that environment executes action opposite to chosen one (trembling hand)
+    #[arg(long = "action-noise", default_value = "0", value_parser = parse_probability)]
+    pub action_noise: f64,
+
+    /// Probability that agent observes opposite action of its opponent than was executed
+    #[arg(long = "observation-noise", default_value = "0", value_parser = parse_probability)]
+    pub observation_noise: f64,
+
+    /// Probability of playing next round after every round (shadow of the future), with it
+    /// number of rounds is limit of game length
+    #[arg(short = 'w', long = "continuation", value_parser = parse_probability)]
+    pub continuation: Option<f64>,
+
     #[arg(short = 'p', long = "policy", default_value = "std")]
     pub policy: SecondPolicy,
 
     #[arg(long = "reward_bias_scale", default_value = "0")]
     pub reward_bias_scale: f32,
 
+    /// Game played, without it reward table is built from payoff options
+    #[arg(long = "game", value_enum)]
+    pub game: Option<GamePreset>,
+
     #[arg(long = "defect-defect", default_value = "3")]
     pub defect_versus_defect: i64,
 
@@ -58,6 +85,15 @@ pub struct EducatorOptions{
     #[arg(long = "coop-coop", default_value = "5")]
     pub coop_versus_coop: i64,
 
+    #[arg(long = "plot-format", value_enum, default_value = "svg")]
+    pub plot_format: PlotFormat,
+
+    #[arg(long = "plot-width", default_value = "400")]
+    pub plot_width: u32,
+
+    #[arg(long = "plot-height", default_value = "300")]
+    pub plot_height: u32,
+
 
     //#[arg(short = 'r', long = "reward", default_value = "env")]
     //pub reward_source: RewardSource,
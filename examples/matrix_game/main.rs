@@ -0,0 +1,195 @@
+mod options;
+
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+use clap::Parser;
+use log::{debug, info};
+use rand::rngs::StdRng;
+use rand::SeedableRng;
+use plotters::style::{colors, RGBColor};
+use serde::Serialize;
+use amfiteatr_core::agent::{AgentGen, PolicyAgent, StatefulAgent, TracingAgentGen};
+use amfiteatr_core::comm::{AgentMpscAdapter, EnvironmentMpscPort};
+use amfiteatr_core::env::TracingBasicEnvironment;
+use amfiteatr_classic::domain::AgentNum;
+use amfiteatr_rl::policy::{ActorCriticPolicy, LearningNetworkPolicy, TrainConfig};
+use amfiteatr_rl::tch::Device;
+use amfiteatr_rl::tch::nn::Adam;
+use amfiteatr_rl::tensor_data::ConversionToTensor;
+use amfiteatr_examples::error::{create_dir_all, ExperimentError, write_json};
+use amfiteatr_examples::matrix::{MatrixAction, MatrixGameDomainNumbered, MatrixHistoryConversion, MatrixHistoryInfoSet, MatrixMixedStrategy, MatrixPairingState};
+use amfiteatr_examples::plots::{Plot, PlotSeries};
+use amfiteatr_examples::policy::a2c_network;
+use amfiteatr_examples::series::{GroupPayoffHistory, GroupPayoffs, PayoffGroupSeries};
+use amfiteatr_examples::sync::{ScriptedGroup, SynchronousModel};
+use crate::options::MatrixOptions;
+
+type D = MatrixGameDomainNumbered;
+type InfoSet = MatrixHistoryInfoSet<AgentNum>;
+type AgentComm = AgentMpscAdapter<D>;
+type Learner = TracingAgentGen<D, ActorCriticPolicy<D, InfoSet, MatrixHistoryConversion>, AgentComm>;
+type Scripted = AgentGen<D, MatrixMixedStrategy<AgentNum>, AgentComm>;
+
+const ACTION_COLORS: [RGBColor; 6] = [colors::RED, colors::BLUE, colors::GREEN, colors::BLACK, colors::MAGENTA, colors::CYAN];
+
+/// Frequency of action taken by learning agents in test episodes after every epoch.
+#[derive(Serialize, Clone, Debug)]
+struct ActionSeries{
+    action: MatrixAction,
+    frequencies: Vec<f32>,
+}
+
+#[derive(Serialize, Clone, Debug)]
+struct MatrixResults{
+    payoffs: Vec<PayoffGroupSeries>,
+    learning_actions: Vec<ActionSeries>,
+}
+
+pub fn setup_logger(options: &MatrixOptions) -> Result<(), fern::InitError> {
+    let dispatch  = fern::Dispatch::new()
+
+        .format(|out, message, record| {
+            out.finish(format_args!(
+                "{}[{}][{}] {}",
+                chrono::Local::now().format("[%H:%M:%S]"),
+                record.target(),
+                record.level(),
+                message
+            ))
+        })
+        .level(options.log_level)
+        .level_for("amfiteatr_core", options.log_level_amfi);
+
+        match &options.log_file{
+            None => dispatch.chain(std::io::stdout()),
+            Some(f) => dispatch.chain(fern::log_file(f)?)
+        }
+
+        .apply()?;
+    Ok(())
+}
+
+type Model = SynchronousModel<D, TracingBasicEnvironment<D, MatrixPairingState<AgentNum>, EnvironmentMpscPort<D>>, Learner, Scripted>;
+
+/// Averages of test episodes.
+struct Evaluation{
+    payoffs: GroupPayoffs,
+    /// Fraction of learners' moves with every action
+    learning_actions: Vec<f32>,
+}
+
+fn evaluate(model: &mut Model, episodes: usize, actions: usize) -> Evaluation{
+    let mut action_counts = vec![0usize; actions];
+    let payoffs = model.evaluate(episodes, |agent|{
+        for (i, count) in action_counts.iter_mut().enumerate(){
+            *count += agent.info_set().count_actions_self(MatrixAction(i as u8));
+        }
+    });
+    let moves = action_counts.iter().sum::<usize>().max(1) as f32;
+    Evaluation{
+        payoffs,
+        learning_actions: action_counts.iter().map(|c| *c as f32 / moves).collect(),
+    }
+}
+
+fn update_policies(model: &mut Model) -> Result<(), ExperimentError<D>>{
+    model.update_policies(|agent|{
+        let trajectories = agent.take_episodes();
+        agent.policy_mut().train_on_trajectories_env_reward(&trajectories[..])
+    })?;
+    Ok(())
+}
+
+fn main() -> Result<(), ExperimentError<D>>{
+    let args = MatrixOptions::parse();
+    setup_logger(&args)?;
+    let device = Device::Cpu;
+    let actions = args.actions as usize;
+    let reward_table = args.game.reward_table(actions);
+    debug!("Reward table: {reward_table:?}");
+
+    let tensor_repr = MatrixHistoryConversion::new(actions, args.number_of_rounds);
+    let input_size = tensor_repr.desired_shape_flatten();
+
+    let mut env_adapter = EnvironmentMpscPort::new();
+    let mut learning_agents = Vec::with_capacity(args.number_of_learning);
+    let mut scripted_agents = Vec::with_capacity(args.number_of_uniform + args.number_of_fixed);
+    let mut id: AgentNum = 0;
+    for _ in 0..args.number_of_learning{
+        let comm = env_adapter.register_agent(id)?;
+        let net = a2c_network(input_size, actions as i64, device);
+        let opt = net.build_optimizer(Adam::default(), 1e-4)?;
+        let policy = ActorCriticPolicy::new(net, opt, tensor_repr, TrainConfig {gamma: 0.99});
+        let state = MatrixHistoryInfoSet::new(id, reward_table.clone());
+        learning_agents.push(Arc::new(Mutex::new(TracingAgentGen::new(state, comm, policy))));
+        id += 1;
+    }
+    for i in 0..args.number_of_uniform + args.number_of_fixed{
+        let comm = env_adapter.register_agent(id)?;
+        let policy = match i < args.number_of_uniform{
+            true => MatrixMixedStrategy::uniform(actions),
+            false => MatrixMixedStrategy::pure(actions, MatrixAction(((i - args.number_of_uniform) % actions) as u8)),
+        }.expect("weights of actions are not negative");
+        let state = MatrixHistoryInfoSet::new(id, reward_table.clone());
+        scripted_agents.push(Arc::new(Mutex::new(AgentGen::new(state, comm, policy))));
+        id += 1;
+    }
+    let mut env_state = MatrixPairingState::new_even(id as usize, args.number_of_rounds, reward_table)?;
+    if let Some(seed) = args.seed{
+        env_state = env_state.with_rng(StdRng::seed_from_u64(seed));
+    }
+    let scripted_groups = vec![ScriptedGroup{name: "Scripted agents", agents: scripted_agents}];
+    let mut model = Model::new(TracingBasicEnvironment::new(env_state, env_adapter), learning_agents, scripted_groups);
+
+    let mut payoffs = GroupPayoffHistory::new(["Learning agents", "Scripted agents"]);
+    let mut learning_actions = vec![Vec::with_capacity(args.epochs + 1); actions];
+    let mut record = |epoch: usize, evaluation: Evaluation|{
+        info!("Epoch {epoch}: learning agents' payoff: {:?}, scripted agents' payoff: {:?}, learning agents' actions: {:.02?}",
+            evaluation.payoffs.learning, evaluation.payoffs.scripted[0], evaluation.learning_actions);
+        payoffs.push(&evaluation.payoffs);
+        for (series, frequency) in learning_actions.iter_mut().zip(evaluation.learning_actions){
+            series.push(frequency);
+        }
+    };
+
+    info!("Starting initial evaluation");
+    record(0, evaluate(&mut model, args.evaluation_episodes, actions));
+    for e in 0..args.epochs{
+        info!("Running training epoch: {}", e);
+        for _ in 0..args.batch_size{
+            model.run_episode();
+        }
+        update_policies(&mut model)?;
+        record(e + 1, evaluate(&mut model, args.evaluation_episodes, actions));
+    }
+
+    let stamp = chrono::Local::now().format("[%Y-%m-%d][%H:%M:%S]");
+    let base_path = "results/matrix_game/";
+    create_dir_all(base_path)?;
+    let name = format!("{:?}{}_{}-{}-{}-{}_{}", args.game, actions, args.number_of_rounds,
+        args.number_of_learning, args.number_of_uniform, args.number_of_fixed, stamp);
+
+    let plot = Plot::new()
+        .size(args.plot_width, args.plot_height)
+        .x_desc("Epoch");
+    let action_series: Vec<PlotSeries> = learning_actions.iter().enumerate()
+        .map(|(i, series)| PlotSeries::new(series.clone(), &format!("Action {i}"), ACTION_COLORS[i % ACTION_COLORS.len()]))
+        .collect();
+    let path = format!("{base_path}/learning_actions-{name}.{}", args.plot_format.extension());
+    plot.clone().y_desc("Frequency").draw(Path::new(&path), &action_series[..])
+        .map_err(|e| ExperimentError::plot(Path::new(&path), e))?;
+
+    let payoff_series = payoffs.plot_series(&[colors::BLACK, colors::RED]);
+    let path = format!("{base_path}/payoffs-{name}.{}", args.plot_format.extension());
+    plot.y_desc("Payoff").draw(Path::new(&path), &payoff_series[..])
+        .map_err(|e| ExperimentError::plot(Path::new(&path), e))?;
+
+    let results = MatrixResults{
+        payoffs: payoffs.into_series(),
+        learning_actions: learning_actions.into_iter().enumerate()
+            .map(|(i, frequencies)| ActionSeries{action: MatrixAction(i as u8), frequencies})
+            .collect(),
+    };
+    write_json(format!("{base_path}/series-{name}.json"), &results, true)?;
+    Ok(())
+}
@@ -0,0 +1,64 @@
+use std::path::PathBuf;
+use log::LevelFilter;
+use clap::Parser;
+use amfiteatr_examples::matrix::MatrixPreset;
+use amfiteatr_examples::plots::PlotFormat;
+
+#[derive(Parser)]
+#[command(author, version, about, long_about = None)]
+pub struct MatrixOptions{
+
+    #[arg(short = 'v', long = "log_level", value_enum, default_value = "info")]
+    pub log_level: LevelFilter,
+
+    #[arg(short = 'a', long = "log_level_amfi", value_enum, default_value = "OFF")]
+    pub log_level_amfi: LevelFilter,
+
+    #[arg(short = 'o', long = "logfile")]
+    pub log_file: Option<PathBuf>,
+
+    #[arg(short = 'e', long = "epochs", default_value = "100")]
+    pub epochs: usize,
+
+    #[arg(short = 'b', long = "batch", default_value = "64")]
+    pub batch_size: usize,
+
+    /// Test episodes played before training and after every epoch
+    #[arg(long = "evaluation-episodes", default_value = "100")]
+    pub evaluation_episodes: usize,
+
+    #[arg(short = 'n', long = "rounds", default_value = "10")]
+    pub number_of_rounds: usize,
+
+    #[arg(long = "game", value_enum, default_value = "rock-paper-scissors")]
+    pub game: MatrixPreset,
+
+    /// Number of actions in game, learners' networks have actor output for each of them
+    #[arg(long = "actions", default_value = "3", value_parser = clap::value_parser!(u8).range(2..))]
+    pub actions: u8,
+
+    #[arg(short = 'l', long = "learners", default_value = "2")]
+    pub number_of_learning: usize,
+
+    /// Agents choosing actions uniformly at random
+    #[arg(short = 'u', long = "uniform", default_value = "2")]
+    pub number_of_uniform: usize,
+
+    /// Agents always playing the same action, actions are assigned to them in turn
+    #[arg(short = 'f', long = "fixed", default_value = "0")]
+    pub number_of_fixed: usize,
+
+    /// Seed of environment (pairings), so runs can be repeated. Policies of agents keep their
+    /// own randomness
+    #[arg(long = "seed")]
+    pub seed: Option<u64>,
+
+    #[arg(long = "plot-format", value_enum, default_value = "svg")]
+    pub plot_format: PlotFormat,
+
+    #[arg(long = "plot-width", default_value = "400")]
+    pub plot_width: u32,
+
+    #[arg(long = "plot-height", default_value = "300")]
+    pub plot_height: u32,
+}
@@ -1,9 +1,8 @@
 mod options;
 
 use std::{thread};
-use std::fs::File;
 use std::path::{Path};
-use log::{debug, info};
+use log::{debug, info, warn};
 use amfiteatr_rl::tch::{Device, nn, Tensor};
 use amfiteatr_rl::tch::nn::{Adam, VarStore};
 use amfiteatr_rl::tensor_data::{ConversionToTensor};
@@ -13,18 +12,22 @@ use plotters::style::colors;
 use amfiteatr_core::agent::*;
 use amfiteatr_core::comm::EnvironmentMpscPort;
 use amfiteatr_core::env::{AutoEnvironmentWithScores, ReseedEnvironment, ScoreEnvironment, TracingBasicEnvironment, TracingEnvironment};
-use amfiteatr_core::error::AmfiError;
 use amfiteatr_classic::agent::{FibonacciForgiveStrategy, LocalHistoryInfoSet, LocalHistoryInfoSetNumbered, LocalHistoryConversionToTensor, SwitchAfterTwo};
 use amfiteatr_classic::domain::{AgentNum, ClassicGameDomain, ClassicGameDomainNumbered};
 use amfiteatr_classic::domain::ClassicAction::{Down, Up};
-use amfiteatr_classic::env::PairingState;
+use amfiteatr_examples::pairing::new_game_state;
+use amfiteatr_examples::pairing::noise::Noise;
 use amfiteatr_classic::policy::ClassicMixedStrategy;
 use amfiteatr_classic::SymmetricRewardTableInt;
+use amfiteatr_examples::games::GamePreset;
 use amfiteatr_rl::policy::{ActorCriticPolicy, LearningNetworkPolicy, TrainConfig};
 use crate::options::EducatorOptions;
 use crate::options::SecondPolicy;
-use amfiteatr_examples::plots::{plot_many_series, PlotSeries};
+use amfiteatr_examples::http::{HttpDashboard, LiveData};
+use amfiteatr_examples::plots::{Dashboard, HeatmapData, Panel, Plot, plot_heatmap, PlotSeries};
 use amfiteatr_examples::series::{MultiAgentPayoffSeries, PayoffSeries};
+use amfiteatr_examples::probe::ResponseProbe;
+use amfiteatr_examples::error::{create_dir_all, EpisodeReport, ExperimentError, Participant, write_json};
 
 /*
 pub struct ModelElements<ID: UsizeAgentId, Seed>{
@@ -63,32 +66,41 @@ type Domain = ClassicGameDomain<AgentNum>;
 //type A2C = ActorCriticPolicy<D, OwnHistoryInfoSetNumbered, OwnHistoryTensorRepr>;
 
 
+/// Runs one game in threads of environment and agents, `episode` is number of game reported
+/// in case of failure.
 pub fn run_game(
+    episode: usize,
     env: &mut (impl AutoEnvironmentWithScores<Domain> + Send + ReseedEnvironment<Domain, ()>),
     agent0: &mut (impl EpisodeMemoryAgent<Domain, ()> + AutomaticAgentRewarded<Domain> + Send + ReseedAgent<Domain, ()>),
     //agent1: &mut (impl MultiEpisodeAgent<Domain, ()> + AutomaticAgentRewarded<Domain> + Send + ReseedAgent<Domain, ()>)
     agent1: &mut Box<dyn ModelAgent<Domain, (), LocalHistoryInfoSetNumbered>>
     )
-    -> Result<(), AmfiError<Domain>>{
+    -> Result<(), ExperimentError<Domain>>{
 
+    let mut report = EpisodeReport::default();
+    let id0 = *agent0.id();
+    let id1 = *agent1.id();
     thread::scope(|s|{
-        s.spawn(||{
+        let env_handle = s.spawn(||{
             env.reseed(());
-            env.run_with_scores().unwrap();
+            env.run_with_scores()
         });
-        s.spawn(||{
+        let handle0 = s.spawn(||{
             agent0.reseed(());
-            agent0.run_episode_rewarded(()).unwrap()
+            agent0.run_episode_rewarded(())
         });
-        s.spawn(||{
+        let handle1 = s.spawn(||{
             //let mut g = agent1.lock().unwrap();
             //g.run_episode_rewarded(()).unwrap()
 
             agent1.reseed(());
-            agent1.run_episode_rewarded(()).unwrap()
+            agent1.run_episode_rewarded(())
         });
+        report.join(Participant::Environment, env_handle);
+        report.join(Participant::Agent(id0), handle0);
+        report.join(Participant::Agent(id1), handle1);
     });
-    Ok(())
+    report.into_result(episode)
 
 }
 /*
@@ -111,11 +123,39 @@ type D = ClassicGameDomainNumbered;
 //type C = SyncCommAgent<D>;
 //type IS = OwnHistoryInfoSet<AgentNum>;
 
+/// Publishes current payoffs of both agents and actions of agent 1 to HTTP dashboard.
+fn publish_live(live: &LiveData, epoch: usize, plot: &Plot, payoffs: [&Vec<f32>; 2], actions: [&Vec<f32>; 2]){
+    live.set_epoch(epoch);
+    let series = MultiAgentPayoffSeries::<D>{
+        agent_series: payoffs.iter().enumerate().map(|(id, p)| PayoffSeries{
+            id: id as AgentNum,
+            payoffs: p.to_vec(),
+        }).collect(),
+    };
+    if let Err(e) = live.publish_json("payoffs", &series){
+        warn!("Failed publishing payoffs: {e}");
+    }
+    let payoff_series = [
+        PlotSeries::new(payoffs[0].clone(), "Agent 0", colors::RED),
+        PlotSeries::new(payoffs[1].clone(), "Agent 1", colors::BLUE),
+    ];
+    let action_series = [
+        PlotSeries::new(actions[0].clone(), "Agent 1 cooperations", colors::BLUE),
+        PlotSeries::new(actions[1].clone(), "Agent 1 defects", colors::RED),
+    ];
+    for (name, desc, series) in [("payoffs", "Payoff", &payoff_series), ("actions", "Actions taken", &action_series)]{
+        match plot.clone().y_desc(desc).to_svg(series){
+            Ok(svg) => live.publish_svg(name, svg),
+            Err(e) => warn!("Failed plotting live {name}: {e}"),
+        }
+    }
+}
+
 
-fn main() -> Result<(), AmfiError<ClassicGameDomain<AgentNum>>>{
+fn main() -> Result<(), ExperimentError<ClassicGameDomain<AgentNum>>>{
 
     let args = EducatorOptions::parse();
-    setup_logger(&args).unwrap();
+    setup_logger(&args)?;
     let device = Device::Cpu;
     //type Domain = ClassicGameDomainNumbered;
     let number_of_players = 2;
@@ -136,14 +176,14 @@ fn main() -> Result<(), AmfiError<ClassicGameDomain<AgentNum>>>{
 
 
     let mut env_adapter = EnvironmentMpscPort::new();
-    let comm0 = env_adapter.register_agent(0).unwrap();
-    let comm1 = env_adapter.register_agent(1).unwrap();
+    let comm0 = env_adapter.register_agent(0)?;
+    let comm1 = env_adapter.register_agent(1)?;
 
-    let reward_table = SymmetricRewardTableInt::new(
+    let reward_table = GamePreset::table_or(args.game, SymmetricRewardTableInt::new(
         args.coop_versus_coop,
         args.coop_versus_defect,
         args.defect_versus_coop,
-        args.defect_versus_defect);
+        args.defect_versus_defect));
 
 
     let net_template = NeuralNetTemplate::new(|path|{
@@ -168,18 +208,19 @@ fn main() -> Result<(), AmfiError<ClassicGameDomain<AgentNum>>>{
 
 
 
-    let env_state_template = PairingState::new_even(number_of_players, args.number_of_rounds, reward_table.into()).unwrap();
+    let env_state_template = new_game_state(number_of_players, args.number_of_rounds, reward_table,
+        Noise::new(args.action_noise, args.observation_noise), args.continuation)?;
     let mut environment = TracingBasicEnvironment::new(env_state_template.clone(), env_adapter);
 
 
     let net0 = A2CNet::new(VarStore::new(device), net_template.get_net_closure());
-    let opt0 = net0.build_optimizer(Adam::default(), 1e-4).unwrap();
+    let opt0 = net0.build_optimizer(Adam::default(), 1e-4)?;
     let normal_policy = ActorCriticPolicy::new(net0, opt0, tensor_repr, TrainConfig {gamma: 0.99});
-    let state0 = LocalHistoryInfoSet::new(0, reward_table.into());
+    let state0 = LocalHistoryInfoSet::new(0, reward_table);
     let mut agent_0 = TracingAgentGen::new(state0, comm0, normal_policy);
 
 
-    let state1 = LocalHistoryInfoSet::new(1, reward_table.into());
+    let state1 = LocalHistoryInfoSet::new(1, reward_table);
 
     let mut agent_1: Box<dyn ModelAgent<D, (), LocalHistoryInfoSetNumbered, >> = match args.policy{
         SecondPolicy::Mixed => {
@@ -191,13 +232,57 @@ fn main() -> Result<(), AmfiError<ClassicGameDomain<AgentNum>>>{
     };
 
 
+    let s_policy = match args.policy{
+        SecondPolicy::Mixed => {format!("mixed-{:.02}", args.defect_proba)}
+        SecondPolicy::SwitchTwo => {format!("switch2")}
+        SecondPolicy::FibonacciForgive => {format!("fibonacci")},
+        SecondPolicy::ForgiveAfterTwo => format!("forgive_2coops"),
+    };
+    let stamp = chrono::Local::now().format("[%Y-%m-%d][%H:%M:%S]");
+    let base_path = "results/one_fixed/";
+    create_dir_all(base_path)?;
+
+    let heatmap_probe = ResponseProbe::new(args.heatmap_memory, args.number_of_rounds, args.heatmap_samples);
+    let write_heatmap = |policy: &_, epoch: usize| -> Result<Option<(usize, HeatmapData)>, ExperimentError<Domain>>{
+        if args.heatmap_every == 0 || !epoch.is_multiple_of(args.heatmap_every){
+            return Ok(None);
+        }
+        match heatmap_probe.heatmap(policy, 0, reward_table){
+            Ok(heatmap) => {
+                let path = format!("{}/heatmap-1l-{}-{:?}-e{:04}_{}.{}",
+                        base_path,
+                        &s_policy.as_str(),
+                        args.number_of_rounds,
+                        epoch,
+                        stamp,
+                        args.plot_format.extension());
+                plot_heatmap(Path::new(&path), &format!("Cooperation probability (epoch {epoch})"), &heatmap,
+                "Round",
+                "Opponent's last actions"
+                ).map_err(|e| ExperimentError::plot(Path::new(&path), e))?;
+                Ok(Some((epoch, heatmap)))
+            },
+            Err(e) => {
+                warn!("Failed probing cooperation heatmap: {e}");
+                Ok(None)
+            }
+        }
+    };
+
+    let http = args.http_port.map(HttpDashboard::start).transpose().map_err(ExperimentError::Http)?;
+    let live_plot = Plot::new()
+        .size(args.plot_width, args.plot_height)
+        .x_desc("Epoch");
+
     //evaluate on start
+    let mut episode = 0;
     let mut scores = [Vec::new(), Vec::new()];
     let mut actions = [Vec::new(), Vec::new()];
     for i in 0..100{
         debug!("Plaing round: {i:} of initial simulation");
         //let mut agent_1_guard = agent_1.lock().unwrap();
-        run_game(&mut environment, &mut agent_0, &mut agent_1)?;
+        run_game(episode, &mut environment, &mut agent_0, &mut agent_1)?;
+        episode += 1;
         scores[0].push(agent_0.current_universal_score()) ;
         scores[1].push(agent_1.current_universal_score());
         actions[0].push(agent_0.info_set().count_actions_self_calculate(Down));
@@ -220,6 +305,10 @@ fn main() -> Result<(), AmfiError<ClassicGameDomain<AgentNum>>>{
     agent_1_coops.push(avg_a[0] as f32);
     agent_1_defects.push(avg_a[1] as f32);
     //custom_payoffs_1.push(avg[2] as f32);
+    let mut last_heatmap = write_heatmap(agent_0.policy(), 0)?;
+    if let Some(http) = &http{
+        publish_live(http.data(), 0, &live_plot, [&payoffs_0, &payoffs_1], [&agent_1_coops, &agent_1_defects]);
+    }
 
 
     for e in 0..args.epochs{
@@ -227,11 +316,13 @@ fn main() -> Result<(), AmfiError<ClassicGameDomain<AgentNum>>>{
         agent_1.clear_episodes();
         info!("Starting epoch {e:}");
         for _g in 0..args.batch_size{
-            run_game(&mut environment, &mut agent_0, &mut agent_1)?;
+            run_game(episode, &mut environment, &mut agent_0, &mut agent_1)?;
+            episode += 1;
         }
         let trajectories_0 = agent_0.take_episodes();
         //let trajectories_1 = agent_1.take_episodes();
         agent_0.policy_mut().train_on_trajectories_env_reward(&trajectories_0[..])?;
+        last_heatmap = write_heatmap(agent_0.policy(), e+1)?.or(last_heatmap);
 
 
 
@@ -239,7 +330,8 @@ fn main() -> Result<(), AmfiError<ClassicGameDomain<AgentNum>>>{
         let mut actions = [Vec::new(), Vec::new()];
         for i in 0..100{
             debug!("Plaing round: {i:} of initial simulation");
-            run_game(&mut environment, &mut agent_0, &mut agent_1)?;
+            run_game(episode, &mut environment, &mut agent_0, &mut agent_1)?;
+            episode += 1;
             scores[0].push(agent_0.current_universal_score());
             scores[1].push(agent_1.current_universal_score());
             actions[0].push(agent_0.info_set().count_actions_self_calculate(Down));
@@ -261,14 +353,19 @@ fn main() -> Result<(), AmfiError<ClassicGameDomain<AgentNum>>>{
         agent_1_coops.push(avg_a[0] as f32);
         agent_1_defects.push(avg_a[1] as f32);
         //custom_payoffs_1.push(avg[2] as f32);
+        if let Some(http) = &http{
+            publish_live(http.data(), e+1, &live_plot, [&payoffs_0, &payoffs_1], [&agent_1_coops, &agent_1_defects]);
+        }
     }
 
-    run_game(&mut environment, &mut agent_0, &mut agent_1)?;
+    run_game(episode, &mut environment, &mut agent_0, &mut agent_1)?;
     //println!("{:?}", agent_0.take_episodes().last().unwrap().list().last().unwrap());
 
 
 
-    println!("{}", environment.trajectory().list().last().unwrap());
+    if let Some(step) = environment.trajectory().list().last(){
+        println!("{}", step);
+    }
 
     println!("Scores: 0: {},\t1: {}", environment.actual_score_of_player(&0), environment.actual_score_of_player(&1));
 
@@ -276,40 +373,15 @@ fn main() -> Result<(), AmfiError<ClassicGameDomain<AgentNum>>>{
     //plot_payoffs(Path::new(format!("agent_0-{:?}-{:?}.svg", args.policy, args.number_of_rounds).as_str()), &payoffs_0[..]).unwrap();
     //plot_payoffs(Path::new(format!("agent_1-{:?}-{:?}.svg", args.policy, args.number_of_rounds).as_str()), &payoffs_1[..]).unwrap();
 
-    let agent0_data = PlotSeries {
-        data: payoffs_0,
-        description: "Agent 0".to_string(),
-        color: colors::RED,
-    };
-    let agent1_data = PlotSeries {
-        data: payoffs_1,
-        description: "Agent 1".to_string(),
-        color: colors::BLUE,
-    };
+    let agent0_data = PlotSeries::new(payoffs_0, "Agent 0", colors::RED);
+    let agent1_data = PlotSeries::new(payoffs_1, "Agent 1", colors::BLUE);
 
-    let agent1_coops = PlotSeries {
-        data: agent_1_coops,
-        description: "Agent 1 cooperations".to_string(),
-        color: colors::BLUE,
-    };
-    let agent1_defects = PlotSeries {
-        data: agent_1_defects,
-        description: "Agent 1 defects".to_string(),
-        color: colors::RED
-    };
+    let agent1_coops = PlotSeries::new(agent_1_coops, "Agent 1 cooperations", colors::BLUE);
+    let agent1_defects = PlotSeries::new(agent_1_defects, "Agent 1 defects", colors::RED);
 
 
 
 
-    let s_policy = match args.policy{
-        SecondPolicy::Mixed => {format!("mixed-{:.02}", args.defect_proba)}
-        SecondPolicy::SwitchTwo => {format!("switch2")}
-        SecondPolicy::FibonacciForgive => {format!("fibonacci")},
-        SecondPolicy::ForgiveAfterTwo => format!("forgive_2coops"),
-    };
-    let stamp = chrono::Local::now().format("[%Y-%m-%d][%H:%M:%S]");
-    let base_path = "results/one_fixed/";
-    std::fs::create_dir_all(&base_path).unwrap();
 
     let mut series = MultiAgentPayoffSeries::<D>{
         agent_series: vec![],
@@ -325,48 +397,77 @@ fn main() -> Result<(), AmfiError<ClassicGameDomain<AgentNum>>>{
 
     });
 
-    plot_many_series(Path::new(
-        format!("{}/payoffs-1l-{}-{:?}_{}.svg",
+    let payoff_series = vec![agent0_data, agent1_data];
+    let action_series = vec![agent1_coops, agent1_defects];
+
+    let plot = Plot::new()
+        .size(args.plot_width, args.plot_height)
+        .x_desc("Epoch");
+
+    let path = format!("{}/payoffs-1l-{}-{:?}_{}.{}",
                 base_path,
                 &s_policy.as_str(),
                 args.number_of_rounds,
-                stamp)
-            .as_str()), "",&[agent0_data, agent1_data,],
-        "Epoch",
-        "Payoff"
-    ).unwrap();
+                stamp,
+                args.plot_format.extension());
+    plot.clone().y_desc("Payoff").draw(Path::new(&path), &payoff_series[..])
+        .map_err(|e| ExperimentError::plot(Path::new(&path), e))?;
     //plot_payoffs(Path::new(format!("custom-payoffs-{:?}-{:?}.svg", args.policy, args.number_of_rounds).as_str()), &agent1_custom_data ).unwrap();
 
-    plot_many_series(Path::new(
-        format!("{}/actions-1l-{}-{:?}_{}.svg",
+    let path = format!("{}/actions-1l-{}-{:?}_{}.{}",
+                base_path,
+                &s_policy.as_str(),
+                args.number_of_rounds,
+                stamp,
+                args.plot_format.extension());
+    plot.clone().y_desc("Actions taken").draw(Path::new(&path), &action_series[..])
+        .map_err(|e| ExperimentError::plot(Path::new(&path), e))?;
+
+    let mut dashboard = Dashboard::new(&format!("One learning agent vs {}", s_policy))
+        .panel_size(args.plot_width, args.plot_height)
+        .panel(Panel::lines(plot.clone().title("Payoffs").y_desc("Payoff"), payoff_series))
+        .panel(Panel::lines(plot.clone().title("Agent 1 actions").y_desc("Actions taken"), action_series));
+    if let Some((epoch, heatmap)) = last_heatmap{
+        dashboard = dashboard.panel(Panel::heatmap(&format!("Cooperation probability (epoch {epoch})"),
+            heatmap, "Round", "Opponent's last actions"));
+    }
+    let path = format!("{}/dashboard-1l-{}-{:?}_{}.{}",
                 base_path,
                 &s_policy.as_str(),
                 args.number_of_rounds,
-                stamp)
-            .as_str(), ), "", &[agent1_coops, agent1_defects,],
-        "Epoch",
-        "Actions taken"
-    ).unwrap();
-
-    let file = File::create(
-        format!("{}/payoffs-1l-{}-{:?}_{}.json",
+                stamp,
+                args.plot_format.extension());
+    dashboard.draw(Path::new(&path))
+        .map_err(|e| ExperimentError::plot(Path::new(&path), e))?;
+
+    write_json(format!("{}/payoffs-1l-{}-{:?}_{}.json",
                 base_path,
                 &s_policy.as_str(),
                 args.number_of_rounds,
-                stamp).as_str()).unwrap();
-    serde_json::to_writer(file, &series).unwrap();
+                stamp), &series, false)?;
+
+    if args.probe_samples > 0{
+        let probe = ResponseProbe::new(args.probe_memory, args.number_of_rounds, args.probe_samples);
+        match probe.probe(agent_0.policy(), *agent_0.id(), reward_table){
+            Ok(report) => {
+                info!("{}", report);
+                write_json(format!("{}/strategy-1l-{}-{:?}_{}.json",
+                            base_path,
+                            &s_policy.as_str(),
+                            args.number_of_rounds,
+                            stamp), &report, true)?;
+            },
+            Err(e) => warn!("Failed probing learned strategy: {e}")
+        }
+    }
 
     if let Some(agent_0_trace) = agent_0.episodes().last(){
-        let file_trace_0 = File::create(
-            format!(
+        write_json(format!(
             "{}/trace0-1l-{}-{:?}-{}.json",
                 base_path,
                 &s_policy.as_str(),
                 args.number_of_rounds,
-                stamp).as_str()
-        ).unwrap();
-
-        serde_json::to_writer(file_trace_0, agent_0_trace).unwrap()
+                stamp), agent_0_trace, false)?;
     }
     /*
     if let Some(agent_1_trace) = agent_1.episodes().last(){
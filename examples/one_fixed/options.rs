@@ -1,6 +1,9 @@
 use std::path::PathBuf;
 use log::LevelFilter;
+use amfiteatr_examples::plots::PlotFormat;
 use clap::{ValueEnum, Parser};
+use amfiteatr_examples::pairing::noise::parse_probability;
+use amfiteatr_examples::games::GamePreset;
 
 #[derive(ValueEnum, Debug, Clone)]
 pub enum SecondPolicy{
@@ -38,6 +41,19 @@ pub struct EducatorOptions{
     #[arg(short = 'n', long = "rounds", default_value = "10")]
     pub number_of_rounds: usize,
 
+    /// Probability that environment executes action opposite to chosen one (trembling hand)
+    #[arg(long = "action-noise", default_value = "0", value_parser = parse_probability)]
+    pub action_noise: f64,
+
+    /// Probability that agent observes opposite action of its opponent than was executed
+    #[arg(long = "observation-noise", default_value = "0", value_parser = parse_probability)]
+    pub observation_noise: f64,
+
+    /// Probability of playing next round after every round (shadow of the future), with it
+    /// number of rounds is limit of game length
+    #[arg(short = 'w', long = "continuation", value_parser = parse_probability)]
+    pub continuation: Option<f64>,
+
     #[arg(short = 'p', long = "policy", default_value = "std")]
     pub policy: SecondPolicy,
 
@@ -47,6 +63,10 @@ pub struct EducatorOptions{
     #[arg(short = 'M', long = "defect-proba", default_value = "0.5")]
     pub defect_proba: f32,
 
+    /// Game played, without it reward table is built from payoff options
+    #[arg(long = "game", value_enum)]
+    pub game: Option<GamePreset>,
+
     #[arg(long = "defect-defect", default_value = "3")]
     pub defect_versus_defect: i64,
 
@@ -59,6 +79,38 @@ pub struct EducatorOptions{
     #[arg(long = "coop-coop", default_value = "5")]
     pub coop_versus_coop: i64,
 
+    /// Number of previous rounds considered when probing learned strategy
+    #[arg(long = "probe-memory", default_value = "1")]
+    pub probe_memory: usize,
+
+    /// Number of sampled actions per probed history, 0 disables probing
+    #[arg(long = "probe-samples", default_value = "200")]
+    pub probe_samples: usize,
+
+    /// Plot cooperation heatmap every given number of epochs, 0 disables heatmaps
+    #[arg(long = "heatmap-every", default_value = "0")]
+    pub heatmap_every: usize,
+
+    /// Number of opponent's last actions on heatmap rows
+    #[arg(long = "heatmap-memory", default_value = "2")]
+    pub heatmap_memory: usize,
+
+    #[arg(long = "heatmap-samples", default_value = "50")]
+    pub heatmap_samples: usize,
+
+    #[arg(long = "plot-format", value_enum, default_value = "svg")]
+    pub plot_format: PlotFormat,
+
+    #[arg(long = "plot-width", default_value = "400")]
+    pub plot_width: u32,
+
+    #[arg(long = "plot-height", default_value = "300")]
+    pub plot_height: u32,
+
+    /// Serve current series and plots on http://127.0.0.1:<port>/ during training
+    #[arg(long = "http-port")]
+    pub http_port: Option<u16>,
+
 
     //#[arg(short = 'r', long = "reward", default_value = "env")]
     //pub reward_source: RewardSource,
@@ -0,0 +1,173 @@
+mod options;
+
+use std::fmt::{Display, Formatter};
+use std::sync::{Arc, Mutex, PoisonError};
+use std::thread;
+use std::time::Instant;
+use clap::Parser;
+use log::info;
+use serde::Serialize;
+use amfiteatr_core::agent::{AgentGen, IdAgent, MultiEpisodeAutoAgentRewarded};
+use amfiteatr_core::comm::{AgentMpscAdapter, EnvironmentMpscPort};
+use amfiteatr_core::env::{AutoEnvironmentWithScores, ReseedEnvironment, TracingBasicEnvironment};
+use amfiteatr_classic::agent::LocalHistoryInfoSet;
+use amfiteatr_classic::domain::{AgentNum, ClassicGameDomainNumbered};
+use amfiteatr_classic::env::PairingState;
+use amfiteatr_classic::policy::ClassicMixedStrategy;
+use amfiteatr_classic::{AsymmetricRewardTableInt, SymmetricRewardTable};
+use amfiteatr_examples::error::{EpisodeReport, ExperimentError, Participant, write_json};
+use amfiteatr_examples::pool::AgentPool;
+use amfiteatr_examples::sync::{run_episode_synchronous, SteppedAgent};
+use crate::options::BenchmarkOptions;
+
+type D = ClassicGameDomainNumbered;
+type Agent = AgentGen<D, ClassicMixedStrategy<AgentNum, LocalHistoryInfoSet<AgentNum>>, AgentMpscAdapter<D>>;
+type SharedAgent = Arc<Mutex<Agent>>;
+type Environment = TracingBasicEnvironment<D, PairingState<AgentNum>, EnvironmentMpscPort<D>>;
+
+/// Way of running agents' threads.
+#[derive(Serialize, Copy, Clone, Debug)]
+#[serde(rename_all = "kebab-case")]
+enum Mode{
+    /// New scoped thread for every agent in every episode
+    SpawnPerEpisode,
+    /// Persistent threads of [`AgentPool`]
+    Pool,
+    /// Agents stepped in thread of environment by [`run_episode_synchronous`]
+    Synchronous,
+}
+
+impl Display for Mode{
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self{
+            Mode::SpawnPerEpisode => write!(f, "spawn per episode"),
+            Mode::Pool => write!(f, "pool"),
+            Mode::Synchronous => write!(f, "synchronous"),
+        }
+    }
+}
+
+#[derive(Serialize, Clone, Debug)]
+struct Measurement{
+    mode: Mode,
+    population: u32,
+    episodes: usize,
+    seconds: f64,
+    episodes_per_second: f64,
+}
+
+pub fn setup_logger(options: &BenchmarkOptions) -> Result<(), fern::InitError> {
+    let dispatch  = fern::Dispatch::new()
+
+        .format(|out, message, record| {
+            out.finish(format_args!(
+                "{}[{}][{}] {}",
+                chrono::Local::now().format("[%H:%M:%S]"),
+                record.target(),
+                record.level(),
+                message
+            ))
+        })
+        .level(options.log_level)
+        .level_for("amfiteatr_core", options.log_level_amfi);
+
+    dispatch.chain(std::io::stdout()).apply()?;
+    Ok(())
+}
+
+fn build_population(population: u32, rounds: usize) -> Result<(Environment, Vec<SharedAgent>), ExperimentError<D>>{
+    let reward_table: AsymmetricRewardTableInt = SymmetricRewardTable::new(2, 1, 4, 0).into();
+    let mut port = EnvironmentMpscPort::new();
+    let mut agents = Vec::with_capacity(population as usize);
+    for i in 0..population as AgentNum{
+        let comm = port.register_agent(i)?;
+        let state = LocalHistoryInfoSet::new(i, reward_table);
+        let agent = AgentGen::new(state, comm, ClassicMixedStrategy::new(0.5));
+        agents.push(Arc::new(Mutex::new(agent)));
+    }
+    let env_state = PairingState::new_even(population as usize, rounds, reward_table)?;
+    Ok((TracingBasicEnvironment::new(env_state, port), agents))
+}
+
+fn run_spawning(environment: &mut Environment, agents: &[SharedAgent]) -> EpisodeReport<D>{
+    let mut report = EpisodeReport::default();
+    thread::scope(|s|{
+        let env_handle = s.spawn(||{
+            environment.reseed(());
+            environment.run_with_scores()
+        });
+        let handles: Vec<_> = agents.iter().map(|agent|{
+            let id = *agent.lock().unwrap_or_else(PoisonError::into_inner).id();
+            (id, s.spawn(move ||{
+                agent.lock().unwrap_or_else(PoisonError::into_inner).run_episode_rewarded(())
+            }))
+        }).collect();
+        report.join(Participant::Environment, env_handle);
+        for (id, handle) in handles{
+            report.join(Participant::Agent(id), handle);
+        }
+    });
+    report
+}
+
+fn run_synchronous(environment: &mut Environment, agents: &[SharedAgent]) -> EpisodeReport<D>{
+    let mut guards: Vec<_> = agents.iter().map(|a| a.lock().unwrap_or_else(PoisonError::into_inner)).collect();
+    let mut stepped: Vec<&mut dyn SteppedAgent<D>> = guards.iter_mut().map(|a| &mut **a as &mut dyn SteppedAgent<D>).collect();
+    let mut report = EpisodeReport::default();
+    report.note(Participant::Environment, Ok(run_episode_synchronous(environment, &mut stepped[..])));
+    report
+}
+
+fn measure(mode: Mode, population: u32, options: &BenchmarkOptions) -> Result<Measurement, ExperimentError<D>>{
+    let (mut environment, agents) = build_population(population, options.number_of_rounds)?;
+    let mut pool = AgentPool::new();
+    if let Mode::Pool = mode{
+        agents.iter().for_each(|a| pool.add(a.clone()));
+    }
+    let start = Instant::now();
+    for episode in 0..options.episodes{
+        let report = match mode{
+            Mode::SpawnPerEpisode => run_spawning(&mut environment, &agents),
+            Mode::Pool => pool.run_episode(&mut environment, |environment|{
+                environment.reseed(());
+                environment.run_with_scores()
+            }),
+            Mode::Synchronous => run_synchronous(&mut environment, &agents),
+        };
+        report.into_result(episode)?;
+    }
+    let seconds = start.elapsed().as_secs_f64();
+    Ok(Measurement{
+        mode, population,
+        episodes: options.episodes,
+        seconds,
+        episodes_per_second: options.episodes as f64 / seconds,
+    })
+}
+
+fn main() -> Result<(), ExperimentError<D>>{
+    let options = BenchmarkOptions::parse();
+    setup_logger(&options)?;
+
+    let mut measurements = Vec::new();
+    for &population in &options.populations{
+        for mode in [Mode::SpawnPerEpisode, Mode::Pool, Mode::Synchronous]{
+            let measurement = measure(mode, population, &options)?;
+            info!("{population} agents, {mode}: {:.2} episodes/s", measurement.episodes_per_second);
+            measurements.push(measurement);
+        }
+    }
+
+    println!("{:>10} {:>20} {:>20} {:>20}", "agents", "spawn [episodes/s]", "pool [episodes/s]", "sync [episodes/s]");
+    for row in measurements.chunks(3){
+        if let [spawn, pool, sync] = row{
+            println!("{:>10} {:>20.2} {:>20.2} {:>20.2}", spawn.population,
+                spawn.episodes_per_second, pool.episodes_per_second, sync.episodes_per_second);
+        }
+    }
+    if let Some(path) = &options.output{
+        write_json(path, &measurements, true)?;
+        info!("Written {path:?}");
+    }
+    Ok(())
+}
@@ -0,0 +1,29 @@
+use std::path::PathBuf;
+use log::LevelFilter;
+use clap::Parser;
+
+#[derive(Parser)]
+#[command(author, version, about, long_about = None)]
+pub struct BenchmarkOptions{
+
+    #[arg(short = 'v', long = "log_level", value_enum, default_value = "info")]
+    pub log_level: LevelFilter,
+
+    #[arg(short = 'a', long = "log_level_amfi", value_enum, default_value = "OFF")]
+    pub log_level_amfi: LevelFilter,
+
+    /// Sizes of populations (must be even) measured one after another
+    #[arg(short = 'p', long = "populations", value_delimiter = ',', default_value = "100,200,500,1000")]
+    pub populations: Vec<u32>,
+
+    /// Episodes measured for every population and execution mode
+    #[arg(short = 'e', long = "episodes", default_value = "20")]
+    pub episodes: usize,
+
+    #[arg(short = 'n', long = "rounds", default_value = "16")]
+    pub number_of_rounds: usize,
+
+    /// Write measured throughput as json
+    #[arg(long = "output")]
+    pub output: Option<PathBuf>,
+}
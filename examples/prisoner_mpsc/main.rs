@@ -1,15 +1,25 @@
+mod options;
+
 use std::thread;
+use clap::Parser;
 use amfiteatr_classic::agent::{LocalHistoryInfoSet};
 use amfiteatr_classic::domain::ClassicAction::Down;
+use amfiteatr_classic::domain::{ClassicGameDomain, TwoPlayersStdName};
 use amfiteatr_classic::domain::TwoPlayersStdName::{Alice, Bob};
-use amfiteatr_classic::env::PairingState;
 use amfiteatr_classic::policy::{ClassicMixedStrategy, ClassicPureStrategy};
 use amfiteatr_classic::SymmetricRewardTableInt;
 use amfiteatr_core::agent::{AgentGen, AutomaticAgentRewarded, StatefulAgent, TracingAgent, TracingAgentGen};
 use amfiteatr_core::comm::EnvironmentMpscPort;
 use amfiteatr_core::env::{AutoEnvironmentWithScores, StatefulEnvironment, TracingBasicEnvironment, TracingEnvironment};
+use amfiteatr_examples::error::{EpisodeReport, ExperimentError, Participant};
+use amfiteatr_examples::pairing::new_game_state;
+use amfiteatr_examples::pairing::noise::Noise;
+use crate::options::MpscOptions;
+
+type Domain = ClassicGameDomain<TwoPlayersStdName>;
 
-fn main() {
+fn main() -> Result<(), ExperimentError<Domain>>{
+    let args = MpscOptions::parse();
     let number_of_players = 2;
     let mut env_adapter = EnvironmentMpscPort::new();
 
@@ -19,35 +29,41 @@ fn main() {
 
 
 
-    let alice_comm = env_adapter.register_agent(Alice).unwrap();
+    let alice_comm = env_adapter.register_agent(Alice)?;
     let alice_policy = ClassicMixedStrategy::new(0.7);
     let alice_state = LocalHistoryInfoSet::new(Alice, reward_table.into());
     let mut alice = TracingAgentGen::new(alice_state, alice_comm, alice_policy);
 
-    let comm_bob = env_adapter.register_agent(Bob).unwrap();
+    let comm_bob = env_adapter.register_agent(Bob)?;
     let bob_policy = ClassicPureStrategy::new(Down);
     let bob_state = LocalHistoryInfoSet::new(Bob, reward_table.into());
     let mut bob = AgentGen::new(bob_state, comm_bob, bob_policy);
 
-    let env_state = PairingState::new_even(number_of_players, 1, reward_table.into()).unwrap();
+    let env_state = new_game_state(number_of_players, 1, reward_table.into(),
+        Noise::new(args.action_noise, args.observation_noise), None)?;
     let mut environment = TracingBasicEnvironment::new(env_state, env_adapter);
 
+    let mut report = EpisodeReport::default();
     thread::scope(|s|{
-        s.spawn(||{
-            environment.run_with_scores().unwrap();
+        let env_handle = s.spawn(||{
+            environment.run_with_scores()
         });
-        s.spawn(||{
-            alice.run_rewarded().unwrap();
+        let alice_handle = s.spawn(||{
+            alice.run_rewarded()
         });
-        s.spawn(||{
-            bob.run_rewarded().unwrap();
+        let bob_handle = s.spawn(||{
+            bob.run_rewarded()
         });
+        report.join(Participant::Environment, env_handle);
+        report.join(Participant::Agent(Alice), alice_handle);
+        report.join(Participant::Agent(Bob), bob_handle);
     });
+    report.into_result(0)?;
 
     println!("Final state: {}", environment.state());
     println!("Trajectory of environment: {:?}", environment.trajectory());
 
     println!("Alice final information set: {}", alice.info_set());
     println!("Trajectory of Alice: {:?}", alice.game_trajectory());
-
+    Ok(())
 }
\ No newline at end of file
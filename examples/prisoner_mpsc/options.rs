@@ -0,0 +1,15 @@
+use clap::Parser;
+use amfiteatr_examples::pairing::noise::parse_probability;
+
+#[derive(Parser)]
+#[command(author, version, about, long_about = None)]
+pub struct MpscOptions{
+
+    /// Probability that environment executes action opposite to chosen one (trembling hand)
+    #[arg(long = "action-noise", default_value = "0", value_parser = parse_probability)]
+    pub action_noise: f64,
+
+    /// Probability that agent observes opposite action of its opponent than was executed
+    #[arg(long = "observation-noise", default_value = "0", value_parser = parse_probability)]
+    pub observation_noise: f64,
+}
@@ -1,84 +1,135 @@
-/*
+mod options;
+
 use std::collections::HashMap;
+use std::net::{SocketAddr, TcpListener};
 use std::thread;
-use amfiteatr_classic::agent::{LocalHistoryInfoSet, MinimalInfoSet};
-use amfiteatr_classic::domain::ClassicAction::Down;
-use amfiteatr_classic::domain::TwoPlayersStdName::{Alice, Bob};
-use amfiteatr_classic::env::PairingState;
-use amfiteatr_classic::policy::{ClassicMixedStrategy, ClassicPureStrategy};
+use clap::Parser;
+use log::{error, info};
+use amfiteatr_classic::agent::LocalHistoryInfoSet;
+use amfiteatr_classic::domain::{AgentNum, ClassicGameDomain};
+use amfiteatr_examples::pairing::new_game_state;
+use amfiteatr_examples::pairing::noise::Noise;
+use amfiteatr_classic::policy::ClassicMixedStrategy;
 use amfiteatr_classic::SymmetricRewardTableInt;
-use amfiteatr_core::agent::{AgentGen, AutomaticAgentRewarded, StatefulAgent, TracingAgent, TracingAgentGen};
-use amfiteatr_core::comm::{DynEndpoint, EnvironmentMpscPort, StdEnvironmentEndpoint};
-use amfiteatr_core::env::{AutoEnvironmentWithScores, RoundRobinUniversalEnvironment, StatefulEnvironment, TracingEnv, TracingEnvironment, TracingHashMapEnvironment};
-use amfiteatr_net_ext::tcp::{TcpComm32, TcpComm512};
-
-fn main() {
-    let number_of_players = 2;
-
-    //alice connected locally
-    let (env_alice_comm, alice_comm) = StdEnvironmentEndpoint::new_pair();
-    let env_alice_comm = DynEndpoint::Std(env_alice_comm);
-
-    let (t_bob_stream, r_bob_stream) = std::sync::mpsc::channel();
-    //create listener for
-    let tcp_listener = std::net::TcpListener::bind("127.0.0.1:8420").unwrap();
-    let wait_connection = thread::spawn(move ||{
-        let(stream_listen_bob, _) = tcp_listener.accept().unwrap();
-        t_bob_stream.send(stream_listen_bob).unwrap();
-    });
-    let stream_bob_env = std::net::TcpStream::connect("127.0.0.1:8420").unwrap();
-    let stram_env_bob = r_bob_stream.recv().unwrap();
-    wait_connection.join().unwrap();
-
-    let bob_comm = TcpComm512::new(stream_bob_env);
-
-
-    let env_bob_comm = DynEndpoint::Dynamic(Box::new(TcpComm512::new(stram_env_bob)));
-
-
-
-    let reward_table = SymmetricRewardTableInt::new(5, 1, 10, 3);
-
-
-
-    //let alice_comm = env_adapter.register_agent(Alice).unwrap();
-    let alice_policy = ClassicMixedStrategy::new(0.7);
-    let alice_state = LocalHistoryInfoSet::new(Alice, reward_table.into());
-    let mut alice = TracingAgentGen::new(alice_state, alice_comm, alice_policy);
-
+use amfiteatr_examples::games::GamePreset;
+use amfiteatr_core::agent::{AgentGen, AutomaticAgentRewarded, IdAgent, RewardedAgent, StatefulAgent};
+use amfiteatr_core::comm::{DynEndpoint, StdEnvironmentEndpoint};
+use amfiteatr_core::domain::{AgentMessage, EnvironmentMessage};
+use amfiteatr_core::env::{RoundRobinUniversalEnvironment, ScoreEnvironment, StatefulEnvironment, TracingHashMapEnvironment};
+use amfiteatr_core::error::{AmfiError, CommunicationError};
+use amfiteatr_examples::error::ExperimentError;
+use amfiteatr_examples::net::{accept_agents, AgentTcpEndpoint};
+use crate::options::{Mode, TcpOptions};
+
+type Domain = ClassicGameDomain<AgentNum>;
+type EnvEndpoint = DynEndpoint<EnvironmentMessage<Domain>, AgentMessage<Domain>, CommunicationError<Domain>>;
+
+pub fn setup_logger(options: &TcpOptions) -> Result<(), fern::InitError> {
+    let dispatch  = fern::Dispatch::new()
+
+        .format(|out, message, record| {
+            out.finish(format_args!(
+                "{}[{}][{}] {}",
+                chrono::Local::now().format("[%H:%M:%S]"),
+                record.target(),
+                record.level(),
+                message
+            ))
+        })
+        .level(options.log_level)
+        .level_for("amfiteatr_examples", options.log_level)
+        .level_for("amfiteatr_core", options.log_level_amfi);
+
+        match &options.log_file{
+            None => dispatch.chain(std::io::stdout()),
+            Some(f) => dispatch.chain(fern::log_file(f)?)
+        }
+
+        .apply()?;
+    Ok(())
+}
 
-    let bob_policy = ClassicPureStrategy::new(Down);
-    let bob_state = LocalHistoryInfoSet::new(Bob, reward_table.into());
-    let mut bob = AgentGen::new(bob_state, bob_comm, bob_policy);
+fn run_remote_agent(address: SocketAddr, id: AgentNum, options: &TcpOptions) -> Result<(), AmfiError<Domain>>{
+    let reward_table = GamePreset::table_or(options.game, SymmetricRewardTableInt::new(5, 1, 10, 3));
+    let (id, comm) = AgentTcpEndpoint::connect(address, Some(id), "")?;
+    let state = LocalHistoryInfoSet::new(id, reward_table);
+    let mut agent = AgentGen::new(state, comm, ClassicMixedStrategy::new(options.defect_probability));
+    agent.run_rewarded()?;
+    info!("Remote agent {id} finished with score {}: {}", agent.current_universal_score(), agent.info_set());
+    Ok(())
+}
 
-    let env_state = PairingState::new_even(number_of_players, 1, reward_table.into()).unwrap();
-    // Environment will try receive on any endpoints switching listening in round robin manner
-    let mut env_endpoints = HashMap::new();
-    env_endpoints.insert(Alice, env_alice_comm);
-    env_endpoints.insert(Bob, env_bob_comm);
-    let mut environment= TracingHashMapEnvironment::new(
-        env_state, env_endpoints);
+fn main() -> Result<(), ExperimentError<Domain>>{
+    let options = TcpOptions::parse();
+    setup_logger(&options)?;
+
+    if options.mode == Mode::Client{
+        let address = options.address.parse::<SocketAddr>()
+            .map_err(|e| AmfiError::Custom(format!("Bad address {}: {e}", options.address)))?;
+        run_remote_agent(address, options.id, &options)?;
+        return Ok(());
+    }
+
+    let number_of_players = options.local + options.remote;
+    let reward_table = GamePreset::table_or(options.game, SymmetricRewardTableInt::new(5, 1, 10, 3));
+    let listener = TcpListener::bind(&options.address)
+        .map_err(|e| AmfiError::Custom(format!("Failed binding {}: {e}", options.address)))?;
+    let address = listener.local_addr()
+        .map_err(|e| AmfiError::Custom(format!("{e}")))?;
+    info!("Environment listening on {address}");
+
+    let mut env_endpoints: HashMap<AgentNum, EnvEndpoint> = HashMap::new();
+    let mut local_agents = Vec::new();
+    for id in 0..options.local{
+        let (env_comm, agent_comm) = StdEnvironmentEndpoint::new_pair();
+        env_endpoints.insert(id, DynEndpoint::Std(env_comm));
+        let state = LocalHistoryInfoSet::new(id, reward_table);
+        local_agents.push(AgentGen::new(state, agent_comm, ClassicMixedStrategy::new(options.defect_probability)));
+    }
+    let remote_ids: Vec<AgentNum> = (options.local..number_of_players).collect();
 
     thread::scope(|s|{
-        s.spawn(||{
-            environment.run_round_robin_with_rewards().unwrap();
-        });
-        s.spawn(||{
-            alice.run_rewarded().unwrap();
-        });
-        s.spawn(||{
-            bob.run_rewarded().unwrap();
-        });
-    });
-
-    println!("Final state: {}", environment.state());
-    println!("Trajectory of environment: {:?}", environment.trajectory());
-
-    println!("Alice final information set: {}", alice.info_set());
-    println!("Trajectory of Alice: {:?}", alice.game_trajectory());
-
+        let remote_agents: Vec<_> = match options.mode{
+            Mode::All => remote_ids.iter()
+                .map(|&id| {
+                    let options = &options;
+                    s.spawn(move || run_remote_agent(address, id, options))
+                })
+                .collect(),
+            _ => Vec::new(),
+        };
+
+        info!("Waiting for remote agents: {remote_ids:?}");
+        for agent in accept_agents(&listener, &remote_ids)?{
+            env_endpoints.insert(agent.id, DynEndpoint::Dynamic(Box::new(agent.endpoint)));
+        }
+
+        let env_state = new_game_state(number_of_players as usize, options.number_of_rounds, reward_table,
+            Noise::new(options.action_noise, options.observation_noise), options.continuation)?;
+        let mut environment = TracingHashMapEnvironment::new(env_state, env_endpoints);
+
+        for agent in local_agents.iter_mut(){
+            s.spawn(move ||{
+                if let Err(e) = agent.run_rewarded(){
+                    error!("Local agent failed: {e}");
+                }
+            });
+        }
+        environment.run_round_robin_with_rewards()?;
+
+        for handle in remote_agents{
+            handle.join().map_err(|_| AmfiError::Custom("Remote agent thread panicked".into()))??;
+        }
+
+        info!("Final state: {}", environment.state());
+        for id in 0..number_of_players{
+            info!("Score of agent {id}: {}", environment.actual_score_of_player(&id));
+        }
+        Ok::<(), AmfiError<Domain>>(())
+    })?;
+
+    for agent in local_agents.iter(){
+        info!("Local agent {} final information set: {}", agent.id(), agent.info_set());
+    }
+    Ok(())
 }
-*/
-
-
-fn main() {}
\ No newline at end of file
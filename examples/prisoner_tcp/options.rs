@@ -0,0 +1,71 @@
+use std::path::PathBuf;
+use log::LevelFilter;
+use clap::{Parser, ValueEnum};
+use amfiteatr_examples::pairing::noise::parse_probability;
+use amfiteatr_examples::games::GamePreset;
+
+#[derive(ValueEnum, Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Mode{
+    /// Environment, local agents and remote agents connecting from threads of this process
+    All,
+    /// Environment and local agents, remote agents connect from other processes
+    Server,
+    /// Single remote agent connecting to server
+    Client,
+}
+
+#[derive(Parser)]
+#[command(author, version, about, long_about = None)]
+pub struct TcpOptions{
+
+    #[arg(short = 'v', long = "log_level", value_enum, default_value = "info")]
+    pub log_level: LevelFilter,
+
+    #[arg(short = 'a', long = "log_level_amfi", value_enum, default_value = "OFF")]
+    pub log_level_amfi: LevelFilter,
+
+    #[arg(short = 'o', long = "logfile")]
+    pub log_file: Option<PathBuf>,
+
+    #[arg(long = "mode", value_enum, default_value = "all")]
+    pub mode: Mode,
+
+    /// Address environment listens on (server) or connects to (client)
+    #[arg(short = 'A', long = "address", default_value = "127.0.0.1:8420")]
+    pub address: String,
+
+    /// Number of agents connected to environment with mpsc channels
+    #[arg(short = 'L', long = "local", default_value = "1")]
+    pub local: u32,
+
+    /// Number of agents connected to environment over TCP, their ids follow ids of local agents
+    #[arg(short = 'R', long = "remote", default_value = "1")]
+    pub remote: u32,
+
+    /// Id of agent run in client mode
+    #[arg(long = "id", default_value = "1")]
+    pub id: u32,
+
+    #[arg(short = 'n', long = "rounds", default_value = "10")]
+    pub number_of_rounds: usize,
+
+    /// Probability that environment executes action opposite to chosen one (trembling hand)
+    #[arg(long = "action-noise", default_value = "0", value_parser = parse_probability)]
+    pub action_noise: f64,
+
+    /// Probability that agent observes opposite action of its opponent than was executed
+    #[arg(long = "observation-noise", default_value = "0", value_parser = parse_probability)]
+    pub observation_noise: f64,
+
+    /// Probability of playing next round after every round (shadow of the future), with it
+    /// number of rounds is limit of game length
+    #[arg(short = 'w', long = "continuation", value_parser = parse_probability)]
+    pub continuation: Option<f64>,
+
+    /// Game played, without it agents play prisoner's dilemma
+    #[arg(long = "game", value_enum)]
+    pub game: Option<GamePreset>,
+
+    #[arg(short = 'p', long = "defect-probability", default_value = "0.3")]
+    pub defect_probability: f64,
+}
@@ -0,0 +1,205 @@
+mod options;
+
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+use clap::Parser;
+use log::{debug, info};
+use rand::rngs::StdRng;
+use rand::SeedableRng;
+use plotters::style::{colors, RGBColor};
+use serde::Serialize;
+use amfiteatr_core::agent::{AgentGen, PolicyAgent, StatefulAgent, TracingAgentGen};
+use amfiteatr_core::comm::{AgentMpscAdapter, EnvironmentMpscPort};
+use amfiteatr_core::env::TracingBasicEnvironment;
+use amfiteatr_classic::domain::AgentNum;
+use amfiteatr_rl::policy::{ActorCriticPolicy, LearningNetworkPolicy, TrainConfig};
+use amfiteatr_rl::tch::Device;
+use amfiteatr_rl::tch::nn::Adam;
+use amfiteatr_rl::tensor_data::ConversionToTensor;
+use amfiteatr_examples::error::{create_dir_all, ExperimentError, write_json};
+use amfiteatr_examples::plots::{Plot, PlotSeries};
+use amfiteatr_examples::policy::a2c_network;
+use amfiteatr_examples::public_goods::{PublicGoodsConversion, PublicGoodsDomainNumbered, PublicGoodsInfoSet, PublicGoodsParams, PublicGoodsState, PublicGoodsStrategy, Punishment};
+use amfiteatr_examples::series::{GroupPayoffHistory, GroupPayoffs, PayoffGroupSeries};
+use amfiteatr_examples::sync::{ScriptedGroup, SynchronousModel};
+use crate::options::PublicGoodsOptions;
+
+type D = PublicGoodsDomainNumbered;
+type InfoSet = PublicGoodsInfoSet<AgentNum>;
+type AgentComm = AgentMpscAdapter<D>;
+type Learner = TracingAgentGen<D, ActorCriticPolicy<D, InfoSet, PublicGoodsConversion>, AgentComm>;
+type Scripted = AgentGen<D, PublicGoodsStrategy<AgentNum>, AgentComm>;
+
+const GROUP_COLORS: [RGBColor; 5] = [colors::BLACK, colors::GREEN, colors::RED, colors::BLUE, colors::MAGENTA];
+
+#[derive(Serialize, Clone, Debug)]
+struct PublicGoodsResults{
+    params: PublicGoodsParams,
+    payoffs: Vec<PayoffGroupSeries>,
+    learning_contributions: Vec<f32>,
+    learning_punishments: Vec<f32>,
+}
+
+pub fn setup_logger(options: &PublicGoodsOptions) -> Result<(), fern::InitError> {
+    let dispatch  = fern::Dispatch::new()
+
+        .format(|out, message, record| {
+            out.finish(format_args!(
+                "{}[{}][{}] {}",
+                chrono::Local::now().format("[%H:%M:%S]"),
+                record.target(),
+                record.level(),
+                message
+            ))
+        })
+        .level(options.log_level)
+        .level_for("amfiteatr_core", options.log_level_amfi);
+
+        match &options.log_file{
+            None => dispatch.chain(std::io::stdout()),
+            Some(f) => dispatch.chain(fern::log_file(f)?)
+        }
+
+        .apply()?;
+    Ok(())
+}
+
+type Model = SynchronousModel<D, TracingBasicEnvironment<D, PublicGoodsState<AgentNum>, EnvironmentMpscPort<D>>, Learner, Scripted>;
+
+/// Averages of test episodes.
+struct Evaluation{
+    payoffs: GroupPayoffs,
+    /// Fraction of rounds in which learners contributed
+    learning_contribution: f32,
+    /// Fraction of rounds in which learners punished
+    learning_punishment: f32,
+}
+
+fn evaluate(model: &mut Model, episodes: usize) -> Evaluation{
+    let mut rounds = 0;
+    let mut contributions = 0;
+    let mut punishments = 0;
+    let payoffs = model.evaluate(episodes, |agent|{
+        rounds += agent.info_set().rounds().len();
+        contributions += agent.info_set().count_contributions();
+        punishments += agent.info_set().count_punishments();
+    });
+    let rounds = rounds.max(1) as f32;
+    Evaluation{
+        payoffs,
+        learning_contribution: contributions as f32 / rounds,
+        learning_punishment: punishments as f32 / rounds,
+    }
+}
+
+fn update_policies(model: &mut Model) -> Result<(), ExperimentError<D>>{
+    model.update_policies(|agent|{
+        let trajectories = agent.take_episodes();
+        agent.policy_mut().train_on_trajectories_env_reward(&trajectories[..])
+    })?;
+    Ok(())
+}
+
+fn main() -> Result<(), ExperimentError<D>>{
+    let args = PublicGoodsOptions::parse();
+    setup_logger(&args)?;
+    let device = Device::Cpu;
+    let params = PublicGoodsParams{
+        group_size: args.group_size,
+        endowment: args.endowment,
+        factor: args.factor,
+        punishment: args.punishment.then_some(Punishment{cost: args.punishment_cost, fine: args.punishment_fine}),
+    };
+    debug!("Game parameters: {params:?}");
+
+    let tensor_repr = PublicGoodsConversion::new(args.number_of_rounds);
+    let input_size = tensor_repr.desired_shape_flatten();
+
+    let mut env_adapter = EnvironmentMpscPort::new();
+    let mut learning_agents = Vec::with_capacity(args.number_of_learning);
+    let mut id: AgentNum = 0;
+    for _ in 0..args.number_of_learning{
+        let comm = env_adapter.register_agent(id)?;
+        let net = a2c_network(input_size, 2, device);
+        let opt = net.build_optimizer(Adam::default(), 1e-4)?;
+        let policy = ActorCriticPolicy::new(net, opt, tensor_repr, TrainConfig {gamma: 0.99});
+        let state = PublicGoodsInfoSet::new(id, params);
+        learning_agents.push(Arc::new(Mutex::new(TracingAgentGen::new(state, comm, policy))));
+        id += 1;
+    }
+    let mut scripted_groups = Vec::new();
+    for (name, number, probability, punish) in [
+        ("Cooperators", args.number_of_cooperators, 1.0, false),
+        ("Defectors", args.number_of_defectors, 0.0, false),
+        ("Punishers", args.number_of_punishers, 1.0, true),
+        ("Mixes", args.number_of_mixes, args.mix_probability, false),
+    ]{
+        let mut agents = Vec::with_capacity(number);
+        for _ in 0..number{
+            let comm = env_adapter.register_agent(id)?;
+            let state = PublicGoodsInfoSet::new(id, params);
+            agents.push(Arc::new(Mutex::new(AgentGen::new(state, comm, PublicGoodsStrategy::new(probability, punish)))));
+            id += 1;
+        }
+        scripted_groups.push(ScriptedGroup{name, agents});
+    }
+    let mut env_state = PublicGoodsState::new(id as usize, args.number_of_rounds, params)?;
+    if let Some(seed) = args.seed{
+        env_state = env_state.with_rng(StdRng::seed_from_u64(seed));
+    }
+    let mut model = Model::new(TracingBasicEnvironment::new(env_state, env_adapter), learning_agents, scripted_groups);
+
+    let mut payoffs = GroupPayoffHistory::new(std::iter::once("Learners").chain(model.scripted_groups.iter().map(|g| g.name)));
+    let mut learning_contributions = Vec::with_capacity(args.epochs + 1);
+    let mut learning_punishments = Vec::with_capacity(args.epochs + 1);
+    let mut record = |epoch: usize, evaluation: Evaluation|{
+        info!("Epoch {epoch}: learning agents' payoff: {:?}, scripted agents' payoffs: {:?}, learning agents' contributions: {:.02}, punishments: {:.02}",
+            evaluation.payoffs.learning, evaluation.payoffs.scripted, evaluation.learning_contribution, evaluation.learning_punishment);
+        payoffs.push(&evaluation.payoffs);
+        learning_contributions.push(evaluation.learning_contribution);
+        learning_punishments.push(evaluation.learning_punishment);
+    };
+
+    info!("Starting initial evaluation");
+    record(0, evaluate(&mut model, args.evaluation_episodes));
+    for e in 0..args.epochs{
+        info!("Running training epoch: {}", e);
+        for _ in 0..args.batch_size{
+            model.run_episode();
+        }
+        update_policies(&mut model)?;
+        record(e + 1, evaluate(&mut model, args.evaluation_episodes));
+    }
+
+    let stamp = chrono::Local::now().format("[%Y-%m-%d][%H:%M:%S]");
+    let base_path = "results/public_goods/";
+    create_dir_all(base_path)?;
+    let name = format!("{}x{}_r{}_{}_{}-{}-{}-{}-{}_{}", args.group_size, args.number_of_rounds, args.factor,
+        match args.punishment { true => "punishment", false => "plain" },
+        args.number_of_learning, args.number_of_cooperators, args.number_of_defectors,
+        args.number_of_punishers, args.number_of_mixes, stamp);
+
+    let plot = Plot::new()
+        .size(args.plot_width, args.plot_height)
+        .x_desc("Epoch");
+    let mut action_series = vec![PlotSeries::new(learning_contributions.clone(), "Contributions", colors::GREEN)];
+    if args.punishment{
+        action_series.push(PlotSeries::new(learning_punishments.clone(), "Punishments", colors::RED));
+    }
+    let path = format!("{base_path}/learning_actions-{name}.{}", args.plot_format.extension());
+    plot.clone().y_desc("Frequency").draw(Path::new(&path), &action_series[..])
+        .map_err(|e| ExperimentError::plot(Path::new(&path), e))?;
+
+    let payoff_series = payoffs.plot_series(&GROUP_COLORS);
+    let path = format!("{base_path}/payoffs-{name}.{}", args.plot_format.extension());
+    plot.y_desc("Payoff").draw(Path::new(&path), &payoff_series[..])
+        .map_err(|e| ExperimentError::plot(Path::new(&path), e))?;
+
+    let results = PublicGoodsResults{
+        params,
+        payoffs: payoffs.into_series(),
+        learning_contributions, learning_punishments,
+    };
+    write_json(format!("{base_path}/series-{name}.json"), &results, true)?;
+    Ok(())
+}
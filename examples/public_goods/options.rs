@@ -0,0 +1,90 @@
+use std::path::PathBuf;
+use log::LevelFilter;
+use clap::Parser;
+use amfiteatr_examples::plots::PlotFormat;
+
+#[derive(Parser)]
+#[command(author, version, about, long_about = None)]
+pub struct PublicGoodsOptions{
+
+    #[arg(short = 'v', long = "log_level", value_enum, default_value = "info")]
+    pub log_level: LevelFilter,
+
+    #[arg(short = 'a', long = "log_level_amfi", value_enum, default_value = "OFF")]
+    pub log_level_amfi: LevelFilter,
+
+    #[arg(short = 'o', long = "logfile")]
+    pub log_file: Option<PathBuf>,
+
+    #[arg(short = 'e', long = "epochs", default_value = "100")]
+    pub epochs: usize,
+
+    #[arg(short = 'b', long = "batch", default_value = "64")]
+    pub batch_size: usize,
+
+    /// Test episodes played before training and after every epoch
+    #[arg(long = "evaluation-episodes", default_value = "100")]
+    pub evaluation_episodes: usize,
+
+    #[arg(short = 'n', long = "rounds", default_value = "10")]
+    pub number_of_rounds: usize,
+
+    /// Size of groups formed every round, population must be divisible by it
+    #[arg(short = 'g', long = "group-size", default_value = "4")]
+    pub group_size: usize,
+
+    #[arg(long = "endowment", default_value = "1.0")]
+    pub endowment: f32,
+
+    /// Multiplication factor of common pool
+    #[arg(short = 'r', long = "factor", default_value = "1.6")]
+    pub factor: f32,
+
+    /// Enables punishment stage after contributions
+    #[arg(short = 'P', long = "punishment")]
+    pub punishment: bool,
+
+    /// Cost paid by punisher for every punished group member
+    #[arg(long = "punishment-cost", default_value = "0.2")]
+    pub punishment_cost: f32,
+
+    /// Fine paid by free rider for every punishing group member
+    #[arg(long = "punishment-fine", default_value = "0.6")]
+    pub punishment_fine: f32,
+
+    #[arg(short = 'l', long = "learners", default_value = "4")]
+    pub number_of_learning: usize,
+
+    /// Agents always contributing and never punishing
+    #[arg(short = 'c', long = "cooperators", default_value = "0")]
+    pub number_of_cooperators: usize,
+
+    /// Agents never contributing
+    #[arg(short = 'd', long = "defectors", default_value = "4")]
+    pub number_of_defectors: usize,
+
+    /// Agents always contributing and punishing free riders
+    #[arg(short = 'p', long = "punishers", default_value = "0")]
+    pub number_of_punishers: usize,
+
+    /// Agents contributing at random
+    #[arg(short = 'm', long = "mixes", default_value = "0")]
+    pub number_of_mixes: usize,
+
+    #[arg(short = 'M', long = "mix-contribution-probability", default_value = "0.5")]
+    pub mix_probability: f64,
+
+    /// Seed of environment (groups), so runs can be repeated. Policies of agents keep their own
+    /// randomness
+    #[arg(long = "seed")]
+    pub seed: Option<u64>,
+
+    #[arg(long = "plot-format", value_enum, default_value = "svg")]
+    pub plot_format: PlotFormat,
+
+    #[arg(long = "plot-width", default_value = "400")]
+    pub plot_width: u32,
+
+    #[arg(long = "plot-height", default_value = "300")]
+    pub plot_height: u32,
+}
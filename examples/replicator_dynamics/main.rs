@@ -1,45 +1,132 @@
 mod options;
 
-use std::fs::File;
+use std::io::Write;
 use std::path::Path;
 use std::sync::{Arc, Mutex};
-use std::thread;
+use std::time::Duration;
 use log::{
     debug,
+    error,
     info,
+    warn,
 };
-use amfiteatr_rl::tch::{Device, nn, Tensor};
+use amfiteatr_rl::tch::{Device, Kind, nn, Tensor};
 use clap::Parser;
-use plotters::style::colors;
+use plotters::style::{colors, RGBColor};
 use amfiteatr_rl::tch::nn::{Adam, VarStore};
 use amfiteatr_core::agent::*;
 use amfiteatr_core::comm::{
     AgentMpscAdapter,
     EnvironmentMpscPort
 };
-use amfiteatr_core::env::{AutoEnvironmentWithScores, ReseedEnvironment, TracingBasicEnvironment, TracingEnvironment};
+use amfiteatr_core::env::{AutoEnvironmentWithScores, ReseedEnvironment, StatefulEnvironment, TracingBasicEnvironment, TracingEnvironment};
 use amfiteatr_classic::policy::{ClassicMixedStrategy, ClassicPureStrategy};
 use amfiteatr_core::agent::RewardedAgent;
 use amfiteatr_core::agent::TracingAgent;
-use amfiteatr_core::domain::DomainParameters;
-use amfiteatr_core::error::AmfiError;
 use amfiteatr_classic::domain::{
     AgentNum,
     ClassicAction,
     ClassicGameDomainNumbered
 };
-use amfiteatr_classic::env::PairingState;
-use amfiteatr_classic::{AsymmetricRewardTableInt, SymmetricRewardTable};
+use amfiteatr_classic::SymmetricRewardTable;
 use amfiteatr_classic::agent::{
     LocalHistoryInfoSet,
     LocalHistoryConversionToTensor};
-use amfiteatr_examples::plots::{plot_many_series, PlotSeries};
+use amfiteatr_examples::batch::{BatchingAdapter, HeadIndexConversion, LearnerPolicy, SharedA2C, SharedA2CHandle, SharedA2CMember};
+use amfiteatr_examples::games::GamePreset;
+use amfiteatr_examples::guard::GuardedAdapter;
+use amfiteatr_examples::error::{create_dir_all, EpisodeReport, ExperimentError, write_json};
+use amfiteatr_examples::diagnostics::{A2CDiagnostics, DiagnosticsSeries, train_a2c};
+use amfiteatr_examples::http::{HttpDashboard, LiveData};
+use amfiteatr_examples::monitor::{EarlyStopping, TrainingMonitor};
+use amfiteatr_examples::pairing::{GameState, new_game_state_with_tables};
+use amfiteatr_examples::pairing::compact::TrajectoryWriter;
+use amfiteatr_examples::pairing::heterogeneous::RewardTables;
+use amfiteatr_examples::pairing::replay::GameReplay;
+use amfiteatr_examples::pairing::noise::Noise;
+use amfiteatr_examples::plots::{Dashboard, HeatmapData, Panel, Plot, plot_heatmap, PlotSeries};
+use amfiteatr_examples::policy::classic_fallback;
+use amfiteatr_examples::pool::AgentPool;
+use amfiteatr_examples::probe::ResponseProbe;
 use amfiteatr_examples::series::PayoffGroupSeries;
-use amfiteatr_rl::policy::{ActorCriticPolicy, LearningNetworkPolicy, TrainConfig};
-use amfiteatr_rl::tensor_data::ConversionToTensor;
+use amfiteatr_examples::sync::{lock_agents, run_reported_episode, SteppedAgent};
+use amfiteatr_rl::policy::{ActorCriticPolicy, TrainConfig};
+use amfiteatr_rl::tensor_data::{ConversionToTensor, FloatTensorReward};
 use amfiteatr_rl::torch_net::{A2CNet, NeuralNetTemplate, TensorA2C};
-use crate::options::ReplicatorOptions;
+use crate::options::{ReplicatorOptions, Sharing, StopMetric, TrajectoryFormat};
+
+
+/// Averages of groups noted after every evaluation.
+#[derive(Default)]
+struct EpochReports{
+    learning: Vec<f32>,
+    hawk: Vec<f32>,
+    dove: Vec<f32>,
+    mixed: Vec<f32>,
+    all: Vec<f32>,
+    coops: Vec<f32>,
+    defects: Vec<f32>,
+}
+
+impl EpochReports{
+    fn payoffs(&self) -> [(&str, &[f32], RGBColor); 5]{
+        [
+            ("Learning agents", &self.learning[..], colors::BLACK),
+            ("Hawk agents", &self.hawk[..], colors::RED),
+            ("Dove agents", &self.dove[..], colors::BLUE),
+            ("Mixed agents", &self.mixed[..], colors::GREEN),
+            ("All agents", &self.all[..], colors::full_palette::GREY_A700),
+        ]
+    }
+
+    /// Share of every group in payoff of whole population after every epoch, `groups` are sizes
+    /// of learning, hawk, dove and mixed groups.
+    fn payoff_shares(&self, groups: [usize; 4]) -> Vec<PlotSeries>{
+        let payoffs = self.payoffs();
+        let totals: Vec<f32> = (0..self.all.len())
+            .map(|e| payoffs.iter().zip(groups)
+                .map(|((_, p, _), n)| n as f32 * p.get(e).copied().unwrap_or(0.0))
+                .sum())
+            .collect();
+        payoffs.iter().zip(groups)
+            .filter(|((_, p, _), n)| *n > 0 && !p.is_empty())
+            .map(|((name, p, color), n)|{
+                let shares = p.iter().zip(&totals)
+                    .map(|(p, total)| if *total == 0.0 { 0.0 } else { n as f32 * p / total })
+                    .collect();
+                PlotSeries::new(shares, name, *color)
+            })
+            .collect()
+    }
+
+    fn actions(&self) -> [(&str, &[f32], RGBColor); 2]{
+        [
+            ("Defects", &self.defects[..], colors::RED),
+            ("Cooperations", &self.coops[..], colors::BLUE),
+        ]
+    }
+}
 
+/// Publishes current payoffs of groups and actions of learning agents to HTTP dashboard.
+fn publish_live(live: &LiveData, epoch: usize, plot: &Plot, reports: &EpochReports){
+    live.set_epoch(epoch);
+    let payoffs = reports.payoffs();
+    let payoff_series: Vec<PayoffGroupSeries> = payoffs.iter().filter(|(_, p, _)| !p.is_empty())
+        .map(|(id, p, _)| PayoffGroupSeries{id: id.to_string(), payoffs: p.to_vec()})
+        .collect();
+    if let Err(e) = live.publish_json("payoffs", &payoff_series){
+        warn!("Failed publishing payoffs: {e}");
+    }
+    for (name, desc, series) in [("payoffs", "Payoff", &payoffs[..]), ("actions", "Actions taken", &reports.actions()[..])]{
+        let plot_series: Vec<PlotSeries> = series.iter().filter(|(_, p, _)| !p.is_empty())
+            .map(|(id, p, color)| PlotSeries::new(p.to_vec(), id, *color))
+            .collect();
+        match plot.clone().y_desc(desc).to_svg(&plot_series[..]){
+            Ok(svg) => live.publish_svg(name, svg),
+            Err(e) => warn!("Failed plotting live {name}: {e}"),
+        }
+    }
+}
 
 pub fn avg(entries: &[f32]) -> Option<f32>{
     if entries.is_empty(){
@@ -77,11 +164,15 @@ pub fn setup_logger(options: &ReplicatorOptions) -> Result<(), fern::InitError>
     Ok(())
 }
 type D = ClassicGameDomainNumbered;
-type S = PairingState<<D as DomainParameters>::AgentId>;
-type Pol = ActorCriticPolicy<D, LocalHistoryInfoSet<<D as DomainParameters>::AgentId>, LocalHistoryConversionToTensor>;
+type S = GameState<AgentNum>;
+type Conversion = HeadIndexConversion<LocalHistoryConversionToTensor>;
+type Pol = LearnerPolicy<D, LocalHistoryInfoSet<AgentNum>, Conversion>;
+type SharedNetwork = SharedA2CHandle<D, LocalHistoryInfoSet<AgentNum>, Conversion>;
 type MixedPolicy = ClassicMixedStrategy<AgentNum, LocalHistoryInfoSet<AgentNum>>;
 type PurePolicy = ClassicPureStrategy<AgentNum, LocalHistoryInfoSet<AgentNum>>;
 type AgentComm = AgentMpscAdapter<D>;
+type EnvAdapter = BatchingAdapter<D, GuardedAdapter<D, EnvironmentMpscPort<D>, LocalHistoryInfoSet<AgentNum>>,
+    LocalHistoryInfoSet<AgentNum>, Conversion>;
 
 pub enum Group{
     Mixes,
@@ -91,12 +182,14 @@ pub enum Group{
 }
 
 struct Model{
-    pub environment:  TracingBasicEnvironment<D, S, EnvironmentMpscPort<D>>,
+    pub environment:  TracingBasicEnvironment<D, S, EnvAdapter>,
     //agents: Arc<Mutex<dyn MultiEpisodeAgent<D, (), InfoSetType=()>>>,
     pub mixed_agents: Vec<Arc<Mutex<AgentGen<D, MixedPolicy, AgentComm>>>>,
     pub hawk_agents: Vec<Arc<Mutex<AgentGen<D, PurePolicy, AgentComm>>>>,
     pub dove_agents: Vec<Arc<Mutex<AgentGen<D, PurePolicy, AgentComm>>>>,
     pub learning_agents: Vec<Arc<Mutex<TracingAgentGen<D, Pol, AgentComm>>>>,
+    /// Network of learning agents if they share one
+    pub shared_network: Option<SharedNetwork>,
 
     //averages in groups in epochs - one entry in vec is average of players in that group for that episode
     pub averages_mixed: Vec<f32>,
@@ -107,6 +200,8 @@ struct Model{
 
     pub average_learning_defects: Vec<f32>,
     pub average_learning_coops: Vec<f32>,
+    /// Number of rounds played in episodes, game length varies with continuation probability
+    pub episode_rounds: Vec<f32>,
 
     learning_defects: Vec<f32>,
     learning_coops: Vec<f32>,
@@ -116,21 +211,30 @@ struct Model{
     scores_dove: Vec<f32>,
     scores_learning: Vec<f32>,
     scores_all: Vec<f32>,
+
+    episode: usize,
+    failed_episodes: usize,
+    /// Failed episodes tolerated before experiment stops
+    max_failed_episodes: usize,
+    /// Agents are run in thread of environment instead of pool
+    synchronous: bool,
+    pool: AgentPool<D>,
 }
 
 impl Model{
 
     #[allow(dead_code)]
-    pub fn new(environment: TracingBasicEnvironment<D, S, EnvironmentMpscPort<D>>) -> Self{
+    pub fn new(environment: TracingBasicEnvironment<D, S, EnvAdapter>) -> Self{
         Self{
             environment, mixed_agents: Vec::new(), hawk_agents: Vec::new(), dove_agents: Vec::new(),
-            learning_agents: Vec::new(), averages_mixed: Vec::new(),
+            learning_agents: Vec::new(), shared_network: None, averages_mixed: Vec::new(),
             averages_hawk: Vec::new(),
             averages_dove: Vec::new(),
             averages_learning: Vec::new(),
             averages_all: Vec::new(),
             average_learning_defects: vec![],
             average_learning_coops: vec![],
+            episode_rounds: vec![],
             learning_defects: vec![],
             learning_coops: vec![],
             scores_mixed: vec![],
@@ -138,17 +242,31 @@ impl Model{
             scores_dove: vec![],
             scores_learning: vec![],
             scores_all: vec![],
+            episode: 0,
+            failed_episodes: 0,
+            max_failed_episodes: usize::MAX,
+            synchronous: false,
+            pool: AgentPool::new(),
         }
     }
 
-    pub fn new_with_agents(environment: TracingBasicEnvironment<D, S, EnvironmentMpscPort<D>>,
+    pub fn new_with_agents(environment: TracingBasicEnvironment<D, S, EnvAdapter>,
                            learning_agents: Vec<Arc<Mutex<TracingAgentGen<D, Pol, AgentComm>>>>,
                            mixed_agents: Vec<Arc<Mutex<AgentGen<D, MixedPolicy, AgentComm>>>>,
                            hawk_agents: Vec<Arc<Mutex<AgentGen<D, PurePolicy, AgentComm>>>>,
                            dove_agents: Vec<Arc<Mutex<AgentGen<D, PurePolicy, AgentComm>>>>,
+                           shared_network: Option<SharedNetwork>,
+                           synchronous: bool,
         ) -> Self{
+        let mut pool = AgentPool::new();
+        if !synchronous{
+            dove_agents.iter().for_each(|a| pool.add(a.clone()));
+            hawk_agents.iter().for_each(|a| pool.add(a.clone()));
+            mixed_agents.iter().for_each(|a| pool.add(a.clone()));
+            learning_agents.iter().for_each(|a| pool.add(a.clone()));
+        }
         Self{
-            environment, learning_agents,
+            environment, learning_agents, shared_network,
             mixed_agents, hawk_agents, dove_agents,
             averages_mixed: vec![],
             averages_hawk: vec![],
@@ -157,6 +275,7 @@ impl Model{
             averages_all: vec![],
             average_learning_defects: vec![],
             average_learning_coops: vec![],
+            episode_rounds: vec![],
             learning_defects: vec![],
             learning_coops: vec![],
             scores_mixed: vec![],
@@ -166,6 +285,11 @@ impl Model{
 
 
             scores_all: vec![],
+            episode: 0,
+            failed_episodes: 0,
+            max_failed_episodes: usize::MAX,
+            synchronous,
+            pool,
         }
 
     }
@@ -177,6 +301,7 @@ impl Model{
         self.averages_mixed.clear();
         self.average_learning_coops.clear();
         self.average_learning_defects.clear();
+        self.episode_rounds.clear();
     }
 
     #[allow(dead_code)]
@@ -189,8 +314,18 @@ impl Model{
 
     }
 
+    /// Appends game of last episode to binary trajectory, labeled with epoch.
+    pub fn record_episode<W: Write>(&self, writer: &mut TrajectoryWriter<W>, epoch: usize) -> Result<(), ExperimentError<D>>{
+        let steps = self.environment.trajectory().list();
+        let invalid_steps = steps.iter().filter(|s| !s.is_action_valid()).count();
+        let game = GameReplay::from_game_state(self.environment.state(), steps.len(), invalid_steps)?;
+        writer.write_game(epoch as u64, &game)?;
+        Ok(())
+    }
+
     pub fn remember_average_group_scores(&mut self){
         self.clear_episode_scores();
+        self.episode_rounds.push(self.environment.state().inner().inner().rounds_played() as f32);
 
         for agent in &self.learning_agents{
             let guard = agent.lock().unwrap();
@@ -268,80 +403,92 @@ impl Model{
         self.learning_defects.clear();
     }
 
-    pub fn run_episode(&mut self) -> Result<(), AmfiError<D>>{
-
-        thread::scope(|s|{
-            s.spawn(||{
-                self.environment.reseed(());
-                self.environment.run_with_scores().unwrap();
-            });
-            for a in  &self.dove_agents{
-                let agent = a.clone();
-                s.spawn(move ||{
-                    let mut guard = agent.lock().unwrap();
-                    guard.run_episode_rewarded(()).unwrap();
-
-                });
-            };
-
-            for a in  &self.hawk_agents{
-                let agent = a.clone();
-                s.spawn(move ||{
-                    let mut guard = agent.lock().unwrap();
-                    guard.run_episode_rewarded(()).unwrap();
-
-                });
-            };
-
-            for a in  &self.mixed_agents{
-                let agent = a.clone();
-                s.spawn(move ||{
-                    let mut guard = agent.lock().unwrap();
-                    guard.run_episode_rewarded(()).unwrap();
-
-                });
-            };
-
-            for a in  &self.learning_agents{
-                let agent = a.clone();
-                s.spawn(move ||{
-                    let mut guard = agent.lock().unwrap();
-                    guard.run_episode_rewarded(()).unwrap();
-
-                });
-            };
-
-
-
-        });
+    /// Runs one episode and returns whether it was completed. Failing or panicking threads do not
+    /// stop experiment, failed episode is logged with failures of environment and agents, until
+    /// more than `max_failed_episodes` failed. Then error of last failed episode is returned.
+    pub fn run_episode(&mut self) -> Result<bool, ExperimentError<D>>{
+        let report = match self.synchronous{
+            true => self.run_episode_synchronous(),
+            false => {
+                self.pool.run_episode(&mut self.environment, |environment|{
+                    environment.reseed(());
+                    environment.run_with_scores()
+                })
+            }
+        };
+
+        let episode = self.episode;
+        self.episode += 1;
+        match report.into_result(episode){
+            Ok(()) => Ok(true),
+            Err(e) => {
+                self.failed_episodes += 1;
+                if self.failed_episodes > self.max_failed_episodes{
+                    error!("Stopping after {} failed episodes", self.failed_episodes);
+                    return Err(e);
+                }
+                warn!("{e}");
+                Ok(false)
+            }
+        }
+    }
 
-        Ok(())
+    fn run_episode_synchronous(&mut self) -> EpisodeReport<D>{
+        let mut doves = lock_agents(&self.dove_agents);
+        let mut hawks = lock_agents(&self.hawk_agents);
+        let mut mixes = lock_agents(&self.mixed_agents);
+        let mut learning = lock_agents(&self.learning_agents);
+        let mut agents: Vec<&mut dyn SteppedAgent<D>> = Vec::with_capacity(doves.len() + hawks.len() + mixes.len() + learning.len());
+        agents.extend(doves.iter_mut().map(|a| &mut **a as &mut dyn SteppedAgent<D>));
+        agents.extend(hawks.iter_mut().map(|a| &mut **a as &mut dyn SteppedAgent<D>));
+        agents.extend(mixes.iter_mut().map(|a| &mut **a as &mut dyn SteppedAgent<D>));
+        agents.extend(learning.iter_mut().map(|a| &mut **a as &mut dyn SteppedAgent<D>));
+        run_reported_episode(&mut self.environment, &mut agents[..])
     }
 
-    pub fn update_policies(&mut self) -> Result<(), AmfiError<D>>{
+    /// Trains learning agents, returns their averaged training diagnostics if they were requested.
+    pub fn update_policies(&mut self, tensor_repr: &Conversion, diagnostics: bool) -> Result<Option<A2CDiagnostics>, ExperimentError<D>>{
+        if let Some(shared) = &self.shared_network{
+            // shared network is trained once on trajectories of all learners
+            let mut trajectories = Vec::new();
+            for a in &self.learning_agents{
+                trajectories.extend(a.lock().unwrap().take_episodes());
+            }
+            let mut shared = shared.lock().unwrap();
+            return Ok(train_a2c(shared.policy_mut(), tensor_repr, &trajectories[..],
+                |step| step.step_universal_reward().to_tensor(), diagnostics)?);
+        }
+        let mut learners_diagnostics = Vec::with_capacity(self.learning_agents.len());
         for a in &self.learning_agents{
             let mut agent = a.lock().unwrap();
             let trajectories = agent.take_episodes();
-            agent.policy_mut().train_on_trajectories_env_reward(&trajectories[..])?;
+            if let LearnerPolicy::Own(policy) = agent.policy_mut(){
+                learners_diagnostics.extend(train_a2c(policy, tensor_repr, &trajectories[..],
+                    |step| step.step_universal_reward().to_tensor(), diagnostics)?);
+            }
         }
-        Ok(())
+        Ok(A2CDiagnostics::mean(&learners_diagnostics[..]))
     }
 }
 
 
-fn main() -> Result<(), AmfiError<D>>{
+fn main() -> Result<(), ExperimentError<D>>{
     debug!("Starting");
 
     let args = ReplicatorOptions::parse();
-    setup_logger(&args).unwrap();
+    setup_logger(&args)?;
     let device = Device::Cpu;
     //let device = Device::Cpu;
 
-    let reward_table: AsymmetricRewardTableInt =
-        SymmetricRewardTable::new(2, 1, 4, 0).into();
+    let reward_table = GamePreset::table_or(args.game, SymmetricRewardTable::new(2, 1, 4, 0));
     //let env_state_template = PairingState::new_even(number_of_players, args.number_of_rounds, reward_table).unwrap();
-    let tensor_repr = LocalHistoryConversionToTensor::new(args.number_of_rounds);
-    let input_size = tensor_repr.desired_shape().iter().product();
+    // with per-agent heads agent's id is appended to its information set tensor
+    let heads = match args.sharing{
+        Sharing::Trunk => args.number_of_learning,
+        Sharing::Independent | Sharing::Shared => 0,
+    };
+    let tensor_repr = HeadIndexConversion::new(LocalHistoryConversionToTensor::new(args.number_of_rounds), heads);
+    let input_size = tensor_repr.inner().desired_shape_flatten();
     //let mut comms = HashMap::<u32, SyncCommEnv<ClassicGameDomainNumbered>>::with_capacity(number_of_players);
 
     let net_template = NeuralNetTemplate::new(|path|{
@@ -359,6 +506,32 @@ fn main() -> Result<(), AmfiError<D>>{
         }}
     });
 
+    // trunk is the same as network above, actor and critic outputs of agent are selected from
+    // heads of all agents with its one-hot index
+    let trunk_network = |var_store: VarStore|{
+        A2CNet::new(var_store, |path|{
+            let heads = heads as i64;
+            let seq = nn::seq()
+                .add(nn::linear(path / "input", input_size, 512, Default::default()))
+                .add(nn::linear(path / "hidden1", 512, 512, Default::default()))
+                .add_fn(|xs|xs.relu());
+            let actor = nn::linear(path / "al", 512, 2 * heads, Default::default());
+            let critic =  nn::linear(path / "ac", 512, heads, Default::default());
+            {move |input: &Tensor|{
+                let input = input.to_device(device);
+                let head = input.narrow(-1, input_size, heads);
+                let xs = input.narrow(-1, 0, input_size).apply(&seq);
+                let mut actor_shape = xs.size();
+                actor_shape.pop();
+                actor_shape.extend([heads, 2]);
+                let actor = (xs.apply(&actor).reshape(&actor_shape[..]) * head.unsqueeze(-1))
+                    .sum_dim_intlist(-2, false, Kind::Float);
+                let critic = (xs.apply(&critic) * head).sum_dim_intlist(-1, true, Kind::Float);
+                TensorA2C{critic, actor}
+            }}
+        })
+    };
+
     let mut env_adapter = EnvironmentMpscPort::new();
 
     let mut learning_agents: Vec<Arc<Mutex<TracingAgentGen<D, Pol, AgentComm>>>> = Vec::new();
@@ -366,26 +539,48 @@ fn main() -> Result<(), AmfiError<D>>{
     let mut hawk_agents: Vec<Arc<Mutex<AgentGen<D, PurePolicy, AgentComm>>>> = Vec::new();
     let mut dove_agents: Vec<Arc<Mutex<AgentGen<D, PurePolicy, AgentComm>>>> = Vec::new();
 
-    let mut report_average_hawk_reward = Vec::with_capacity(args.epochs + 1);
-    let mut report_average_dove_reward = Vec::with_capacity(args.epochs + 1);
-    let mut report_average_mixed_reward = Vec::with_capacity(args.epochs + 1);
-    let mut report_average_all_reward = Vec::with_capacity(args.epochs + 1);
-    let mut report_average_learning_reward = Vec::with_capacity(args.epochs + 1);
-    let mut report_average_coops = Vec::with_capacity(args.epochs + 1);
-    let mut report_average_defects = Vec::with_capacity(args.epochs + 1);
+    let mut reports = EpochReports::default();
+    let mut training_diagnostics = DiagnosticsSeries::default();
 
     let offset_learning = 0 as AgentNum;
     let offset_mixed = args.number_of_learning as AgentNum;
     let offset_hawk = args.number_of_mixes as AgentNum + offset_mixed;
     let offset_dove = args.number_of_hawks as AgentNum + offset_hawk;
     let total_number_of_players = offset_dove as usize + args.number_of_doves;
+    let tables = match &args.reward_tables{
+        Some(path) => RewardTables::from_file(path, total_number_of_players, reward_table)?,
+        None => RewardTables::uniform(total_number_of_players, reward_table),
+    };
+
+    let shared_net = match args.sharing{
+        _ if args.number_of_learning == 0 => None,
+        Sharing::Independent => None,
+        Sharing::Shared => Some(A2CNet::new(VarStore::new(device), net_template.get_net_closure())),
+        Sharing::Trunk => Some(trunk_network(VarStore::new(device))),
+    };
+    let shared_network = match shared_net{
+        Some(net) => {
+            let opt = net.build_optimizer(Adam::default(), 1e-4)?;
+            let policy = ActorCriticPolicy::new(net, opt, tensor_repr, TrainConfig {gamma: 0.99});
+            Some(SharedA2C::new(policy, tensor_repr).into_handle())
+        },
+        None => None,
+    };
 
     for i in offset_learning..offset_mixed{
         let comm = env_adapter.register_agent(i)?;
-        let state = LocalHistoryInfoSet::new(i, reward_table);
-        let net = A2CNet::new(VarStore::new(device), net_template.get_net_closure());
-        let opt = net.build_optimizer(Adam::default(), 1e-4).unwrap();
-        let policy = ActorCriticPolicy::new(net, opt, tensor_repr, TrainConfig {gamma: 0.99});
+        let state = LocalHistoryInfoSet::new(i, tables.table_of(i as usize));
+        let policy = match &shared_network{
+            Some(shared) => {
+                shared.lock().unwrap().add_member(i);
+                LearnerPolicy::Shared(SharedA2CMember::new(shared.clone()))
+            },
+            None => {
+                let net = A2CNet::new(VarStore::new(device), net_template.get_net_closure());
+                let opt = net.build_optimizer(Adam::default(), 1e-4)?;
+                LearnerPolicy::Own(ActorCriticPolicy::new(net, opt, tensor_repr, TrainConfig {gamma: 0.99}))
+            }
+        };
         let agent = TracingAgentGen::new(state, comm, policy);
         learning_agents.push(Arc::new(Mutex::new(agent)));
 
@@ -394,7 +589,7 @@ fn main() -> Result<(), AmfiError<D>>{
 
     for i in offset_mixed..offset_hawk{
         let comm = env_adapter.register_agent(i)?;
-        let state = LocalHistoryInfoSet::new(i, reward_table);
+        let state = LocalHistoryInfoSet::new(i, tables.table_of(i as usize));
 
         let policy = MixedPolicy::new(args.mix_probability_of_hawk);
         let agent = AgentGen::new(state, comm, policy);
@@ -403,7 +598,7 @@ fn main() -> Result<(), AmfiError<D>>{
 
     for i in offset_hawk..offset_dove{
         let comm = env_adapter.register_agent(i)?;
-        let state = LocalHistoryInfoSet::new(i, reward_table);
+        let state = LocalHistoryInfoSet::new(i, tables.table_of(i as usize));
 
         let policy = PurePolicy::new(ClassicAction::Up);
         let agent = AgentGen::new(state, comm, policy);
@@ -412,183 +607,281 @@ fn main() -> Result<(), AmfiError<D>>{
     }
     for i in offset_dove..total_number_of_players as AgentNum{
         let comm = env_adapter.register_agent(i)?;
-        let state = LocalHistoryInfoSet::new(i, reward_table);
+        let state = LocalHistoryInfoSet::new(i, tables.table_of(i as usize));
 
         let policy = PurePolicy::new(ClassicAction::Down);
         let agent = AgentGen::new(state, comm, policy);
         dove_agents.push(Arc::new(Mutex::new(agent)));
 
     }
-    let env_state = PairingState::new_even(total_number_of_players,
-                                           args.number_of_rounds, reward_table)?;
-    let environment = TracingBasicEnvironment::new(env_state, env_adapter);
+    let env_state = new_game_state_with_tables(args.number_of_rounds, tables.clone(),
+        Noise::new(args.action_noise, args.observation_noise), args.continuation, args.seed)?;
+    let timeout = (args.agent_timeout_ms > 0).then(|| Duration::from_millis(args.agent_timeout_ms));
+    let fallback = classic_fallback(args.fallback, args.fallback_strategy, reward_table);
+    let adapter = BatchingAdapter::new(GuardedAdapter::new(env_adapter, timeout, fallback), shared_network.clone(),
+        Box::new({
+            let tables = tables.clone();
+            move |id| LocalHistoryInfoSet::new(*id, tables.table_of(*id as usize))
+        }));
+    let environment = TracingBasicEnvironment::new(env_state, adapter);
 
 
     let mut model = Model::new_with_agents(environment, learning_agents, mixed_agents,
-                                           hawk_agents, dove_agents);
+                                           hawk_agents, dove_agents, shared_network, args.synchronous);
+    model.max_failed_episodes = args.max_failed_episodes;
+
+    let stamp = chrono::Local::now().format("[%Y-%m-%d][%H:%M:%S]");
+    let base_path = "results/replicator_dynamics/";
+    create_dir_all(base_path)?;
+    let mut recorder = match args.trajectory_format{
+        TrajectoryFormat::Json => None,
+        TrajectoryFormat::Binary => Some(TrajectoryWriter::create(Path::new(&format!("{}/game-trajectories-{:?}_{}-{}-{}-{}_{}.bin",
+                base_path,
+                args.number_of_rounds,
+                args.number_of_learning,
+                args.number_of_hawks,
+                args.number_of_doves,
+                args.number_of_mixes,
+                stamp)), &tables)?),
+    };
+
+    let heatmap_probe = ResponseProbe::new(args.heatmap_memory, args.number_of_rounds, args.heatmap_samples);
+    // heatmap is probed on first learning agent as representative of group
+    let write_heatmap = |model: &Model, epoch: usize| -> Result<Option<(usize, HeatmapData)>, ExperimentError<D>>{
+        if args.heatmap_every == 0 || !epoch.is_multiple_of(args.heatmap_every){
+            return Ok(None);
+        }
+        let agent = match model.learning_agents.first(){
+            Some(agent) => agent,
+            None => return Ok(None),
+        };
+        let guard = agent.lock().unwrap();
+        match heatmap_probe.heatmap(guard.policy(), *guard.id(), tables.table_of(*guard.id() as usize)){
+            Ok(heatmap) => {
+                let path = format!("{}/heatmap-replicator-{:?}_{}-{}-{}-{}-e{:04}_{}.{}",
+                        base_path,
+                        args.number_of_rounds,
+                        args.number_of_learning,
+                        args.number_of_hawks,
+                        args.number_of_doves,
+                        args.number_of_mixes,
+                        epoch,
+                        stamp,
+                        args.plot_format.extension()
+                );
+                plot_heatmap(Path::new(&path), &format!("Cooperation probability (epoch {epoch})"), &heatmap,
+                "Round",
+                "Opponent's last actions"
+                ).map_err(|e| ExperimentError::plot(Path::new(&path), e))?;
+                Ok(Some((epoch, heatmap)))
+            },
+            Err(e) => {
+                warn!("Failed probing cooperation heatmap: {e}");
+                Ok(None)
+            }
+        }
+    };
+
+    // with continuation probability length of game varies, so average length is reported
+    let describe_rounds = |model: &Model| match args.continuation{
+        None => args.number_of_rounds.to_string(),
+        Some(_) => format!("{:.02} (average)", avg(&model.episode_rounds).unwrap_or(0.0)),
+    };
 
     // inital test
 
     info!("Starting initial evaluation");
+    let mut last_heatmap = write_heatmap(&model, 0)?;
     for _i in 0..100{
-        model.run_episode()?;
-        model.remember_average_group_scores();
+        if model.run_episode()?{
+            model.remember_average_group_scores();
+            if let Some(writer) = &mut recorder{
+                model.record_episode(writer, 0)?;
+            }
+        }
     }
 
 
+    let rounds_desc = describe_rounds(&model);
     if let Some(average) = avg(&model.averages_learning){
-            info!("Average learning agent score in {} rounds: {:.02}", args.number_of_rounds, average );
-            report_average_learning_reward.push(average);
+            info!("Average learning agent score in {} rounds: {:.02}", rounds_desc, average );
+            reports.learning.push(average);
         }
         if let Some(average) = avg(&model.averages_dove){
-            info!("Average dove agent score in {} rounds: {:.02}", args.number_of_rounds, average );
-            report_average_dove_reward.push(average);
+            info!("Average dove agent score in {} rounds: {:.02}", rounds_desc, average );
+            reports.dove.push(average);
         }
         if let Some(average) = avg(&model.averages_hawk){
-            info!("Average hawk agent score in {} rounds: {:.02}", args.number_of_rounds, average );
-            report_average_hawk_reward.push(average);
+            info!("Average hawk agent score in {} rounds: {:.02}", rounds_desc, average );
+            reports.hawk.push(average);
         }
         if let Some(average) = avg(&model.averages_mixed){
-            info!("Average mixed({}) agent score in {} rounds: {:.02}", args.mix_probability_of_hawk , args.number_of_rounds, average );
-            report_average_mixed_reward.push(average);
+            info!("Average mixed({}) agent score in {} rounds: {:.02}", args.mix_probability_of_hawk , rounds_desc, average );
+            reports.mixed.push(average);
         }
         if let Some(average) = avg(&model.averages_all){
-            info!("Average any agent score in {} rounds: {:.02}", args.number_of_rounds, average );
-            report_average_all_reward.push(average);
+            info!("Average any agent score in {} rounds: {:.02}", rounds_desc, average );
+            reports.all.push(average);
         }
         if let Some(average) = avg(&model.average_learning_defects){
-            info!("Average learning agent defected {}  in rounds: {:.02}", average, args.number_of_rounds,);
-            report_average_defects.push(average);
+            info!("Average learning agent defected {}  in rounds: {:.02}", average, rounds_desc);
+            reports.defects.push(average);
         }
         if let Some(average) = avg(&model.average_learning_coops){
-            info!("Average learning agent cooperated {}  in rounds: {:.02}", average, args.number_of_rounds,);
-            report_average_coops.push(average);
+            info!("Average learning agent cooperated {}  in rounds: {:.02}", average, rounds_desc);
+            reports.coops.push(average);
         }
 
+    let http = args.http_port.map(HttpDashboard::start).transpose().map_err(ExperimentError::Http)?;
+    let live_plot = Plot::new()
+        .size(args.plot_width, args.plot_height)
+        .x_desc("Epoch");
+    if let Some(http) = &http{
+        publish_live(http.data(), 0, &live_plot, &reports);
+    }
+
+    let mut monitor = TrainingMonitor::new(args.epochs, args.progress_window, args.progress);
+    let mut stopping: Vec<(StopMetric, EarlyStopping)> = args.stop_on.iter().map(|m|{
+        (*m, EarlyStopping::new(&format!("{m:?}"), args.stop_patience, args.stop_epsilon)
+            .min_epochs(args.stop_min_epochs))
+    }).collect();
     for e in 0..args.epochs{
         info!("Running training epoch: {}", e);
         for _ in 0..args.batch_size{
             model.run_episode()?;
         }
-        model.update_policies()?;
+        if let Some(diagnostics) = model.update_policies(&tensor_repr, args.diagnostics)?{
+            info!("Training diagnostics after epoch {}: {}", e, diagnostics);
+            training_diagnostics.push(&diagnostics);
+        }
+        last_heatmap = write_heatmap(&model, e+1)?.or(last_heatmap);
 
         info!("Testing after epoch: {}", e);
         model.clear_averages();
         for _i in 0..100{
-            model.run_episode()?;
-            model.remember_average_group_scores();
+            if model.run_episode()?{
+                model.remember_average_group_scores();
+                if let Some(writer) = &mut recorder{
+                    model.record_episode(writer, e + 1)?;
+                }
+            }
 
         }
+        let rounds_desc = describe_rounds(&model);
         if let Some(average) = avg(&model.averages_learning){
-            info!("Average learning agent score in {} rounds: {:.02}", args.number_of_rounds, average );
-            report_average_learning_reward.push(average);
+            info!("Average learning agent score in {} rounds: {:.02}", rounds_desc, average );
+            reports.learning.push(average);
         }
         if let Some(average) = avg(&model.averages_dove){
-            info!("Average dove agent score in {} rounds: {:.02}", args.number_of_rounds, average );
-            report_average_dove_reward.push(average);
+            info!("Average dove agent score in {} rounds: {:.02}", rounds_desc, average );
+            reports.dove.push(average);
         }
         if let Some(average) = avg(&model.averages_hawk){
-            info!("Average hawk agent score in {} rounds: {:.02}", args.number_of_rounds, average );
-            report_average_hawk_reward.push(average);
+            info!("Average hawk agent score in {} rounds: {:.02}", rounds_desc, average );
+            reports.hawk.push(average);
         }
         if let Some(average) = avg(&model.averages_mixed){
-            info!("Average mixed({}) agent score in {} rounds: {:.02}", args.mix_probability_of_hawk , args.number_of_rounds, average );
-            report_average_mixed_reward.push(average);
+            info!("Average mixed({}) agent score in {} rounds: {:.02}", args.mix_probability_of_hawk , rounds_desc, average );
+            reports.mixed.push(average);
         }
         if let Some(average) = avg(&model.averages_all){
-            info!("Average any agent score in {} rounds: {:.02}", args.number_of_rounds, average );
-            report_average_all_reward.push(average);
+            info!("Average any agent score in {} rounds: {:.02}", rounds_desc, average );
+            reports.all.push(average);
         }
         if let Some(average) = avg(&model.average_learning_defects){
-            info!("Average learning agent defected {}  in rounds: {:.02}", average, args.number_of_rounds,);
-            report_average_defects.push(average);
+            info!("Average learning agent defected {}  in rounds: {:.02}", average, rounds_desc);
+            reports.defects.push(average);
         }
         if let Some(average) = avg(&model.average_learning_coops){
-            info!("Average learning agent cooperated {}  in rounds: {:.02}", average, args.number_of_rounds,);
-            report_average_coops.push(average);
+            info!("Average learning agent cooperated {}  in rounds: {:.02}", average, rounds_desc);
+            reports.coops.push(average);
         }
 
+        let metric = |m: StopMetric| match m{
+            StopMetric::LearningPayoff => reports.learning.last().copied(),
+            StopMetric::AllPayoff => reports.all.last().copied(),
+            StopMetric::Cooperation => reports.coops.last().zip(avg(&model.episode_rounds)).map(|(c, rounds)| c / rounds),
+        };
+        for m in [StopMetric::LearningPayoff, StopMetric::AllPayoff, StopMetric::Cooperation]{
+            if let Some(value) = metric(m){
+                monitor.record(&format!("{m:?}"), value);
+            }
+        }
+        monitor.finish_epoch();
+        if let Some(http) = &http{
+            publish_live(http.data(), e+1, &live_plot, &reports);
+        }
+        let mut converged = !stopping.is_empty();
+        for (m, detector) in stopping.iter_mut(){
+            // metrics missing in this population (no learning agents) never converge
+            converged &= metric(*m).is_some_and(|value| detector.update(value));
+        }
+        if converged{
+            info!("Metrics {:?} reached plateau after epoch {}, stopping training", args.stop_on, e);
+            monitor.finish(&format!("converged after {} epochs", e + 1));
+            break;
+        }
+        if e + 1 == args.epochs{
+            monitor.finish(&format!("{} epochs", args.epochs));
+        }
+    }
+    if let Some(shared) = &model.shared_network{
+        info!("Shared network made {} batched forward passes", shared.lock().unwrap().batches());
     }
 
     let mut payoff_series = vec![];
 
 
-    if !report_average_learning_reward.is_empty(){
+    if !reports.learning.is_empty(){
         payoff_series.push(PayoffGroupSeries{
             id: "Learning".to_string(),
-            payoffs: report_average_learning_reward.clone(),
+            payoffs: reports.learning.clone(),
         });
     }
-    if !report_average_hawk_reward.is_empty(){
+    if !reports.hawk.is_empty(){
         payoff_series.push(PayoffGroupSeries{
             id: "Hawk".to_string(),
-            payoffs: report_average_hawk_reward.clone(),
+            payoffs: reports.hawk.clone(),
         });
     }
-    if !report_average_dove_reward.is_empty(){
+    if !reports.dove.is_empty(){
         payoff_series.push(PayoffGroupSeries{
             id: "Dove".to_string(),
-            payoffs: report_average_dove_reward.clone(),
+            payoffs: reports.dove.clone(),
         });
     }
-    if !report_average_mixed_reward.is_empty(){
+    if !reports.mixed.is_empty(){
         payoff_series.push(PayoffGroupSeries{
             id: "Mixed".to_string(),
-            payoffs: report_average_mixed_reward.clone(),
+            payoffs: reports.mixed.clone(),
         });
     }
-    if !report_average_all_reward.is_empty(){
+    if !reports.all.is_empty(){
         payoff_series.push(PayoffGroupSeries{
             id: "All".to_string(),
-            payoffs: report_average_all_reward.clone(),
+            payoffs: reports.all.clone(),
         });
     }
 
 
 
-    
-    let payoff_plot_data_learning = PlotSeries {
-        data: report_average_learning_reward,
-        description: "Learning agents".to_string(),
-        color: colors::BLACK,
-    };
+    let payoff_share_series = reports.payoff_shares([args.number_of_learning, args.number_of_hawks,
+        args.number_of_doves, args.number_of_mixes]);
 
-    let payoff_plot_data_all = PlotSeries {
-        data: report_average_all_reward,
-        description: "All agents".to_string(),
-        color: colors::full_palette::GREY_A700,
-    };
+    let payoff_plot_data_learning = PlotSeries::new(reports.learning, "Learning agents", colors::BLACK);
 
-    let payoff_plot_data_hawk = PlotSeries {
-        data: report_average_hawk_reward,
-        description: "Hawk agents".to_string(),
-        color: colors::RED,
-    };
+    let payoff_plot_data_all = PlotSeries::new(reports.all, "All agents", colors::full_palette::GREY_A700);
 
-    let payoff_plot_data_dove = PlotSeries {
-        data: report_average_dove_reward,
-        description: "Dove agents".to_string(),
-        color: colors::BLUE,
-    };
+    let payoff_plot_data_hawk = PlotSeries::new(reports.hawk, "Hawk agents", colors::RED);
 
-    let payoff_plot_data_mixed = PlotSeries {
-        data: report_average_mixed_reward,
-        description: "Mixed agents".to_string(),
-        color: colors::GREEN,
-    };
+    let payoff_plot_data_dove = PlotSeries::new(reports.dove, "Dove agents", colors::BLUE);
+
+    let payoff_plot_data_mixed = PlotSeries::new(reports.mixed, "Mixed agents", colors::GREEN);
 
     let mut plot_action_series = vec![];
 
-    let plot_series_defect = PlotSeries {
-        data: report_average_defects,
-        description: "Defects".to_string(),
-        color: colors::RED,
-    };
-    let plot_series_coops = PlotSeries {
-        data: report_average_coops,
-        description: "Cooperations".to_string(),
-        color: colors::BLUE,
-    };
+    let plot_series_defect = PlotSeries::new(reports.defects, "Defects", colors::RED);
+    let plot_series_coops = PlotSeries::new(reports.coops, "Cooperations", colors::BLUE);
 
 
 
@@ -622,61 +915,103 @@ fn main() -> Result<(), AmfiError<D>>{
 
 
 
-    let stamp = chrono::Local::now().format("[%Y-%m-%d][%H:%M:%S]");
-    let base_path = "results/replicator_dynamics/";
-    std::fs::create_dir_all(&base_path).unwrap();
 
-    plot_many_series(Path::new(
-        format!("{}/payoffs-replicator-{:?}_{}-{}-{}-{}_{}.svg",
+    let plot = Plot::new()
+        .size(args.plot_width, args.plot_height)
+        .x_desc("Epoch");
+
+    let path = format!("{}/payoffs-replicator-{:?}_{}-{}-{}-{}_{}.{}",
                 base_path,
                 args.number_of_rounds,
                 args.number_of_learning,
                 args.number_of_hawks,
                 args.number_of_doves,
                 args.number_of_mixes,
-                stamp
-        ).as_str()), "",&plot_payoff_series[..],
-        "Epoch",
-        "Payoff"
-    ).unwrap();
-
-    plot_many_series(Path::new(
-        format!("{}/learning_actions-replicator-{:?}_{}-{}-{}-{}_{}.svg",
+                stamp,
+                args.plot_format.extension()
+    );
+    plot.clone().y_desc("Payoff").draw(Path::new(&path), &plot_payoff_series[..])
+        .map_err(|e| ExperimentError::plot(Path::new(&path), e))?;
+
+    let path = format!("{}/learning_actions-replicator-{:?}_{}-{}-{}-{}_{}.{}",
                 base_path,
                 args.number_of_rounds,
                 args.number_of_learning,
                 args.number_of_hawks,
                 args.number_of_doves,
                 args.number_of_mixes,
-                stamp
-        ).as_str()), "",&plot_action_series[..],
-        "Epoch",
-        "Actions taken"
-    ).unwrap();
-
-
-
-    let file = File::create(
-        format!("{}/payoffs-replicator-{:?}_{}-{}-{}-{}_{}.json",
+                stamp,
+                args.plot_format.extension()
+    );
+    plot.clone().y_desc("Actions taken").draw(Path::new(&path), &plot_action_series[..])
+        .map_err(|e| ExperimentError::plot(Path::new(&path), e))?;
+
+    let mut dashboard = Dashboard::new(&format!("Replicator: {} learning, {} hawks, {} doves, {} mixed",
+                                              args.number_of_learning, args.number_of_hawks,
+                                              args.number_of_doves, args.number_of_mixes))
+        .panel_size(args.plot_width, args.plot_height)
+        .panel(Panel::lines(plot.clone().title("Group payoffs").y_desc("Payoff"), plot_payoff_series))
+        .panel(Panel::lines(plot.clone().title("Learning agents actions per episode").y_desc("Average count"), plot_action_series))
+        .panel(Panel::lines(plot.clone().title("Shares of total payoff").y_desc("Share"), payoff_share_series));
+    if !training_diagnostics.is_empty(){
+        for panel in training_diagnostics.panels(&plot, "Training"){
+            dashboard = dashboard.panel(panel);
+        }
+    }
+    if let Some((epoch, heatmap)) = last_heatmap{
+        dashboard = dashboard.panel(Panel::heatmap(&format!("Cooperation probability (epoch {epoch})"),
+            heatmap, "Round", "Opponent's last actions"));
+    }
+    let path = format!("{}/dashboard-replicator-{:?}_{}-{}-{}-{}_{}.{}",
                 base_path,
                 args.number_of_rounds,
                 args.number_of_learning,
                 args.number_of_hawks,
                 args.number_of_doves,
                 args.number_of_mixes,
-                stamp).as_str()).unwrap();
-    serde_json::to_writer(file, &payoff_series).unwrap();
+                stamp,
+                args.plot_format.extension()
+    );
+    dashboard.draw(Path::new(&path))
+        .map_err(|e| ExperimentError::plot(Path::new(&path), e))?;
 
-    let file = File::create(
-        format!("{}/game-trajectory-{:?}_{}-{}-{}-{}_{}.json",
+
+
+    write_json(format!("{}/payoffs-replicator-{:?}_{}-{}-{}-{}_{}.json",
                 base_path,
                 args.number_of_rounds,
                 args.number_of_learning,
                 args.number_of_hawks,
                 args.number_of_doves,
                 args.number_of_mixes,
-                stamp).as_str()).unwrap();
-    serde_json::to_writer_pretty(file, &model.environment.trajectory()).unwrap();
+                stamp), &payoff_series, false)?;
+
+    if !training_diagnostics.is_empty(){
+        write_json(format!("{}/diagnostics-replicator-{:?}_{}-{}-{}-{}_{}.json",
+                    base_path,
+                    args.number_of_rounds,
+                    args.number_of_learning,
+                    args.number_of_hawks,
+                    args.number_of_doves,
+                    args.number_of_mixes,
+                    stamp), &training_diagnostics, false)?;
+    }
+
+    match recorder{
+        Some(writer) => {
+            writer.finish()?;
+        },
+        None => {
+            write_json(format!("{}/game-trajectory-{:?}_{}-{}-{}-{}_{}.json",
+                    base_path,
+                    args.number_of_rounds,
+                    args.number_of_learning,
+                    args.number_of_hawks,
+                    args.number_of_doves,
+                    args.number_of_mixes,
+                    stamp), &model.environment.trajectory(), true)?;
+        },
+    }
     
 
 
@@ -1,6 +1,42 @@
 use std::path::PathBuf;
 use log::LevelFilter;
-use clap::Parser;
+use amfiteatr_examples::plots::PlotFormat;
+use amfiteatr_examples::policy::FallbackKind;
+use amfiteatr_examples::probe::KnownStrategy;
+use clap::{Parser, ValueEnum};
+use amfiteatr_examples::games::GamePreset;
+use amfiteatr_examples::pairing::noise::parse_probability;
+
+/// Metric watched for early stopping.
+#[derive(ValueEnum, Copy, Clone, Debug, PartialEq, Eq)]
+pub enum StopMetric{
+    /// Average payoff of learning agents
+    LearningPayoff,
+    /// Average payoff of all agents
+    AllPayoff,
+    /// Fraction of rounds in which learning agents cooperated
+    Cooperation,
+}
+
+/// Parameters shared by learning agents.
+#[derive(ValueEnum, Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Sharing{
+    /// Every agent has own network and optimiser
+    Independent,
+    /// One network trained on trajectories of all agents
+    Shared,
+    /// Shared trunk trained on trajectories of all agents, with actor and critic heads of every agent
+    Trunk,
+}
+
+/// Format of saved game trajectories.
+#[derive(ValueEnum, Copy, Clone, Debug, PartialEq, Eq)]
+pub enum TrajectoryFormat{
+    /// Pretty json of last environment trajectory
+    Json,
+    /// Compact binary file with games of all evaluation episodes
+    Binary,
+}
 
 #[derive(Parser)]
 #[command(author, version, about, long_about = None)]
@@ -39,9 +75,36 @@ pub struct ReplicatorOptions{
     #[arg(short = 'e', long = "epochs", default_value = "10")]
     pub epochs: usize,
 
+    /// Compute training diagnostics (losses, entropy, gradient norm) of every update, costs
+    /// additional forward and backward pass per update
+    #[arg(long = "diagnostics")]
+    pub diagnostics: bool,
+
     #[arg(short = 'n', long = "rounds", default_value = "32")]
     pub number_of_rounds: usize,
 
+    /// Probability that environment executes action opposite to chosen one (trembling hand)
+    #[arg(long = "action-noise", default_value = "0", value_parser = parse_probability)]
+    pub action_noise: f64,
+
+    /// Probability that agent observes opposite action of its opponent than was executed
+    #[arg(long = "observation-noise", default_value = "0", value_parser = parse_probability)]
+    pub observation_noise: f64,
+
+    /// Probability of playing next round after every round (shadow of the future), with it
+    /// number of rounds is limit of game length
+    #[arg(short = 'w', long = "continuation", value_parser = parse_probability)]
+    pub continuation: Option<f64>,
+
+    /// Game played, without it agents play hawk-dove game
+    #[arg(long = "game", value_enum)]
+    pub game: Option<GamePreset>,
+
+    /// Json file assigning reward tables or payoff multipliers to agents, agents not listed
+    /// there use table of game
+    #[arg(long = "reward-tables")]
+    pub reward_tables: Option<PathBuf>,
+
     #[arg(short = 'H', long = "hawks", default_value = "0")]
     pub number_of_hawks: usize,
 
@@ -60,6 +123,88 @@ pub struct ReplicatorOptions{
     #[arg(short = 'b', long = "batch", default_value = "64")]
     pub batch_size: usize,
 
+    /// Plot cooperation heatmap of first learning agent every given number of epochs, 0 disables heatmaps
+    #[arg(long = "heatmap-every", default_value = "0")]
+    pub heatmap_every: usize,
+
+    /// Number of opponent's last actions on heatmap rows
+    #[arg(long = "heatmap-memory", default_value = "2")]
+    pub heatmap_memory: usize,
+
+    #[arg(long = "heatmap-samples", default_value = "50")]
+    pub heatmap_samples: usize,
+
+    #[arg(long = "plot-format", value_enum, default_value = "svg")]
+    pub plot_format: PlotFormat,
+
+    #[arg(long = "trajectory-format", value_enum, default_value = "json")]
+    pub trajectory_format: TrajectoryFormat,
+
+    #[arg(long = "plot-width", default_value = "400")]
+    pub plot_width: u32,
+
+    #[arg(long = "plot-height", default_value = "300")]
+    pub plot_height: u32,
+
+    /// Show progress bar with estimated time and rolling averages of metrics
+    #[arg(long = "progress")]
+    pub progress: bool,
+
+    /// Number of last epochs averaged in progress bar
+    #[arg(long = "progress-window", default_value = "5")]
+    pub progress_window: usize,
+
+    /// Stop training when all given metrics reach plateau
+    #[arg(long = "stop-on", value_enum, value_delimiter = ',')]
+    pub stop_on: Vec<StopMetric>,
+
+    /// Number of epochs in which metric must change less than epsilon to stop training
+    #[arg(long = "stop-patience", default_value = "5")]
+    pub stop_patience: usize,
+
+    #[arg(long = "stop-epsilon", default_value = "0.01")]
+    pub stop_epsilon: f32,
+
+    /// Do not stop training earlier than after given number of epochs
+    #[arg(long = "stop-min-epochs", default_value = "0")]
+    pub stop_min_epochs: usize,
+
+    /// Serve current series and plots on http://127.0.0.1:<port>/ during training
+    #[arg(long = "http-port")]
+    pub http_port: Option<u16>,
+
+    /// Time in milliseconds for agent to answer before fallback replaces it, 0 disables timeout,
+    /// so agent that stopped without disconnecting blocks environment forever
+    #[arg(long = "agent-timeout-ms", default_value = "10000")]
+    pub agent_timeout_ms: u64,
+
+    /// Replacement of agent that timed out or disconnected, with forfeit its episode is aborted
+    #[arg(long = "fallback", value_enum, default_value = "cooperate")]
+    pub fallback: FallbackKind,
+
+    #[arg(long = "fallback-strategy", value_enum, default_value = "tit-for-tat")]
+    pub fallback_strategy: KnownStrategy,
+
+    /// How learning agents share network parameters. With shared network their actions are
+    /// sampled in one batched forward pass per round
+    #[arg(long = "sharing", value_enum, default_value = "independent")]
+    pub sharing: Sharing,
+
+    /// Failed episodes (e.g. aborted by forfeit or failing agent) tolerated before experiment
+    /// stops with error of the last one
+    #[arg(long = "max-failed-episodes", default_value = "10")]
+    pub max_failed_episodes: usize,
+
+    /// Run agents in thread of environment, without messages. Fast for scripted populations,
+    /// learning agents with shared network are not batched in this mode
+    #[arg(long = "sync")]
+    pub synchronous: bool,
+
+    /// Seed of environment (pairings, noise and length of games), so runs can be repeated and
+    /// compared between modes. Policies of agents keep their own randomness
+    #[arg(long = "seed")]
+    pub seed: Option<u64>,
+
 
 
 
@@ -0,0 +1,215 @@
+mod options;
+
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+use clap::Parser;
+use log::{debug, info};
+use rand::rngs::StdRng;
+use rand::SeedableRng;
+use plotters::style::{colors, RGBColor};
+use serde::Serialize;
+use amfiteatr_core::agent::{AgentGen, PolicyAgent, StatefulAgent, TracingAgentGen};
+use amfiteatr_core::comm::{AgentMpscAdapter, EnvironmentMpscPort};
+use amfiteatr_core::env::TracingBasicEnvironment;
+use amfiteatr_classic::SymmetricRewardTable;
+use amfiteatr_classic::domain::AgentNum;
+use amfiteatr_rl::policy::{ActorCriticPolicy, LearningNetworkPolicy, TrainConfig};
+use amfiteatr_rl::tch::Device;
+use amfiteatr_rl::tch::nn::Adam;
+use amfiteatr_rl::tensor_data::ConversionToTensor;
+use amfiteatr_examples::games::GamePreset;
+use amfiteatr_examples::error::{create_dir_all, ExperimentError, write_json};
+use amfiteatr_examples::plots::{Plot, PlotSeries};
+use amfiteatr_examples::policy::a2c_network;
+use amfiteatr_examples::pairing::reputation::{ReputationConversion, ReputationDomainNumbered, ReputationInfoSet, ReputationPolicy, ReputationState, ReputationStrategy};
+use amfiteatr_examples::series::{avg, GroupPayoffHistory, GroupPayoffs, PayoffGroupSeries};
+use amfiteatr_examples::sync::{ScriptedGroup, SynchronousModel};
+use crate::options::ReputationOptions;
+
+type D = ReputationDomainNumbered;
+type InfoSet = ReputationInfoSet<AgentNum>;
+type AgentComm = AgentMpscAdapter<D>;
+type Learner = TracingAgentGen<D, ActorCriticPolicy<D, InfoSet, ReputationConversion>, AgentComm>;
+type Scripted = AgentGen<D, ReputationPolicy<AgentNum>, AgentComm>;
+
+const GROUP_COLORS: [RGBColor; 5] = [colors::BLACK, colors::GREEN, colors::RED, colors::BLUE, colors::MAGENTA];
+
+#[derive(Serialize, Clone, Debug)]
+struct ReputationResults{
+    payoffs: Vec<PayoffGroupSeries>,
+    reputations: Vec<PayoffGroupSeries>,
+    learning_acceptance: Vec<f32>,
+    learning_cooperation: Vec<f32>,
+}
+
+pub fn setup_logger(options: &ReputationOptions) -> Result<(), fern::InitError> {
+    let dispatch  = fern::Dispatch::new()
+
+        .format(|out, message, record| {
+            out.finish(format_args!(
+                "{}[{}][{}] {}",
+                chrono::Local::now().format("[%H:%M:%S]"),
+                record.target(),
+                record.level(),
+                message
+            ))
+        })
+        .level(options.log_level)
+        .level_for("amfiteatr_core", options.log_level_amfi);
+
+        match &options.log_file{
+            None => dispatch.chain(std::io::stdout()),
+            Some(f) => dispatch.chain(fern::log_file(f)?)
+        }
+
+        .apply()?;
+    Ok(())
+}
+
+type Model = SynchronousModel<D, TracingBasicEnvironment<D, ReputationState<AgentNum>, EnvironmentMpscPort<D>>, Learner, Scripted>;
+
+/// Averages of test episodes.
+struct Evaluation{
+    payoffs: GroupPayoffs,
+    /// Reputations of agents at the end of episode
+    reputations: GroupPayoffs,
+    /// Fraction of proposed partners accepted by learners
+    learning_acceptance: f32,
+    /// Fraction of played encounters in which learners cooperated
+    learning_cooperation: f32,
+}
+
+fn evaluate(model: &mut Model, episodes: usize) -> Evaluation{
+    let mut learning_reputations = Vec::new();
+    let mut scripted_reputations = vec![Vec::new(); model.scripted_groups.len()];
+    let (mut proposals, mut accepted, mut played, mut cooperated) = (0, 0, 0, 0);
+    let payoffs = model.evaluate_groups(episodes, |agent|{
+        let info_set = agent.info_set();
+        learning_reputations.push(info_set.own_reputation() as f32);
+        proposals += info_set.history().len();
+        accepted += info_set.count_accepted();
+        played += info_set.count_played();
+        cooperated += info_set.count_cooperated();
+    }, |group, agent| scripted_reputations[group].push(agent.info_set().own_reputation() as f32));
+    Evaluation{
+        payoffs,
+        reputations: GroupPayoffs{
+            learning: avg(&learning_reputations),
+            scripted: scripted_reputations.iter().map(|r| avg(r)).collect(),
+        },
+        learning_acceptance: accepted as f32 / proposals.max(1) as f32,
+        learning_cooperation: cooperated as f32 / played.max(1) as f32,
+    }
+}
+
+fn update_policies(model: &mut Model) -> Result<(), ExperimentError<D>>{
+    model.update_policies(|agent|{
+        let trajectories = agent.take_episodes();
+        agent.policy_mut().train_on_trajectories_env_reward(&trajectories[..])
+    })?;
+    Ok(())
+}
+
+fn main() -> Result<(), ExperimentError<D>>{
+    let args = ReputationOptions::parse();
+    setup_logger(&args)?;
+    let device = Device::Cpu;
+    let reward_table = GamePreset::table_or(args.game, SymmetricRewardTable::new(3, 0, 5, 1));
+    debug!("Reward table: {reward_table:?}");
+
+    let tensor_repr = ReputationConversion::new(args.number_of_rounds);
+    let input_size = tensor_repr.desired_shape_flatten();
+
+    let mut env_adapter = EnvironmentMpscPort::new();
+    let mut learning_agents = Vec::with_capacity(args.number_of_learning);
+    let mut id: AgentNum = 0;
+    for _ in 0..args.number_of_learning{
+        let comm = env_adapter.register_agent(id)?;
+        let net = a2c_network(input_size, 2, device);
+        let opt = net.build_optimizer(Adam::default(), 1e-4)?;
+        let policy = ActorCriticPolicy::new(net, opt, tensor_repr, TrainConfig {gamma: 0.99});
+        let state = ReputationInfoSet::new(id, reward_table, args.rule, args.outside_option);
+        learning_agents.push(Arc::new(Mutex::new(TracingAgentGen::new(state, comm, policy))));
+        id += 1;
+    }
+    let mut scripted_groups = Vec::new();
+    for (name, number, strategy) in [
+        ("Cooperators", args.number_of_cooperators, ReputationStrategy::Cooperator),
+        ("Defectors", args.number_of_defectors, ReputationStrategy::Defector),
+        ("Discriminators", args.number_of_discriminators, ReputationStrategy::Discriminator),
+        ("Selectors", args.number_of_selectors, ReputationStrategy::Selector),
+    ]{
+        let mut agents = Vec::with_capacity(number);
+        for _ in 0..number{
+            let comm = env_adapter.register_agent(id)?;
+            let state = ReputationInfoSet::new(id, reward_table, args.rule, args.outside_option);
+            agents.push(Arc::new(Mutex::new(AgentGen::new(state, comm, ReputationPolicy::new(strategy)))));
+            id += 1;
+        }
+        scripted_groups.push(ScriptedGroup{name, agents});
+    }
+    let mut env_state = ReputationState::new_even(id as usize, args.number_of_rounds, reward_table, args.rule, args.outside_option)?;
+    if let Some(seed) = args.seed{
+        env_state = env_state.with_rng(StdRng::seed_from_u64(seed));
+    }
+    let mut model = Model::new(TracingBasicEnvironment::new(env_state, env_adapter), learning_agents, scripted_groups);
+
+    let names: Vec<&str> = std::iter::once("Learners").chain(model.scripted_groups.iter().map(|g| g.name)).collect();
+    let mut payoffs = GroupPayoffHistory::new(names.iter().copied());
+    let mut reputations = GroupPayoffHistory::new(names.iter().copied());
+    let mut learning_acceptance = Vec::with_capacity(args.epochs + 1);
+    let mut learning_cooperation = Vec::with_capacity(args.epochs + 1);
+    let mut record = |epoch: usize, evaluation: Evaluation|{
+        info!("Epoch {epoch}: learning agents' payoff: {:?}, reputation: {:?}, scripted agents' payoffs: {:?}, reputations: {:?}, learning agents' acceptance: {:.02}, cooperation: {:.02}",
+            evaluation.payoffs.learning, evaluation.reputations.learning, evaluation.payoffs.scripted,
+            evaluation.reputations.scripted, evaluation.learning_acceptance, evaluation.learning_cooperation);
+        payoffs.push(&evaluation.payoffs);
+        reputations.push(&evaluation.reputations);
+        learning_acceptance.push(evaluation.learning_acceptance);
+        learning_cooperation.push(evaluation.learning_cooperation);
+    };
+
+    info!("Starting initial evaluation");
+    record(0, evaluate(&mut model, args.evaluation_episodes));
+    for e in 0..args.epochs{
+        info!("Running training epoch: {}", e);
+        for _ in 0..args.batch_size{
+            model.run_episode();
+        }
+        update_policies(&mut model)?;
+        record(e + 1, evaluate(&mut model, args.evaluation_episodes));
+    }
+
+    let stamp = chrono::Local::now().format("[%Y-%m-%d][%H:%M:%S]");
+    let base_path = "results/reputation/";
+    create_dir_all(base_path)?;
+    let name = format!("{:?}_{}_{}-{}-{}-{}-{}_{}", args.rule, args.number_of_rounds,
+        args.number_of_learning, args.number_of_cooperators, args.number_of_defectors,
+        args.number_of_discriminators, args.number_of_selectors, stamp);
+
+    let plot = Plot::new()
+        .size(args.plot_width, args.plot_height)
+        .x_desc("Epoch");
+    let action_series = [
+        PlotSeries::new(learning_acceptance.clone(), "Acceptance", colors::BLUE),
+        PlotSeries::new(learning_cooperation.clone(), "Cooperation", colors::GREEN),
+    ];
+    let path = format!("{base_path}/learning_actions-{name}.{}", args.plot_format.extension());
+    plot.clone().y_desc("Frequency").draw(Path::new(&path), &action_series[..])
+        .map_err(|e| ExperimentError::plot(Path::new(&path), e))?;
+
+    for (desc, file, series) in [("Payoff", "payoffs", &payoffs), ("Reputation", "reputations", &reputations)]{
+        let plot_series = series.plot_series(&GROUP_COLORS);
+        let path = format!("{base_path}/{file}-{name}.{}", args.plot_format.extension());
+        plot.clone().y_desc(desc).draw(Path::new(&path), &plot_series[..])
+            .map_err(|e| ExperimentError::plot(Path::new(&path), e))?;
+    }
+
+    let results = ReputationResults{
+        payoffs: payoffs.into_series(),
+        reputations: reputations.into_series(),
+        learning_acceptance, learning_cooperation,
+    };
+    write_json(format!("{base_path}/series-{name}.json"), &results, true)?;
+    Ok(())
+}
@@ -0,0 +1,77 @@
+use std::path::PathBuf;
+use log::LevelFilter;
+use clap::Parser;
+use amfiteatr_examples::games::GamePreset;
+use amfiteatr_examples::pairing::reputation::ReputationRule;
+use amfiteatr_examples::plots::PlotFormat;
+
+#[derive(Parser)]
+#[command(author, version, about, long_about = None)]
+pub struct ReputationOptions{
+
+    #[arg(short = 'v', long = "log_level", value_enum, default_value = "info")]
+    pub log_level: LevelFilter,
+
+    #[arg(short = 'a', long = "log_level_amfi", value_enum, default_value = "OFF")]
+    pub log_level_amfi: LevelFilter,
+
+    #[arg(short = 'o', long = "logfile")]
+    pub log_file: Option<PathBuf>,
+
+    #[arg(short = 'e', long = "epochs", default_value = "100")]
+    pub epochs: usize,
+
+    #[arg(short = 'b', long = "batch", default_value = "64")]
+    pub batch_size: usize,
+
+    /// Test episodes played before training and after every epoch
+    #[arg(long = "evaluation-episodes", default_value = "100")]
+    pub evaluation_episodes: usize,
+
+    #[arg(short = 'n', long = "rounds", default_value = "10")]
+    pub number_of_rounds: usize,
+
+    /// Game played, without it agents play prisoners' dilemma
+    #[arg(long = "game", value_enum)]
+    pub game: Option<GamePreset>,
+
+    #[arg(short = 'r', long = "rule", value_enum, default_value = "image-scoring")]
+    pub rule: ReputationRule,
+
+    /// Payoff of both agents when one of them refuses encounter
+    #[arg(long = "outside-option", default_value = "1", allow_negative_numbers = true)]
+    pub outside_option: i64,
+
+    #[arg(short = 'l', long = "learners", default_value = "4")]
+    pub number_of_learning: usize,
+
+    /// Agents accepting everyone and always cooperating
+    #[arg(short = 'c', long = "cooperators", default_value = "0")]
+    pub number_of_cooperators: usize,
+
+    /// Agents accepting everyone and always defecting
+    #[arg(short = 'd', long = "defectors", default_value = "2")]
+    pub number_of_defectors: usize,
+
+    /// Agents cooperating only with partners of good reputation
+    #[arg(short = 'D', long = "discriminators", default_value = "2")]
+    pub number_of_discriminators: usize,
+
+    /// Agents refusing partners of bad reputation
+    #[arg(short = 's', long = "selectors", default_value = "0")]
+    pub number_of_selectors: usize,
+
+    /// Seed of environment (proposed partners), so runs can be repeated. Policies of agents keep
+    /// their own randomness
+    #[arg(long = "seed")]
+    pub seed: Option<u64>,
+
+    #[arg(long = "plot-format", value_enum, default_value = "svg")]
+    pub plot_format: PlotFormat,
+
+    #[arg(long = "plot-width", default_value = "400")]
+    pub plot_width: u32,
+
+    #[arg(long = "plot-height", default_value = "300")]
+    pub plot_height: u32,
+}
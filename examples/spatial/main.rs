@@ -0,0 +1,147 @@
+mod options;
+
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+use clap::Parser;
+use log::{debug, info};
+use rand::rngs::StdRng;
+use rand::SeedableRng;
+use plotters::style::colors;
+use serde::Serialize;
+use amfiteatr_core::agent::AgentGen;
+use amfiteatr_core::comm::{AgentMpscAdapter, EnvironmentMpscPort};
+use amfiteatr_core::env::{BasicEnvironment, StatefulEnvironment};
+use amfiteatr_classic::SymmetricRewardTable;
+use amfiteatr_classic::domain::AgentNum;
+use amfiteatr_examples::error::{create_dir_all, ExperimentError, write_json};
+use amfiteatr_examples::games::GamePreset;
+use amfiteatr_examples::plots::{Plot, PlotSeries};
+use amfiteatr_examples::spatial::{ImitationPolicy, SpatialDomainNumbered, SpatialInfoSet, SpatialState, Topology};
+use amfiteatr_examples::sync::{lock_agents, run_reported_episode, SteppedAgent};
+use crate::options::SpatialOptions;
+
+type D = SpatialDomainNumbered;
+type Agent = AgentGen<D, ImitationPolicy<AgentNum>, AgentMpscAdapter<D>>;
+
+/// Frame of animation, files are named so that they sort in order of generations.
+#[derive(Serialize, Clone, Debug)]
+struct Frame{
+    generation: usize,
+    file: String,
+    cooperation: f32,
+}
+
+#[derive(Serialize, Clone, Debug)]
+struct SpatialResults{
+    columns: usize,
+    rows: usize,
+    cooperation: Vec<f32>,
+    average_payoff: Vec<f32>,
+    /// Strategies of nodes in every generation, `C` for cooperation and `D` for defection
+    snapshots: Vec<String>,
+}
+
+pub fn setup_logger(options: &SpatialOptions) -> Result<(), fern::InitError> {
+    let dispatch  = fern::Dispatch::new()
+
+        .format(|out, message, record| {
+            out.finish(format_args!(
+                "{}[{}][{}] {}",
+                chrono::Local::now().format("[%H:%M:%S]"),
+                record.target(),
+                record.level(),
+                message
+            ))
+        })
+        .level(options.log_level);
+
+        match &options.log_file{
+            None => dispatch.chain(std::io::stdout()),
+            Some(f) => dispatch.chain(fern::log_file(f)?)
+        }
+
+        .apply()?;
+    Ok(())
+}
+
+fn main() -> Result<(), ExperimentError<D>>{
+    let args = SpatialOptions::parse();
+    setup_logger(&args)?;
+    let reward_table = GamePreset::table_or(args.game, SymmetricRewardTable::new(3, 0, 5, 1));
+    debug!("Reward table: {reward_table:?}");
+
+    let topology = Arc::new(match &args.graph{
+        Some(path) => Topology::from_edge_list(path)?,
+        None => Topology::lattice(args.width, args.height, args.neighbourhood, !args.bounded)?,
+    });
+    let (columns, rows) = topology.grid();
+    info!("Population of {} agents drawn in {columns}x{rows} grid", topology.size());
+
+    let mut env_adapter = EnvironmentMpscPort::new();
+    let mut agents = Vec::with_capacity(topology.size());
+    for id in 0..topology.size() as AgentNum{
+        let comm = env_adapter.register_agent(id)?;
+        let mut policy = ImitationPolicy::new(args.update_rule, args.temperature, args.cooperation);
+        if let Some(seed) = args.seed{
+            policy = policy.with_rng(StdRng::seed_from_u64(seed.wrapping_add(id as u64)));
+        }
+        agents.push(Arc::new(Mutex::new(Agent::new(SpatialInfoSet::new(id, topology.clone()), comm, policy))));
+    }
+    // Every generation is round of one episode, so agents imitate what they saw in previous round.
+    let env_state = SpatialState::new(topology.clone(), reward_table, args.generations + 1);
+    let mut environment = BasicEnvironment::new(env_state, env_adapter);
+    {
+        let mut guards = lock_agents(&agents);
+        let mut stepped: Vec<&mut dyn SteppedAgent<D>> = guards.iter_mut()
+            .map(|a| &mut **a as &mut dyn SteppedAgent<D>)
+            .collect();
+        run_reported_episode(&mut environment, &mut stepped[..]).into_result(0)?;
+    }
+
+    let stamp = chrono::Local::now().format("[%Y-%m-%d][%H:%M:%S]");
+    let name = match &args.graph{
+        Some(path) => format!("{}", path.file_stem().unwrap_or_default().to_string_lossy()),
+        None => format!("{}x{}-{:?}", args.width, args.height, args.neighbourhood),
+    };
+    let base_path = format!("results/spatial/{name}_{:?}_{}_{stamp}", args.update_rule,
+        args.game.map(|g| format!("{g:?}")).unwrap_or_else(|| String::from("default")));
+    let frames_path = format!("{base_path}/frames");
+    create_dir_all(&frames_path)?;
+
+    let generations = environment.state().generations();
+    let mut cooperation = Vec::with_capacity(generations.len());
+    let mut average_payoff = Vec::with_capacity(generations.len());
+    let mut snapshots = Vec::with_capacity(generations.len());
+    let mut frames = Vec::new();
+    for (previous, report) in generations.iter().take(1).chain(generations.iter()).zip(generations.iter()){
+        let generation = report.generation;
+        info!("Generation {generation}: cooperation: {:.03}, average payoff: {:.03}", report.cooperation(), report.average_payoff());
+        cooperation.push(report.cooperation());
+        average_payoff.push(report.average_payoff());
+        snapshots.push(report.snapshot());
+        if args.frame_every > 0 && generation % args.frame_every == 0{
+            let file = format!("frame-{generation:06}.svg");
+            let path = format!("{frames_path}/{file}");
+            report.draw_frame(&topology, previous, Path::new(&path), args.cell_size)
+                .map_err(|e| ExperimentError::plot(Path::new(&path), e))?;
+            frames.push(Frame{generation, file, cooperation: report.cooperation()});
+        }
+    }
+
+    let plot = Plot::new()
+        .size(args.plot_width, args.plot_height)
+        .x_desc("Generation");
+    let path = format!("{base_path}/cooperation.{}", args.plot_format.extension());
+    plot.clone().y_desc("Cooperation").y_range(0.0..1.0)
+        .draw(Path::new(&path), &[PlotSeries::new(cooperation.clone(), "Cooperators", colors::BLUE)])
+        .map_err(|e| ExperimentError::plot(Path::new(&path), e))?;
+    let path = format!("{base_path}/payoff.{}", args.plot_format.extension());
+    plot.y_desc("Payoff")
+        .draw(Path::new(&path), &[PlotSeries::new(average_payoff.clone(), "Average payoff", colors::BLACK)])
+        .map_err(|e| ExperimentError::plot(Path::new(&path), e))?;
+
+    write_json(format!("{frames_path}/frames.json"), &frames, true)?;
+    let results = SpatialResults{columns, rows, cooperation, average_payoff, snapshots};
+    write_json(format!("{base_path}/series.json"), &results, true)?;
+    Ok(())
+}
@@ -0,0 +1,75 @@
+use std::path::PathBuf;
+use log::LevelFilter;
+use clap::Parser;
+use amfiteatr_examples::games::GamePreset;
+use amfiteatr_examples::pairing::noise::parse_probability;
+use amfiteatr_examples::plots::PlotFormat;
+use amfiteatr_examples::spatial::{Neighbourhood, UpdateRule};
+
+#[derive(Parser)]
+#[command(author, version, about, long_about = None)]
+pub struct SpatialOptions{
+
+    #[arg(short = 'v', long = "log_level", value_enum, default_value = "info")]
+    pub log_level: LevelFilter,
+
+    #[arg(short = 'o', long = "logfile")]
+    pub log_file: Option<PathBuf>,
+
+    #[arg(short = 'g', long = "generations", default_value = "100")]
+    pub generations: usize,
+
+    /// Game played, without it agents play prisoners' dilemma
+    #[arg(long = "game", value_enum)]
+    pub game: Option<GamePreset>,
+
+    #[arg(short = 'W', long = "width", default_value = "50")]
+    pub width: usize,
+
+    #[arg(short = 'H', long = "height", default_value = "50")]
+    pub height: usize,
+
+    #[arg(long = "neighbourhood", value_enum, default_value = "moore")]
+    pub neighbourhood: Neighbourhood,
+
+    /// Lattice without wrapping edges
+    #[arg(long = "bounded")]
+    pub bounded: bool,
+
+    /// File with edge list of graph, when given it replaces lattice
+    #[arg(short = 'G', long = "graph")]
+    pub graph: Option<PathBuf>,
+
+    /// Probability that agent cooperates in first generation
+    #[arg(short = 'c', long = "cooperation", default_value = "0.5", value_parser = parse_probability)]
+    pub cooperation: f64,
+
+    #[arg(short = 'u', long = "update", value_enum, default_value = "best-neighbour")]
+    pub update_rule: UpdateRule,
+
+    /// Noise of imitation in Fermi update rule
+    #[arg(long = "temperature", default_value = "0.1")]
+    pub temperature: f32,
+
+    /// Draw strategy snapshot every this many generations, 0 disables frames
+    #[arg(long = "frame-every", default_value = "1")]
+    pub frame_every: usize,
+
+    /// Size of agent's cell in frames in pixels
+    #[arg(long = "cell-size", default_value = "8")]
+    pub cell_size: u32,
+
+    /// Seed of imitating agents' random choices, grid itself is played without randomness, so
+    /// seeded runs can be repeated
+    #[arg(long = "seed")]
+    pub seed: Option<u64>,
+
+    #[arg(long = "plot-format", value_enum, default_value = "svg")]
+    pub plot_format: PlotFormat,
+
+    #[arg(long = "plot-width", default_value = "400")]
+    pub plot_width: u32,
+
+    #[arg(long = "plot-height", default_value = "300")]
+    pub plot_height: u32,
+}
@@ -0,0 +1,88 @@
+mod options;
+
+use clap::Parser;
+use log::info;
+use amfiteatr_classic::agent::{LocalHistoryConversionToTensor, LocalHistoryInfoSet};
+use amfiteatr_classic::domain::{AgentNum, ClassicGameDomain};
+use amfiteatr_classic::SymmetricRewardTableInt;
+use amfiteatr_examples::games::GamePreset;
+use amfiteatr_core::agent::{AgentGen, AutomaticAgentRewarded, Policy, RewardedAgent, StatefulAgent};
+use amfiteatr_core::error::AmfiError;
+use amfiteatr_rl::policy::{ActorCriticPolicy, TrainConfig};
+use amfiteatr_rl::tch::Device;
+use amfiteatr_rl::tch::nn::Adam;
+use amfiteatr_rl::tensor_data::ConversionToTensor;
+use amfiteatr_examples::error::ExperimentError;
+use amfiteatr_examples::net::AgentTcpEndpoint;
+use amfiteatr_examples::policy::{a2c_network, KnownStrategyPolicy};
+use crate::options::ClientOptions;
+
+type Domain = ClassicGameDomain<AgentNum>;
+
+pub fn setup_logger(options: &ClientOptions) -> Result<(), fern::InitError> {
+    let dispatch  = fern::Dispatch::new()
+
+        .format(|out, message, record| {
+            out.finish(format_args!(
+                "{}[{}][{}] {}",
+                chrono::Local::now().format("[%H:%M:%S]"),
+                record.target(),
+                record.level(),
+                message
+            ))
+        })
+        .level(options.log_level)
+        .level_for("amfiteatr_examples", options.log_level)
+        .level_for("amfiteatr_core", options.log_level_amfi);
+
+        match &options.log_file{
+            None => dispatch.chain(std::io::stdout()),
+            Some(f) => dispatch.chain(fern::log_file(f)?)
+        }
+
+        .apply()?;
+    Ok(())
+}
+
+fn play<P: Policy<Domain, InfoSetType = LocalHistoryInfoSet<AgentNum>>>(
+    options: &ClientOptions,
+    name: &str,
+    policy: P) -> Result<(), AmfiError<Domain>>{
+
+    let reward_table = GamePreset::table_or(options.game, SymmetricRewardTableInt::new(
+        options.coop_versus_coop,
+        options.coop_versus_defect,
+        options.defect_versus_coop,
+        options.defect_versus_defect));
+    let (id, comm) = AgentTcpEndpoint::connect(&options.address, options.id, name)?;
+    info!("Connected to {} as agent {id} ({name})", options.address);
+    let state = LocalHistoryInfoSet::new(id, reward_table);
+    let mut agent = AgentGen::new(state, comm, policy);
+    agent.run_rewarded()?;
+    info!("Finished with score {}: {}", agent.current_universal_score(), agent.info_set());
+    Ok(())
+}
+
+fn main() -> Result<(), ExperimentError<Domain>>{
+    let options = ClientOptions::parse();
+    setup_logger(&options)?;
+
+    let played = match &options.load_file{
+        None => {
+            let name = options.name.clone().unwrap_or_else(|| options.strategy.name().to_string());
+            play(&options, &name, KnownStrategyPolicy::new(options.strategy))
+        },
+        Some(load_file) => {
+            let tensor_repr = LocalHistoryConversionToTensor::new(options.number_of_rounds);
+            let mut net = a2c_network(tensor_repr.desired_shape().iter().product(), 2, Device::Cpu);
+            net.var_store_mut().load(load_file)
+                .map_err(|e| AmfiError::Custom(format!("Failed loading network from {load_file:?}: {e}")))?;
+            let optimizer = net.build_optimizer(Adam::default(), 1e-4)
+                .map_err(|e| AmfiError::Custom(format!("{e}")))?;
+            let policy = ActorCriticPolicy::new(net, optimizer, tensor_repr, TrainConfig {gamma: 0.99});
+            let name = options.name.clone().unwrap_or_else(|| String::from("A2C"));
+            play(&options, &name, policy)
+        }
+    };
+    Ok(played?)
+}
@@ -0,0 +1,57 @@
+use std::path::PathBuf;
+use log::LevelFilter;
+use clap::Parser;
+use amfiteatr_examples::probe::KnownStrategy;
+use amfiteatr_examples::games::GamePreset;
+
+#[derive(Parser)]
+#[command(author, version, about, long_about = None)]
+pub struct ClientOptions{
+
+    #[arg(short = 'v', long = "log_level", value_enum, default_value = "info")]
+    pub log_level: LevelFilter,
+
+    #[arg(short = 'a', long = "log_level_amfi", value_enum, default_value = "OFF")]
+    pub log_level_amfi: LevelFilter,
+
+    #[arg(short = 'o', long = "logfile")]
+    pub log_file: Option<PathBuf>,
+
+    #[arg(short = 'A', long = "address", default_value = "127.0.0.1:8420")]
+    pub address: String,
+
+    /// Ask server for given agent id, by default server assigns first free id
+    #[arg(long = "id")]
+    pub id: Option<u32>,
+
+    /// Name shown on scoreboard, defaults to name of strategy
+    #[arg(long = "name")]
+    pub name: Option<String>,
+
+    #[arg(short = 's', long = "strategy", value_enum, default_value = "tit-for-tat")]
+    pub strategy: KnownStrategy,
+
+    /// Play with actor-critic network loaded from checkpoint instead of strategy
+    #[arg(short = 'l', long = "load")]
+    pub load_file: Option<PathBuf>,
+
+    /// Number of rounds network was trained for (determines network input size)
+    #[arg(short = 'n', long = "rounds", default_value = "10")]
+    pub number_of_rounds: usize,
+
+    /// Game played, without it reward table is built from payoff options
+    #[arg(long = "game", value_enum)]
+    pub game: Option<GamePreset>,
+
+    #[arg(long = "defect-defect", default_value = "3")]
+    pub defect_versus_defect: i64,
+
+    #[arg(long = "coop-defect", default_value = "1")]
+    pub coop_versus_defect: i64,
+
+    #[arg(long = "defect-coop", default_value = "10")]
+    pub defect_versus_coop: i64,
+
+    #[arg(long = "coop-coop", default_value = "5")]
+    pub coop_versus_coop: i64,
+}
@@ -0,0 +1,103 @@
+mod options;
+
+use std::collections::HashMap;
+use std::net::TcpListener;
+use std::time::Duration;
+use clap::Parser;
+use log::{error, info};
+use amfiteatr_classic::domain::{AgentNum, ClassicGameDomain};
+use amfiteatr_examples::pairing::new_game_state;
+use amfiteatr_examples::pairing::noise::Noise;
+use amfiteatr_classic::SymmetricRewardTableInt;
+use amfiteatr_examples::games::GamePreset;
+use amfiteatr_core::env::{AutoEnvironmentWithScores, ScoreEnvironment, TracingBasicEnvironment, TracingEnvironment};
+use amfiteatr_core::error::AmfiError;
+use amfiteatr_examples::error::{create_dir_all, ExperimentError, write_json};
+use amfiteatr_examples::guard::{GuardedAdapter, GuardEvent};
+use amfiteatr_examples::net::{accept_agents, EndpointAdapter};
+use amfiteatr_examples::policy::classic_fallback;
+use amfiteatr_examples::tournament::Scoreboard;
+use crate::options::ServerOptions;
+
+type Domain = ClassicGameDomain<AgentNum>;
+
+pub fn setup_logger(options: &ServerOptions) -> Result<(), fern::InitError> {
+    let dispatch  = fern::Dispatch::new()
+
+        .format(|out, message, record| {
+            out.finish(format_args!(
+                "{}[{}][{}] {}",
+                chrono::Local::now().format("[%H:%M:%S]"),
+                record.target(),
+                record.level(),
+                message
+            ))
+        })
+        .level(options.log_level)
+        .level_for("amfiteatr_examples", options.log_level)
+        .level_for("amfiteatr_core", options.log_level_amfi);
+
+        match &options.log_file{
+            None => dispatch.chain(std::io::stdout()),
+            Some(f) => dispatch.chain(fern::log_file(f)?)
+        }
+
+        .apply()?;
+    Ok(())
+}
+
+fn main() -> Result<(), ExperimentError<Domain>>{
+    let options = ServerOptions::parse();
+    setup_logger(&options)?;
+
+    let reward_table = GamePreset::table_or(options.game, SymmetricRewardTableInt::new(
+        options.coop_versus_coop,
+        options.coop_versus_defect,
+        options.defect_versus_coop,
+        options.defect_versus_defect));
+    let env_state = new_game_state(options.number_of_players as usize, options.number_of_rounds, reward_table,
+        Noise::new(options.action_noise, options.observation_noise), options.continuation)?;
+
+    let listener = TcpListener::bind(&options.address)
+        .map_err(|e| AmfiError::<Domain>::Custom(format!("Failed binding {}: {e}", options.address)))?;
+    info!("Tournament server listening on {}, waiting for {} agents", options.address, options.number_of_players);
+    let ids: Vec<AgentNum> = (0..options.number_of_players).collect();
+    let agents = accept_agents(&listener, &ids)?;
+
+    let mut names = HashMap::new();
+    let mut endpoints = HashMap::new();
+    for agent in agents{
+        names.insert(agent.id, agent.name);
+        endpoints.insert(agent.id, agent.endpoint);
+    }
+    let timeout = (options.agent_timeout_ms > 0).then(|| Duration::from_millis(options.agent_timeout_ms));
+    let fallback = classic_fallback(options.fallback, options.fallback_strategy, reward_table);
+    let adapter = GuardedAdapter::new(EndpointAdapter::new(endpoints)?, timeout, fallback);
+    let guard_log = adapter.log();
+    let mut environment = TracingBasicEnvironment::new(env_state, adapter);
+    // scoreboard of interrupted tournament is still written
+    if let Err(e) = environment.run_with_scores(){
+        error!("Tournament interrupted: {e}");
+    }
+    let events = guard_log.take();
+    if !events.is_empty(){
+        let replaced = events.iter().filter(|e| matches!(e, GuardEvent::Replaced { .. })).count();
+        info!("Guard noted {} events, {replaced} moves played by fallback", events.len());
+    }
+
+    let scoreboard = Scoreboard::new(
+        options.number_of_rounds,
+        ids.iter().map(|id| (*id, names.remove(id).unwrap_or_default(), environment.actual_score_of_player(id))),
+        environment.trajectory());
+    info!("Tournament finished:\n{scoreboard}");
+
+    let stamp = chrono::Local::now().format("[%Y-%m-%d][%H:%M:%S]");
+    create_dir_all(&options.output)?;
+    let path = options.output.join(format!("scoreboard_{stamp}.json"));
+    write_json(&path, &scoreboard, false)?;
+    info!("Written {path:?}");
+    let path = options.output.join(format!("trajectory_{stamp}.json"));
+    write_json(&path, environment.trajectory(), false)?;
+    info!("Written {path:?}");
+    Ok(())
+}
@@ -0,0 +1,76 @@
+use std::path::PathBuf;
+use log::LevelFilter;
+use clap::Parser;
+use amfiteatr_examples::policy::FallbackKind;
+use amfiteatr_examples::probe::KnownStrategy;
+use amfiteatr_examples::pairing::noise::parse_probability;
+use amfiteatr_examples::games::GamePreset;
+
+#[derive(Parser)]
+#[command(author, version, about, long_about = None)]
+pub struct ServerOptions{
+
+    #[arg(short = 'v', long = "log_level", value_enum, default_value = "info")]
+    pub log_level: LevelFilter,
+
+    #[arg(short = 'a', long = "log_level_amfi", value_enum, default_value = "OFF")]
+    pub log_level_amfi: LevelFilter,
+
+    #[arg(short = 'o', long = "logfile")]
+    pub log_file: Option<PathBuf>,
+
+    #[arg(short = 'A', long = "address", default_value = "127.0.0.1:8420")]
+    pub address: String,
+
+    /// Number of agents (must be even), server waits until all of them connect
+    #[arg(short = 'N', long = "players", default_value = "4")]
+    pub number_of_players: u32,
+
+    #[arg(short = 'n', long = "rounds", default_value = "10")]
+    pub number_of_rounds: usize,
+
+    /// Probability that environment executes action opposite to chosen one (trembling hand)
+    #[arg(long = "action-noise", default_value = "0", value_parser = parse_probability)]
+    pub action_noise: f64,
+
+    /// Probability that agent observes opposite action of its opponent than was executed
+    #[arg(long = "observation-noise", default_value = "0", value_parser = parse_probability)]
+    pub observation_noise: f64,
+
+    /// Probability of playing next round after every round (shadow of the future), with it
+    /// number of rounds is limit of game length
+    #[arg(short = 'w', long = "continuation", value_parser = parse_probability)]
+    pub continuation: Option<f64>,
+
+    /// Directory where trajectory and scoreboard are written
+    #[arg(long = "output", default_value = "results/tournament")]
+    pub output: PathBuf,
+
+    /// Game played, without it reward table is built from payoff options
+    #[arg(long = "game", value_enum)]
+    pub game: Option<GamePreset>,
+
+    #[arg(long = "defect-defect", default_value = "3")]
+    pub defect_versus_defect: i64,
+
+    #[arg(long = "coop-defect", default_value = "1")]
+    pub coop_versus_defect: i64,
+
+    #[arg(long = "defect-coop", default_value = "10")]
+    pub defect_versus_coop: i64,
+
+    #[arg(long = "coop-coop", default_value = "5")]
+    pub coop_versus_coop: i64,
+
+    /// Time in milliseconds for agent to answer before fallback replaces it, 0 disables timeout,
+    /// so agent that stopped without disconnecting blocks environment forever
+    #[arg(long = "agent-timeout-ms", default_value = "10000")]
+    pub agent_timeout_ms: u64,
+
+    /// Replacement of agent that timed out or disconnected
+    #[arg(long = "fallback", value_enum, default_value = "strategy")]
+    pub fallback: FallbackKind,
+
+    #[arg(long = "fallback-strategy", value_enum, default_value = "tit-for-tat")]
+    pub fallback_strategy: KnownStrategy,
+}
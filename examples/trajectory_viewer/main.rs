@@ -0,0 +1,151 @@
+mod options;
+
+use std::path::Path;
+use clap::Parser;
+use log::{info, warn};
+use plotters::style::{colors, RGBColor};
+use amfiteatr_classic::domain::{ClassicAction, ClassicGameDomainNumbered};
+use amfiteatr_examples::error::{create_dir_all, ExperimentError, write_json};
+use amfiteatr_examples::pairing::compact::{is_compact_file, TrajectoryReader};
+use amfiteatr_examples::pairing::replay::{GameReplay, ReplayEncounter, ReplayError};
+use amfiteatr_examples::plots::{Plot, PlotSeries};
+use crate::options::ViewerOptions;
+
+type D = ClassicGameDomainNumbered;
+
+const AGENT_COLORS: [RGBColor; 5] = [colors::BLACK, colors::GREEN, colors::RED, colors::BLUE, colors::MAGENTA];
+
+pub fn setup_logger(options: &ViewerOptions) -> Result<(), fern::InitError> {
+    let dispatch  = fern::Dispatch::new()
+
+        .format(|out, message, record| {
+            out.finish(format_args!(
+                "{}[{}][{}] {}",
+                chrono::Local::now().format("[%H:%M:%S]"),
+                record.target(),
+                record.level(),
+                message
+            ))
+        })
+        .level(options.log_level);
+
+        match &options.log_file{
+            None => dispatch.chain(std::io::stderr()),
+            Some(f) => dispatch.chain(fern::log_file(f)?)
+        }
+
+        .apply()?;
+    Ok(())
+}
+
+fn action_name(action: ClassicAction) -> &'static str{
+    match action{
+        ClassicAction::Up => "Up",
+        ClassicAction::Down => "Down",
+    }
+}
+
+/// Executed action, followed by intended one in parentheses when noise flipped it.
+fn action_cell(encounter: &ReplayEncounter) -> String{
+    match encounter.intended{
+        Some(intended) if encounter.is_flipped() => format!("{} ({})", action_name(encounter.action), action_name(intended)),
+        _ => action_name(encounter.action).to_string(),
+    }
+}
+
+fn print_table(replay: &GameReplay, agents: &[usize], from: usize, to: usize){
+    println!("{:>6} {:>6} {:>8} {:>6} {:>12} {:>8} {:>7} {:>7}",
+        "round", "agent", "partner", "side", "action", "partner", "reward", "score");
+    for round in replay.rounds.iter().skip(from).take(to.saturating_sub(from)){
+        for encounter in round.encounters.iter()
+            .filter(|e| agents.is_empty() || agents.contains(&e.agent)){
+            println!("{:>6} {:>6} {:>8} {:>6} {:>12} {:>8} {:>7} {:>7}",
+                round.round, encounter.agent, encounter.partner, format!("{:?}", encounter.side),
+                action_cell(encounter), action_name(encounter.partner_action),
+                encounter.reward, encounter.score);
+        }
+    }
+}
+
+/// Streams binary trajectory keeping only selected game.
+fn read_binary_game(path: &Path, index: Option<usize>) -> Result<GameReplay, ExperimentError<D>>{
+    let mut selected = None;
+    let mut games = 0;
+    for (i, game) in TrajectoryReader::open(path)?.enumerate(){
+        let game = game?;
+        games += 1;
+        if index.is_none_or(|index| index == i){
+            selected = Some(game);
+        }
+    }
+    let game = selected.ok_or(ReplayError::Format("no game with given index"))?;
+    info!("Binary trajectory has {games} games, showing game {} played in epoch {}",
+        index.unwrap_or(games - 1), game.label);
+    Ok(game.replay)
+}
+
+fn main() -> Result<(), ExperimentError<D>>{
+    let args = ViewerOptions::parse();
+    setup_logger(&args)?;
+
+    let replay = match is_compact_file(&args.trajectory)?{
+        true => read_binary_game(&args.trajectory, args.game)?,
+        false => GameReplay::from_json_file(&args.trajectory)?,
+    };
+    info!("Loaded {} rounds of {} players from {} steps ({} invalid)",
+        replay.rounds.len(), replay.players, replay.steps, replay.invalid_steps);
+    if replay.scores() != replay.recorded_scores{
+        warn!("Scores of replay differ from scores noted by environment: {:?} != {:?}",
+            replay.scores(), replay.recorded_scores);
+    }
+    if let Some(agent) = args.agents.iter().find(|a| **a >= replay.players){
+        warn!("Agent {agent} is not in game of {} players", replay.players);
+    }
+
+    if !args.quiet{
+        print_table(&replay, &args.agents, args.from_round, args.to_round.unwrap_or(replay.rounds.len()));
+    }
+    if args.no_plots{
+        return Ok(());
+    }
+
+    let metrics = replay.metrics(&args.agents);
+    let base_path = match &args.output{
+        Some(path) => path.clone(),
+        None => Path::new("results/trajectory_viewer")
+            .join(args.trajectory.file_stem().unwrap_or_default()),
+    };
+    create_dir_all(&base_path)?;
+    write_json(base_path.join("metrics.json"), &metrics, true)?;
+
+    let plot = Plot::new()
+        .size(args.plot_width, args.plot_height)
+        .x_desc("Round");
+    let path = base_path.join(format!("cooperation.{}", args.plot_format.extension()));
+    plot.clone().y_desc("Cooperation").y_range(0.0..1.0)
+        .draw(&path, &[PlotSeries::new(metrics.cooperation.clone(), "Cooperators", colors::BLUE)])
+        .map_err(|e| ExperimentError::plot(&path, e))?;
+    let path = base_path.join(format!("reward.{}", args.plot_format.extension()));
+    plot.clone().y_desc("Reward")
+        .draw(&path, &[PlotSeries::new(metrics.average_reward.clone(), "Average reward", colors::BLACK)])
+        .map_err(|e| ExperimentError::plot(&path, e))?;
+    // lines of single agents are readable only for few of them
+    let series: Vec<PlotSeries> = match metrics.agents.len() <= AGENT_COLORS.len(){
+        true => metrics.agents.iter().zip(metrics.scores.iter()).enumerate()
+            .map(|(i, (agent, scores))| PlotSeries::new(scores.iter().map(|s| *s as f32).collect(),
+                &format!("Agent {agent}"), AGENT_COLORS[i]))
+            .collect(),
+        false => {
+            let average = (0..replay.rounds.len())
+                .map(|r| metrics.scores.iter().map(|s| s[r] as f32).sum::<f32>() / metrics.agents.len() as f32)
+                .collect();
+            vec![PlotSeries::new(average, "Average score", colors::BLACK)]
+        },
+    };
+    let path = base_path.join(format!("scores.{}", args.plot_format.extension()));
+    plot.y_desc("Score")
+        .draw(&path, &series)
+        .map_err(|e| ExperimentError::plot(&path, e))?;
+    info!("Metrics and plots written to {base_path:?}");
+    Ok(())
+}
@@ -0,0 +1,55 @@
+use std::path::PathBuf;
+use log::LevelFilter;
+use clap::Parser;
+use amfiteatr_examples::plots::PlotFormat;
+
+#[derive(Parser)]
+#[command(author, version, about, long_about = None)]
+pub struct ViewerOptions{
+
+    #[arg(short = 'v', long = "log_level", value_enum, default_value = "info")]
+    pub log_level: LevelFilter,
+
+    #[arg(short = 'o', long = "logfile")]
+    pub log_file: Option<PathBuf>,
+
+    /// Game trajectory written by replicator_dynamics or tournament_server, json or binary
+    pub trajectory: PathBuf,
+
+    /// Index of game in binary trajectory, without it last game is shown
+    #[arg(short = 'g', long = "game")]
+    pub game: Option<usize>,
+
+    /// Show only encounters of these agents, metrics are computed for them
+    #[arg(short = 'a', long = "agent", value_delimiter = ',')]
+    pub agents: Vec<usize>,
+
+    /// First round shown in table
+    #[arg(long = "from-round", default_value = "0")]
+    pub from_round: usize,
+
+    /// Round after last shown in table, without it table ends with game
+    #[arg(long = "to-round")]
+    pub to_round: Option<usize>,
+
+    /// Skip printing table of rounds
+    #[arg(short = 'q', long = "quiet")]
+    pub quiet: bool,
+
+    /// Directory for metrics and plots, without it results/trajectory_viewer/<trajectory name> is used
+    #[arg(short = 'O', long = "output")]
+    pub output: Option<PathBuf>,
+
+    /// Do not write metrics and plots
+    #[arg(long = "no-plots")]
+    pub no_plots: bool,
+
+    #[arg(long = "plot-format", value_enum, default_value = "svg")]
+    pub plot_format: PlotFormat,
+
+    #[arg(long = "plot-width", default_value = "400")]
+    pub plot_width: u32,
+
+    #[arg(long = "plot-height", default_value = "300")]
+    pub plot_height: u32,
+}
@@ -0,0 +1,320 @@
+use std::collections::{HashMap, HashSet};
+use std::fmt::Debug;
+use std::sync::{Arc, Mutex, PoisonError};
+use log::{debug, trace};
+use amfiteatr_core::agent::{EvaluatedInformationSet, InformationSet, Policy};
+use amfiteatr_core::comm::{BroadcastingEnvironmentAdapter, EnvironmentAdapter};
+use amfiteatr_core::domain::{AgentMessage, DomainParameters, EnvironmentMessage};
+use amfiteatr_core::env::ListPlayers;
+use amfiteatr_core::error::CommunicationError;
+use amfiteatr_rl::policy::{ActorCriticPolicy, LearningNetworkPolicy};
+use amfiteatr_rl::tch::{self, Kind, Tensor};
+use amfiteatr_rl::error::TensorRepresentationError;
+use amfiteatr_rl::tensor_data::{ActionTensor, ConversionToTensor, ConvertToTensor};
+use amfiteatr_classic::agent::LocalHistoryInfoSet;
+use amfiteatr_classic::domain::UsizeAgentId;
+use crate::guard::InfoSetBuilder;
+
+/// Conversion appending one-hot index of agent's head to tensor made by inner conversion, so
+/// network with shared trunk can pick output heads of agent. Head index is agent's id.
+/// With zero heads tensor is the same as made by inner conversion.
+#[derive(Copy, Clone, Debug)]
+pub struct HeadIndexConversion<W: ConversionToTensor>{
+    inner: W,
+    heads: usize,
+    shape: [i64; 1],
+}
+
+impl<W: ConversionToTensor> HeadIndexConversion<W>{
+    pub fn new(inner: W, heads: usize) -> Self{
+        let shape = [inner.desired_shape_flatten() + heads as i64];
+        Self{inner, heads, shape}
+    }
+
+    pub fn inner(&self) -> &W{
+        &self.inner
+    }
+
+    pub fn heads(&self) -> usize{
+        self.heads
+    }
+}
+
+impl<W: ConversionToTensor> Default for HeadIndexConversion<W>{
+    fn default() -> Self {
+        Self::new(W::default(), 0)
+    }
+}
+
+impl<W: ConversionToTensor> ConversionToTensor for HeadIndexConversion<W>{
+    fn desired_shape(&self) -> &[i64]{
+        &self.shape[..]
+    }
+}
+
+impl<ID: UsizeAgentId, W: ConversionToTensor> ConvertToTensor<HeadIndexConversion<W>> for LocalHistoryInfoSet<ID>
+where LocalHistoryInfoSet<ID>: ConvertToTensor<W>{
+    fn try_to_tensor(&self, way: &HeadIndexConversion<W>) -> Result<Tensor, TensorRepresentationError>{
+        let features = self.try_to_tensor_flat(&way.inner)?;
+        if way.heads == 0{
+            return Ok(features);
+        }
+        let head = self.agent_id().as_usize();
+        if head >= way.heads{
+            return Err(TensorRepresentationError::InfoSetNotFit {
+                info_set: format!("Information set of agent {} (head index)", self.agent_id()),
+                shape: Vec::from(way.desired_shape()),
+            });
+        }
+        let mut one_hot = vec![0.0f32; way.heads];
+        one_hot[head] = 1.0;
+        Ok(Tensor::cat(&[features, Tensor::from_slice(&one_hot[..])], 0))
+    }
+}
+
+/// Actor-critic policy shared by group of learning agents. Actions of all members are sampled
+/// in one forward pass on information sets mirrored by [`BatchingAdapter`], members only pick up
+/// their decisions.
+pub struct SharedA2C<DP: DomainParameters, IS: InformationSet<DP> + Debug + ConvertToTensor<W>, W: ConversionToTensor>{
+    policy: ActorCriticPolicy<DP, IS, W>,
+    conversion: W,
+    members: HashSet<DP::AgentId>,
+    decisions: HashMap<DP::AgentId, DP::ActionType>,
+    batches: usize,
+}
+
+/// Handle to [`SharedA2C`] held by members and [`BatchingAdapter`].
+pub type SharedA2CHandle<DP, IS, W> = Arc<Mutex<SharedA2C<DP, IS, W>>>;
+
+impl<DP: DomainParameters, IS: EvaluatedInformationSet<DP> + Debug + ConvertToTensor<W>, W: ConversionToTensor> SharedA2C<DP, IS, W>
+where DP::ActionType: ActionTensor{
+    /// Conversion must be the same as used by policy.
+    pub fn new(policy: ActorCriticPolicy<DP, IS, W>, conversion: W) -> Self{
+        Self{
+            policy,
+            conversion,
+            members: HashSet::new(),
+            decisions: HashMap::new(),
+            batches: 0,
+        }
+    }
+
+    pub fn into_handle(self) -> SharedA2CHandle<DP, IS, W>{
+        Arc::new(Mutex::new(self))
+    }
+
+    pub fn add_member(&mut self, agent: DP::AgentId){
+        self.members.insert(agent);
+    }
+
+    pub fn is_member(&self, agent: &DP::AgentId) -> bool{
+        self.members.contains(agent)
+    }
+
+    pub fn policy(&self) -> &ActorCriticPolicy<DP, IS, W>{
+        &self.policy
+    }
+
+    pub fn policy_mut(&mut self) -> &mut ActorCriticPolicy<DP, IS, W>{
+        &mut self.policy
+    }
+
+    /// Number of batched forward passes made so far.
+    pub fn batches(&self) -> usize{
+        self.batches
+    }
+
+    /// Samples actions for all members that have information set, in one forward pass.
+    pub fn decide(&mut self, info_sets: &HashMap<DP::AgentId, IS>){
+        let members: Vec<&DP::AgentId> = self.members.iter().filter(|m| info_sets.contains_key(m)).collect();
+        if members.is_empty(){
+            return;
+        }
+        let states: Vec<Tensor> = members.iter().map(|m| info_sets[*m].to_tensor(&self.conversion)).collect();
+        let batch = Tensor::stack(&states[..], 0);
+        let out = tch::no_grad(|| (self.policy.network().net())(&batch));
+        let actions = out.actor.softmax(-1, Kind::Float).multinomial(1, true);
+        self.decisions.clear();
+        for (i, member) in members.into_iter().enumerate(){
+            match DP::ActionType::try_from_tensor(&actions.get(i as i64)){
+                Ok(action) => {
+                    self.decisions.insert(member.clone(), action);
+                },
+                Err(e) => debug!("Failed converting batched action of agent {member}: {e}"),
+            }
+        }
+        self.batches += 1;
+        trace!("Batched decisions of {} agents", self.decisions.len());
+    }
+
+    pub fn take_decision(&mut self, agent: &DP::AgentId) -> Option<DP::ActionType>{
+        self.decisions.remove(agent)
+    }
+
+    pub fn clear_decisions(&mut self){
+        self.decisions.clear();
+    }
+}
+
+/// Policy of agent in group sharing [`SharedA2C`]. Decision made in batch is used if present,
+/// otherwise (e.g. when probing policy outside of game) shared network is evaluated on given
+/// information set.
+pub struct SharedA2CMember<DP: DomainParameters, IS: InformationSet<DP> + Debug + ConvertToTensor<W>, W: ConversionToTensor>{
+    shared: SharedA2CHandle<DP, IS, W>,
+}
+
+impl<DP: DomainParameters, IS: InformationSet<DP> + Debug + ConvertToTensor<W>, W: ConversionToTensor> SharedA2CMember<DP, IS, W>{
+    pub fn new(shared: SharedA2CHandle<DP, IS, W>) -> Self{
+        Self{shared}
+    }
+
+    pub fn shared(&self) -> &SharedA2CHandle<DP, IS, W>{
+        &self.shared
+    }
+}
+
+impl<DP: DomainParameters, IS: EvaluatedInformationSet<DP> + Debug + ConvertToTensor<W>, W: ConversionToTensor> Policy<DP> for SharedA2CMember<DP, IS, W>
+where DP::ActionType: ActionTensor{
+    type InfoSetType = IS;
+
+    fn select_action(&self, state: &Self::InfoSetType) -> Option<DP::ActionType>{
+        let mut shared = self.shared.lock().unwrap_or_else(PoisonError::into_inner);
+        shared.take_decision(state.agent_id())
+            .or_else(|| shared.policy().select_action(state))
+    }
+}
+
+/// Policy of learning agent, either with own network or member of group sharing one.
+pub enum LearnerPolicy<DP: DomainParameters, IS: InformationSet<DP> + Debug + ConvertToTensor<W>, W: ConversionToTensor>{
+    Own(ActorCriticPolicy<DP, IS, W>),
+    Shared(SharedA2CMember<DP, IS, W>),
+}
+
+impl<DP: DomainParameters, IS: EvaluatedInformationSet<DP> + Debug + ConvertToTensor<W>, W: ConversionToTensor> Policy<DP> for LearnerPolicy<DP, IS, W>
+where DP::ActionType: ActionTensor{
+    type InfoSetType = IS;
+
+    fn select_action(&self, state: &Self::InfoSetType) -> Option<DP::ActionType>{
+        match self{
+            LearnerPolicy::Own(policy) => policy.select_action(state),
+            LearnerPolicy::Shared(member) => member.select_action(state),
+        }
+    }
+}
+
+/// Environment adapter mirroring information sets of [`SharedA2C`] members. When member is asked
+/// to move after any of them got update, decisions of all members are made in one batch.
+/// In pairing games updates come at the end of round, so there is one forward pass per round.
+///
+/// Without shared network messages are just passed to inner adapter.
+pub struct BatchingAdapter<DP: DomainParameters, A, IS: InformationSet<DP> + Debug + ConvertToTensor<W>, W: ConversionToTensor>{
+    adapter: A,
+    shared: Option<SharedA2CHandle<DP, IS, W>>,
+    info_set: InfoSetBuilder<DP, IS>,
+    info_sets: HashMap<DP::AgentId, IS>,
+    outdated: bool,
+}
+
+impl<DP: DomainParameters, A, IS: EvaluatedInformationSet<DP> + Debug + ConvertToTensor<W>, W: ConversionToTensor> BatchingAdapter<DP, A, IS, W>
+where DP::ActionType: ActionTensor{
+    pub fn new(adapter: A, shared: Option<SharedA2CHandle<DP, IS, W>>, info_set: InfoSetBuilder<DP, IS>) -> Self{
+        Self{
+            adapter,
+            shared,
+            info_set,
+            info_sets: HashMap::new(),
+            outdated: true,
+        }
+    }
+
+    pub fn inner(&self) -> &A{
+        &self.adapter
+    }
+
+    fn observe(&mut self, agent: &DP::AgentId, message: &EnvironmentMessage<DP>){
+        let shared = match &self.shared{
+            Some(shared) => shared,
+            None => return,
+        };
+        match message{
+            EnvironmentMessage::UpdateState(update) => {
+                if !shared.lock().unwrap_or_else(PoisonError::into_inner).is_member(agent){
+                    return;
+                }
+                let mirrored = self.info_sets.entry(agent.clone()).or_insert_with(|| (self.info_set)(agent));
+                if let Err(e) = mirrored.update(update.clone()){
+                    debug!("Failed updating mirrored information set of agent {agent}: {e}");
+                }
+                self.outdated = true;
+            },
+            EnvironmentMessage::YourMove if self.outdated => {
+                let mut shared = shared.lock().unwrap_or_else(PoisonError::into_inner);
+                if !shared.is_member(agent){
+                    return;
+                }
+                for member in shared.members.iter(){
+                    if !self.info_sets.contains_key(member){
+                        self.info_sets.insert(member.clone(), (self.info_set)(member));
+                    }
+                }
+                shared.decide(&self.info_sets);
+                self.outdated = false;
+            },
+            _ => {}
+        }
+    }
+
+    fn end_game(&mut self){
+        self.info_sets.clear();
+        self.outdated = true;
+        if let Some(shared) = &self.shared{
+            shared.lock().unwrap_or_else(PoisonError::into_inner).clear_decisions();
+        }
+    }
+}
+
+fn ends_game<DP: DomainParameters>(message: &EnvironmentMessage<DP>) -> bool{
+    matches!(message, EnvironmentMessage::GameFinished | EnvironmentMessage::GameFinishedWithIllegalAction(_)
+        | EnvironmentMessage::Kill | EnvironmentMessage::ErrorNotify(_))
+}
+
+impl<DP: DomainParameters, A: EnvironmentAdapter<DP>, IS: EvaluatedInformationSet<DP> + Debug + ConvertToTensor<W>, W: ConversionToTensor> EnvironmentAdapter<DP> for BatchingAdapter<DP, A, IS, W>
+where DP::ActionType: ActionTensor{
+    fn send(&mut self, agent: &DP::AgentId, message: EnvironmentMessage<DP>) -> Result<(), CommunicationError<DP>>{
+        self.observe(agent, &message);
+        if ends_game(&message){
+            self.end_game();
+        }
+        self.adapter.send(agent, message)
+    }
+
+    fn receive_blocking(&mut self) -> Result<(DP::AgentId, AgentMessage<DP>), CommunicationError<DP>>{
+        self.adapter.receive_blocking()
+    }
+
+    fn receive_non_blocking(&mut self) -> Result<Option<(DP::AgentId, AgentMessage<DP>)>, CommunicationError<DP>>{
+        self.adapter.receive_non_blocking()
+    }
+
+    fn is_agent_connected(&self, agent_id: &DP::AgentId) -> bool{
+        self.adapter.is_agent_connected(agent_id)
+    }
+}
+
+impl<DP: DomainParameters, A: BroadcastingEnvironmentAdapter<DP>, IS: EvaluatedInformationSet<DP> + Debug + ConvertToTensor<W>, W: ConversionToTensor> BroadcastingEnvironmentAdapter<DP> for BatchingAdapter<DP, A, IS, W>
+where DP::ActionType: ActionTensor{
+    fn send_all(&mut self, message: EnvironmentMessage<DP>) -> Result<(), CommunicationError<DP>>{
+        if ends_game(&message){
+            self.end_game();
+        }
+        self.adapter.send_all(message)
+    }
+}
+
+impl<DP: DomainParameters, A: ListPlayers<DP>, IS: InformationSet<DP> + Debug + ConvertToTensor<W>, W: ConversionToTensor> ListPlayers<DP> for BatchingAdapter<DP, A, IS, W>{
+    type IterType = A::IterType;
+
+    fn players(&self) -> Self::IterType{
+        self.adapter.players()
+    }
+}
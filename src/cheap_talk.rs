@@ -0,0 +1,519 @@
+use std::fmt::{Display, Formatter};
+use std::marker::PhantomData;
+use std::sync::Arc;
+use clap::ValueEnum;
+use log::debug;
+use serde::Serialize;
+use amfiteatr_core::agent::{AgentTraceStep, EvaluatedInformationSet, InformationSet, Policy, PresentPossibleActions, Trajectory};
+use amfiteatr_core::domain::{Action, DomainParameters, Renew};
+use amfiteatr_core::env::{EnvironmentStateSequential, EnvironmentStateUniScore};
+use amfiteatr_core::error::{AmfiError, ConvertError};
+use amfiteatr_classic::AsymmetricRewardTableInt;
+use amfiteatr_classic::domain::{AgentNum, ClassicAction, ClassicGameError, ClassicGameUpdate, IntReward, UsizeAgentId};
+use amfiteatr_classic::env::PairingVec;
+use amfiteatr_rl::error::TensorRepresentationError;
+use amfiteatr_rl::tch::Tensor;
+use amfiteatr_rl::tensor_data::{ActionTensor, ConversionToTensor, ConvertToTensor};
+use crate::pairing::GameState;
+use crate::pairing::heterogeneous::encounter_reward;
+
+/// Index of decision interpreted by stage: in message stage it is symbol of vocabulary, in play
+/// stage `0` is `Up` (defection) and `1` is `Down` (cooperation), as in tensor form of [`ClassicAction`].
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash, Serialize)]
+pub struct TalkAction(pub u8);
+
+impl TalkAction{
+    pub fn play(action: ClassicAction) -> Self{
+        match action{
+            ClassicAction::Up => TalkAction(0),
+            ClassicAction::Down => TalkAction(1),
+        }
+    }
+
+    /// Action of play stage, `None` for indexes other than `0` and `1`.
+    pub fn as_classic(&self) -> Option<ClassicAction>{
+        match self.0{
+            0 => Some(ClassicAction::Up),
+            1 => Some(ClassicAction::Down),
+            _ => None,
+        }
+    }
+}
+
+impl Display for TalkAction{
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+impl Action for TalkAction{}
+
+impl ActionTensor for TalkAction{
+    fn to_tensor(&self) -> Tensor {
+        Tensor::from_slice(&[self.0 as f32])
+    }
+
+    fn try_from_tensor(t: &Tensor) -> Result<Self, ConvertError> {
+        let v: Vec<i64> = Vec::try_from(t)
+            .map_err(|_| ConvertError::ActionDeserialize(format!("{t}")))?;
+        v.first().and_then(|i| u8::try_from(*i).ok())
+            .map(TalkAction)
+            .ok_or_else(|| ConvertError::ActionDeserialize(format!("{t}")))
+    }
+}
+
+#[derive(Serialize, Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Stage{
+    Message,
+    Play,
+}
+
+#[derive(thiserror::Error, Debug, Clone, PartialEq)]
+pub enum CheapTalkError<ID: UsizeAgentId>{
+    #[error("Order in game was violated. Expected player: {expected:?} given: {acted:}")]
+    GameViolatedOrder{
+        acted: ID,
+        expected: Option<ID>
+    },
+    #[error("Player: {0} played after GameOver")]
+    ActionAfterGameOver(ID),
+    #[error("Player: {agent} made invalid action {action} in {stage:?} stage")]
+    InvalidAction{
+        agent: ID,
+        action: TalkAction,
+        stage: Stage,
+    },
+    #[error(transparent)]
+    Game(#[from] ClassicGameError<ID>),
+}
+
+#[derive(Clone, Debug, Serialize)]
+pub struct CheapTalkDomain<ID: UsizeAgentId>{
+    _id: PhantomData<ID>
+}
+
+pub type CheapTalkDomainNumbered = CheapTalkDomain<AgentNum>;
+
+#[derive(Clone, Debug)]
+pub enum CheapTalkUpdate<ID: UsizeAgentId>{
+    /// Pairings of current round and messages of all agents, sent after message stage
+    Messages{
+        pairings: Arc<PairingVec<ID>>,
+        messages: Arc<Vec<u8>>,
+    },
+    /// Update of classic game sent when round is finished
+    Round(ClassicGameUpdate<ID>),
+}
+
+impl<ID: UsizeAgentId> DomainParameters for CheapTalkDomain<ID>{
+    type ActionType = TalkAction;
+    type GameErrorType = CheapTalkError<ID>;
+    type UpdateType = CheapTalkUpdate<ID>;
+    type AgentId = ID;
+    type UniversalReward = IntReward;
+}
+
+impl<ID: UsizeAgentId> From<CheapTalkError<ID>> for AmfiError<CheapTalkDomain<ID>>{
+    fn from(value: CheapTalkError<ID>) -> Self {
+        AmfiError::Game(value)
+    }
+}
+
+fn first_stage(vocabulary: Option<u8>) -> Stage{
+    match vocabulary{
+        Some(_) => Stage::Message,
+        None => Stage::Play,
+    }
+}
+
+/// Pairing game of [`GameState`] with optional message stage. When `vocabulary` is set, at the
+/// start of every round each player sends one symbol observed by its partner, then round of
+/// inner game is played, with its noise, length and reward tables. Messages cost nothing and
+/// bind to nothing.
+#[derive(Clone, Debug, Serialize)]
+pub struct CheapTalkState<ID: UsizeAgentId>{
+    inner: GameState<ID>,
+    vocabulary: Option<u8>,
+    stage: Stage,
+    messages: Vec<u8>,
+}
+
+impl<ID: UsizeAgentId> CheapTalkState<ID>{
+    pub fn new(inner: GameState<ID>, vocabulary: Option<u8>) -> Self{
+        let players = inner.inner().inner().inner().players();
+        Self{inner, vocabulary, stage: first_stage(vocabulary), messages: Vec::with_capacity(players)}
+    }
+
+    pub fn inner(&self) -> &GameState<ID>{
+        &self.inner
+    }
+
+    pub fn vocabulary(&self) -> Option<u8>{
+        self.vocabulary
+    }
+
+    fn players(&self) -> usize{
+        self.inner.inner().inner().inner().players()
+    }
+}
+
+impl<ID: UsizeAgentId> Display for CheapTalkState<ID>{
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.inner)
+    }
+}
+
+impl<ID: UsizeAgentId> EnvironmentStateSequential<CheapTalkDomain<ID>> for CheapTalkState<ID>{
+    type Updates = Vec<(ID, CheapTalkUpdate<ID>)>;
+
+    fn current_player(&self) -> Option<ID> {
+        match (self.stage, self.is_finished()){
+            (_, true) => None,
+            (Stage::Message, false) => Some(ID::make_from_usize(self.messages.len())),
+            (Stage::Play, false) => self.inner.current_player(),
+        }
+    }
+
+    fn is_finished(&self) -> bool {
+        self.inner.is_finished()
+    }
+
+    fn forward(&mut self, agent: ID, action: TalkAction) -> Result<Self::Updates, CheapTalkError<ID>> {
+        let invalid = CheapTalkError::InvalidAction{agent, action, stage: self.stage};
+        match self.stage{
+            Stage::Message => {
+                let expected = self.current_player().ok_or(CheapTalkError::ActionAfterGameOver(agent))?;
+                if expected != agent{
+                    return Err(CheapTalkError::GameViolatedOrder{acted: agent, expected: Some(expected)});
+                }
+                if action.0 >= self.vocabulary.unwrap_or(0){
+                    return Err(invalid);
+                }
+                self.messages.push(action.0);
+                if self.messages.len() < self.players(){
+                    return Ok(Vec::new());
+                }
+                self.stage = Stage::Play;
+                let update = CheapTalkUpdate::Messages{
+                    pairings: Arc::new(self.inner.inner().inner().inner().actual_pairings().clone()),
+                    messages: Arc::new(std::mem::take(&mut self.messages)),
+                };
+                Ok((0..self.players()).map(|i| (ID::make_from_usize(i), update.clone())).collect())
+            },
+            Stage::Play => {
+                let updates = self.inner.forward(agent, action.as_classic().ok_or(invalid)?)?;
+                if !updates.is_empty(){
+                    debug!("Finished round with messages: {:?}", self.vocabulary.is_some());
+                    self.stage = first_stage(self.vocabulary);
+                }
+                Ok(updates.into_iter().map(|(id, update)| (id, CheapTalkUpdate::Round(update))).collect())
+            }
+        }
+    }
+}
+
+impl<ID: UsizeAgentId> EnvironmentStateUniScore<CheapTalkDomain<ID>> for CheapTalkState<ID>{
+    fn state_score_of_player(&self, agent: &ID) -> IntReward {
+        self.inner.state_score_of_player(agent)
+    }
+}
+
+impl<ID: UsizeAgentId> Renew<()> for CheapTalkState<ID>{
+    fn renew_from(&mut self, base: ()) {
+        self.inner.renew_from(base);
+        self.stage = first_stage(self.vocabulary);
+        self.messages.clear();
+    }
+}
+
+/// What agent knows about one encounter.
+#[derive(Serialize, Copy, Clone, Debug)]
+pub struct TalkMemory{
+    pub own_message: Option<u8>,
+    pub partner_message: Option<u8>,
+    pub own_action: ClassicAction,
+    pub partner_action: ClassicAction,
+    pub payoff: IntReward,
+}
+
+/// Information set of agent remembering messages and actions of own encounters, as they were
+/// reported (with noise of observation). Payoffs are evaluated with agent's own reward table.
+#[derive(Clone, Debug, Serialize)]
+pub struct CheapTalkInfoSet<ID: UsizeAgentId>{
+    id: ID,
+    reward_table: AsymmetricRewardTableInt,
+    vocabulary: Option<u8>,
+    stage: Stage,
+    own_message: Option<u8>,
+    partner_message: Option<u8>,
+    history: Vec<TalkMemory>,
+    payoff: IntReward,
+}
+
+impl<ID: UsizeAgentId> CheapTalkInfoSet<ID>{
+    pub fn new(id: ID, reward_table: AsymmetricRewardTableInt, vocabulary: Option<u8>) -> Self{
+        Self{
+            id, reward_table, vocabulary,
+            stage: first_stage(vocabulary),
+            own_message: None,
+            partner_message: None,
+            history: Vec::new(),
+            payoff: 0,
+        }
+    }
+
+    /// Stage in which agent makes its next decision.
+    pub fn stage(&self) -> Stage{
+        self.stage
+    }
+
+    /// Message of partner in current round, known in play stage.
+    pub fn partner_message(&self) -> Option<u8>{
+        self.partner_message
+    }
+
+    pub fn history(&self) -> &[TalkMemory]{
+        &self.history[..]
+    }
+}
+
+impl<ID: UsizeAgentId> Display for CheapTalkInfoSet<ID>{
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Agent: {}, stage: {:?}, partner message: {:?}, rounds: {}, payoff: {}",
+            self.id, self.stage, self.partner_message, self.history.len(), self.payoff)
+    }
+}
+
+impl<ID: UsizeAgentId> InformationSet<CheapTalkDomain<ID>> for CheapTalkInfoSet<ID>{
+    fn agent_id(&self) -> &ID {
+        &self.id
+    }
+
+    fn is_action_valid(&self, action: &TalkAction) -> bool {
+        match self.stage{
+            Stage::Message => action.0 < self.vocabulary.unwrap_or(0),
+            Stage::Play => action.as_classic().is_some(),
+        }
+    }
+
+    fn update(&mut self, update: CheapTalkUpdate<ID>) -> Result<(), CheapTalkError<ID>> {
+        match update{
+            CheapTalkUpdate::Messages{pairings, messages} => {
+                let partner = pairings[self.id.as_usize()].paired_player;
+                self.own_message = messages.get(self.id.as_usize()).copied();
+                self.partner_message = messages.get(partner.as_usize()).copied();
+                self.stage = Stage::Play;
+            },
+            CheapTalkUpdate::Round(update) => {
+                let report = update.encounters.get(&self.id)
+                    .ok_or(CheapTalkError::Game(ClassicGameError::EncounterNotReported(self.id.as_usize() as AgentNum)))?;
+                let payoff = encounter_reward(&self.reward_table, report);
+                self.history.push(TalkMemory{
+                    own_message: self.own_message.take(),
+                    partner_message: self.partner_message.take(),
+                    own_action: report.own_action,
+                    partner_action: report.other_player_action,
+                    payoff,
+                });
+                self.payoff += payoff;
+                self.stage = first_stage(self.vocabulary);
+            }
+        }
+        Ok(())
+    }
+}
+
+impl<ID: UsizeAgentId> PresentPossibleActions<CheapTalkDomain<ID>> for CheapTalkInfoSet<ID>{
+    type ActionIteratorType = Vec<TalkAction>;
+
+    fn available_actions(&self) -> Self::ActionIteratorType {
+        match self.stage{
+            Stage::Message => (0..self.vocabulary.unwrap_or(0)).map(TalkAction).collect(),
+            Stage::Play => vec![TalkAction(0), TalkAction(1)],
+        }
+    }
+}
+
+impl<ID: UsizeAgentId> EvaluatedInformationSet<CheapTalkDomain<ID>> for CheapTalkInfoSet<ID>{
+    type RewardType = IntReward;
+
+    fn current_subjective_score(&self) -> Self::RewardType {
+        self.payoff
+    }
+
+    fn penalty_for_illegal(&self) -> Self::RewardType {
+        -100
+    }
+}
+
+impl<ID: UsizeAgentId> Renew<()> for CheapTalkInfoSet<ID>{
+    fn renew_from(&mut self, _base: ()) {
+        self.stage = first_stage(self.vocabulary);
+        self.own_message = None;
+        self.partner_message = None;
+        self.history.clear();
+        self.payoff = 0;
+    }
+}
+
+/// Represents current stage and messages of current round (one-hot) followed by history of
+/// rounds: one-hot own and partner's message and both actions (`-1` for rounds not yet played).
+#[derive(Copy, Clone, Debug, Default)]
+pub struct CheapTalkConversion{
+    vocabulary: usize,
+    rounds: usize,
+    shape: [i64; 1],
+}
+
+impl CheapTalkConversion{
+    pub fn new(vocabulary: Option<u8>, rounds: usize) -> Self{
+        let vocabulary = vocabulary.unwrap_or(0) as usize;
+        Self{vocabulary, rounds, shape: [(1 + 2 * vocabulary + rounds * (2 * vocabulary + 2)) as i64]}
+    }
+
+    fn round_values(&self) -> usize{
+        2 * self.vocabulary + 2
+    }
+}
+
+impl ConversionToTensor for CheapTalkConversion{
+    fn desired_shape(&self) -> &[i64] {
+        &self.shape[..]
+    }
+}
+
+impl<ID: UsizeAgentId> ConvertToTensor<CheapTalkConversion> for CheapTalkInfoSet<ID>{
+    fn try_to_tensor(&self, way: &CheapTalkConversion) -> Result<Tensor, TensorRepresentationError> {
+        if self.history.len() > way.rounds{
+            return Err(TensorRepresentationError::InfoSetNotFit {
+                info_set: format!("Cheap talk history of length {}", self.history.len()),
+                shape: Vec::from(way.desired_shape()),
+            });
+        }
+        let v = way.vocabulary;
+        let mut values = vec![0.0f32; way.shape[0] as usize];
+        let mut one_hot = |offset: usize, message: Option<u8>|{
+            if let Some(m) = message.filter(|m| (*m as usize) < v){
+                values[offset + m as usize] = 1.0;
+            }
+        };
+        one_hot(1, self.own_message);
+        one_hot(1 + v, self.partner_message);
+        let history_offset = 1 + 2 * v;
+        for (i, round) in self.history.iter().enumerate(){
+            let offset = history_offset + i * way.round_values();
+            one_hot(offset, round.own_message);
+            one_hot(offset + v, round.partner_message);
+        }
+        values[0] = match self.stage{
+            Stage::Message => 0.0,
+            Stage::Play => 1.0,
+        };
+        for i in 0..way.rounds{
+            let offset = history_offset + i * way.round_values() + 2 * v;
+            let (own, partner) = match self.history.get(i){
+                Some(round) => (TalkAction::play(round.own_action).0 as f32, TalkAction::play(round.partner_action).0 as f32),
+                None => (-1.0, -1.0),
+            };
+            values[offset] = own;
+            values[offset + 1] = partner;
+        }
+        Ok(Tensor::f_from_slice(&values[..])?)
+    }
+}
+
+/// Scripted behaviour. By convention of scripted agents symbol `1` announces cooperation and
+/// `0` announces defection.
+#[derive(Serialize, Copy, Clone, Debug, PartialEq, Eq, ValueEnum)]
+pub enum TalkStrategy{
+    /// Announces and plays cooperation
+    Cooperator,
+    /// Announces and plays defection
+    Defector,
+    /// Announces cooperation and defects
+    Liar,
+    /// Announces cooperation and cooperates when partner announced it too
+    Truster,
+}
+
+pub struct TalkPolicy<ID: UsizeAgentId>{
+    strategy: TalkStrategy,
+    _id: PhantomData<ID>,
+}
+
+impl<ID: UsizeAgentId> TalkPolicy<ID>{
+    pub fn new(strategy: TalkStrategy) -> Self{
+        Self{strategy, _id: PhantomData}
+    }
+}
+
+impl<ID: UsizeAgentId> Policy<CheapTalkDomain<ID>> for TalkPolicy<ID>{
+    type InfoSetType = CheapTalkInfoSet<ID>;
+
+    fn select_action(&self, state: &Self::InfoSetType) -> Option<TalkAction> {
+        let cooperation = match (state.stage(), self.strategy){
+            (Stage::Message, TalkStrategy::Defector) => false,
+            (Stage::Message, _) => true,
+            (Stage::Play, TalkStrategy::Cooperator) => true,
+            (Stage::Play, TalkStrategy::Defector | TalkStrategy::Liar) => false,
+            (Stage::Play, TalkStrategy::Truster) => state.partner_message().map(|m| m == 1).unwrap_or(true),
+        };
+        match (state.stage(), cooperation){
+            (Stage::Message, _) => state.available_actions().into_iter().nth(cooperation as usize),
+            (Stage::Play, true) => Some(TalkAction::play(ClassicAction::Down)),
+            (Stage::Play, false) => Some(TalkAction::play(ClassicAction::Up)),
+        }
+    }
+}
+
+/// Policy using separate inner policies for messages and actions, so that learning agent has
+/// networks with output for every symbol and for every action.
+pub struct StagePolicy<P>{
+    pub messaging: P,
+    pub acting: P,
+}
+
+impl<ID: UsizeAgentId, P: Policy<CheapTalkDomain<ID>, InfoSetType = CheapTalkInfoSet<ID>>> Policy<CheapTalkDomain<ID>> for StagePolicy<P>{
+    type InfoSetType = CheapTalkInfoSet<ID>;
+
+    fn select_action(&self, state: &Self::InfoSetType) -> Option<TalkAction> {
+        match state.stage(){
+            Stage::Message => self.messaging.select_action(state),
+            Stage::Play => self.acting.select_action(state),
+        }
+    }
+}
+
+pub type TalkTrajectory<ID> = Trajectory<CheapTalkDomain<ID>, CheapTalkInfoSet<ID>>;
+
+/// Splits trajectories into steps of message stage and steps of play stage, for training
+/// [`StagePolicy`]. Message does not change score by itself, so every message step is credited
+/// with score after the play step following it.
+pub fn split_by_stage<ID: UsizeAgentId>(trajectories: &[TalkTrajectory<ID>]) -> (Vec<TalkTrajectory<ID>>, Vec<TalkTrajectory<ID>>){
+    let mut messaging = Vec::with_capacity(trajectories.len());
+    let mut acting = Vec::with_capacity(trajectories.len());
+    for trajectory in trajectories{
+        let mut messages = Trajectory::new();
+        let mut actions = Trajectory::new();
+        let steps = trajectory.list();
+        for (i, step) in steps.iter().enumerate(){
+            match step.step_info_set().stage(){
+                Stage::Message => {
+                    let credited = steps.get(i + 1).unwrap_or(step);
+                    messages.push_trace_step(AgentTraceStep::new(
+                        step.step_info_set().clone(), *step.taken_action(),
+                        *step.universal_score_before(), *credited.universal_score_after(),
+                        *step.subjective_score_before(), *credited.subjective_score_after()));
+                },
+                Stage::Play => actions.push_trace_step(step.clone()),
+            }
+        }
+        if let Some(info_set) = trajectory.final_information_set(){
+            messages.finalize(info_set.clone());
+            actions.finalize(info_set.clone());
+        }
+        messaging.push(messages);
+        acting.push(actions);
+    }
+    (messaging, acting)
+}
@@ -0,0 +1,220 @@
+use std::fmt::{Debug, Display, Formatter};
+use log::debug;
+use plotters::style::colors;
+use serde::Serialize;
+use amfiteatr_core::agent::{AgentTraceStep, EvaluatedInformationSet, Trajectory};
+use amfiteatr_core::domain::DomainParameters;
+use amfiteatr_core::error::AmfiError;
+use amfiteatr_rl::error::AmfiRLError;
+use amfiteatr_rl::policy::{ActorCriticPolicy, LearningNetworkPolicy};
+use amfiteatr_rl::tch::{Kind, Tensor};
+use amfiteatr_rl::tensor_data::{ActionTensor, ConversionToTensor, ConvertToTensor};
+use amfiteatr_rl::torch_net::TensorA2C;
+use crate::plots::{Axis, AxisConfig, LineStyle, Panel, Plot, PlotSeries};
+
+/// Loss terms and statistics of one A2C update, evaluated on training batch before the update.
+#[derive(Serialize, Copy, Clone, Debug, Default, PartialEq)]
+pub struct A2CDiagnostics{
+    pub actor_loss: f32,
+    pub critic_loss: f32,
+    pub entropy: f32,
+    /// `1 - Var(returns - values) / Var(returns)`, `0` when returns do not vary.
+    pub explained_variance: f32,
+    /// Norm of all gradients before clipping.
+    pub grad_norm: f32,
+}
+
+impl A2CDiagnostics{
+    /// Average of diagnostics of several agents, `None` if there are none.
+    pub fn mean(diagnostics: &[A2CDiagnostics]) -> Option<Self>{
+        if diagnostics.is_empty(){
+            return None
+        }
+        let n = diagnostics.len() as f32;
+        let sum = |f: fn(&A2CDiagnostics) -> f32| diagnostics.iter().map(f).sum::<f32>() / n;
+        Some(Self{
+            actor_loss: sum(|d| d.actor_loss),
+            critic_loss: sum(|d| d.critic_loss),
+            entropy: sum(|d| d.entropy),
+            explained_variance: sum(|d| d.explained_variance),
+            grad_norm: sum(|d| d.grad_norm),
+        })
+    }
+}
+
+impl Display for A2CDiagnostics{
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "actor loss: {:.4}, critic loss: {:.4}, entropy: {:.4}, explained variance: {:.4}, gradient norm: {:.4}",
+            self.actor_loss, self.critic_loss, self.entropy, self.explained_variance, self.grad_norm)
+    }
+}
+
+fn scalar<DP: DomainParameters>(tensor: &Tensor, context: &str) -> Result<f32, AmfiRLError<DP>>{
+    tensor.f_double_value(&[]).map(|v| v as f32).map_err(|error| AmfiRLError::Torch {
+        error,
+        context: context.to_string(),
+    })
+}
+
+/// Computes [`A2CDiagnostics`] of update that [`ActorCriticPolicy::train_on_trajectories`] would
+/// make on these trajectories. Batch and loss are built the same way as in training.
+/// Gradients are computed and cleared afterwards, network parameters are not changed.
+///
+/// Conversion of information sets must be given, because policy does not expose it.
+pub fn a2c_diagnostics<
+    DP: DomainParameters,
+    InfoSet: EvaluatedInformationSet<DP> + Debug + ConvertToTensor<W>,
+    W: ConversionToTensor,
+    R: Fn(&AgentTraceStep<DP, InfoSet>) -> Tensor
+>(
+    policy: &ActorCriticPolicy<DP, InfoSet, W>,
+    conversion: &W,
+    trajectories: &[Trajectory<DP, InfoSet>],
+    reward_f: R) -> Result<A2CDiagnostics, AmfiRLError<DP>>
+where <DP as DomainParameters>::ActionType: ActionTensor{
+
+    let device = policy.network().device();
+    let gamma = policy.config().gamma;
+    let mut states = Vec::new();
+    let mut actions = Vec::new();
+    let mut returns = Vec::new();
+    for t in trajectories{
+        let Some(last) = t.list().last() else {
+            continue;
+        };
+        let mut discounted = Tensor::zeros(reward_f(last).size(), (Kind::Float, device));
+        let mut trajectory_returns = Vec::with_capacity(t.list().len());
+        for step in t.list().iter().rev(){
+            discounted = reward_f(step).to_device(device) + discounted * gamma;
+            trajectory_returns.push(discounted.shallow_clone());
+        }
+        trajectory_returns.reverse();
+        states.extend(t.list().iter().map(|step| step.step_info_set().to_tensor(conversion)));
+        actions.extend(t.list().iter().map(|step| step.taken_action().to_tensor().to_kind(Kind::Int64)));
+        returns.append(&mut trajectory_returns);
+    }
+    if states.is_empty(){
+        return Err(AmfiError::Custom("No steps in trajectories to compute training diagnostics".into()).into())
+    }
+    debug!("Computing A2C diagnostics on batch of {} steps", states.len());
+    let states_batch = Tensor::stack(&states[..], 0).to_device(device);
+    let returns_batch = Tensor::stack(&returns[..], 0).to_device(device);
+    let action_batch = Tensor::stack(&actions[..], 0).to_device(device);
+
+    let TensorA2C{actor, critic} = (policy.network().net())(&states_batch);
+    let log_probs = actor.log_softmax(-1, Kind::Float);
+    let probs = actor.softmax(-1, Kind::Float);
+    let action_log_probs = log_probs.gather(1, &action_batch, false);
+    let entropy = (-&log_probs * &probs).sum_dim_intlist(-1, false, Kind::Float).mean(Kind::Float);
+    let advantages = &returns_batch - &critic;
+    let critic_loss = (&advantages * &advantages).mean(Kind::Float);
+    let actor_loss = (-advantages.detach() * action_log_probs).mean(Kind::Float);
+
+    let returns_variance = scalar(&returns_batch.var(true), "variance of returns")?;
+    let explained_variance = match returns_variance > f32::EPSILON{
+        true => 1.0 - scalar(&advantages.var(true), "variance of advantages")? / returns_variance,
+        false => 0.0,
+    };
+
+    let loss: Tensor = &critic_loss * 0.5 + &actor_loss - &entropy * 0.01;
+    let mut variables = policy.var_store().trainable_variables();
+    for v in variables.iter_mut(){
+        v.zero_grad();
+    }
+    loss.backward();
+    let mut squared_norm = 0.0;
+    for v in variables.iter_mut(){
+        let grad = v.grad();
+        if grad.defined(){
+            squared_norm += scalar(&grad.norm(), "gradient norm")?.powi(2);
+        }
+        v.zero_grad();
+    }
+
+    Ok(A2CDiagnostics{
+        actor_loss: scalar(&actor_loss, "actor loss")?,
+        critic_loss: scalar(&critic_loss, "critic loss")?,
+        entropy: scalar(&entropy, "entropy")?,
+        explained_variance,
+        grad_norm: squared_norm.sqrt(),
+    })
+}
+
+/// Trains policy with [`LearningNetworkPolicy::train_on_trajectories`]. When `diagnostics` is
+/// set, [`A2CDiagnostics`] of this update are returned. Computing them takes additional forward
+/// and backward pass, because policy does not expose its optimizer.
+pub fn train_a2c<
+    DP: DomainParameters,
+    InfoSet: EvaluatedInformationSet<DP> + Debug + ConvertToTensor<W>,
+    W: ConversionToTensor,
+    R: Fn(&AgentTraceStep<DP, InfoSet>) -> Tensor
+>(
+    policy: &mut ActorCriticPolicy<DP, InfoSet, W>,
+    conversion: &W,
+    trajectories: &[Trajectory<DP, InfoSet>],
+    reward_f: R,
+    diagnostics: bool) -> Result<Option<A2CDiagnostics>, AmfiRLError<DP>>
+where <DP as DomainParameters>::ActionType: ActionTensor{
+    let diagnostics = match diagnostics{
+        true => Some(a2c_diagnostics(policy, conversion, trajectories, &reward_f)?),
+        false => None,
+    };
+    policy.train_on_trajectories(trajectories, reward_f)?;
+    Ok(diagnostics)
+}
+
+/// Diagnostics of consecutive training epochs.
+#[derive(Serialize, Clone, Debug, Default)]
+pub struct DiagnosticsSeries{
+    pub actor_loss: Vec<f32>,
+    pub critic_loss: Vec<f32>,
+    pub entropy: Vec<f32>,
+    pub explained_variance: Vec<f32>,
+    pub grad_norm: Vec<f32>,
+}
+
+impl DiagnosticsSeries{
+    pub fn push(&mut self, diagnostics: &A2CDiagnostics){
+        self.actor_loss.push(diagnostics.actor_loss);
+        self.critic_loss.push(diagnostics.critic_loss);
+        self.entropy.push(diagnostics.entropy);
+        self.explained_variance.push(diagnostics.explained_variance);
+        self.grad_norm.push(diagnostics.grad_norm);
+    }
+
+    pub fn is_empty(&self) -> bool{
+        self.actor_loss.is_empty()
+    }
+
+    /// Two dashboard panels: losses with entropy, and explained variance with gradient norm.
+    /// Series start from first training epoch, so x axis is shifted by one against payoffs.
+    pub fn panels(&self, plot: &Plot, title_prefix: &str) -> [Panel; 2]{
+        let losses = vec![
+            PlotSeries::new(self.actor_loss.clone(), "Actor loss", colors::RED),
+            PlotSeries::new(self.critic_loss.clone(), "Critic loss", colors::BLUE),
+            PlotSeries::new(self.entropy.clone(), "Entropy", colors::GREEN)
+                .line_style(LineStyle::Dashed)
+                .axis(Axis::Secondary),
+        ];
+        let statistics = vec![
+            PlotSeries::new(self.explained_variance.clone(), "Explained variance", colors::BLACK),
+            PlotSeries::new(self.grad_norm.clone(), "Gradient norm", colors::MAGENTA)
+                .line_style(LineStyle::Dashed)
+                .axis(Axis::Secondary),
+        ];
+        [
+            Panel::lines(plot.clone()
+                .title(&format!("{title_prefix} losses"))
+                .x_desc("Training epoch")
+                .y_desc("Loss")
+                .secondary_y(AxisConfig{description: "Entropy".to_string(), ..Default::default()}),
+                losses),
+            Panel::lines(plot.clone()
+                .title(&format!("{title_prefix} statistics"))
+                .x_desc("Training epoch")
+                .y_desc("Explained variance")
+                .secondary_y(AxisConfig{description: "Gradient norm".to_string(), ..Default::default()}),
+                statistics),
+        ]
+    }
+}
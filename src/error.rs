@@ -0,0 +1,221 @@
+use std::any::Any;
+use std::fmt::{Display, Formatter};
+use std::fs::File;
+use std::path::{Path, PathBuf};
+use std::thread::{self, ScopedJoinHandle};
+use serde::Serialize;
+use amfiteatr_core::domain::DomainParameters;
+use amfiteatr_core::error::{AmfiError, CommunicationError};
+use amfiteatr_classic::domain::{ClassicGameDomain, ClassicGameError, UsizeAgentId};
+use amfiteatr_rl::error::AmfiRLError;
+use amfiteatr_rl::tch::TchError;
+use crate::cheap_talk::{CheapTalkDomain, CheapTalkError};
+use crate::matrix::{MatrixGameDomain, MatrixGameError};
+use crate::pairing::heterogeneous::RewardTablesError;
+use crate::pairing::replay::ReplayError;
+use crate::pairing::reputation::{ReputationDomain, ReputationError};
+use crate::probe::ProbeError;
+use crate::public_goods::{PublicGoodsDomain, PublicGoodsError};
+use crate::spatial::SpatialError;
+
+/// Thread taking part in episode.
+#[derive(Clone, Debug)]
+pub enum Participant<DP: DomainParameters>{
+    Environment,
+    Agent(DP::AgentId),
+}
+
+impl<DP: DomainParameters> Display for Participant<DP>{
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self{
+            Participant::Environment => write!(f, "environment"),
+            Participant::Agent(id) => write!(f, "agent {id}"),
+        }
+    }
+}
+
+/// Reason why thread of participant did not finish episode.
+#[derive(thiserror::Error, Clone, Debug)]
+pub enum ThreadFailure<DP: DomainParameters>{
+    #[error("{0}")]
+    Error(AmfiError<DP>),
+    #[error("panicked: {0}")]
+    Panic(String),
+}
+
+#[derive(Clone, Debug)]
+pub struct ParticipantFailure<DP: DomainParameters>{
+    pub participant: Participant<DP>,
+    pub failure: ThreadFailure<DP>,
+}
+
+impl<DP: DomainParameters> Display for ParticipantFailure<DP>{
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}: {}", self.participant, self.failure)
+    }
+}
+
+/// Error stopping experiment.
+#[derive(thiserror::Error, Debug)]
+pub enum ExperimentError<DP: DomainParameters>{
+    #[error("Episode {episode} failed: {}", list_failures(.failures))]
+    Episode{
+        episode: usize,
+        failures: Vec<ParticipantFailure<DP>>,
+    },
+    #[error(transparent)]
+    Amfi(#[from] AmfiError<DP>),
+    #[error(transparent)]
+    Rl(#[from] AmfiRLError<DP>),
+    #[error("Failed setting up logger: {0}")]
+    Logger(#[from] fern::InitError),
+    #[error("Failed accessing {path:?}: {source}")]
+    Io{
+        path: PathBuf,
+        source: std::io::Error,
+    },
+    #[error("Failed writing json to {path:?}: {source}")]
+    Json{
+        path: PathBuf,
+        source: serde_json::Error,
+    },
+    #[error("Failed plotting {path:?}: {message}")]
+    Plot{
+        path: PathBuf,
+        message: String,
+    },
+    #[error("Failed probing policy: {0}")]
+    Probe(#[from] ProbeError),
+    #[error(transparent)]
+    Spatial(#[from] SpatialError),
+    #[error(transparent)]
+    RewardTables(#[from] RewardTablesError),
+    #[error(transparent)]
+    Replay(#[from] ReplayError),
+    #[error("Failed starting HTTP dashboard: {0}")]
+    Http(std::io::Error),
+}
+
+impl<DP: DomainParameters> From<CommunicationError<DP>> for ExperimentError<DP>{
+    fn from(value: CommunicationError<DP>) -> Self {
+        Self::Amfi(value.into())
+    }
+}
+
+impl<DP: DomainParameters> From<TchError> for ExperimentError<DP>{
+    fn from(value: TchError) -> Self {
+        Self::Rl(value.into())
+    }
+}
+
+impl<ID: UsizeAgentId> From<ClassicGameError<ID>> for ExperimentError<ClassicGameDomain<ID>>{
+    fn from(value: ClassicGameError<ID>) -> Self {
+        Self::Amfi(value.into())
+    }
+}
+
+impl<ID: UsizeAgentId> From<MatrixGameError<ID>> for ExperimentError<MatrixGameDomain<ID>>{
+    fn from(value: MatrixGameError<ID>) -> Self {
+        Self::Amfi(value.into())
+    }
+}
+
+impl<ID: UsizeAgentId> From<PublicGoodsError<ID>> for ExperimentError<PublicGoodsDomain<ID>>{
+    fn from(value: PublicGoodsError<ID>) -> Self {
+        Self::Amfi(value.into())
+    }
+}
+
+impl<ID: UsizeAgentId> From<ReputationError<ID>> for ExperimentError<ReputationDomain<ID>>{
+    fn from(value: ReputationError<ID>) -> Self {
+        Self::Amfi(value.into())
+    }
+}
+
+impl<ID: UsizeAgentId> From<CheapTalkError<ID>> for ExperimentError<CheapTalkDomain<ID>>{
+    fn from(value: CheapTalkError<ID>) -> Self {
+        Self::Amfi(value.into())
+    }
+}
+
+fn list_failures<DP: DomainParameters>(failures: &[ParticipantFailure<DP>]) -> String{
+    failures.iter().map(|f| f.to_string()).collect::<Vec<_>>().join("; ")
+}
+
+impl<DP: DomainParameters> ExperimentError<DP>{
+    pub fn io(path: &Path, source: std::io::Error) -> Self{
+        Self::Io{path: path.to_path_buf(), source}
+    }
+
+    pub fn plot(path: &Path, error: impl Display) -> Self{
+        Self::Plot{path: path.to_path_buf(), message: error.to_string()}
+    }
+}
+
+pub fn create_dir_all<DP: DomainParameters>(path: impl AsRef<Path>) -> Result<(), ExperimentError<DP>>{
+    std::fs::create_dir_all(path.as_ref()).map_err(|e| ExperimentError::io(path.as_ref(), e))
+}
+
+pub fn create_file<DP: DomainParameters>(path: impl AsRef<Path>) -> Result<File, ExperimentError<DP>>{
+    File::create(path.as_ref()).map_err(|e| ExperimentError::io(path.as_ref(), e))
+}
+
+/// Writes value as json to new file.
+pub fn write_json<DP: DomainParameters, T: Serialize + ?Sized>(path: impl AsRef<Path>, value: &T, pretty: bool) -> Result<(), ExperimentError<DP>>{
+    let file = create_file(path.as_ref())?;
+    match pretty{
+        true => serde_json::to_writer_pretty(file, value),
+        false => serde_json::to_writer(file, value),
+    }.map_err(|source| ExperimentError::Json{path: path.as_ref().to_path_buf(), source})
+}
+
+/// Failures of threads taking part in one episode.
+#[derive(Clone, Debug)]
+pub struct EpisodeReport<DP: DomainParameters>{
+    pub failures: Vec<ParticipantFailure<DP>>,
+}
+
+impl<DP: DomainParameters> Default for EpisodeReport<DP>{
+    fn default() -> Self {
+        Self{failures: Vec::new()}
+    }
+}
+
+impl<DP: DomainParameters> EpisodeReport<DP>{
+    pub fn is_complete(&self) -> bool{
+        self.failures.is_empty()
+    }
+
+    /// Joins thread of participant, noting error it returned or panic.
+    pub fn join<T>(&mut self, participant: Participant<DP>, handle: ScopedJoinHandle<'_, Result<T, AmfiError<DP>>>){
+        self.note(participant, handle.join());
+    }
+
+    /// Notes outcome of participant's run, as returned by joined thread or `catch_unwind`.
+    pub fn note<T>(&mut self, participant: Participant<DP>, outcome: thread::Result<Result<T, AmfiError<DP>>>){
+        let failure = match outcome{
+            Ok(Ok(_)) => return,
+            Ok(Err(e)) => ThreadFailure::Error(e),
+            Err(panic) => ThreadFailure::Panic(panic_message(&panic)),
+        };
+        self.failures.push(ParticipantFailure{participant, failure});
+    }
+
+    /// Converts report of episode with given number to error if any thread failed.
+    pub fn into_result(self, episode: usize) -> Result<(), ExperimentError<DP>>{
+        match self.failures.is_empty(){
+            true => Ok(()),
+            false => Err(ExperimentError::Episode{episode, failures: self.failures}),
+        }
+    }
+}
+
+fn panic_message(panic: &Box<dyn Any + Send>) -> String{
+    if let Some(s) = panic.downcast_ref::<&str>(){
+        s.to_string()
+    } else if let Some(s) = panic.downcast_ref::<String>(){
+        s.clone()
+    } else {
+        String::from("unknown cause")
+    }
+}
@@ -0,0 +1,42 @@
+use clap::ValueEnum;
+use serde::{Deserialize, Serialize};
+use amfiteatr_classic::{AsymmetricRewardTableInt, SymmetricRewardTableInt};
+
+/// Well known two player games with two actions. Action `Down` is cooperation (stag, dove,
+/// ballet, heads) and `Up` is defection (hare, hawk, fight, tails).
+#[derive(Serialize, Deserialize, Copy, Clone, Debug, PartialEq, Eq, ValueEnum)]
+#[serde(rename_all(deserialize = "kebab-case"))]
+pub enum GamePreset{
+    PrisonersDilemma,
+    StagHunt,
+    Chicken,
+    /// Left player prefers to meet on `Up`, right player on `Down`
+    BattleOfTheSexes,
+    /// Zero sum game, left player wins when actions match
+    MatchingPennies,
+}
+
+impl GamePreset{
+    pub fn reward_table(&self) -> AsymmetricRewardTableInt{
+        match self{
+            GamePreset::PrisonersDilemma => SymmetricRewardTableInt::new(3, 0, 5, 1).into(),
+            GamePreset::StagHunt => SymmetricRewardTableInt::new(4, 0, 3, 2).into(),
+            GamePreset::Chicken => SymmetricRewardTableInt::new(3, 1, 4, 0).into(),
+            // tables are indexed with action of left player first
+            GamePreset::BattleOfTheSexes => AsymmetricRewardTableInt::new(
+                SymmetricRewardTableInt::new(2, 0, 0, 3),
+                SymmetricRewardTableInt::new(3, 0, 0, 2)),
+            GamePreset::MatchingPennies => AsymmetricRewardTableInt::new(
+                SymmetricRewardTableInt::new(1, -1, -1, 1),
+                SymmetricRewardTableInt::new(-1, 1, 1, -1)),
+        }
+    }
+
+    /// Reward table of preset or custom table if no preset is selected.
+    pub fn table_or(preset: Option<GamePreset>, custom: SymmetricRewardTableInt) -> AsymmetricRewardTableInt{
+        match preset{
+            Some(game) => game.reward_table(),
+            None => custom.into(),
+        }
+    }
+}
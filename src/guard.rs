@@ -0,0 +1,404 @@
+use std::collections::{HashMap, HashSet};
+use std::fmt::{Display, Formatter};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+use log::{debug, warn};
+use amfiteatr_core::agent::{InformationSet, Policy};
+use amfiteatr_core::comm::{BroadcastingEnvironmentAdapter, EnvironmentAdapter};
+use amfiteatr_core::domain::{AgentMessage, DomainParameters, EnvironmentMessage};
+use amfiteatr_core::env::ListPlayers;
+use amfiteatr_core::error::CommunicationError;
+
+/// Creates information set for agent of given id.
+pub type InfoSetBuilder<DP, IS> = Box<dyn Fn(&<DP as DomainParameters>::AgentId) -> IS + Send>;
+
+type Received<DP> = Result<Option<(<DP as DomainParameters>::AgentId, AgentMessage<DP>)>, CommunicationError<DP>>;
+
+/// What [`GuardedAdapter`] does in place of agent that did not answer in time or disconnected.
+pub enum Fallback<DP: DomainParameters, IS: InformationSet<DP>>{
+    /// Play given action.
+    DefaultAction(DP::ActionType),
+    /// Quit game in the name of agent, so the episode ends with error.
+    Forfeit,
+    /// Play scripted policy. Its information sets are created with `info_set` and updated with
+    /// every update sent to agent.
+    Policy{
+        policy: Box<dyn Policy<DP, InfoSetType = IS>>,
+        info_set: InfoSetBuilder<DP, IS>,
+    },
+}
+
+/// Event noted by [`GuardedAdapter`].
+#[derive(Clone, Debug)]
+pub enum GuardEvent<DP: DomainParameters>{
+    TimedOut{
+        agent: DP::AgentId,
+        waited: Duration,
+    },
+    Disconnected{
+        agent: DP::AgentId,
+        error: CommunicationError<DP>,
+    },
+    Replaced{
+        agent: DP::AgentId,
+        action: DP::ActionType,
+    },
+    Forfeited{
+        agent: DP::AgentId,
+    },
+    LateMessageDropped{
+        agent: DP::AgentId,
+    },
+}
+
+impl<DP: DomainParameters> Display for GuardEvent<DP>{
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self{
+            GuardEvent::TimedOut { agent, waited } => write!(f, "agent {agent} did not answer in {waited:?}"),
+            GuardEvent::Disconnected { agent, error } => write!(f, "agent {agent} disconnected: {error}"),
+            GuardEvent::Replaced { agent, action } => write!(f, "played {action} for agent {agent}"),
+            GuardEvent::Forfeited { agent } => write!(f, "agent {agent} forfeited game"),
+            GuardEvent::LateMessageDropped { agent } => write!(f, "dropped late message of agent {agent}"),
+        }
+    }
+}
+
+/// Shared list of events noted by [`GuardedAdapter`], readable after adapter is moved into
+/// environment.
+#[derive(Clone, Debug)]
+pub struct GuardLog<DP: DomainParameters>{
+    events: Arc<Mutex<Vec<GuardEvent<DP>>>>,
+}
+
+impl<DP: DomainParameters> Default for GuardLog<DP>{
+    fn default() -> Self {
+        Self{events: Default::default()}
+    }
+}
+
+impl<DP: DomainParameters> GuardLog<DP>{
+    fn push(&self, event: GuardEvent<DP>){
+        match event{
+            GuardEvent::Replaced { .. } => debug!("Guard: {event}"),
+            _ => warn!("Guard: {event}"),
+        }
+        self.events.lock().unwrap().push(event);
+    }
+
+    /// Removes and returns noted events.
+    pub fn take(&self) -> Vec<GuardEvent<DP>>{
+        std::mem::take(&mut *self.events.lock().unwrap())
+    }
+
+    pub fn len(&self) -> usize{
+        self.events.lock().unwrap().len()
+    }
+
+    pub fn is_empty(&self) -> bool{
+        self.len() == 0
+    }
+}
+
+/// Environment adapter watching agents asked to move. Agent that does not answer within timeout,
+/// or whose connection fails, is replaced with [`Fallback`] until the end of current game, and its
+/// messages are dropped. Messages to replaced agent are still sent, so slow agent can finish
+/// its episode.
+///
+/// Without timeout only disconnected agents are replaced. When environment broadcasts error
+/// notification agents are also killed, so they do not wait for the end of aborted game.
+pub struct GuardedAdapter<DP: DomainParameters, A, IS: InformationSet<DP>>{
+    adapter: A,
+    timeout: Option<Duration>,
+    fallback: Fallback<DP, IS>,
+    info_sets: HashMap<DP::AgentId, IS>,
+    waiting: Option<(DP::AgentId, Instant)>,
+    replaced: HashSet<DP::AgentId>,
+    // agents that timed out on their move in current game, their answer may still come
+    late: HashSet<DP::AgentId>,
+    log: GuardLog<DP>,
+}
+
+impl<DP: DomainParameters, A: EnvironmentAdapter<DP>, IS: InformationSet<DP>> GuardedAdapter<DP, A, IS>{
+    pub fn new(adapter: A, timeout: Option<Duration>, fallback: Fallback<DP, IS>) -> Self{
+        Self{
+            adapter,
+            timeout,
+            fallback,
+            info_sets: HashMap::new(),
+            waiting: None,
+            replaced: HashSet::new(),
+            late: HashSet::new(),
+            log: GuardLog::default(),
+        }
+    }
+
+    /// Handle to events noted by this adapter.
+    pub fn log(&self) -> GuardLog<DP>{
+        self.log.clone()
+    }
+
+    fn observe(&mut self, agent: &DP::AgentId, message: &EnvironmentMessage<DP>){
+        match message{
+            EnvironmentMessage::YourMove => self.waiting = Some((agent.clone(), Instant::now())),
+            EnvironmentMessage::UpdateState(update) => {
+                if let Fallback::Policy { info_set, .. } = &self.fallback{
+                    let mirrored = self.info_sets.entry(agent.clone()).or_insert_with(|| info_set(agent));
+                    if let Err(e) = mirrored.update(update.clone()){
+                        debug!("Failed updating mirrored information set of agent {agent}: {e}");
+                    }
+                }
+            },
+            _ => {}
+        }
+    }
+
+    fn end_game(&mut self){
+        self.info_sets.clear();
+        self.replaced.clear();
+        self.late.clear();
+        self.waiting = None;
+    }
+
+    fn replace(&mut self, agent: &DP::AgentId, event: GuardEvent<DP>){
+        self.log.push(event);
+        self.replaced.insert(agent.clone());
+    }
+
+    fn fallback_message(&mut self, agent: &DP::AgentId) -> AgentMessage<DP>{
+        let action = match &self.fallback{
+            Fallback::DefaultAction(action) => Some(action.clone()),
+            Fallback::Forfeit => None,
+            Fallback::Policy { policy, info_set } => {
+                let mirrored = self.info_sets.entry(agent.clone()).or_insert_with(|| info_set(agent));
+                policy.select_action(mirrored)
+            }
+        };
+        match action{
+            Some(action) => {
+                self.log.push(GuardEvent::Replaced{agent: agent.clone(), action: action.clone()});
+                AgentMessage::TakeAction(action)
+            },
+            None => {
+                self.log.push(GuardEvent::Forfeited{agent: agent.clone()});
+                AgentMessage::Quit
+            }
+        }
+    }
+
+    /// Answers in the name of replaced agent if it was asked to move.
+    fn pending_fallback(&mut self) -> Option<(DP::AgentId, AgentMessage<DP>)>{
+        match &self.waiting{
+            Some((agent, _)) if self.replaced.contains(agent) => {
+                let agent = agent.clone();
+                self.waiting = None;
+                let message = self.fallback_message(&agent);
+                Some((agent, message))
+            },
+            _ => None
+        }
+    }
+
+    fn filter_received(&mut self, received: Received<DP>) -> Received<DP>{
+        match received{
+            Ok(Some((agent, AgentMessage::TakeAction(_)))) if self.late.remove(&agent) => {
+                self.log.push(GuardEvent::LateMessageDropped{agent});
+                Ok(None)
+            },
+            Ok(Some((agent, _))) if self.replaced.contains(&agent) => {
+                self.log.push(GuardEvent::LateMessageDropped{agent});
+                Ok(None)
+            },
+            Ok(Some((agent, message))) => {
+                if matches!(&self.waiting, Some((waiting, _)) if waiting == &agent){
+                    self.waiting = None;
+                }
+                Ok(Some((agent, message)))
+            },
+            Ok(None) => {
+                if let (Some(timeout), Some((agent, since))) = (self.timeout, &self.waiting){
+                    if since.elapsed() > timeout{
+                        let agent = agent.clone();
+                        self.late.insert(agent.clone());
+                        self.replace(&agent, GuardEvent::TimedOut{agent: agent.clone(), waited: timeout});
+                        return Ok(self.pending_fallback());
+                    }
+                }
+                Ok(None)
+            },
+            Err(CommunicationError::RecvEmptyBufferError(_) | CommunicationError::RecvEmptyBufferErrorUnspecified) => Ok(None),
+            Err(error) => {
+                let agent = error_agent(&error).or_else(|| self.waiting.as_ref().map(|(a, _)| a.clone()));
+                match agent{
+                    Some(agent) if self.replaced.contains(&agent) => Ok(None),
+                    Some(agent) => {
+                        self.replace(&agent, GuardEvent::Disconnected{agent: agent.clone(), error});
+                        Ok(self.pending_fallback())
+                    },
+                    None => Err(error),
+                }
+            }
+        }
+    }
+}
+
+/// Agent whose connection caused error, if error says it.
+fn error_agent<DP: DomainParameters>(error: &CommunicationError<DP>) -> Option<DP::AgentId>{
+    match error{
+        CommunicationError::SendError(agent, _)
+        | CommunicationError::BroadcastSendError(agent)
+        | CommunicationError::RecvError(agent, _)
+        | CommunicationError::RecvPeerDisconnectedError(agent)
+        | CommunicationError::ConnectionToAgentNotFound(agent) => Some(agent.clone()),
+        _ => None,
+    }
+}
+
+impl<DP: DomainParameters, A: EnvironmentAdapter<DP>, IS: InformationSet<DP>> EnvironmentAdapter<DP> for GuardedAdapter<DP, A, IS>{
+    fn send(&mut self, agent: &DP::AgentId, message: EnvironmentMessage<DP>) -> Result<(), CommunicationError<DP>>{
+        self.observe(agent, &message);
+        let finished = matches!(message, EnvironmentMessage::GameFinished
+            | EnvironmentMessage::GameFinishedWithIllegalAction(_) | EnvironmentMessage::Kill);
+        let result = self.adapter.send(agent, message);
+        let result = match result{
+            Err(_) if self.replaced.contains(agent) => Ok(()),
+            Err(error) => {
+                self.replace(agent, GuardEvent::Disconnected{agent: agent.clone(), error: error.specify_id(agent.clone())});
+                Ok(())
+            },
+            Ok(()) => Ok(()),
+        };
+        if finished{
+            self.end_game();
+        }
+        result
+    }
+
+    fn receive_blocking(&mut self) -> Result<(DP::AgentId, AgentMessage<DP>), CommunicationError<DP>>{
+        let mut idle = 0u32;
+        loop{
+            if let Some(answer) = self.pending_fallback(){
+                return Ok(answer);
+            }
+            let received = match self.timeout{
+                None => self.adapter.receive_blocking().map(Some),
+                Some(_) => self.adapter.receive_non_blocking(),
+            };
+            if let Some(received) = self.filter_received(received)?{
+                return Ok(received);
+            }
+            // agents usually answer quickly, so sleeping starts only after a while
+            idle = idle.saturating_add(1);
+            if idle < 1000{
+                thread::yield_now();
+            } else {
+                thread::sleep(Duration::from_micros(100));
+            }
+        }
+    }
+
+    fn receive_non_blocking(&mut self) -> Result<Option<(DP::AgentId, AgentMessage<DP>)>, CommunicationError<DP>>{
+        if let Some(answer) = self.pending_fallback(){
+            return Ok(Some(answer));
+        }
+        let received = self.adapter.receive_non_blocking();
+        self.filter_received(received)
+    }
+
+    fn is_agent_connected(&self, agent_id: &DP::AgentId) -> bool{
+        self.adapter.is_agent_connected(agent_id)
+    }
+}
+
+impl<DP: DomainParameters, A: BroadcastingEnvironmentAdapter<DP>, IS: InformationSet<DP>> BroadcastingEnvironmentAdapter<DP> for GuardedAdapter<DP, A, IS>{
+    fn send_all(&mut self, message: EnvironmentMessage<DP>) -> Result<(), CommunicationError<DP>>{
+        let finished = matches!(message, EnvironmentMessage::GameFinished
+            | EnvironmentMessage::GameFinishedWithIllegalAction(_) | EnvironmentMessage::Kill);
+        // environment stops after notifying error, but agents wait for the end of game
+        let aborted = matches!(message, EnvironmentMessage::ErrorNotify(_));
+        let mut result = self.adapter.send_all(message);
+        if aborted{
+            result = result.and(self.adapter.send_all(EnvironmentMessage::Kill));
+        }
+        let result = match result{
+            // replaced agent may be unable to receive
+            Err(e) if !self.replaced.is_empty() => {
+                debug!("Ignoring broadcast error with replaced agents: {e}");
+                Ok(())
+            },
+            r => r,
+        };
+        if finished || aborted{
+            self.end_game();
+        }
+        result
+    }
+}
+
+impl<DP: DomainParameters, A: ListPlayers<DP>, IS: InformationSet<DP>> ListPlayers<DP> for GuardedAdapter<DP, A, IS>{
+    type IterType = A::IterType;
+
+    fn players(&self) -> Self::IterType{
+        self.adapter.players()
+    }
+}
+
+#[cfg(test)]
+mod tests{
+    use std::time::Duration;
+    use amfiteatr_classic::agent::LocalHistoryInfoSet;
+    use amfiteatr_classic::domain::{AgentNum, ClassicAction, ClassicGameDomainNumbered};
+    use amfiteatr_core::comm::{AgentAdapter, AgentMpscAdapter, EnvironmentAdapter, EnvironmentMpscPort};
+    use amfiteatr_core::domain::{AgentMessage, EnvironmentMessage};
+    use super::{Fallback, GuardedAdapter, GuardEvent};
+
+    type D = ClassicGameDomainNumbered;
+    type Guard = GuardedAdapter<D, EnvironmentMpscPort<D>, LocalHistoryInfoSet<AgentNum>>;
+
+    fn guarded(fallback: Fallback<D, LocalHistoryInfoSet<AgentNum>>) -> (Guard, AgentMpscAdapter<D>){
+        let mut port = EnvironmentMpscPort::new();
+        let agent = port.register_agent(0).unwrap();
+        (GuardedAdapter::new(port, Some(Duration::from_millis(20)), fallback), agent)
+    }
+
+    fn is_action(received: &(AgentNum, AgentMessage<D>), action: ClassicAction) -> bool{
+        matches!(received, (0, AgentMessage::TakeAction(a)) if *a == action)
+    }
+
+    #[test]
+    fn silent_agent_is_replaced_until_end_of_game(){
+        let (mut guard, mut agent) = guarded(Fallback::DefaultAction(ClassicAction::Down));
+        let log = guard.log();
+        guard.send(&0, EnvironmentMessage::YourMove).unwrap();
+        assert!(is_action(&guard.receive_blocking().unwrap(), ClassicAction::Down));
+        let events = log.take();
+        assert!(matches!(events[..], [GuardEvent::TimedOut{agent: 0, ..}, GuardEvent::Replaced{agent: 0, action: ClassicAction::Down}]));
+
+        // answer of replaced agent is dropped and fallback keeps playing
+        agent.send(AgentMessage::TakeAction(ClassicAction::Up)).unwrap();
+        assert!(guard.receive_non_blocking().unwrap().is_none());
+        assert!(matches!(log.take()[..], [GuardEvent::LateMessageDropped{agent: 0}]));
+        guard.send(&0, EnvironmentMessage::YourMove).unwrap();
+        assert!(is_action(&guard.receive_blocking().unwrap(), ClassicAction::Down));
+    }
+
+    #[test]
+    fn agent_timed_out_in_previous_game_plays_next_one(){
+        let (mut guard, mut agent) = guarded(Fallback::DefaultAction(ClassicAction::Down));
+        guard.send(&0, EnvironmentMessage::YourMove).unwrap();
+        assert!(is_action(&guard.receive_blocking().unwrap(), ClassicAction::Down));
+        guard.send(&0, EnvironmentMessage::GameFinished).unwrap();
+
+        guard.send(&0, EnvironmentMessage::YourMove).unwrap();
+        agent.send(AgentMessage::TakeAction(ClassicAction::Up)).unwrap();
+        assert!(is_action(&guard.receive_blocking().unwrap(), ClassicAction::Up));
+    }
+
+    #[test]
+    fn forfeit_quits_in_name_of_silent_agent(){
+        let (mut guard, _agent) = guarded(Fallback::Forfeit);
+        guard.send(&0, EnvironmentMessage::YourMove).unwrap();
+        assert!(matches!(guard.receive_blocking().unwrap(), (0, AgentMessage::Quit)));
+        assert!(guard.log().take().iter().any(|e| matches!(e, GuardEvent::Forfeited{agent: 0})));
+    }
+}
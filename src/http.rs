@@ -0,0 +1,139 @@
+use std::collections::BTreeMap;
+use std::io::{BufRead, BufReader, Write};
+use std::net::{Ipv4Addr, SocketAddr, TcpListener, TcpStream};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::Duration;
+use log::{debug, info, warn};
+use serde::Serialize;
+
+#[derive(Default)]
+struct LiveContent{
+    epoch: usize,
+    json: BTreeMap<String, String>,
+    svg: BTreeMap<String, String>,
+}
+
+/// Data shared between experiment and HTTP server. Cloning gives handle to the same data.
+#[derive(Clone, Default)]
+pub struct LiveData{
+    content: Arc<Mutex<LiveContent>>,
+}
+
+impl LiveData{
+    pub fn set_epoch(&self, epoch: usize){
+        self.content.lock().unwrap().epoch = epoch;
+    }
+
+    /// Replaces document served at `/json/<name>`.
+    pub fn publish_json<T: Serialize>(&self, name: &str, value: &T) -> Result<(), serde_json::Error>{
+        let json = serde_json::to_string(value)?;
+        self.content.lock().unwrap().json.insert(name.to_string(), json);
+        Ok(())
+    }
+
+    /// Replaces image served at `/svg/<name>`.
+    pub fn publish_svg(&self, name: &str, svg: String){
+        self.content.lock().unwrap().svg.insert(name.to_string(), svg);
+    }
+
+    fn index(&self) -> String{
+        let content = self.content.lock().unwrap();
+        let mut page = format!("<!DOCTYPE html><html><head><meta charset=\"utf-8\">\
+            <meta http-equiv=\"refresh\" content=\"10\"><title>Experiment</title></head>\
+            <body><h1>Epoch {}</h1>", content.epoch);
+        for name in content.svg.keys(){
+            page.push_str(&format!("<img src=\"/svg/{name}\" alt=\"{name}\">"));
+        }
+        page.push_str("<ul>");
+        for name in content.json.keys(){
+            page.push_str(&format!("<li><a href=\"/json/{name}\">{name}</a></li>"));
+        }
+        page.push_str("</ul></body></html>");
+        page
+    }
+
+    fn response(&self, path: &str) -> (&'static str, &'static str, String){
+        let content = self.content.lock().unwrap();
+        let found = |document: Option<&String>, content_type| match document{
+            Some(d) => ("200 OK", content_type, d.clone()),
+            None => ("404 Not Found", "text/plain", format!("No document: {path}")),
+        };
+        match path{
+            "/" | "/index.html" => {
+                drop(content);
+                ("200 OK", "text/html; charset=utf-8", self.index())
+            },
+            "/epoch" => ("200 OK", "application/json", format!("{}", content.epoch)),
+            p => match (p.strip_prefix("/json/"), p.strip_prefix("/svg/")){
+                (Some(name), _) => found(content.json.get(name), "application/json"),
+                (_, Some(name)) => found(content.svg.get(name), "image/svg+xml"),
+                _ => found(None, "text/plain"),
+            }
+        }
+    }
+}
+
+/// Minimal HTTP server listening on localhost, serving latest series and plots of running
+/// experiment. It runs in background thread until program ends.
+///
+/// Routes: `/` (index refreshing every 10 seconds), `/epoch`, `/json/<name>` and `/svg/<name>`.
+pub struct HttpDashboard{
+    address: SocketAddr,
+    data: LiveData,
+}
+
+impl HttpDashboard{
+    /// Binds `127.0.0.1:port` (`0` picks free port) and starts serving.
+    pub fn start(port: u16) -> std::io::Result<Self>{
+        let listener = TcpListener::bind((Ipv4Addr::LOCALHOST, port))?;
+        let address = listener.local_addr()?;
+        let data = LiveData::default();
+        let server_data = data.clone();
+        thread::Builder::new().name("http-dashboard".into()).spawn(move ||{
+            for stream in listener.incoming(){
+                match stream{
+                    Ok(stream) => {
+                        if let Err(e) = Self::handle(stream, &server_data){
+                            debug!("Failed serving HTTP request: {e}");
+                        }
+                    },
+                    Err(e) => warn!("Failed accepting HTTP connection: {e}"),
+                }
+            }
+        })?;
+        info!("Serving experiment dashboard on http://{address}/");
+        Ok(Self{address, data})
+    }
+
+    pub fn address(&self) -> SocketAddr{
+        self.address
+    }
+
+    pub fn data(&self) -> &LiveData{
+        &self.data
+    }
+
+    fn handle(stream: TcpStream, data: &LiveData) -> std::io::Result<()>{
+        stream.set_read_timeout(Some(Duration::from_secs(5)))?;
+        let mut reader = BufReader::new(stream.try_clone()?);
+        let mut request_line = String::new();
+        reader.read_line(&mut request_line)?;
+        // headers are not used, but are read so client gets complete response
+        let mut header = String::new();
+        while reader.read_line(&mut header)? > 2{
+            header.clear();
+        }
+        let mut parts = request_line.split_whitespace();
+        let (status, content_type, body) = match (parts.next(), parts.next()){
+            (Some("GET"), Some(path)) => data.response(path.split('?').next().unwrap_or(path)),
+            _ => ("405 Method Not Allowed", "text/plain", String::from("Only GET is supported")),
+        };
+        debug!("HTTP {} -> {}", request_line.trim(), status);
+        let mut stream = stream;
+        write!(stream, "HTTP/1.1 {status}\r\nContent-Type: {content_type}\r\nContent-Length: {}\r\n\
+            Cache-Control: no-store\r\nConnection: close\r\n\r\n", body.len())?;
+        stream.write_all(body.as_bytes())?;
+        stream.flush()
+    }
+}
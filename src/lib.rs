@@ -3,4 +3,20 @@ pub mod setup;
 pub mod pairing;
 pub mod plots;
 pub mod series;
-
+pub mod probe;
+pub mod diagnostics;
+pub mod monitor;
+pub mod http;
+pub mod net;
+pub mod policy;
+pub mod tournament;
+pub mod guard;
+pub mod error;
+pub mod pool;
+pub mod batch;
+pub mod sync;
+pub mod games;
+pub mod matrix;
+pub mod public_goods;
+pub mod spatial;
+pub mod cheap_talk;
@@ -0,0 +1,519 @@
+use std::collections::HashMap;
+use std::fmt::{Display, Formatter};
+use std::marker::PhantomData;
+use std::sync::Arc;
+use clap::ValueEnum;
+use log::debug;
+use rand::distributions::WeightedIndex;
+use rand::prelude::{Distribution, SliceRandom};
+use rand::rngs::StdRng;
+use rand::{thread_rng, SeedableRng};
+use serde::Serialize;
+use amfiteatr_core::agent::{EvaluatedInformationSet, InformationSet, Policy, PresentPossibleActions};
+use amfiteatr_core::domain::{Action, DomainParameters, Renew};
+use amfiteatr_core::env::{EnvironmentStateSequential, EnvironmentStateUniScore};
+use amfiteatr_core::error::{AmfiError, ConvertError};
+use amfiteatr_classic::{AsymmetricRewardTableInt, Side};
+use amfiteatr_classic::domain::{AgentNum, ClassicAction, IntReward, UsizeAgentId};
+use amfiteatr_rl::error::TensorRepresentationError;
+use amfiteatr_rl::tch::Tensor;
+use amfiteatr_rl::tensor_data::{ActionTensor, ConversionToTensor, ConvertToTensor};
+
+/// Action in two player game with any number of actions, identified by its index.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash, Serialize)]
+pub struct MatrixAction(pub u8);
+
+impl MatrixAction{
+    pub fn index(&self) -> usize{
+        self.0 as usize
+    }
+}
+
+impl Display for MatrixAction{
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "#{}", self.0)
+    }
+}
+
+impl Action for MatrixAction{}
+
+impl ActionTensor for MatrixAction{
+    fn to_tensor(&self) -> Tensor {
+        Tensor::from_slice(&[self.0 as f32])
+    }
+
+    fn try_from_tensor(t: &Tensor) -> Result<Self, ConvertError> {
+        let v: Vec<i64> = Vec::try_from(t)
+            .map_err(|_| ConvertError::ActionDeserialize(format!("{t}")))?;
+        match v.first().map(|i| u8::try_from(*i)){
+            Some(Ok(i)) => Ok(MatrixAction(i)),
+            _ => Err(ConvertError::ActionDeserialize(format!("{t}")))
+        }
+    }
+}
+
+/// Classic actions are mapped in order of their tensor representation (`Up` is 0, `Down` is 1).
+impl From<ClassicAction> for MatrixAction{
+    fn from(value: ClassicAction) -> Self {
+        match value{
+            ClassicAction::Up => MatrixAction(0),
+            ClassicAction::Down => MatrixAction(1),
+        }
+    }
+}
+
+/// Payoffs of two player game with `n` actions, for both sides of encounter.
+#[derive(Clone, Debug, Serialize)]
+pub struct MatrixRewardTable{
+    actions: usize,
+    /// Pairs of rewards for left and right player, indexed by `left_action * actions + right_action`
+    rewards: Vec<(IntReward, IntReward)>,
+}
+
+impl MatrixRewardTable{
+    /// Creates symmetric game, `reward(a, b)` is reward of player playing `a` against `b`.
+    pub fn symmetric<F: Fn(usize, usize) -> IntReward>(actions: usize, reward: F) -> Self{
+        let rewards = (0..actions)
+            .flat_map(|l| (0..actions).map(move |r| (l, r)))
+            .map(|(l, r)| (reward(l, r), reward(r, l)))
+            .collect();
+        Self{actions, rewards}
+    }
+
+    /// Generalised rock-paper-scissors, for odd number of actions every action beats the same number
+    /// of actions as it loses with. Winner gets 1, loser -1.
+    pub fn rock_paper_scissors(actions: usize) -> Self{
+        Self::symmetric(actions, |a, b|{
+            let distance = (actions + a - b) % actions;
+            if distance == 0 {
+                0
+            } else if distance <= actions / 2 {
+                1
+            } else {
+                -1
+            }
+        })
+    }
+
+    /// Players get 1 when they play the same action.
+    pub fn coordination(actions: usize) -> Self{
+        Self::symmetric(actions, |a, b| (a == b) as IntReward)
+    }
+
+    pub fn number_of_actions(&self) -> usize{
+        self.actions
+    }
+
+    pub fn rewards(&self, left: MatrixAction, right: MatrixAction) -> (IntReward, IntReward){
+        self.rewards[left.index() * self.actions + right.index()]
+    }
+
+    pub fn reward_for_side(&self, side: Side, left: MatrixAction, right: MatrixAction) -> IntReward{
+        let (l, r) = self.rewards(left, right);
+        match side{
+            Side::Left => l,
+            Side::Right => r,
+        }
+    }
+}
+
+impl From<AsymmetricRewardTableInt> for MatrixRewardTable{
+    fn from(value: AsymmetricRewardTableInt) -> Self {
+        let actions = [ClassicAction::Up, ClassicAction::Down];
+        let rewards = actions.iter()
+            .flat_map(|l| actions.iter().map(move |r| value.rewards(*l, *r)))
+            .collect();
+        Self{actions: 2, rewards}
+    }
+}
+
+/// Ready-made games with configurable number of actions.
+#[derive(Serialize, Copy, Clone, Debug, PartialEq, Eq, ValueEnum)]
+pub enum MatrixPreset{
+    RockPaperScissors,
+    Coordination,
+}
+
+impl MatrixPreset{
+    pub fn reward_table(&self, actions: usize) -> MatrixRewardTable{
+        match self{
+            MatrixPreset::RockPaperScissors => MatrixRewardTable::rock_paper_scissors(actions),
+            MatrixPreset::Coordination => MatrixRewardTable::coordination(actions),
+        }
+    }
+}
+
+#[derive(thiserror::Error, Debug, Clone, PartialEq, Eq)]
+pub enum MatrixGameError<ID: UsizeAgentId>{
+    #[error("Order in game was violated. Expected player: {expected:?} given: {acted:}")]
+    GameViolatedOrder{
+        acted: ID,
+        expected: Option<ID>
+    },
+    #[error("Player: {0} played after GameOver")]
+    ActionAfterGameOver(ID),
+    #[error("Player {agent} played action {action}, but game has {actions} actions")]
+    ActionOutOfRange{
+        agent: ID,
+        action: MatrixAction,
+        actions: usize,
+    },
+    #[error("Odd number of players: {0}")]
+    ExpectedEvenNumberOfPlayers(usize),
+    #[error("Update does no include encounter report for agent: {0}")]
+    EncounterNotReported(ID),
+}
+
+/// Domain of pairwise games with any number of actions.
+#[derive(Clone, Debug, Serialize)]
+pub struct MatrixGameDomain<ID: UsizeAgentId>{
+    _id: PhantomData<ID>
+}
+
+/// Encounter of agent in one round, seen from its side.
+#[derive(Copy, Clone, Debug, Serialize)]
+pub struct MatrixEncounter<ID: UsizeAgentId>{
+    pub own_action: MatrixAction,
+    pub other_player_action: MatrixAction,
+    pub side: Side,
+    pub other_id: ID,
+}
+
+impl<ID: UsizeAgentId> MatrixEncounter<ID>{
+    pub fn left_action(&self) -> MatrixAction{
+        match self.side{
+            Side::Left => self.own_action,
+            Side::Right => self.other_player_action,
+        }
+    }
+
+    pub fn right_action(&self) -> MatrixAction{
+        match self.side{
+            Side::Left => self.other_player_action,
+            Side::Right => self.own_action,
+        }
+    }
+
+    pub fn reward(&self, table: &MatrixRewardTable) -> IntReward{
+        table.reward_for_side(self.side, self.left_action(), self.right_action())
+    }
+}
+
+/// Reports of all encounters in finished round.
+#[derive(Clone, Debug)]
+pub struct MatrixGameUpdate<ID: UsizeAgentId>{
+    pub encounters: Arc<HashMap<ID, MatrixEncounter<ID>>>,
+}
+
+impl<ID: UsizeAgentId> DomainParameters for MatrixGameDomain<ID>{
+    type ActionType = MatrixAction;
+    type GameErrorType = MatrixGameError<ID>;
+    type UpdateType = MatrixGameUpdate<ID>;
+    type AgentId = ID;
+    type UniversalReward = IntReward;
+}
+
+pub type MatrixGameDomainNumbered = MatrixGameDomain<AgentNum>;
+
+impl<ID: UsizeAgentId> From<MatrixGameError<ID>> for AmfiError<MatrixGameDomain<ID>>{
+    fn from(value: MatrixGameError<ID>) -> Self {
+        AmfiError::Game(value)
+    }
+}
+
+#[derive(Copy, Clone, Debug, Serialize)]
+struct Pairing<ID: UsizeAgentId>{
+    paired_player: ID,
+    side: Side,
+}
+
+/// State of game with players paired randomly every round, like
+/// [`PairingState`](amfiteatr_classic::env::PairingState) for two actions. Players act in order
+/// of their ids and after every round each of them is informed about all encounters.
+#[derive(Clone, Debug, Serialize)]
+pub struct MatrixPairingState<ID: UsizeAgentId>{
+    reward_table: MatrixRewardTable,
+    target_rounds: usize,
+    rounds_played: usize,
+    indexes: Vec<usize>,
+    pairings: Vec<Pairing<ID>>,
+    actions: Vec<Option<MatrixAction>>,
+    score_cache: Vec<IntReward>,
+    current_player_index: usize,
+    #[serde(skip)]
+    rng: StdRng,
+}
+
+impl<ID: UsizeAgentId> MatrixPairingState<ID>{
+    pub fn new_even(players: usize, target_rounds: usize, reward_table: MatrixRewardTable) -> Result<Self, MatrixGameError<ID>>{
+        if !players.is_multiple_of(2){
+            return Err(MatrixGameError::ExpectedEvenNumberOfPlayers(players));
+        }
+        let mut state = Self{
+            reward_table, target_rounds,
+            rounds_played: 0,
+            indexes: (0..players).collect(),
+            pairings: Vec::with_capacity(players),
+            actions: vec![None; players],
+            score_cache: vec![0; players],
+            current_player_index: 0,
+            rng: StdRng::from_entropy(),
+        };
+        state.prepare_pairings();
+        Ok(state)
+    }
+
+    /// Replaces generator of pairings, e.g. with seeded one, and pairs players again with it.
+    pub fn with_rng(mut self, rng: StdRng) -> Self{
+        self.rng = rng;
+        self.indexes.sort_unstable();
+        self.prepare_pairings();
+        self
+    }
+
+    fn prepare_pairings(&mut self){
+        self.indexes.shuffle(&mut self.rng);
+        let mut pairings = vec![Pairing{paired_player: ID::make_from_usize(0), side: Side::Left}; self.indexes.len()];
+        for pair in self.indexes.chunks_exact(2){
+            pairings[pair[0]] = Pairing{paired_player: ID::make_from_usize(pair[1]), side: Side::Left};
+            pairings[pair[1]] = Pairing{paired_player: ID::make_from_usize(pair[0]), side: Side::Right};
+        }
+        self.pairings = pairings;
+        self.actions.iter_mut().for_each(|a| *a = None);
+    }
+
+    pub fn reward_table(&self) -> &MatrixRewardTable{
+        &self.reward_table
+    }
+
+    pub fn rounds_played(&self) -> usize{
+        self.rounds_played
+    }
+
+    fn encounters(&self) -> HashMap<ID, MatrixEncounter<ID>>{
+        self.pairings.iter().enumerate().filter_map(|(i, pairing)|{
+            let own_action = self.actions[i]?;
+            let other_player_action = self.actions[pairing.paired_player.as_usize()]?;
+            Some((ID::make_from_usize(i), MatrixEncounter{
+                own_action, other_player_action,
+                side: pairing.side,
+                other_id: pairing.paired_player,
+            }))
+        }).collect()
+    }
+}
+
+impl<ID: UsizeAgentId> Display for MatrixPairingState<ID>{
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Rounds played: {}, scores: {:?}", self.rounds_played, self.score_cache)
+    }
+}
+
+impl<ID: UsizeAgentId> EnvironmentStateSequential<MatrixGameDomain<ID>> for MatrixPairingState<ID>{
+    type Updates = Vec<(ID, MatrixGameUpdate<ID>)>;
+
+    fn current_player(&self) -> Option<ID> {
+        match self.is_finished(){
+            true => None,
+            false => Some(ID::make_from_usize(self.current_player_index)),
+        }
+    }
+
+    fn is_finished(&self) -> bool {
+        self.rounds_played >= self.target_rounds
+    }
+
+    fn forward(&mut self, agent: ID, action: MatrixAction) -> Result<Self::Updates, MatrixGameError<ID>> {
+        let expected = self.current_player().ok_or(MatrixGameError::ActionAfterGameOver(agent))?;
+        if expected != agent{
+            return Err(MatrixGameError::GameViolatedOrder{acted: agent, expected: Some(expected)});
+        }
+        if action.index() >= self.reward_table.number_of_actions(){
+            return Err(MatrixGameError::ActionOutOfRange{agent, action, actions: self.reward_table.number_of_actions()});
+        }
+        let index = agent.as_usize();
+        self.actions[index] = Some(action);
+        let pairing = self.pairings[index];
+        if let Some(other_action) = self.actions[pairing.paired_player.as_usize()]{
+            let (left, right) = match pairing.side{
+                Side::Left => (action, other_action),
+                Side::Right => (other_action, action),
+            };
+            let (left_reward, right_reward) = self.reward_table.rewards(left, right);
+            let (own, other) = match pairing.side{
+                Side::Left => (left_reward, right_reward),
+                Side::Right => (right_reward, left_reward),
+            };
+            self.score_cache[index] += own;
+            self.score_cache[pairing.paired_player.as_usize()] += other;
+        }
+        self.current_player_index += 1;
+        if self.current_player_index < self.pairings.len(){
+            return Ok(Vec::new());
+        }
+        let update = MatrixGameUpdate{encounters: Arc::new(self.encounters())};
+        self.rounds_played += 1;
+        self.current_player_index = 0;
+        self.prepare_pairings();
+        debug!("Finished round {}", self.rounds_played);
+        Ok((0..self.pairings.len()).map(|i| (ID::make_from_usize(i), update.clone())).collect())
+    }
+}
+
+impl<ID: UsizeAgentId> EnvironmentStateUniScore<MatrixGameDomain<ID>> for MatrixPairingState<ID>{
+    fn state_score_of_player(&self, agent: &ID) -> IntReward {
+        self.score_cache[agent.as_usize()]
+    }
+}
+
+impl<ID: UsizeAgentId> Renew<()> for MatrixPairingState<ID>{
+    fn renew_from(&mut self, _base: ()) {
+        self.score_cache.iter_mut().for_each(|s| *s = 0);
+        self.rounds_played = 0;
+        self.current_player_index = 0;
+        self.prepare_pairings();
+    }
+}
+
+/// Information set of agent remembering its own encounters.
+#[derive(Clone, Debug, Serialize)]
+pub struct MatrixHistoryInfoSet<ID: UsizeAgentId>{
+    id: ID,
+    reward_table: MatrixRewardTable,
+    previous_encounters: Vec<MatrixEncounter<ID>>,
+    cache_table_payoff: IntReward,
+}
+
+impl<ID: UsizeAgentId> MatrixHistoryInfoSet<ID>{
+    pub fn new(id: ID, reward_table: MatrixRewardTable) -> Self{
+        Self{id, reward_table, previous_encounters: Vec::new(), cache_table_payoff: 0}
+    }
+
+    pub fn previous_encounters(&self) -> &[MatrixEncounter<ID>]{
+        &self.previous_encounters[..]
+    }
+
+    pub fn count_actions_self(&self, action: MatrixAction) -> usize{
+        self.previous_encounters.iter().filter(|e| e.own_action == action).count()
+    }
+}
+
+impl<ID: UsizeAgentId> Display for MatrixHistoryInfoSet<ID>{
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Agent: {}, rounds: {}, table payoff: {}", self.id, self.previous_encounters.len(), self.cache_table_payoff)
+    }
+}
+
+impl<ID: UsizeAgentId> InformationSet<MatrixGameDomain<ID>> for MatrixHistoryInfoSet<ID>{
+    fn agent_id(&self) -> &ID {
+        &self.id
+    }
+
+    fn is_action_valid(&self, action: &MatrixAction) -> bool {
+        action.index() < self.reward_table.number_of_actions()
+    }
+
+    fn update(&mut self, update: MatrixGameUpdate<ID>) -> Result<(), MatrixGameError<ID>> {
+        let report = *update.encounters.get(&self.id)
+            .ok_or(MatrixGameError::EncounterNotReported(self.id))?;
+        self.cache_table_payoff += report.reward(&self.reward_table);
+        self.previous_encounters.push(report);
+        Ok(())
+    }
+}
+
+impl<ID: UsizeAgentId> PresentPossibleActions<MatrixGameDomain<ID>> for MatrixHistoryInfoSet<ID>{
+    type ActionIteratorType = Vec<MatrixAction>;
+
+    fn available_actions(&self) -> Self::ActionIteratorType {
+        (0..self.reward_table.number_of_actions()).map(|i| MatrixAction(i as u8)).collect()
+    }
+}
+
+impl<ID: UsizeAgentId> EvaluatedInformationSet<MatrixGameDomain<ID>> for MatrixHistoryInfoSet<ID>{
+    type RewardType = IntReward;
+
+    fn current_subjective_score(&self) -> Self::RewardType {
+        self.cache_table_payoff
+    }
+
+    fn penalty_for_illegal(&self) -> Self::RewardType {
+        -100
+    }
+}
+
+impl<ID: UsizeAgentId> Renew<()> for MatrixHistoryInfoSet<ID>{
+    fn renew_from(&mut self, _base: ()) {
+        self.previous_encounters.clear();
+        self.cache_table_payoff = 0;
+    }
+}
+
+/// Represents history as one-hot encoded own and opponent's actions in every round, rounds not
+/// played yet are zeros.
+#[derive(Copy, Clone, Debug, Default)]
+pub struct MatrixHistoryConversion{
+    actions: usize,
+    rounds: usize,
+    shape: [i64; 1],
+}
+
+impl MatrixHistoryConversion{
+    pub fn new(actions: usize, rounds: usize) -> Self{
+        Self{actions, rounds, shape: [(2 * actions * rounds) as i64]}
+    }
+}
+
+impl ConversionToTensor for MatrixHistoryConversion{
+    fn desired_shape(&self) -> &[i64] {
+        &self.shape[..]
+    }
+}
+
+impl<ID: UsizeAgentId> ConvertToTensor<MatrixHistoryConversion> for MatrixHistoryInfoSet<ID>{
+    fn try_to_tensor(&self, way: &MatrixHistoryConversion) -> Result<Tensor, TensorRepresentationError> {
+        if self.previous_encounters.len() > way.rounds{
+            return Err(TensorRepresentationError::InfoSetNotFit {
+                info_set: format!("Matrix game history of length {}", self.previous_encounters.len()),
+                shape: Vec::from(way.desired_shape()),
+            });
+        }
+        let mut values = vec![0.0f32; way.shape[0] as usize];
+        for (round, encounter) in self.previous_encounters.iter().enumerate(){
+            let offset = round * 2 * way.actions;
+            values[offset + encounter.own_action.index()] = 1.0;
+            values[offset + way.actions + encounter.other_player_action.index()] = 1.0;
+        }
+        Ok(Tensor::f_from_slice(&values[..])?)
+    }
+}
+
+/// Policy choosing actions with fixed probabilities, independent of history.
+pub struct MatrixMixedStrategy<ID: UsizeAgentId>{
+    distribution: WeightedIndex<f64>,
+    _id: PhantomData<ID>,
+}
+
+impl<ID: UsizeAgentId> MatrixMixedStrategy<ID>{
+    /// Creates strategy choosing action with probability proportional to its weight.
+    pub fn new(weights: &[f64]) -> Result<Self, rand::distributions::WeightedError>{
+        Ok(Self{distribution: WeightedIndex::new(weights)?, _id: PhantomData})
+    }
+
+    pub fn uniform(actions: usize) -> Result<Self, rand::distributions::WeightedError>{
+        Self::new(&vec![1.0; actions])
+    }
+
+    pub fn pure(actions: usize, action: MatrixAction) -> Result<Self, rand::distributions::WeightedError>{
+        let weights: Vec<f64> = (0..actions).map(|i| (i == action.index()) as u8 as f64).collect();
+        Self::new(&weights)
+    }
+}
+
+impl<ID: UsizeAgentId> Policy<MatrixGameDomain<ID>> for MatrixMixedStrategy<ID>{
+    type InfoSetType = MatrixHistoryInfoSet<ID>;
+
+    fn select_action(&self, _state: &Self::InfoSetType) -> Option<MatrixAction> {
+        Some(MatrixAction(self.distribution.sample(&mut thread_rng()) as u8))
+    }
+}
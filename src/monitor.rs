@@ -0,0 +1,125 @@
+use std::collections::VecDeque;
+use std::time::Duration;
+use indicatif::{ProgressBar, ProgressStyle};
+use log::info;
+
+/// Terminal progress bar of training epochs, with estimated time left and rolling averages of
+/// recorded metrics.
+///
+/// Bar is drawn on stderr, so it does not mix with log written to stdout or file.
+pub struct TrainingMonitor{
+    bar: ProgressBar,
+    window: usize,
+    metrics: Vec<(String, VecDeque<f32>)>,
+}
+
+impl TrainingMonitor{
+    /// Creates monitor for `epochs` epochs, averaging metrics over last `window` epochs.
+    /// When `visible` is false nothing is drawn.
+    pub fn new(epochs: usize, window: usize, visible: bool) -> Self{
+        let bar = match visible{
+            true => ProgressBar::new(epochs as u64),
+            false => ProgressBar::hidden(),
+        };
+        bar.set_style(ProgressStyle::with_template(
+            "{elapsed_precise} [{wide_bar}] {pos}/{len} epochs (ETA {eta}) {msg}")
+            .unwrap_or_else(|_| ProgressStyle::default_bar())
+            .progress_chars("=> "));
+        bar.enable_steady_tick(Duration::from_millis(500));
+        Self{
+            bar,
+            window: window.max(1),
+            metrics: Vec::new(),
+        }
+    }
+
+    /// Records value of metric in current epoch. Metrics are shown in order of first record.
+    pub fn record(&mut self, name: &str, value: f32){
+        let values = match self.metrics.iter().position(|(n, _)| n == name){
+            Some(i) => &mut self.metrics[i].1,
+            None => {
+                self.metrics.push((name.to_string(), VecDeque::with_capacity(self.window)));
+                &mut self.metrics.last_mut().unwrap().1
+            }
+        };
+        if values.len() == self.window{
+            values.pop_front();
+        }
+        values.push_back(value);
+    }
+
+    /// Average of metric over last epochs of window.
+    pub fn rolling_mean(&self, name: &str) -> Option<f32>{
+        self.metrics.iter().find(|(n, _)| n == name)
+            .filter(|(_, values)| !values.is_empty())
+            .map(|(_, values)| values.iter().sum::<f32>() / values.len() as f32)
+    }
+
+    /// Ends epoch: moves progress bar and refreshes shown metrics.
+    pub fn finish_epoch(&mut self){
+        let message = self.metrics.iter()
+            .filter_map(|(name, _)| self.rolling_mean(name).map(|v| format!("{name}: {v:.3}")))
+            .collect::<Vec<String>>()
+            .join(", ");
+        self.bar.set_message(message);
+        self.bar.inc(1);
+    }
+
+    /// Closes progress bar, leaving it on screen with final message.
+    pub fn finish(&self, message: &str){
+        info!("Training finished: {message}");
+        self.bar.abandon_with_message(message.to_string());
+    }
+}
+
+/// Detects plateau of metric: training should stop when metric changed by less than `epsilon`
+/// in each of last `patience` epochs.
+#[derive(Clone, Debug)]
+pub struct EarlyStopping{
+    name: String,
+    patience: usize,
+    epsilon: f32,
+    min_epochs: usize,
+    epochs: usize,
+    last: Option<f32>,
+    stale: usize,
+}
+
+impl EarlyStopping{
+    pub fn new(name: &str, patience: usize, epsilon: f32) -> Self{
+        Self{
+            name: name.to_string(),
+            patience,
+            epsilon,
+            min_epochs: 0,
+            epochs: 0,
+            last: None,
+            stale: 0,
+        }
+    }
+
+    /// Plateau is not reported before given number of epochs.
+    pub fn min_epochs(mut self, min_epochs: usize) -> Self{
+        self.min_epochs = min_epochs;
+        self
+    }
+
+    pub fn name(&self) -> &str{
+        &self.name
+    }
+
+    /// Registers value of metric after next epoch, returns true if metric reached plateau.
+    pub fn update(&mut self, value: f32) -> bool{
+        self.epochs += 1;
+        self.stale = match self.last{
+            Some(last) if (value - last).abs() < self.epsilon => self.stale + 1,
+            _ => 0,
+        };
+        self.last = Some(value);
+        self.converged()
+    }
+
+    pub fn converged(&self) -> bool{
+        self.patience > 0 && self.stale >= self.patience && self.epochs >= self.min_epochs
+    }
+}
@@ -0,0 +1,562 @@
+use std::collections::{HashMap, HashSet};
+use std::fmt::Debug;
+use std::io::{ErrorKind, Read, Write};
+use std::marker::PhantomData;
+use std::net::{Shutdown, TcpListener, TcpStream, ToSocketAddrs};
+use std::sync::Arc;
+use std::sync::mpsc::{channel, Receiver, TryRecvError};
+use std::thread;
+use log::{debug, info, warn};
+use serde::{Deserialize, Serialize};
+use serde::de::DeserializeOwned;
+use amfiteatr_classic::domain::{ClassicAction, ClassicGameDomain, ClassicGameUpdate, EncounterReport, IntReward, UsizeAgentId};
+use amfiteatr_classic::env::PlayerPairing;
+use amfiteatr_classic::Side;
+use amfiteatr_core::agent::AgentActionPair;
+use amfiteatr_core::comm::{BidirectionalEndpoint, BroadcastingEnvironmentAdapter, EnvironmentAdapter, EnvironmentEndpoint};
+use amfiteatr_core::domain::{AgentMessage, DomainParameters, EnvironmentMessage};
+use amfiteatr_core::env::ListPlayers;
+use amfiteatr_core::error::{AmfiError, CommunicationError};
+
+/// Message that can be sent through [`TcpJsonEndpoint`]. It is converted to serializable
+/// form before sending and restored from it after receiving.
+pub trait WireMessage: Sized{
+    type Wire: Serialize + DeserializeOwned;
+
+    fn to_wire(&self) -> Self::Wire;
+    fn from_wire(wire: Self::Wire) -> Self;
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct WireEncounter{
+    pub agent: usize,
+    pub own_action: ClassicAction,
+    pub other_player_action: ClassicAction,
+    pub side: Side,
+    pub other_id: usize,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct WirePairing{
+    pub paired_player: usize,
+    pub taken_action: Option<ClassicAction>,
+    pub side: Side,
+}
+
+/// [`EnvironmentMessage`] of classic game in serializable form. Agents are referenced by
+/// number, errors are sent as text.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub enum WireEnvironmentMessage{
+    YourMove,
+    MoveRefused,
+    GameFinished,
+    GameFinishedWithIllegalAction(usize),
+    Kill,
+    UpdateState{
+        encounters: Vec<WireEncounter>,
+        pairing: Option<Vec<WirePairing>>,
+    },
+    ActionNotify{
+        agent: usize,
+        action: ClassicAction,
+    },
+    RewardFragment(IntReward),
+    ErrorNotify(String),
+}
+
+/// [`AgentMessage`] of classic game in serializable form.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub enum WireAgentMessage{
+    TakeAction(ClassicAction),
+    NotifyError(String),
+    Quit,
+}
+
+impl<ID: UsizeAgentId> WireMessage for EnvironmentMessage<ClassicGameDomain<ID>>{
+    type Wire = WireEnvironmentMessage;
+
+    fn to_wire(&self) -> WireEnvironmentMessage{
+        match self{
+            EnvironmentMessage::YourMove => WireEnvironmentMessage::YourMove,
+            EnvironmentMessage::MoveRefused => WireEnvironmentMessage::MoveRefused,
+            EnvironmentMessage::GameFinished => WireEnvironmentMessage::GameFinished,
+            EnvironmentMessage::GameFinishedWithIllegalAction(id) =>
+                WireEnvironmentMessage::GameFinishedWithIllegalAction(id.as_usize()),
+            EnvironmentMessage::Kill => WireEnvironmentMessage::Kill,
+            EnvironmentMessage::UpdateState(update) => WireEnvironmentMessage::UpdateState {
+                encounters: update.encounters.iter().map(|(agent, report)| WireEncounter{
+                    agent: agent.as_usize(),
+                    own_action: report.own_action,
+                    other_player_action: report.other_player_action,
+                    side: report.side,
+                    other_id: report.other_id.as_usize(),
+                }).collect(),
+                pairing: update.pairing.as_ref().map(|pairing| pairing.iter().map(|p| WirePairing{
+                    paired_player: p.paired_player.as_usize(),
+                    taken_action: p.taken_action,
+                    side: p.side,
+                }).collect()),
+            },
+            EnvironmentMessage::ActionNotify(pair) => WireEnvironmentMessage::ActionNotify {
+                agent: pair.agent.as_usize(),
+                action: pair.action,
+            },
+            EnvironmentMessage::RewardFragment(reward) => WireEnvironmentMessage::RewardFragment(*reward),
+            EnvironmentMessage::ErrorNotify(e) => WireEnvironmentMessage::ErrorNotify(format!("{e}")),
+        }
+    }
+
+    fn from_wire(wire: WireEnvironmentMessage) -> Self{
+        match wire{
+            WireEnvironmentMessage::YourMove => EnvironmentMessage::YourMove,
+            WireEnvironmentMessage::MoveRefused => EnvironmentMessage::MoveRefused,
+            WireEnvironmentMessage::GameFinished => EnvironmentMessage::GameFinished,
+            WireEnvironmentMessage::GameFinishedWithIllegalAction(id) =>
+                EnvironmentMessage::GameFinishedWithIllegalAction(ID::make_from_usize(id)),
+            WireEnvironmentMessage::Kill => EnvironmentMessage::Kill,
+            WireEnvironmentMessage::UpdateState { encounters, pairing } =>
+                EnvironmentMessage::UpdateState(ClassicGameUpdate{
+                    encounters: Arc::new(encounters.into_iter().map(|e| (ID::make_from_usize(e.agent), EncounterReport{
+                        own_action: e.own_action,
+                        other_player_action: e.other_player_action,
+                        side: e.side,
+                        other_id: ID::make_from_usize(e.other_id),
+                    })).collect()),
+                    pairing: pairing.map(|pairing| Arc::new(pairing.into_iter().map(|p| PlayerPairing{
+                        paired_player: ID::make_from_usize(p.paired_player),
+                        taken_action: p.taken_action,
+                        side: p.side,
+                    }).collect())),
+                }),
+            WireEnvironmentMessage::ActionNotify { agent, action } =>
+                EnvironmentMessage::ActionNotify(AgentActionPair::new(ID::make_from_usize(agent), action)),
+            WireEnvironmentMessage::RewardFragment(reward) => EnvironmentMessage::RewardFragment(reward),
+            WireEnvironmentMessage::ErrorNotify(e) => EnvironmentMessage::ErrorNotify(AmfiError::Custom(e)),
+        }
+    }
+}
+
+impl<ID: UsizeAgentId> WireMessage for AgentMessage<ClassicGameDomain<ID>>{
+    type Wire = WireAgentMessage;
+
+    fn to_wire(&self) -> WireAgentMessage{
+        match self{
+            AgentMessage::TakeAction(action) => WireAgentMessage::TakeAction(*action),
+            AgentMessage::NotifyError(e) => WireAgentMessage::NotifyError(format!("{e}")),
+            AgentMessage::Quit => WireAgentMessage::Quit,
+        }
+    }
+
+    fn from_wire(wire: WireAgentMessage) -> Self{
+        match wire{
+            WireAgentMessage::TakeAction(action) => AgentMessage::TakeAction(action),
+            WireAgentMessage::NotifyError(e) => AgentMessage::NotifyError(AmfiError::Custom(e)),
+            WireAgentMessage::Quit => AgentMessage::Quit,
+        }
+    }
+}
+
+/// First line sent by agent after connecting, so environment knows whom the connection serves.
+/// Agent may ask for particular id or leave choice to environment.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+struct Hello{
+    agent: Option<usize>,
+    #[serde(default)]
+    name: String,
+}
+
+/// Environment's answer to [`Hello`].
+#[derive(Serialize, Deserialize, Clone, Debug)]
+enum HelloReply{
+    Accepted(usize),
+    Refused(String),
+}
+
+/// Endpoint over TCP stream sending messages as JSON, one message per line.
+///
+/// Unlike fixed size buffers used by `amfiteatr_net_ext`, lines do not limit size of message
+/// and do not require messages to implement `speedy` traits.
+pub struct TcpJsonEndpoint<DP: DomainParameters, OT, IT>{
+    stream: TcpStream,
+    buffer: Vec<u8>,
+    _dp: PhantomData<DP>,
+    _ot: PhantomData<OT>,
+    _it: PhantomData<IT>,
+}
+
+/// Environment's side of TCP connection with agent of classic game.
+pub type EnvironmentTcpEndpoint<ID> = TcpJsonEndpoint<
+    ClassicGameDomain<ID>,
+    EnvironmentMessage<ClassicGameDomain<ID>>,
+    AgentMessage<ClassicGameDomain<ID>>>;
+
+/// Agent's side of TCP connection with environment of classic game.
+pub type AgentTcpEndpoint<ID> = TcpJsonEndpoint<
+    ClassicGameDomain<ID>,
+    AgentMessage<ClassicGameDomain<ID>>,
+    EnvironmentMessage<ClassicGameDomain<ID>>>;
+
+impl<DP: DomainParameters, OT, IT> TcpJsonEndpoint<DP, OT, IT>{
+    pub fn new(stream: TcpStream) -> Self{
+        Self{
+            stream,
+            buffer: Vec::new(),
+            _dp: PhantomData,
+            _ot: PhantomData,
+            _it: PhantomData,
+        }
+    }
+
+    fn take_line(&mut self) -> Option<Vec<u8>>{
+        let end = self.buffer.iter().position(|b| *b == b'\n')?;
+        let mut line: Vec<u8> = self.buffer.drain(..=end).collect();
+        line.pop();
+        Some(line)
+    }
+
+    fn read_some(&mut self) -> Result<usize, std::io::Error>{
+        let mut chunk = [0u8; 1024];
+        let n = self.stream.read(&mut chunk)?;
+        self.buffer.extend_from_slice(&chunk[..n]);
+        Ok(n)
+    }
+
+    fn write_line<T: Serialize>(&mut self, value: &T) -> Result<(), CommunicationError<DP>>{
+        let mut line = serde_json::to_vec(value)
+            .map_err(|e| CommunicationError::SerializeError(format!("{e}")))?;
+        line.push(b'\n');
+        self.stream.write_all(&line)
+            .map_err(|e| CommunicationError::SendErrorUnspecified(format!("{e}")))
+    }
+
+    fn read_line_blocking(&mut self) -> Result<Vec<u8>, CommunicationError<DP>>{
+        loop{
+            if let Some(line) = self.take_line(){
+                return Ok(line)
+            }
+            match self.read_some(){
+                Ok(0) => return Err(CommunicationError::RecvPeerDisconnectedErrorUnspecified),
+                Ok(_) => {},
+                Err(e) if e.kind() == ErrorKind::Interrupted => {},
+                Err(e) => return Err(CommunicationError::RecvErrorUnspecified(format!("{e}"))),
+            }
+        }
+    }
+}
+
+impl<ID: UsizeAgentId> AgentTcpEndpoint<ID>{
+    /// Connects to environment asking for id `id` (any free id when `None`).
+    /// Returns id assigned by environment.
+    pub fn connect<A: ToSocketAddrs>(address: A, id: Option<ID>, name: &str)
+        -> Result<(ID, Self), CommunicationError<ClassicGameDomain<ID>>>{
+
+        let stream = TcpStream::connect(address)
+            .map_err(|e| CommunicationError::SendErrorUnspecified(format!("{e}")))?;
+        let mut endpoint = Self::new(stream);
+        endpoint.write_line(&Hello{agent: id.map(|id| id.as_usize()), name: name.to_string()})?;
+        let line = endpoint.read_line_blocking()?;
+        match serde_json::from_slice(&line)
+            .map_err(|e| CommunicationError::DeserializeError(format!("{e}")))?{
+            HelloReply::Accepted(id) => Ok((ID::make_from_usize(id), endpoint)),
+            HelloReply::Refused(reason) => Err(CommunicationError::SendErrorUnspecified(
+                format!("Environment refused connection: {reason}"))),
+        }
+    }
+}
+
+/// Agent connected to environment over TCP.
+pub struct ConnectedAgent<ID: UsizeAgentId>{
+    pub id: ID,
+    /// Name given by agent when connecting, may be empty
+    pub name: String,
+    pub endpoint: EnvironmentTcpEndpoint<ID>,
+}
+
+/// Accepts connections on listener until every agent of `ids` is connected.
+/// Agents asking for id not on the list or already taken are refused and accepting continues.
+pub fn accept_agents<ID: UsizeAgentId>(listener: &TcpListener, ids: &[ID])
+    -> Result<Vec<ConnectedAgent<ID>>, CommunicationError<ClassicGameDomain<ID>>>{
+
+    let mut agents: Vec<ConnectedAgent<ID>> = Vec::with_capacity(ids.len());
+    while agents.len() < ids.len(){
+        let (stream, address) = listener.accept()
+            .map_err(|e| CommunicationError::RecvErrorUnspecified(format!("{e}")))?;
+        let mut endpoint = EnvironmentTcpEndpoint::<ID>::new(stream);
+        let hello: Hello = match endpoint.read_line_blocking()
+            .and_then(|line| serde_json::from_slice(&line)
+                .map_err(|e| CommunicationError::DeserializeError(format!("{e}")))){
+            Ok(hello) => hello,
+            Err(e) => {
+                warn!("Dropping connection from {address}: {e}");
+                continue;
+            }
+        };
+        let taken = |id: &ID| agents.iter().any(|a| &a.id == id);
+        let assigned = match hello.agent.map(ID::make_from_usize){
+            None => ids.iter().find(|id| !taken(id)).copied()
+                .ok_or_else(|| String::from("no free places")),
+            Some(id) if !ids.contains(&id) => Err(format!("no place for agent {id}")),
+            Some(id) if taken(&id) => Err(format!("agent {id} is already connected")),
+            Some(id) => Ok(id),
+        };
+        match assigned{
+            Ok(id) => {
+                endpoint.write_line(&HelloReply::Accepted(id.as_usize()))?;
+                info!("Agent {id} ({}) connected from {address}", hello.name);
+                agents.push(ConnectedAgent{id, name: hello.name, endpoint});
+            },
+            Err(reason) => {
+                warn!("Refusing connection from {address}: {reason}");
+                let _ = endpoint.write_line(&HelloReply::Refused(reason));
+            }
+        }
+    }
+    Ok(agents)
+}
+
+impl<DP: DomainParameters, OT, IT> BidirectionalEndpoint for TcpJsonEndpoint<DP, OT, IT>
+where OT: WireMessage + Debug, IT: WireMessage + Debug{
+    type OutwardType = OT;
+    type InwardType = IT;
+    type Error = CommunicationError<DP>;
+
+    fn send(&mut self, message: OT) -> Result<(), Self::Error>{
+        debug!("Sending over TCP: {message:?}");
+        self.write_line(&message.to_wire())
+    }
+
+    fn receive_blocking(&mut self) -> Result<IT, Self::Error>{
+        let line = self.read_line_blocking()?;
+        serde_json::from_slice(&line)
+            .map(IT::from_wire)
+            .map_err(|e| CommunicationError::DeserializeError(format!("{e}")))
+    }
+
+    fn receive_non_blocking(&mut self) -> Result<Option<IT>, Self::Error>{
+        if !self.buffer.contains(&b'\n'){
+            self.stream.set_nonblocking(true)
+                .map_err(|e| CommunicationError::RecvErrorUnspecified(format!("{e}")))?;
+            let read = self.read_some();
+            self.stream.set_nonblocking(false)
+                .map_err(|e| CommunicationError::RecvErrorUnspecified(format!("{e}")))?;
+            match read{
+                Ok(0) => return Err(CommunicationError::RecvPeerDisconnectedErrorUnspecified),
+                Ok(_) => {},
+                Err(e) if e.kind() == ErrorKind::WouldBlock || e.kind() == ErrorKind::Interrupted => {},
+                Err(e) => return Err(CommunicationError::RecvErrorUnspecified(format!("{e}"))),
+            }
+        }
+        match self.take_line(){
+            None => Ok(None),
+            Some(line) => serde_json::from_slice(&line)
+                .map(|wire| Some(IT::from_wire(wire)))
+                .map_err(|e| CommunicationError::DeserializeError(format!("{e}")))
+        }
+    }
+}
+
+/// Endpoint which can give second handle of its connection, so receiving may block in
+/// another thread while sending goes on.
+pub trait SplitEndpoint<DP: DomainParameters>: EnvironmentEndpoint<DP>{
+    type Reader: EnvironmentEndpoint<DP> + Send + 'static;
+
+    /// Handle used only for receiving, it takes over messages already buffered by endpoint.
+    fn reader(&mut self) -> Result<Self::Reader, CommunicationError<DP>>;
+
+    /// Closes connection, so reader blocked on it returns.
+    fn close(&mut self){}
+}
+
+impl<DP: DomainParameters, OT, IT> SplitEndpoint<DP> for TcpJsonEndpoint<DP, OT, IT>
+where Self: EnvironmentEndpoint<DP>, OT: Send + 'static, IT: Send + 'static{
+    type Reader = Self;
+
+    fn reader(&mut self) -> Result<Self, CommunicationError<DP>>{
+        let stream = self.stream.try_clone()
+            .map_err(|e| CommunicationError::RecvErrorUnspecified(format!("{e}")))?;
+        let mut reader = Self::new(stream);
+        reader.buffer = std::mem::take(&mut self.buffer);
+        Ok(reader)
+    }
+
+    fn close(&mut self){
+        let _ = self.stream.shutdown(Shutdown::Both);
+    }
+}
+
+type Inbound<DP> = (<DP as DomainParameters>::AgentId, Result<AgentMessage<DP>, CommunicationError<DP>>);
+
+fn is_disconnection<DP: DomainParameters>(error: &CommunicationError<DP>) -> bool{
+    matches!(error, CommunicationError::RecvPeerDisconnectedError(_) | CommunicationError::RecvError(..))
+}
+
+/// Environment adapter over endpoints of individual agents, for example [`EnvironmentTcpEndpoint`].
+/// Unlike adapters in `amfiteatr_core` it lists players and reports errors with id of agent.
+///
+/// Every endpoint is read by its own thread, which passes messages to one channel, so waiting
+/// for message does not poll connections. Reading from endpoint stops after it reports
+/// disconnection, and when no endpoint is read anymore receiving fails with
+/// [`RecvPeerDisconnectedErrorUnspecified`](CommunicationError::RecvPeerDisconnectedErrorUnspecified).
+/// Connections are closed when adapter is dropped.
+pub struct EndpointAdapter<DP: DomainParameters, T: SplitEndpoint<DP>>{
+    endpoints: HashMap<DP::AgentId, T>,
+    disconnected: HashSet<DP::AgentId>,
+    inbound: Receiver<Inbound<DP>>,
+}
+
+impl<DP: DomainParameters, T: SplitEndpoint<DP>> EndpointAdapter<DP, T>{
+    /// Starts reading threads of endpoints.
+    pub fn new(mut endpoints: HashMap<DP::AgentId, T>) -> Result<Self, CommunicationError<DP>>{
+        let (sender, inbound) = channel();
+        for (agent, endpoint) in endpoints.iter_mut(){
+            let mut reader = endpoint.reader().map_err(|e| e.specify_id(agent.clone()))?;
+            let sender = sender.clone();
+            let agent = agent.clone();
+            thread::Builder::new()
+                .name(format!("endpoint-{agent}"))
+                .spawn(move ||{
+                    loop{
+                        let received = reader.receive_blocking().map_err(|e| e.specify_id(agent.clone()));
+                        let disconnected = matches!(&received, Err(e) if is_disconnection(e));
+                        if sender.send((agent.clone(), received)).is_err() || disconnected{
+                            break;
+                        }
+                    }
+                })
+                .map_err(|e| CommunicationError::RecvErrorUnspecified(format!("Failed starting reader of agent: {e}")))?;
+        }
+        Ok(Self{
+            endpoints,
+            disconnected: HashSet::new(),
+            inbound,
+        })
+    }
+
+    fn accept(&mut self, (agent, received): Inbound<DP>) -> Result<(DP::AgentId, AgentMessage<DP>), CommunicationError<DP>>{
+        match received{
+            Ok(message) => Ok((agent, message)),
+            Err(e) => {
+                if is_disconnection(&e){
+                    self.disconnected.insert(agent);
+                }
+                Err(e)
+            }
+        }
+    }
+}
+
+impl<DP: DomainParameters, T: SplitEndpoint<DP>> EnvironmentAdapter<DP> for EndpointAdapter<DP, T>{
+    fn send(&mut self, agent: &DP::AgentId, message: EnvironmentMessage<DP>) -> Result<(), CommunicationError<DP>>{
+        if self.disconnected.contains(agent){
+            return Err(CommunicationError::SendError(agent.clone(), String::from("agent disconnected")));
+        }
+        match self.endpoints.get_mut(agent){
+            Some(endpoint) => endpoint.send(message).map_err(|e| e.specify_id(agent.clone())),
+            None => Err(CommunicationError::ConnectionToAgentNotFound(agent.clone())),
+        }
+    }
+
+    fn receive_blocking(&mut self) -> Result<(DP::AgentId, AgentMessage<DP>), CommunicationError<DP>>{
+        match self.inbound.recv(){
+            Ok(inbound) => self.accept(inbound),
+            Err(_) => Err(CommunicationError::RecvPeerDisconnectedErrorUnspecified),
+        }
+    }
+
+    fn receive_non_blocking(&mut self) -> Result<Option<(DP::AgentId, AgentMessage<DP>)>, CommunicationError<DP>>{
+        match self.inbound.try_recv(){
+            Ok(inbound) => self.accept(inbound).map(Some),
+            Err(TryRecvError::Empty) => Ok(None),
+            Err(TryRecvError::Disconnected) => Err(CommunicationError::RecvPeerDisconnectedErrorUnspecified),
+        }
+    }
+
+    fn is_agent_connected(&self, agent_id: &DP::AgentId) -> bool{
+        self.endpoints.contains_key(agent_id) && !self.disconnected.contains(agent_id)
+    }
+}
+
+impl<DP: DomainParameters, T: SplitEndpoint<DP>> Drop for EndpointAdapter<DP, T>{
+    fn drop(&mut self) {
+        self.endpoints.values_mut().for_each(|endpoint| endpoint.close());
+    }
+}
+
+impl<DP: DomainParameters, T: SplitEndpoint<DP>> BroadcastingEnvironmentAdapter<DP> for EndpointAdapter<DP, T>{
+    fn send_all(&mut self, message: EnvironmentMessage<DP>) -> Result<(), CommunicationError<DP>>{
+        let mut result = Ok(());
+        for (agent, endpoint) in self.endpoints.iter_mut(){
+            if self.disconnected.contains(agent){
+                continue;
+            }
+            if let Err(e) = endpoint.send(message.clone()){
+                if result.is_ok(){
+                    result = Err(e.specify_id(agent.clone()));
+                }
+            }
+        }
+        result
+    }
+}
+
+impl<DP: DomainParameters, T: SplitEndpoint<DP>> ListPlayers<DP> for EndpointAdapter<DP, T>{
+    type IterType = <Vec<DP::AgentId> as IntoIterator>::IntoIter;
+
+    fn players(&self) -> Self::IterType{
+        self.endpoints.keys().cloned().collect::<Vec<_>>().into_iter()
+    }
+}
+
+#[cfg(test)]
+mod tests{
+    use std::collections::HashSet;
+    use std::net::TcpListener;
+    use std::thread;
+    use amfiteatr_classic::domain::{AgentNum, ClassicAction, ClassicGameDomainNumbered};
+    use amfiteatr_core::comm::{BidirectionalEndpoint, EnvironmentAdapter};
+    use amfiteatr_core::domain::AgentMessage;
+    use amfiteatr_core::error::CommunicationError;
+    use super::{accept_agents, AgentTcpEndpoint, EndpointAdapter, EnvironmentTcpEndpoint};
+
+    type Adapter = EndpointAdapter<ClassicGameDomainNumbered, EnvironmentTcpEndpoint<AgentNum>>;
+
+    fn connected(players: AgentNum) -> (Adapter, Vec<AgentTcpEndpoint<AgentNum>>){
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = listener.local_addr().unwrap();
+        let clients = thread::spawn(move || (0..players)
+            .map(|id| AgentTcpEndpoint::connect(address, Some(id), "test").unwrap().1)
+            .collect());
+        let ids: Vec<AgentNum> = (0..players).collect();
+        let endpoints = accept_agents(&listener, &ids).unwrap().into_iter()
+            .map(|agent| (agent.id, agent.endpoint))
+            .collect();
+        (EndpointAdapter::new(endpoints).unwrap(), clients.join().unwrap())
+    }
+
+    #[test]
+    fn messages_of_agents_are_received(){
+        let (mut adapter, mut clients) = connected(2);
+        assert!(adapter.receive_non_blocking().unwrap().is_none());
+        clients[1].send(AgentMessage::TakeAction(ClassicAction::Down)).unwrap();
+        let (agent, message) = adapter.receive_blocking().unwrap();
+        assert_eq!(agent, 1);
+        assert!(matches!(message, AgentMessage::TakeAction(ClassicAction::Down)));
+    }
+
+    #[test]
+    fn receiving_fails_after_every_agent_disconnected(){
+        let (mut adapter, clients) = connected(2);
+        drop(clients);
+        let mut disconnected = HashSet::new();
+        for _ in 0..2{
+            match adapter.receive_blocking(){
+                Err(CommunicationError::RecvPeerDisconnectedError(agent)) => disconnected.insert(agent),
+                other => panic!("expected disconnection of agent, got {other:?}"),
+            };
+        }
+        assert_eq!(disconnected, HashSet::from([0, 1]));
+        assert!(!adapter.is_agent_connected(&0) && !adapter.is_agent_connected(&1));
+        assert!(matches!(adapter.receive_blocking(), Err(CommunicationError::RecvPeerDisconnectedErrorUnspecified)));
+        assert!(matches!(adapter.receive_non_blocking(), Err(CommunicationError::RecvPeerDisconnectedErrorUnspecified)));
+    }
+}
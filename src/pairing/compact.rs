@@ -0,0 +1,429 @@
+use std::fs::File;
+use std::io::{BufReader, BufWriter, ErrorKind, Read, Write};
+use std::path::Path;
+use amfiteatr_classic::{AsymmetricRewardTableInt, Side, SymmetricRewardTableInt};
+use amfiteatr_classic::domain::{ClassicAction, IntReward};
+use crate::pairing::heterogeneous::RewardTables;
+use crate::pairing::replay::{GameReplay, RecordedPairing, ReplayError};
+
+pub const MAGIC: &[u8; 6] = b"AMFTRJ";
+pub const VERSION: u8 = 1;
+const GAME_FRAME: u8 = 1;
+/// Largest number of players accepted from header, larger ones are treated as corrupt file.
+pub const MAX_PLAYERS: usize = 1 << 20;
+const ACTIONS: [(ClassicAction, ClassicAction); 4] = [
+    (ClassicAction::Down, ClassicAction::Down), (ClassicAction::Down, ClassicAction::Up),
+    (ClassicAction::Up, ClassicAction::Down), (ClassicAction::Up, ClassicAction::Up)];
+
+/// Checks if file starts with [`MAGIC`] of binary trajectory.
+pub fn is_compact_file(path: &Path) -> Result<bool, ReplayError>{
+    let mut file = File::open(path).map_err(|source| ReplayError::Io{path: path.to_path_buf(), source})?;
+    let mut magic = [0u8; MAGIC.len()];
+    match file.read_exact(&mut magic){
+        Ok(()) => Ok(&magic == MAGIC),
+        Err(e) if e.kind() == ErrorKind::UnexpectedEof => Ok(false),
+        Err(source) => Err(ReplayError::Io{path: path.to_path_buf(), source}),
+    }
+}
+
+fn write_varint(writer: &mut impl Write, mut value: u64) -> std::io::Result<()>{
+    loop{
+        let byte = (value & 0x7f) as u8;
+        value >>= 7;
+        if value == 0{
+            return writer.write_all(&[byte]);
+        }
+        writer.write_all(&[byte | 0x80])?;
+    }
+}
+
+fn write_signed(writer: &mut impl Write, value: i64) -> std::io::Result<()>{
+    write_varint(writer, ((value << 1) ^ (value >> 63)) as u64)
+}
+
+/// Bits as value of first bit and lengths of following runs.
+fn write_runs(writer: &mut impl Write, bits: &[bool]) -> std::io::Result<()>{
+    let first = match bits.first(){
+        Some(first) => *first,
+        None => return Ok(()),
+    };
+    writer.write_all(&[first as u8])?;
+    let mut current = first;
+    let mut length = 0u64;
+    for bit in bits{
+        if *bit != current{
+            write_varint(writer, length)?;
+            current = *bit;
+            length = 0;
+        }
+        length += 1;
+    }
+    write_varint(writer, length)
+}
+
+fn read_byte(reader: &mut impl Read) -> Result<u8, ReplayError>{
+    let mut byte = [0u8];
+    reader.read_exact(&mut byte).map_err(ReplayError::Stream)?;
+    Ok(byte[0])
+}
+
+fn read_varint(reader: &mut impl Read) -> Result<u64, ReplayError>{
+    let mut value = 0u64;
+    for shift in (0..64).step_by(7){
+        let byte = read_byte(reader)?;
+        value |= ((byte & 0x7f) as u64) << shift;
+        if byte & 0x80 == 0{
+            return Ok(value);
+        }
+    }
+    Err(ReplayError::Format("varint longer than 64 bits"))
+}
+
+fn read_usize(reader: &mut impl Read) -> Result<usize, ReplayError>{
+    usize::try_from(read_varint(reader)?).map_err(|_| ReplayError::Format("length does not fit in usize"))
+}
+
+fn read_signed(reader: &mut impl Read) -> Result<i64, ReplayError>{
+    let value = read_varint(reader)?;
+    Ok(((value >> 1) as i64) ^ -((value & 1) as i64))
+}
+
+fn read_runs(reader: &mut impl Read, len: usize) -> Result<Vec<bool>, ReplayError>{
+    let mut bits = Vec::with_capacity(len);
+    if len == 0{
+        return Ok(bits);
+    }
+    let mut current = read_byte(reader)? != 0;
+    while bits.len() < len{
+        let length = read_usize(reader)?;
+        if length == 0 || length > len - bits.len(){
+            return Err(ReplayError::Format("run of actions does not match number of players"));
+        }
+        bits.extend(std::iter::repeat_n(current, length));
+        current = !current;
+    }
+    Ok(bits)
+}
+
+fn action_bit(action: ClassicAction) -> bool{
+    action == ClassicAction::Down
+}
+
+fn bit_action(bit: bool) -> ClassicAction{
+    match bit{
+        true => ClassicAction::Down,
+        false => ClassicAction::Up,
+    }
+}
+
+fn write_table(writer: &mut impl Write, table: &AsymmetricRewardTableInt) -> std::io::Result<()>{
+    for side in [Side::Left, Side::Right]{
+        for (left, right) in ACTIONS{
+            write_signed(writer, table.reward_for_side(side, left, right))?;
+        }
+    }
+    Ok(())
+}
+
+fn read_table(reader: &mut impl Read) -> Result<AsymmetricRewardTableInt, ReplayError>{
+    let mut side = || -> Result<SymmetricRewardTableInt, ReplayError>{
+        Ok(SymmetricRewardTableInt::new(read_signed(reader)?, read_signed(reader)?,
+                                        read_signed(reader)?, read_signed(reader)?))
+    };
+    let left = side()?;
+    Ok(AsymmetricRewardTableInt::new(left, side()?))
+}
+
+/// Game read from binary trajectory with label given when it was written.
+#[derive(Clone, Debug)]
+pub struct LabeledGame{
+    pub label: u64,
+    pub replay: GameReplay,
+}
+
+/// Streaming writer of compact binary trajectory of games played by the same players, for runs
+/// in which json of every episode would be too large.
+///
+/// File starts with header: [`MAGIC`], version byte, number of players and reward tables of
+/// default and every agent. Header is followed by games, each starting with byte `1`, so file
+/// may be read while it is still written. Integers are stored as LEB128 varints (signed ones
+/// zigzag encoded). Every round stores pairs of agents (left agent first), run length encoded
+/// bits of executed actions (`1` for `Down`) and of intended actions, which are present only
+/// when noisy state noted them.
+pub struct TrajectoryWriter<W: Write>{
+    writer: W,
+    players: usize,
+}
+
+impl TrajectoryWriter<BufWriter<File>>{
+    pub fn create(path: &Path, tables: &RewardTables) -> Result<Self, ReplayError>{
+        let file = File::create(path).map_err(|source| ReplayError::Io{path: path.to_path_buf(), source})?;
+        Self::new(BufWriter::new(file), tables)
+    }
+}
+
+impl<W: Write> TrajectoryWriter<W>{
+    pub fn new(mut writer: W, tables: &RewardTables) -> Result<Self, ReplayError>{
+        let mut header = || -> std::io::Result<()>{
+            writer.write_all(MAGIC)?;
+            writer.write_all(&[VERSION])?;
+            write_varint(&mut writer, tables.players() as u64)?;
+            write_table(&mut writer, &tables.default_table())?;
+            (0..tables.players()).try_for_each(|agent| write_table(&mut writer, &tables.table_of(agent)))
+        };
+        header().map_err(ReplayError::Stream)?;
+        Ok(Self{writer, players: tables.players()})
+    }
+
+    /// Appends game, label is free for caller (e.g. epoch in which game was played).
+    pub fn write_game(&mut self, label: u64, game: &GameReplay) -> Result<(), ReplayError>{
+        if game.players != self.players{
+            return Err(ReplayError::Format("game has different number of players than trajectory"));
+        }
+        if game.recorded_scores.len() != self.players{
+            return Err(ReplayError::Format("number of recorded scores does not match number of players"));
+        }
+        let noisy = game.rounds.iter().flat_map(|r| r.encounters.iter()).any(|e| e.intended.is_some());
+        let w = &mut self.writer;
+        let mut frame = || -> std::io::Result<()>{
+            w.write_all(&[GAME_FRAME])?;
+            write_varint(w, label)?;
+            write_varint(w, game.steps as u64)?;
+            write_varint(w, game.invalid_steps as u64)?;
+            game.recorded_scores.iter().try_for_each(|s| write_signed(w, *s))?;
+            write_varint(w, game.rounds.len() as u64)?;
+            w.write_all(&[noisy as u8])?;
+            for round in game.rounds.iter(){
+                for encounter in round.encounters.iter().filter(|e| matches!(e.side, Side::Left)){
+                    write_varint(w, encounter.agent as u64)?;
+                    write_varint(w, encounter.partner as u64)?;
+                }
+                let executed: Vec<bool> = round.encounters.iter().map(|e| action_bit(e.action)).collect();
+                write_runs(w, &executed)?;
+                if noisy{
+                    let intended: Vec<bool> = round.encounters.iter()
+                        .map(|e| action_bit(e.intended.unwrap_or(e.action))).collect();
+                    write_runs(w, &intended)?;
+                }
+            }
+            Ok(())
+        };
+        frame().map_err(ReplayError::Stream)
+    }
+
+    /// Flushes and returns inner writer.
+    pub fn finish(mut self) -> Result<W, ReplayError>{
+        self.writer.flush().map_err(ReplayError::Stream)?;
+        Ok(self.writer)
+    }
+}
+
+/// Streaming reader of binary trajectory, iterates over games in order they were written.
+pub struct TrajectoryReader<R: Read>{
+    reader: R,
+    tables: RewardTables,
+}
+
+impl TrajectoryReader<BufReader<File>>{
+    pub fn open(path: &Path) -> Result<Self, ReplayError>{
+        let file = File::open(path).map_err(|source| ReplayError::Io{path: path.to_path_buf(), source})?;
+        Self::new(BufReader::new(file))
+    }
+}
+
+impl<R: Read> TrajectoryReader<R>{
+    pub fn new(mut reader: R) -> Result<Self, ReplayError>{
+        let mut magic = [0u8; MAGIC.len()];
+        reader.read_exact(&mut magic).map_err(ReplayError::Stream)?;
+        if &magic != MAGIC{
+            return Err(ReplayError::Format("missing header of binary trajectory"));
+        }
+        if read_byte(&mut reader)? != VERSION{
+            return Err(ReplayError::Format("unsupported version of binary trajectory"));
+        }
+        let players = read_usize(&mut reader)?;
+        // rounds without players would be read from no bytes at all
+        if players == 0 || !players.is_multiple_of(2) || players > MAX_PLAYERS{
+            return Err(ReplayError::Format("number of players is not even or out of range"));
+        }
+        let default = read_table(&mut reader)?;
+        let mut tables = Vec::new();
+        for _ in 0..players{
+            tables.push(read_table(&mut reader)?);
+        }
+        Ok(Self{reader, tables: RewardTables::from_tables(default, tables)})
+    }
+
+    pub fn tables(&self) -> &RewardTables{
+        &self.tables
+    }
+
+    pub fn players(&self) -> usize{
+        self.tables.players()
+    }
+
+    fn read_game(&mut self) -> Result<LabeledGame, ReplayError>{
+        let players = self.players();
+        let r = &mut self.reader;
+        let label = read_varint(r)?;
+        let steps = read_usize(r)?;
+        let invalid_steps = read_usize(r)?;
+        let mut recorded_scores: Vec<IntReward> = Vec::new();
+        for _ in 0..players{
+            recorded_scores.push(read_signed(r)?);
+        }
+        // rounds are taken from file, so vectors grow with rounds actually read
+        let rounds = read_usize(r)?;
+        let noisy = read_byte(r)? != 0;
+        let mut pairings = Vec::new();
+        let mut intended = Vec::new();
+        for _ in 0..rounds{
+            let mut round = vec![None; players];
+            for _ in 0..players / 2{
+                let (left, right) = (read_usize(r)?, read_usize(r)?);
+                let (Some(l), Some(rr)) = (round.get(left), round.get(right)) else{
+                    return Err(ReplayError::Format("paired agent out of range"));
+                };
+                if l.is_some() || rr.is_some() || left == right{
+                    return Err(ReplayError::Format("agent paired twice in round"));
+                }
+                round[left] = Some((right, Side::Left));
+                round[right] = Some((left, Side::Right));
+            }
+            let executed = read_runs(r, players)?;
+            let round = round.into_iter().zip(executed)
+                .map(|(pairing, bit)| pairing.map(|(paired_player, side)| RecordedPairing{
+                    paired_player, side,
+                    taken_action: Some(bit_action(bit)),
+                }))
+                .collect::<Option<Vec<_>>>()
+                .ok_or(ReplayError::Format("agent without pair in round"))?;
+            pairings.push(round);
+            if noisy{
+                intended.push(read_runs(r, players)?.into_iter().map(|b| Some(bit_action(b))).collect());
+            }
+        }
+        let replay = GameReplay::from_records(self.tables.clone(), &pairings, &intended, recorded_scores, steps, invalid_steps)?;
+        Ok(LabeledGame{label, replay})
+    }
+}
+
+impl<R: Read> Iterator for TrajectoryReader<R>{
+    type Item = Result<LabeledGame, ReplayError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let mut frame = [0u8];
+        match self.reader.read(&mut frame){
+            Ok(0) => None,
+            Ok(_) if frame[0] == GAME_FRAME => Some(self.read_game()),
+            Ok(_) => Some(Err(ReplayError::Format("unknown frame"))),
+            Err(e) => Some(Err(ReplayError::Stream(e))),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests{
+    use amfiteatr_classic::domain::{AgentNum, ClassicAction};
+    use amfiteatr_core::env::EnvironmentStateSequential;
+    use crate::games::GamePreset;
+    use crate::pairing::new_game_state_with_tables;
+    use crate::pairing::heterogeneous::{RewardTables, scaled_table};
+    use crate::pairing::noise::Noise;
+    use crate::pairing::replay::{GameReplay, ReplayError};
+    use super::{TrajectoryReader, TrajectoryWriter, MAGIC, VERSION};
+
+    const PLAYERS: usize = 6;
+
+    fn played_game(tables: RewardTables, noise: Noise) -> GameReplay{
+        let mut state = new_game_state_with_tables::<AgentNum>(10, tables, noise, Some(0.9), Some(5)).unwrap();
+        let mut steps = 0;
+        while let Some(agent) = state.current_player(){
+            let action = match (agent as usize + steps) % 3{
+                0 => ClassicAction::Up,
+                _ => ClassicAction::Down,
+            };
+            state.forward(agent, action).unwrap();
+            steps += 1;
+        }
+        GameReplay::from_game_state(&state, steps, 0).unwrap()
+    }
+
+    fn pd_tables() -> RewardTables{
+        let table = GamePreset::PrisonersDilemma.reward_table();
+        RewardTables::from_tables(table, (0..PLAYERS).map(|a| scaled_table(table, 1 + a as i64 % 2)).collect())
+    }
+
+    fn write(tables: &RewardTables, games: &[GameReplay]) -> Vec<u8>{
+        let mut writer = TrajectoryWriter::new(Vec::new(), tables).unwrap();
+        for (label, game) in games.iter().enumerate(){
+            writer.write_game(label as u64, game).unwrap();
+        }
+        writer.finish().unwrap()
+    }
+
+    fn read(bytes: &[u8]) -> Result<Vec<GameReplay>, ReplayError>{
+        TrajectoryReader::new(bytes)?.map(|game| game.map(|g| g.replay)).collect()
+    }
+
+    fn json(games: &[GameReplay]) -> serde_json::Value{
+        serde_json::to_value(games).unwrap()
+    }
+
+    #[test]
+    fn games_are_read_as_written(){
+        let tables = pd_tables();
+        let noisy = played_game(tables.clone(), Noise::new(0.2, 0.2));
+        let mut silent = noisy.clone();
+        silent.rounds.iter_mut().flat_map(|r| r.encounters.iter_mut()).for_each(|e| e.intended = None);
+        assert!(noisy.rounds.iter().flat_map(|r| r.encounters.iter()).any(|e| e.is_flipped()));
+
+        let games = [noisy, silent];
+        let bytes = write(&tables, &games);
+        let mut reader = TrajectoryReader::new(&bytes[..]).unwrap();
+        assert_eq!(serde_json::to_value(reader.tables()).unwrap(), serde_json::to_value(&tables).unwrap());
+        assert_eq!(reader.next().unwrap().unwrap().label, 0);
+        assert_eq!(json(&read(&bytes).unwrap()), json(&games));
+    }
+
+    #[test]
+    fn negative_rewards_are_read_as_written(){
+        let table = scaled_table(GamePreset::PrisonersDilemma.reward_table(), -3);
+        let tables = RewardTables::uniform(PLAYERS, table);
+        let game = played_game(tables.clone(), Noise::new(0.0, 0.0));
+        assert!(game.recorded_scores.iter().any(|s| *s < 0));
+
+        let games = [game];
+        assert_eq!(json(&read(&write(&tables, &games)).unwrap()), json(&games));
+    }
+
+    #[test]
+    fn game_with_missing_scores_is_not_written(){
+        let tables = pd_tables();
+        let mut game = played_game(tables.clone(), Noise::new(0.0, 0.0));
+        game.recorded_scores.pop();
+        let mut writer = TrajectoryWriter::new(Vec::new(), &tables).unwrap();
+        assert!(matches!(writer.write_game(0, &game), Err(ReplayError::Format(_))));
+        assert!(read(&writer.finish().unwrap()).unwrap().is_empty());
+    }
+
+    #[test]
+    fn truncated_trajectory_is_rejected(){
+        let tables = pd_tables();
+        let game = played_game(tables.clone(), Noise::new(0.2, 0.2));
+        let header = write(&tables, &[]).len();
+        let bytes = write(&tables, &[game]);
+        // trajectory cut right after header is valid one without games
+        for end in (0..bytes.len()).filter(|end| *end != header){
+            assert!(read(&bytes[..end]).is_err(), "trajectory cut after {end} bytes was accepted");
+        }
+    }
+
+    #[test]
+    fn corrupt_number_of_players_is_rejected(){
+        let header = |players: &[u8]| [&MAGIC[..], &[VERSION], players].concat();
+        for players in [&[0u8][..], &[3], &[0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0x01]]{
+            assert!(matches!(TrajectoryReader::new(&header(players)[..]), Err(ReplayError::Format(_))));
+        }
+    }
+}
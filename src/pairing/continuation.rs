@@ -0,0 +1,106 @@
+use std::fmt::{Display, Formatter};
+use std::marker::PhantomData;
+use log::debug;
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use serde::Serialize;
+use amfiteatr_core::domain::Renew;
+use amfiteatr_core::env::{EnvironmentStateSequential, EnvironmentStateUniScore};
+use amfiteatr_classic::domain::{ClassicAction, ClassicGameDomain, ClassicGameError, ClassicGameUpdate, IntReward, UsizeAgentId};
+
+/// Wrapper of pairing state making length of game random. After every round game continues
+/// with probability `continuation` (shadow of the future), so agents can not know which round
+/// is the last one. Number of rounds of inner state is limit of game length.
+#[derive(Clone, Debug, Serialize)]
+pub struct ContinuationState<ID: UsizeAgentId, S>{
+    inner: S,
+    continuation: f64,
+    rounds_played: usize,
+    stopped: bool,
+    #[serde(skip)]
+    rng: StdRng,
+    #[serde(skip)]
+    _id: PhantomData<ID>,
+}
+
+impl<ID: UsizeAgentId, S> ContinuationState<ID, S>{
+    pub fn new(inner: S, continuation: f64) -> Self{
+        Self{inner, continuation, rounds_played: 0, stopped: false, rng: StdRng::from_entropy(), _id: PhantomData}
+    }
+
+    /// Replaces generator deciding whether game continues, e.g. with seeded one.
+    pub fn with_rng(mut self, rng: StdRng) -> Self{
+        self.rng = rng;
+        self
+    }
+
+    pub fn inner(&self) -> &S{
+        &self.inner
+    }
+
+    pub fn continuation(&self) -> f64{
+        self.continuation
+    }
+
+    /// Number of finished rounds in episode.
+    pub fn rounds_played(&self) -> usize{
+        self.rounds_played
+    }
+}
+
+impl<ID: UsizeAgentId, S: Display> Display for ContinuationState<ID, S>{
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.inner)
+    }
+}
+
+impl<ID: UsizeAgentId, S> EnvironmentStateSequential<ClassicGameDomain<ID>> for ContinuationState<ID, S>
+where S: EnvironmentStateSequential<ClassicGameDomain<ID>, Updates = Vec<(ID, ClassicGameUpdate<ID>)>>{
+    type Updates = Vec<(ID, ClassicGameUpdate<ID>)>;
+
+    fn current_player(&self) -> Option<ID> {
+        match self.stopped{
+            true => None,
+            false => self.inner.current_player(),
+        }
+    }
+
+    fn is_finished(&self) -> bool {
+        self.stopped || self.inner.is_finished()
+    }
+
+    fn forward(&mut self, agent: ID, action: ClassicAction) -> Result<Self::Updates, ClassicGameError<ID>> {
+        if self.stopped{
+            return Err(ClassicGameError::ActionAfterGameOver(agent));
+        }
+        let updates = self.inner.forward(agent, action)?;
+        if updates.is_empty(){
+            return Ok(updates);
+        }
+        self.rounds_played += 1;
+        if self.inner.is_finished() || self.rng.gen_bool(self.continuation){
+            return Ok(updates);
+        }
+        debug!("Game stops after round {}", self.rounds_played);
+        self.stopped = true;
+        // there is no next round, so no pairing is announced
+        Ok(updates.into_iter()
+            .map(|(id, update)| (id, ClassicGameUpdate{encounters: update.encounters, pairing: None}))
+            .collect())
+    }
+}
+
+impl<ID: UsizeAgentId, S> EnvironmentStateUniScore<ClassicGameDomain<ID>> for ContinuationState<ID, S>
+where S: EnvironmentStateUniScore<ClassicGameDomain<ID>> + EnvironmentStateSequential<ClassicGameDomain<ID>, Updates = Vec<(ID, ClassicGameUpdate<ID>)>>{
+    fn state_score_of_player(&self, agent: &ID) -> IntReward {
+        self.inner.state_score_of_player(agent)
+    }
+}
+
+impl<ID: UsizeAgentId, S: Renew<()>> Renew<()> for ContinuationState<ID, S>{
+    fn renew_from(&mut self, base: ()) {
+        self.inner.renew_from(base);
+        self.rounds_played = 0;
+        self.stopped = false;
+    }
+}
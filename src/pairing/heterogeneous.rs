@@ -0,0 +1,233 @@
+use std::fmt::{Display, Formatter};
+use std::marker::PhantomData;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use serde::{Deserialize, Serialize};
+use amfiteatr_core::domain::Renew;
+use amfiteatr_core::env::{EnvironmentStateSequential, EnvironmentStateUniScore};
+use amfiteatr_classic::{AsymmetricRewardTableInt, Side, SymmetricRewardTableInt};
+use amfiteatr_classic::domain::{ClassicAction, ClassicGameDomain, ClassicGameError, ClassicGameUpdate, EncounterReport, IntReward, UsizeAgentId};
+use crate::games::GamePreset;
+
+#[derive(thiserror::Error, Debug)]
+pub enum RewardTablesError{
+    #[error("Failed reading reward tables {path:?}: {source}")]
+    Io{
+        path: PathBuf,
+        source: std::io::Error,
+    },
+    #[error("Invalid reward tables {path:?}: {source}")]
+    Json{
+        path: PathBuf,
+        source: serde_json::Error,
+    },
+    #[error("Reward tables assign table to agent {agent}, but there are {players} players")]
+    UnknownAgent{
+        agent: usize,
+        players: usize,
+    },
+    #[error("Group {group} of reward tables sets both game and table")]
+    AmbiguousTable{
+        group: usize,
+    },
+}
+
+/// Agents of group, single id or range of ids (`to` is excluded).
+#[derive(Deserialize, Clone, Debug)]
+#[serde(untagged)]
+pub enum AgentSelector{
+    One(usize),
+    Range{
+        from: usize,
+        to: usize,
+    },
+}
+
+impl AgentSelector{
+    fn agents(&self) -> std::ops::Range<usize>{
+        match self{
+            AgentSelector::One(agent) => *agent..*agent + 1,
+            AgentSelector::Range{from, to} => *from..*to,
+        }
+    }
+}
+
+/// Table of group: preset `game`, custom symmetric `table` given as `[cc, cd, dc, dd]` (`c` is
+/// `Down` and `d` is `Up`) or default table when neither is set. All payoffs of group are
+/// multiplied by `multiplier`.
+#[derive(Deserialize, Clone, Debug)]
+pub struct GroupTableConfig{
+    pub agents: Vec<AgentSelector>,
+    pub game: Option<GamePreset>,
+    pub table: Option<[IntReward; 4]>,
+    #[serde(default = "default_multiplier")]
+    pub multiplier: IntReward,
+}
+
+fn default_multiplier() -> IntReward{
+    1
+}
+
+/// Configuration of reward tables read from json file, for example:
+/// ```json
+/// {"groups": [
+///     {"agents": [0, 1], "game": "stag-hunt"},
+///     {"agents": [{"from": 10, "to": 20}], "table": [3, 0, 5, 1], "multiplier": 2}
+/// ]}
+/// ```
+/// Agents of later groups override earlier ones, agents not in any group use default table.
+#[derive(Deserialize, Clone, Debug, Default)]
+pub struct RewardTablesConfig{
+    pub groups: Vec<GroupTableConfig>,
+}
+
+/// Table multiplied by integer factor on both sides.
+pub fn scaled_table(table: AsymmetricRewardTableInt, multiplier: IntReward) -> AsymmetricRewardTableInt{
+    let side = |side: Side|{
+        let r = |l, r| table.reward_for_side(side, l, r) * multiplier;
+        SymmetricRewardTableInt::new(
+            r(ClassicAction::Down, ClassicAction::Down), r(ClassicAction::Down, ClassicAction::Up),
+            r(ClassicAction::Up, ClassicAction::Down), r(ClassicAction::Up, ClassicAction::Up))
+    };
+    AsymmetricRewardTableInt::new(side(Side::Left), side(Side::Right))
+}
+
+/// Reward of agent in encounter reported to it, from side on which it played.
+pub fn encounter_reward(table: &AsymmetricRewardTableInt, report: &EncounterReport<impl UsizeAgentId>) -> IntReward{
+    match report.side{
+        Side::Left => table.reward_for_side(Side::Left, report.own_action, report.other_player_action),
+        Side::Right => table.reward_for_side(Side::Right, report.other_player_action, report.own_action),
+    }
+}
+
+/// Reward table of every agent, indexed by id.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct RewardTables{
+    default: AsymmetricRewardTableInt,
+    tables: Vec<AsymmetricRewardTableInt>,
+}
+
+impl RewardTables{
+    pub fn uniform(players: usize, table: AsymmetricRewardTableInt) -> Self{
+        Self{default: table, tables: vec![table; players]}
+    }
+
+    /// Tables given directly, `tables[i]` is table of agent `i`.
+    pub fn from_tables(default: AsymmetricRewardTableInt, tables: Vec<AsymmetricRewardTableInt>) -> Self{
+        Self{default, tables}
+    }
+
+    pub fn from_config(config: &RewardTablesConfig, players: usize, default: AsymmetricRewardTableInt) -> Result<Self, RewardTablesError>{
+        let mut tables = vec![default; players];
+        for (group, entry) in config.groups.iter().enumerate(){
+            let table = match (entry.game, entry.table){
+                (Some(_), Some(_)) => return Err(RewardTablesError::AmbiguousTable{group}),
+                (Some(game), None) => game.reward_table(),
+                (None, Some([cc, cd, dc, dd])) => SymmetricRewardTableInt::new(cc, cd, dc, dd).into(),
+                (None, None) => default,
+            };
+            let table = scaled_table(table, entry.multiplier);
+            for agent in entry.agents.iter().flat_map(|s| s.agents()){
+                *tables.get_mut(agent).ok_or(RewardTablesError::UnknownAgent{agent, players})? = table;
+            }
+        }
+        Ok(Self{default, tables})
+    }
+
+    /// Reads configuration from json file.
+    pub fn from_file(path: &Path, players: usize, default: AsymmetricRewardTableInt) -> Result<Self, RewardTablesError>{
+        let file = std::fs::File::open(path)
+            .map_err(|source| RewardTablesError::Io{path: path.to_path_buf(), source})?;
+        let config: RewardTablesConfig = serde_json::from_reader(std::io::BufReader::new(file))
+            .map_err(|source| RewardTablesError::Json{path: path.to_path_buf(), source})?;
+        Self::from_config(&config, players, default)
+    }
+
+    /// Table of agents not assigned to any group.
+    pub fn default_table(&self) -> AsymmetricRewardTableInt{
+        self.default
+    }
+
+    pub fn players(&self) -> usize{
+        self.tables.len()
+    }
+
+    pub fn table_of(&self, agent: usize) -> AsymmetricRewardTableInt{
+        self.tables[agent]
+    }
+
+    /// Reward of agent in encounter, evaluated with its own table.
+    pub fn reward(&self, agent: usize, report: &EncounterReport<impl UsizeAgentId>) -> IntReward{
+        encounter_reward(&self.tables[agent], report)
+    }
+}
+
+/// Wrapper of pairing state scoring every agent with its own reward table, so partners in
+/// encounter may have unequal incentives. Inner state must report executed actions, so it has
+/// to be wrapped in noise and not the other way.
+#[derive(Clone, Debug, Serialize)]
+pub struct HeterogeneousState<ID: UsizeAgentId, S>{
+    inner: S,
+    tables: Arc<RewardTables>,
+    score_cache: Vec<IntReward>,
+    #[serde(skip)]
+    _id: PhantomData<ID>,
+}
+
+impl<ID: UsizeAgentId, S> HeterogeneousState<ID, S>{
+    pub fn new(inner: S, tables: RewardTables) -> Self{
+        Self{inner, score_cache: vec![0; tables.players()], tables: Arc::new(tables), _id: PhantomData}
+    }
+
+    pub fn inner(&self) -> &S{
+        &self.inner
+    }
+
+    pub fn tables(&self) -> &RewardTables{
+        &self.tables
+    }
+}
+
+impl<ID: UsizeAgentId, S: Display> Display for HeterogeneousState<ID, S>{
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.inner)
+    }
+}
+
+impl<ID: UsizeAgentId, S> EnvironmentStateSequential<ClassicGameDomain<ID>> for HeterogeneousState<ID, S>
+where S: EnvironmentStateSequential<ClassicGameDomain<ID>, Updates = Vec<(ID, ClassicGameUpdate<ID>)>>{
+    type Updates = Vec<(ID, ClassicGameUpdate<ID>)>;
+
+    fn current_player(&self) -> Option<ID> {
+        self.inner.current_player()
+    }
+
+    fn is_finished(&self) -> bool {
+        self.inner.is_finished()
+    }
+
+    fn forward(&mut self, agent: ID, action: ClassicAction) -> Result<Self::Updates, ClassicGameError<ID>> {
+        let updates = self.inner.forward(agent, action)?;
+        // every player gets the same report of round
+        if let Some((_, update)) = updates.first(){
+            for (id, report) in update.encounters.iter(){
+                self.score_cache[id.as_usize()] += self.tables.reward(id.as_usize(), report);
+            }
+        }
+        Ok(updates)
+    }
+}
+
+impl<ID: UsizeAgentId, S> EnvironmentStateUniScore<ClassicGameDomain<ID>> for HeterogeneousState<ID, S>
+where S: EnvironmentStateSequential<ClassicGameDomain<ID>, Updates = Vec<(ID, ClassicGameUpdate<ID>)>>{
+    fn state_score_of_player(&self, agent: &ID) -> IntReward {
+        self.score_cache[agent.as_usize()]
+    }
+}
+
+impl<ID: UsizeAgentId, S: Renew<()>> Renew<()> for HeterogeneousState<ID, S>{
+    fn renew_from(&mut self, base: ()) {
+        self.inner.renew_from(base);
+        self.score_cache.iter_mut().for_each(|s| *s = 0);
+    }
+}
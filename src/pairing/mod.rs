@@ -0,0 +1,41 @@
+pub mod noise;
+pub mod continuation;
+pub mod reputation;
+pub mod heterogeneous;
+pub mod replay;
+pub mod compact;
+pub mod seeded;
+
+use rand::rngs::StdRng;
+use rand::SeedableRng;
+use amfiteatr_classic::AsymmetricRewardTableInt;
+use amfiteatr_classic::domain::{ClassicGameError, UsizeAgentId};
+use crate::pairing::continuation::ContinuationState;
+use crate::pairing::heterogeneous::{HeterogeneousState, RewardTables};
+use crate::pairing::noise::{Noise, NoisyState};
+use crate::pairing::seeded::SeededPairingState;
+
+/// Pairing state with noise, random length of game and reward table of every agent.
+pub type GameState<ID> = NoisyState<ID, HeterogeneousState<ID, ContinuationState<ID, SeededPairingState<ID>>>>;
+
+/// Creates state of game for even number of players. Without `continuation` game has exactly
+/// `rounds` rounds, otherwise after every round it continues with given probability, up to `rounds`.
+pub fn new_game_state<ID: UsizeAgentId>(players: usize, rounds: usize, reward_table: AsymmetricRewardTableInt,
+                                        noise: Noise, continuation: Option<f64>) -> Result<GameState<ID>, ClassicGameError<ID>>{
+    new_game_state_with_tables(rounds, RewardTables::uniform(players, reward_table), noise, continuation, None)
+}
+
+/// Creates state of game in which every agent is scored with own reward table, number of players
+/// is number of tables. With `seed` pairings, noise and length of game are the same in every run.
+pub fn new_game_state_with_tables<ID: UsizeAgentId>(rounds: usize, tables: RewardTables, noise: Noise,
+                                                    continuation: Option<f64>, seed: Option<u64>) -> Result<GameState<ID>, ClassicGameError<ID>>{
+    let mut seeds = match seed{
+        Some(seed) => StdRng::seed_from_u64(seed),
+        None => StdRng::from_entropy(),
+    };
+    let mut rng = || StdRng::seed_from_u64(rand::Rng::gen(&mut seeds));
+    // inner state keeps own scores, they are replaced by scores from tables
+    let pairing = SeededPairingState::new_even_with_rng(tables.players(), rounds, tables.default_table(), rng())?;
+    let continued = ContinuationState::new(pairing, continuation.unwrap_or(1.0)).with_rng(rng());
+    Ok(NoisyState::new(HeterogeneousState::new(continued, tables), noise).with_rng(rng()))
+}
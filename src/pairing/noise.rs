@@ -0,0 +1,208 @@
+use std::collections::HashMap;
+use std::fmt::{Display, Formatter};
+use std::sync::Arc;
+use log::debug;
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use serde::{Deserialize, Serialize};
+use amfiteatr_core::domain::Renew;
+use amfiteatr_core::env::{EnvironmentStateSequential, EnvironmentStateUniScore};
+use amfiteatr_classic::domain::{ClassicAction, ClassicGameDomain, ClassicGameError, ClassicGameUpdate, EncounterReport, IntReward, UsizeAgentId};
+
+/// Probabilities of noise in game.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Serialize)]
+pub struct Noise{
+    /// Probability that action executed by environment is opposite to chosen one (trembling hand).
+    pub action: f64,
+    /// Probability that agent sees opposite action of its opponent than was executed.
+    pub observation: f64,
+}
+
+impl Noise{
+    pub fn new(action: f64, observation: f64) -> Self{
+        Self{action, observation}
+    }
+
+    pub fn is_silent(&self) -> bool{
+        self.action == 0.0 && self.observation == 0.0
+    }
+}
+
+/// Parses probability for command line options, accepting values in `[0, 1]`.
+pub fn parse_probability(s: &str) -> Result<f64, String>{
+    let p: f64 = s.parse().map_err(|e| format!("{e}"))?;
+    match (0.0..=1.0).contains(&p){
+        true => Ok(p),
+        false => Err(format!("{p} is not probability in range [0, 1]")),
+    }
+}
+
+pub fn flipped(action: ClassicAction) -> ClassicAction{
+    match action{
+        ClassicAction::Up => ClassicAction::Down,
+        ClassicAction::Down => ClassicAction::Up,
+    }
+}
+
+/// Action chosen by agent and action that environment executed instead.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ExecutedAction{
+    pub intended: ClassicAction,
+    pub executed: ClassicAction,
+}
+
+impl ExecutedAction{
+    pub fn is_flipped(&self) -> bool{
+        self.intended != self.executed
+    }
+}
+
+/// Wrapper of pairing state adding noise to actions and observations.
+///
+/// Action of agent is flipped with probability [`Noise::action`] before it is passed to inner state,
+/// so scores and encounter reports use executed action. Intended and executed actions are noted
+/// in state, so they are present in environment's trajectory, while agent's trajectory keeps intended
+/// action as taken and executed one as own action in its information set.
+///
+/// When round ends every agent independently with probability [`Noise::observation`] gets
+/// update in which action of its opponent is flipped (in its own report and opponent's report).
+#[derive(Clone, Debug, Serialize)]
+pub struct NoisyState<ID: UsizeAgentId, S>{
+    inner: S,
+    noise: Noise,
+    actions: Vec<Option<ExecutedAction>>,
+    previous_actions: Vec<Arc<Vec<Option<ExecutedAction>>>>,
+    observation_flips: usize,
+    #[serde(skip)]
+    rng: StdRng,
+    #[serde(skip)]
+    _id: std::marker::PhantomData<ID>,
+}
+
+impl<ID: UsizeAgentId, S> NoisyState<ID, S>{
+    pub fn new(inner: S, noise: Noise) -> Self{
+        Self{inner, noise, actions: Vec::new(), previous_actions: Vec::new(), observation_flips: 0,
+            rng: StdRng::from_entropy(), _id: Default::default()}
+    }
+
+    /// Replaces generator of noise, e.g. with seeded one.
+    pub fn with_rng(mut self, rng: StdRng) -> Self{
+        self.rng = rng;
+        self
+    }
+
+    pub fn inner(&self) -> &S{
+        &self.inner
+    }
+
+    pub fn noise(&self) -> Noise{
+        self.noise
+    }
+
+    /// Intended and executed actions in finished rounds of episode, indexed by agent.
+    pub fn previous_actions(&self) -> &[Arc<Vec<Option<ExecutedAction>>>]{
+        &self.previous_actions[..]
+    }
+
+    pub fn action_flips(&self) -> usize{
+        self.previous_actions.iter().flat_map(|round| round.iter())
+            .chain(self.actions.iter())
+            .flatten()
+            .filter(|a| a.is_flipped())
+            .count()
+    }
+
+    /// Number of updates in episode with opponent's action flipped.
+    pub fn observation_flips(&self) -> usize{
+        self.observation_flips
+    }
+
+    fn misperceived(&self, observer: &ID, encounters: &HashMap<ID, EncounterReport<ID>>)
+        -> Option<Arc<HashMap<ID, EncounterReport<ID>>>>{
+        let opponent = encounters.get(observer)?.other_id;
+        let mut encounters = encounters.clone();
+        if let Some(report) = encounters.get_mut(observer){
+            report.other_player_action = flipped(report.other_player_action);
+        }
+        if let Some(report) = encounters.get_mut(&opponent){
+            report.own_action = flipped(report.own_action);
+        }
+        Some(Arc::new(encounters))
+    }
+}
+
+impl<ID: UsizeAgentId, S: Display> Display for NoisyState<ID, S>{
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.inner)?;
+        if !self.noise.is_silent(){
+            write!(f, "\nNoise: flipped actions: {}, flipped observations: {}", self.action_flips(), self.observation_flips)?;
+        }
+        Ok(())
+    }
+}
+
+impl<ID: UsizeAgentId, S> EnvironmentStateSequential<ClassicGameDomain<ID>> for NoisyState<ID, S>
+where S: EnvironmentStateSequential<ClassicGameDomain<ID>, Updates = Vec<(ID, ClassicGameUpdate<ID>)>>{
+    type Updates = Vec<(ID, ClassicGameUpdate<ID>)>;
+
+    fn current_player(&self) -> Option<ID> {
+        self.inner.current_player()
+    }
+
+    fn is_finished(&self) -> bool {
+        self.inner.is_finished()
+    }
+
+    fn forward(&mut self, agent: ID, action: ClassicAction) -> Result<Self::Updates, ClassicGameError<ID>> {
+        let executed = match self.noise.action > 0.0 && self.rng.gen_bool(self.noise.action){
+            true => {
+                debug!("Action {action:?} of agent {agent} trembles into {:?}", flipped(action));
+                flipped(action)
+            },
+            false => action,
+        };
+        let updates = self.inner.forward(agent, executed)?;
+        let index = agent.as_usize();
+        if self.actions.len() <= index{
+            self.actions.resize(index + 1, None);
+        }
+        self.actions[index] = Some(ExecutedAction{intended: action, executed});
+        if updates.is_empty(){
+            return Ok(updates);
+        }
+        let round = std::mem::take(&mut self.actions);
+        self.previous_actions.push(Arc::new(round));
+        if self.noise.observation == 0.0{
+            return Ok(updates);
+        }
+        Ok(updates.into_iter().map(|(observer, update)|{
+            if !self.rng.gen_bool(self.noise.observation){
+                return (observer, update);
+            }
+            match self.misperceived(&observer, &update.encounters){
+                Some(encounters) => {
+                    debug!("Agent {observer} misperceives action of its opponent");
+                    self.observation_flips += 1;
+                    (observer, ClassicGameUpdate{encounters, pairing: update.pairing})
+                },
+                None => (observer, update),
+            }
+        }).collect())
+    }
+}
+
+impl<ID: UsizeAgentId, S> EnvironmentStateUniScore<ClassicGameDomain<ID>> for NoisyState<ID, S>
+where S: EnvironmentStateUniScore<ClassicGameDomain<ID>> + EnvironmentStateSequential<ClassicGameDomain<ID>, Updates = Vec<(ID, ClassicGameUpdate<ID>)>>{
+    fn state_score_of_player(&self, agent: &ID) -> IntReward {
+        self.inner.state_score_of_player(agent)
+    }
+}
+
+impl<ID: UsizeAgentId, S: Renew<()>> Renew<()> for NoisyState<ID, S>{
+    fn renew_from(&mut self, base: ()) {
+        self.inner.renew_from(base);
+        self.actions.clear();
+        self.previous_actions.clear();
+        self.observation_flips = 0;
+    }
+}
@@ -0,0 +1,247 @@
+use std::path::{Path, PathBuf};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use amfiteatr_classic::{AsymmetricRewardTableInt, Side};
+use amfiteatr_core::env::EnvironmentStateUniScore;
+use amfiteatr_classic::domain::{AgentNum, ClassicAction, EncounterReport, IntReward, UsizeAgentId};
+use crate::pairing::GameState;
+use crate::pairing::heterogeneous::RewardTables;
+use crate::pairing::noise::ExecutedAction;
+
+#[derive(thiserror::Error, Debug)]
+pub enum ReplayError{
+    #[error("Failed reading trajectory {path:?}: {source}")]
+    Io{
+        path: PathBuf,
+        source: std::io::Error,
+    },
+    #[error("Invalid trajectory {path:?}: {source}")]
+    Json{
+        path: PathBuf,
+        source: serde_json::Error,
+    },
+    #[error("Trajectory has no {0} in final state")]
+    MissingField(&'static str),
+    #[error("Failed streaming binary trajectory: {0}")]
+    Stream(std::io::Error),
+    #[error("Malformed trajectory: {0}")]
+    Format(&'static str),
+    #[error("Agent {agent} is paired with {partner} in round {round}, but not the other way")]
+    InconsistentPairing{
+        round: usize,
+        agent: usize,
+        partner: usize,
+    },
+}
+
+#[derive(Deserialize, Copy, Clone, Debug)]
+pub(crate) struct RecordedPairing{
+    pub(crate) paired_player: usize,
+    pub(crate) taken_action: Option<ClassicAction>,
+    pub(crate) side: Side,
+}
+
+#[derive(Deserialize, Clone, Debug)]
+struct RecordedStep{
+    is_action_valid: bool,
+}
+
+/// Environment trajectory written by pairing game, only final state is read as it keeps
+/// pairings and actions of all finished rounds.
+#[derive(Deserialize, Clone, Debug)]
+struct RecordedTrajectory{
+    history: Vec<RecordedStep>,
+    final_state: Value,
+}
+
+/// Finds layer of wrapped state (e.g. noisy, continuation) having given field.
+fn find_layer<'a>(state: &'a Value, field: &str) -> Option<&'a Value>{
+    let mut layer = state;
+    loop{
+        if layer.get(field).is_some(){
+            return Some(layer);
+        }
+        layer = layer.get("inner")?;
+    }
+}
+
+fn field<T: serde::de::DeserializeOwned>(layer: &Value, name: &'static str) -> Result<T, ReplayError>{
+    let value = layer.get(name).ok_or(ReplayError::MissingField(name))?;
+    T::deserialize(value).map_err(|_| ReplayError::MissingField(name))
+}
+
+/// Encounter of agent in round as seen from its side.
+#[derive(Serialize, Copy, Clone, Debug)]
+pub struct ReplayEncounter{
+    pub agent: usize,
+    pub partner: usize,
+    pub side: Side,
+    /// Action chosen by agent, present only when it was recorded by noisy state.
+    pub intended: Option<ClassicAction>,
+    pub action: ClassicAction,
+    pub partner_action: ClassicAction,
+    pub reward: IntReward,
+    /// Score of agent after this round.
+    pub score: IntReward,
+}
+
+impl ReplayEncounter{
+    pub fn is_flipped(&self) -> bool{
+        self.intended.is_some_and(|a| a != self.action)
+    }
+}
+
+#[derive(Serialize, Clone, Debug)]
+pub struct ReplayRound{
+    pub round: usize,
+    /// Encounters indexed by agent.
+    pub encounters: Vec<ReplayEncounter>,
+}
+
+/// Per round metrics regenerated from replay.
+#[derive(Serialize, Clone, Debug, Default)]
+pub struct ReplayMetrics{
+    pub agents: Vec<usize>,
+    /// Fraction of selected agents that cooperated (executed `Down`) in round.
+    pub cooperation: Vec<f32>,
+    pub average_reward: Vec<f32>,
+    /// Scores of selected agents after every round, in order of `agents`.
+    pub scores: Vec<Vec<IntReward>>,
+}
+
+/// Game of pairing environment reconstructed from saved trajectory.
+#[derive(Serialize, Clone, Debug)]
+pub struct GameReplay{
+    pub players: usize,
+    pub tables: RewardTables,
+    pub steps: usize,
+    pub invalid_steps: usize,
+    pub rounds: Vec<ReplayRound>,
+    /// Scores noted by environment, for comparison with scores of replay.
+    pub recorded_scores: Vec<IntReward>,
+}
+
+impl GameReplay{
+    /// Reads trajectory written as json by [`write_json`](crate::error::write_json).
+    pub fn from_json_file(path: &Path) -> Result<Self, ReplayError>{
+        let file = std::fs::File::open(path)
+            .map_err(|source| ReplayError::Io{path: path.to_path_buf(), source})?;
+        let trajectory: RecordedTrajectory = serde_json::from_reader(std::io::BufReader::new(file))
+            .map_err(|source| ReplayError::Json{path: path.to_path_buf(), source})?;
+        let steps = trajectory.history.len();
+        let invalid_steps = trajectory.history.iter().filter(|s| !s.is_action_valid).count();
+        Self::from_final_state(&trajectory.final_state, steps, invalid_steps)
+    }
+
+    /// Reconstructs game from state of finished episode, e.g. state of environment after run.
+    pub fn from_game_state<ID: UsizeAgentId>(state: &GameState<ID>, steps: usize, invalid_steps: usize) -> Result<Self, ReplayError>{
+        let heterogeneous = state.inner();
+        let pairing = heterogeneous.inner().inner();
+        let previous: Vec<Vec<RecordedPairing>> = pairing.previous_pairings().iter()
+            .map(|round| round.iter().map(|p| RecordedPairing{
+                paired_player: p.paired_player.as_usize(),
+                taken_action: p.taken_action,
+                side: p.side,
+            }).collect())
+            .collect();
+        let intended: Vec<Vec<Option<ClassicAction>>> = state.previous_actions().iter()
+            .map(|round| round.iter().map(|a| a.map(|a| a.intended)).collect())
+            .collect();
+        let recorded_scores = (0..pairing.players())
+            .map(|agent| heterogeneous.state_score_of_player(&ID::make_from_usize(agent)))
+            .collect();
+        Self::from_records(heterogeneous.tables().clone(), &previous, &intended, recorded_scores, steps, invalid_steps)
+    }
+
+    fn from_final_state(state: &Value, steps: usize, invalid_steps: usize) -> Result<Self, ReplayError>{
+        let pairing = find_layer(state, "previous_pairings").ok_or(ReplayError::MissingField("previous_pairings"))?;
+        let previous: Vec<Vec<RecordedPairing>> = field(pairing, "previous_pairings")?;
+        let default_table: AsymmetricRewardTableInt = field(pairing, "reward_table")?;
+        let players = previous.first().map(|r| r.len())
+            .unwrap_or_else(|| pairing.get("indexes").and_then(Value::as_array).map_or(0, |i| i.len()));
+        // per agent tables are present since heterogeneous state was introduced
+        let (tables, recorded_scores) = match find_layer(state, "tables"){
+            Some(layer) => (field(layer, "tables")?, field(layer, "score_cache")?),
+            None => (RewardTables::uniform(players, default_table), field(pairing, "score_cache")?),
+        };
+        let intended: Vec<Vec<Option<ExecutedAction>>> = match find_layer(state, "previous_actions"){
+            Some(layer) => field(layer, "previous_actions")?,
+            None => Vec::new(),
+        };
+        let intended: Vec<Vec<Option<ClassicAction>>> = intended.into_iter()
+            .map(|round| round.into_iter().map(|a| a.map(|a| a.intended)).collect())
+            .collect();
+        Self::from_records(tables, &previous, &intended, recorded_scores, steps, invalid_steps)
+    }
+
+    /// Scores encounters of recorded rounds. Intended actions are optional, rounds missing in
+    /// `intended` are treated as played without noise.
+    pub(crate) fn from_records(tables: RewardTables, previous: &[Vec<RecordedPairing>], intended: &[Vec<Option<ClassicAction>>],
+                               recorded_scores: Vec<IntReward>, steps: usize, invalid_steps: usize) -> Result<Self, ReplayError>{
+        let players = tables.players();
+        let mut scores = vec![0; players];
+        let mut rounds = Vec::with_capacity(previous.len());
+        for (round, pairings) in previous.iter().enumerate(){
+            if pairings.len() != players{
+                return Err(ReplayError::Format("number of pairings differs from number of players"));
+            }
+            let mut encounters = Vec::with_capacity(players);
+            for (agent, pairing) in pairings.iter().enumerate(){
+                let partner = pairing.paired_player;
+                let partner_action = pairings.get(partner).filter(|p| p.paired_player == agent)
+                    .and_then(|p| p.taken_action)
+                    .ok_or(ReplayError::InconsistentPairing{round, agent, partner})?;
+                let action = pairing.taken_action.ok_or(ReplayError::MissingField("taken_action"))?;
+                let report = EncounterReport{
+                    own_action: action,
+                    other_player_action: partner_action,
+                    side: pairing.side,
+                    other_id: partner as AgentNum,
+                };
+                let reward = tables.reward(agent, &report);
+                scores[agent] += reward;
+                encounters.push(ReplayEncounter{
+                    agent, partner, action, partner_action, reward,
+                    side: pairing.side,
+                    intended: intended.get(round).and_then(|r| r.get(agent)).copied().flatten(),
+                    score: scores[agent],
+                });
+            }
+            rounds.push(ReplayRound{round, encounters});
+        }
+        Ok(Self{players, tables, steps, invalid_steps, rounds, recorded_scores})
+    }
+
+    /// Scores of agents after last round of replay.
+    pub fn scores(&self) -> Vec<IntReward>{
+        self.rounds.last()
+            .map(|r| r.encounters.iter().map(|e| e.score).collect())
+            .unwrap_or_else(|| vec![0; self.players])
+    }
+
+    /// Metrics of given agents, all agents are used when none is given.
+    pub fn metrics(&self, agents: &[usize]) -> ReplayMetrics{
+        let agents: Vec<usize> = match agents.is_empty(){
+            true => (0..self.players).collect(),
+            false => agents.iter().copied().filter(|a| *a < self.players).collect(),
+        };
+        if agents.is_empty(){
+            return ReplayMetrics::default();
+        }
+        let mut metrics = ReplayMetrics{
+            scores: vec![Vec::with_capacity(self.rounds.len()); agents.len()],
+            ..Default::default()
+        };
+        for round in self.rounds.iter(){
+            let selected = || agents.iter().map(|a| &round.encounters[*a]);
+            let cooperators = selected().filter(|e| e.action == ClassicAction::Down).count();
+            metrics.cooperation.push(cooperators as f32 / agents.len() as f32);
+            metrics.average_reward.push(selected().map(|e| e.reward as f32).sum::<f32>() / agents.len() as f32);
+            for (series, encounter) in metrics.scores.iter_mut().zip(selected()){
+                series.push(encounter.score);
+            }
+        }
+        metrics.agents = agents;
+        metrics
+    }
+}
@@ -0,0 +1,651 @@
+use std::fmt::{Display, Formatter};
+use std::marker::PhantomData;
+use std::sync::Arc;
+use clap::ValueEnum;
+use log::debug;
+use rand::prelude::SliceRandom;
+use rand::rngs::StdRng;
+use rand::SeedableRng;
+use serde::Serialize;
+use amfiteatr_core::agent::{EvaluatedInformationSet, InformationSet, Policy, PresentPossibleActions};
+use amfiteatr_core::domain::{DomainParameters, Renew};
+use amfiteatr_core::env::{EnvironmentStateSequential, EnvironmentStateUniScore};
+use amfiteatr_core::error::AmfiError;
+use amfiteatr_classic::{AsymmetricRewardTableInt, Side};
+use amfiteatr_classic::domain::{AgentNum, ClassicAction, IntReward, UsizeAgentId};
+use amfiteatr_rl::error::TensorRepresentationError;
+use amfiteatr_rl::tch::Tensor;
+use amfiteatr_rl::tensor_data::{ConversionToTensor, ConvertToTensor};
+use crate::sync::OpeningUpdates;
+
+/// Bound of image score.
+pub const IMAGE_SCORE_LIMIT: i32 = 5;
+
+/// How public reputation of agent changes after it acts towards partner. Reputation is good when
+/// it is not negative.
+#[derive(Serialize, Copy, Clone, Debug, PartialEq, Eq, ValueEnum)]
+pub enum ReputationRule{
+    /// Score rises with every cooperation and falls with every defection, bounded by [`IMAGE_SCORE_LIMIT`]
+    ImageScoring,
+    /// Cooperation gives good standing (`1`), defection gives bad standing (`-1`) unless partner
+    /// had bad standing
+    Standing,
+}
+
+/// Observable act of agent judged by reputation rule.
+#[derive(Serialize, Copy, Clone, Debug, PartialEq, Eq)]
+pub struct Assessment{
+    pub action: ClassicAction,
+    pub partner_reputation: i32,
+}
+
+impl ReputationRule{
+    pub fn initial(&self) -> i32{
+        match self{
+            ReputationRule::ImageScoring => 0,
+            ReputationRule::Standing => 1,
+        }
+    }
+
+    /// Greatest absolute value of reputation.
+    pub fn limit(&self) -> i32{
+        match self{
+            ReputationRule::ImageScoring => IMAGE_SCORE_LIMIT,
+            ReputationRule::Standing => 1,
+        }
+    }
+
+    pub fn assess(&self, reputation: i32, assessment: &Assessment) -> i32{
+        match (self, assessment.action){
+            (ReputationRule::ImageScoring, ClassicAction::Down) => (reputation + 1).min(IMAGE_SCORE_LIMIT),
+            (ReputationRule::ImageScoring, ClassicAction::Up) => (reputation - 1).max(-IMAGE_SCORE_LIMIT),
+            (ReputationRule::Standing, ClassicAction::Down) => 1,
+            (ReputationRule::Standing, ClassicAction::Up) => match is_good(assessment.partner_reputation){
+                true => -1,
+                false => 1,
+            },
+        }
+    }
+
+    /// Reputation of agent after acts of its trajectory.
+    pub fn replay<'a>(&self, trajectory: impl IntoIterator<Item = &'a Assessment>) -> i32{
+        trajectory.into_iter().fold(self.initial(), |reputation, a| self.assess(reputation, a))
+    }
+}
+
+pub fn is_good(reputation: i32) -> bool{
+    reputation >= 0
+}
+
+#[derive(thiserror::Error, Debug, Clone, PartialEq, Eq)]
+pub enum ReputationError<ID: UsizeAgentId>{
+    #[error("Order in game was violated. Expected player: {expected:?} given: {acted:}")]
+    GameViolatedOrder{
+        acted: ID,
+        expected: Option<ID>
+    },
+    #[error("Player: {0} played after GameOver")]
+    ActionAfterGameOver(ID),
+    #[error("Odd number of players: {0}")]
+    ExpectedEvenNumberOfPlayers(u32),
+}
+
+#[derive(Clone, Debug, Serialize)]
+pub struct ReputationDomain<ID: UsizeAgentId>{
+    _id: PhantomData<ID>
+}
+
+pub type ReputationDomainNumbered = ReputationDomain<AgentNum>;
+
+/// Stage of round. In choice stage `Down` accepts proposed partner and `Up` refuses them, in play
+/// stage actions have meaning of reward table (`Down` is cooperation).
+#[derive(Serialize, Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Stage{
+    Choice,
+    Play,
+}
+
+/// Partners proposed for round and reputations of all agents, seen when deciding on partners.
+#[derive(Clone, Debug, Serialize)]
+pub struct Proposal<ID: UsizeAgentId>{
+    pub partners: Vec<ID>,
+    pub reputations: Vec<i32>,
+}
+
+/// Result of round.
+#[derive(Clone, Debug, Serialize)]
+pub struct RoundReport<ID: UsizeAgentId>{
+    pub partners: Vec<ID>,
+    pub choices: Vec<ClassicAction>,
+    /// Actions of agents that played, `None` for agents whose pair was refused
+    pub actions: Vec<Option<ClassicAction>>,
+    /// Reputations before round
+    pub reputations: Vec<i32>,
+}
+
+#[derive(Clone, Debug)]
+pub enum ReputationUpdate<ID: UsizeAgentId>{
+    /// Sent at start of every round, before choice stage
+    Proposal(Arc<Proposal<ID>>),
+    /// Choices of all agents, sent after choice stage
+    Choices(Arc<Vec<ClassicAction>>),
+    Round(Arc<RoundReport<ID>>),
+}
+
+impl<ID: UsizeAgentId> DomainParameters for ReputationDomain<ID>{
+    type ActionType = ClassicAction;
+    type GameErrorType = ReputationError<ID>;
+    type UpdateType = ReputationUpdate<ID>;
+    type AgentId = ID;
+    type UniversalReward = IntReward;
+}
+
+impl<ID: UsizeAgentId> From<ReputationError<ID>> for AmfiError<ReputationDomain<ID>>{
+    fn from(value: ReputationError<ID>) -> Self {
+        AmfiError::Game(value)
+    }
+}
+
+/// Pairing game in which agents choose whether to play with partner proposed by environment,
+/// knowing their public reputation. Proposal of the first round is sent as opening update of
+/// episode, proposals of next rounds follow reports of previous ones. Pair plays only if both
+/// partners accept, otherwise both get outside option. Reputation of agent is updated with
+/// [`ReputationRule::assess`] after every encounter it played.
+///
+/// Partner choice is limited to accepting or refusing the one partner proposed for round,
+/// agents do not select among several candidates.
+#[derive(Clone, Debug, Serialize)]
+pub struct ReputationState<ID: UsizeAgentId>{
+    rule: ReputationRule,
+    reward_table: AsymmetricRewardTableInt,
+    outside_option: IntReward,
+    target_rounds: usize,
+    rounds_played: usize,
+    indexes: Vec<usize>,
+    partners: Vec<ID>,
+    stage: Stage,
+    choices: Vec<ClassicAction>,
+    actions: Vec<Option<ClassicAction>>,
+    reputations: Vec<i32>,
+    score_cache: Vec<IntReward>,
+    current_player_index: usize,
+    #[serde(skip)]
+    rng: StdRng,
+}
+
+impl<ID: UsizeAgentId> ReputationState<ID>{
+    pub fn new_even(players: usize, target_rounds: usize, reward_table: AsymmetricRewardTableInt,
+                    rule: ReputationRule, outside_option: IntReward) -> Result<Self, ReputationError<ID>>{
+        if !players.is_multiple_of(2){
+            return Err(ReputationError::ExpectedEvenNumberOfPlayers(players as u32));
+        }
+        let mut state = Self{
+            rule, reward_table, outside_option, target_rounds,
+            rounds_played: 0,
+            indexes: (0..players).collect(),
+            partners: Vec::new(),
+            stage: Stage::Choice,
+            choices: Vec::with_capacity(players),
+            actions: vec![None; players],
+            reputations: vec![rule.initial(); players],
+            score_cache: vec![0; players],
+            current_player_index: 0,
+            rng: StdRng::from_entropy(),
+        };
+        state.prepare_partners();
+        Ok(state)
+    }
+
+    /// Replaces generator of proposed partners, e.g. with seeded one, and proposes partners
+    /// again with it.
+    pub fn with_rng(mut self, rng: StdRng) -> Self{
+        self.rng = rng;
+        self.indexes.sort_unstable();
+        self.prepare_partners();
+        self
+    }
+
+    fn prepare_partners(&mut self){
+        self.indexes.shuffle(&mut self.rng);
+        let mut partners = vec![ID::make_from_usize(0); self.indexes.len()];
+        for pair in self.indexes.chunks(2){
+            partners[pair[0]] = ID::make_from_usize(pair[1]);
+            partners[pair[1]] = ID::make_from_usize(pair[0]);
+        }
+        self.partners = partners;
+    }
+
+    pub fn rule(&self) -> ReputationRule{
+        self.rule
+    }
+
+    pub fn reputations(&self) -> &[i32]{
+        &self.reputations[..]
+    }
+
+    pub fn rounds_played(&self) -> usize{
+        self.rounds_played
+    }
+
+    fn proposal(&self) -> Vec<(ID, ReputationUpdate<ID>)>{
+        let update = ReputationUpdate::Proposal(Arc::new(Proposal{
+            partners: self.partners.clone(),
+            reputations: self.reputations.clone(),
+        }));
+        (0..self.score_cache.len()).map(|i| (ID::make_from_usize(i), update.clone())).collect()
+    }
+
+    fn matched(&self, index: usize) -> bool{
+        self.choices.get(index) == Some(&ClassicAction::Down)
+            && self.choices.get(self.partners[index].as_usize()) == Some(&ClassicAction::Down)
+    }
+
+    /// Index of next player at least `from` that plays in current stage.
+    fn next_player_from(&self, from: usize) -> usize{
+        match self.stage{
+            Stage::Choice => from,
+            Stage::Play => (from..self.score_cache.len()).find(|i| self.matched(*i)).unwrap_or(self.score_cache.len()),
+        }
+    }
+
+    fn finish_round(&mut self) -> Vec<(ID, ReputationUpdate<ID>)>{
+        let reputations = self.reputations.clone();
+        for i in 0..self.score_cache.len(){
+            let partner = self.partners[i].as_usize();
+            self.score_cache[i] += match (self.actions[i], self.actions[partner]){
+                (Some(own), Some(other)) => {
+                    let assessment = Assessment{action: own, partner_reputation: reputations[partner]};
+                    self.reputations[i] = self.rule.assess(self.reputations[i], &assessment);
+                    match i < partner{
+                        true => self.reward_table.reward_for_side(Side::Left, own, other),
+                        false => self.reward_table.reward_for_side(Side::Right, other, own),
+                    }
+                },
+                _ => self.outside_option,
+            };
+        }
+        let partners = std::mem::take(&mut self.partners);
+        self.prepare_partners();
+        self.rounds_played += 1;
+        debug!("Finished round {}, reputations: {:?}", self.rounds_played, self.reputations);
+        let report = RoundReport{
+            partners,
+            choices: std::mem::take(&mut self.choices),
+            actions: std::mem::replace(&mut self.actions, vec![None; self.score_cache.len()]),
+            reputations,
+        };
+        self.stage = Stage::Choice;
+        self.current_player_index = 0;
+        let update = ReputationUpdate::Round(Arc::new(report));
+        let mut updates: Vec<_> = (0..self.score_cache.len()).map(|i| (ID::make_from_usize(i), update.clone())).collect();
+        if !self.is_finished(){
+            updates.extend(self.proposal());
+        }
+        updates
+    }
+}
+
+impl<ID: UsizeAgentId> Display for ReputationState<ID>{
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Rounds played: {}, reputations: {:?}, scores: {:?}", self.rounds_played, self.reputations, self.score_cache)
+    }
+}
+
+impl<ID: UsizeAgentId> EnvironmentStateSequential<ReputationDomain<ID>> for ReputationState<ID>{
+    type Updates = Vec<(ID, ReputationUpdate<ID>)>;
+
+    fn current_player(&self) -> Option<ID> {
+        match self.is_finished(){
+            true => None,
+            false => Some(ID::make_from_usize(self.current_player_index)),
+        }
+    }
+
+    fn is_finished(&self) -> bool {
+        self.rounds_played >= self.target_rounds
+    }
+
+    fn forward(&mut self, agent: ID, action: ClassicAction) -> Result<Self::Updates, ReputationError<ID>> {
+        let expected = self.current_player().ok_or(ReputationError::ActionAfterGameOver(agent))?;
+        if expected != agent{
+            return Err(ReputationError::GameViolatedOrder{acted: agent, expected: Some(expected)});
+        }
+        let players = self.score_cache.len();
+        match self.stage{
+            Stage::Choice => {
+                self.choices.push(action);
+                self.current_player_index += 1;
+                if self.current_player_index < players{
+                    return Ok(Vec::new());
+                }
+                self.stage = Stage::Play;
+                self.current_player_index = self.next_player_from(0);
+                let mut updates: Vec<_> = (0..players)
+                    .map(|i| (ID::make_from_usize(i), ReputationUpdate::Choices(Arc::new(self.choices.clone()))))
+                    .collect();
+                if self.current_player_index >= players{
+                    // nobody accepted partner
+                    updates.extend(self.finish_round());
+                }
+                Ok(updates)
+            },
+            Stage::Play => {
+                self.actions[agent.as_usize()] = Some(action);
+                self.current_player_index = self.next_player_from(self.current_player_index + 1);
+                match self.current_player_index < players{
+                    true => Ok(Vec::new()),
+                    false => Ok(self.finish_round()),
+                }
+            }
+        }
+    }
+}
+
+impl<ID: UsizeAgentId> EnvironmentStateUniScore<ReputationDomain<ID>> for ReputationState<ID>{
+    fn state_score_of_player(&self, agent: &ID) -> IntReward {
+        self.score_cache[agent.as_usize()]
+    }
+}
+
+impl<ID: UsizeAgentId> OpeningUpdates<ReputationDomain<ID>> for ReputationState<ID>{
+    fn opening_updates(&self) -> Vec<(ID, ReputationUpdate<ID>)> {
+        match self.is_finished(){
+            true => Vec::new(),
+            false => self.proposal(),
+        }
+    }
+}
+
+impl<ID: UsizeAgentId> Renew<()> for ReputationState<ID>{
+    fn renew_from(&mut self, _base: ()) {
+        let players = self.score_cache.len();
+        self.score_cache.iter_mut().for_each(|s| *s = 0);
+        self.rounds_played = 0;
+        self.current_player_index = 0;
+        self.stage = Stage::Choice;
+        self.choices.clear();
+        self.actions = vec![None; players];
+        self.reputations = vec![self.rule.initial(); players];
+        self.prepare_partners();
+    }
+}
+
+/// What agent knows about one round.
+#[derive(Serialize, Copy, Clone, Debug)]
+pub struct RoundMemory{
+    pub accepted: bool,
+    pub partner_accepted: bool,
+    pub own_action: Option<ClassicAction>,
+    pub partner_action: Option<ClassicAction>,
+    pub own_reputation: i32,
+    pub partner_reputation: i32,
+    pub payoff: IntReward,
+}
+
+/// Information set of agent knowing reputation of its partner and history of own encounters.
+#[derive(Clone, Debug, Serialize)]
+pub struct ReputationInfoSet<ID: UsizeAgentId>{
+    id: ID,
+    rule: ReputationRule,
+    reward_table: AsymmetricRewardTableInt,
+    outside_option: IntReward,
+    stage: Stage,
+    partner: Option<ID>,
+    own_reputation: i32,
+    partner_reputation: i32,
+    partner_accepted: bool,
+    history: Vec<RoundMemory>,
+    payoff: IntReward,
+}
+
+impl<ID: UsizeAgentId> ReputationInfoSet<ID>{
+    pub fn new(id: ID, reward_table: AsymmetricRewardTableInt, rule: ReputationRule, outside_option: IntReward) -> Self{
+        Self{
+            id, rule, reward_table, outside_option,
+            stage: Stage::Choice,
+            partner: None,
+            own_reputation: rule.initial(),
+            partner_reputation: rule.initial(),
+            partner_accepted: false,
+            history: Vec::new(),
+            payoff: 0,
+        }
+    }
+
+    /// Stage in which agent makes its next decision.
+    pub fn stage(&self) -> Stage{
+        self.stage
+    }
+
+    pub fn partner(&self) -> Option<&ID>{
+        self.partner.as_ref()
+    }
+
+    pub fn partner_reputation(&self) -> i32{
+        self.partner_reputation
+    }
+
+    pub fn own_reputation(&self) -> i32{
+        self.own_reputation
+    }
+
+    /// Whether partner accepted encounter, known in play stage.
+    pub fn partner_accepted(&self) -> bool{
+        self.partner_accepted
+    }
+
+    pub fn history(&self) -> &[RoundMemory]{
+        &self.history[..]
+    }
+
+    pub fn count_accepted(&self) -> usize{
+        self.history.iter().filter(|r| r.accepted).count()
+    }
+
+    pub fn count_played(&self) -> usize{
+        self.history.iter().filter(|r| r.own_action.is_some()).count()
+    }
+
+    pub fn count_cooperated(&self) -> usize{
+        self.history.iter().filter(|r| r.own_action == Some(ClassicAction::Down)).count()
+    }
+}
+
+impl<ID: UsizeAgentId> Display for ReputationInfoSet<ID>{
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Agent: {}, reputation: {}, partner reputation: {}, rounds: {}, payoff: {}",
+            self.id, self.own_reputation, self.partner_reputation, self.history.len(), self.payoff)
+    }
+}
+
+impl<ID: UsizeAgentId> InformationSet<ReputationDomain<ID>> for ReputationInfoSet<ID>{
+    fn agent_id(&self) -> &ID {
+        &self.id
+    }
+
+    fn is_action_valid(&self, _action: &ClassicAction) -> bool {
+        true
+    }
+
+    fn update(&mut self, update: ReputationUpdate<ID>) -> Result<(), ReputationError<ID>> {
+        match update{
+            ReputationUpdate::Proposal(proposal) => {
+                let partner = proposal.partners[self.id.as_usize()];
+                self.partner_reputation = proposal.reputations[partner.as_usize()];
+                self.own_reputation = proposal.reputations[self.id.as_usize()];
+                self.partner = Some(partner);
+                self.partner_accepted = false;
+                self.stage = Stage::Choice;
+            },
+            ReputationUpdate::Choices(choices) => {
+                self.partner_accepted = self.partner
+                    .map(|p| choices[p.as_usize()] == ClassicAction::Down)
+                    .unwrap_or(false);
+                self.stage = Stage::Play;
+            },
+            ReputationUpdate::Round(report) => {
+                let own = self.id.as_usize();
+                let partner = report.partners[own].as_usize();
+                let own_action = report.actions[own];
+                let partner_action = report.actions[partner];
+                let payoff = match (own_action, partner_action){
+                    (Some(a), Some(b)) => match own < partner{
+                        true => self.reward_table.reward_for_side(Side::Left, a, b),
+                        false => self.reward_table.reward_for_side(Side::Right, b, a),
+                    },
+                    _ => self.outside_option,
+                };
+                self.history.push(RoundMemory{
+                    accepted: report.choices[own] == ClassicAction::Down,
+                    partner_accepted: report.choices[partner] == ClassicAction::Down,
+                    own_action, partner_action,
+                    own_reputation: report.reputations[own],
+                    partner_reputation: report.reputations[partner],
+                    payoff,
+                });
+                self.payoff += payoff;
+                if let Some(action) = own_action{
+                    let assessment = Assessment{action, partner_reputation: report.reputations[partner]};
+                    self.own_reputation = self.rule.assess(self.own_reputation, &assessment);
+                }
+                self.partner = None;
+                self.partner_accepted = false;
+                self.stage = Stage::Choice;
+            }
+        }
+        Ok(())
+    }
+}
+
+impl<ID: UsizeAgentId> PresentPossibleActions<ReputationDomain<ID>> for ReputationInfoSet<ID>{
+    type ActionIteratorType = [ClassicAction; 2];
+
+    fn available_actions(&self) -> Self::ActionIteratorType {
+        [ClassicAction::Up, ClassicAction::Down]
+    }
+}
+
+impl<ID: UsizeAgentId> EvaluatedInformationSet<ReputationDomain<ID>> for ReputationInfoSet<ID>{
+    type RewardType = IntReward;
+
+    fn current_subjective_score(&self) -> Self::RewardType {
+        self.payoff
+    }
+
+    fn penalty_for_illegal(&self) -> Self::RewardType {
+        -100
+    }
+}
+
+impl<ID: UsizeAgentId> Renew<()> for ReputationInfoSet<ID>{
+    fn renew_from(&mut self, _base: ()) {
+        self.stage = Stage::Choice;
+        self.partner = None;
+        self.own_reputation = self.rule.initial();
+        self.partner_reputation = self.rule.initial();
+        self.partner_accepted = false;
+        self.history.clear();
+        self.payoff = 0;
+    }
+}
+
+/// Represents current decision (stage, own and partner's reputation scaled to `[-1, 1]`) and
+/// last rounds of history, six values per round: own and partner's choice, own and partner's
+/// action (`-1` when pair did not play) and reputations of both.
+#[derive(Copy, Clone, Debug, Default)]
+pub struct ReputationConversion{
+    rounds: usize,
+    shape: [i64; 1],
+}
+
+impl ReputationConversion{
+    const ROUND_VALUES: usize = 6;
+    const CURRENT_VALUES: usize = 3;
+
+    pub fn new(rounds: usize) -> Self{
+        Self{rounds, shape: [(Self::CURRENT_VALUES + rounds * Self::ROUND_VALUES) as i64]}
+    }
+}
+
+impl ConversionToTensor for ReputationConversion{
+    fn desired_shape(&self) -> &[i64] {
+        &self.shape[..]
+    }
+}
+
+impl<ID: UsizeAgentId> ConvertToTensor<ReputationConversion> for ReputationInfoSet<ID>{
+    fn try_to_tensor(&self, way: &ReputationConversion) -> Result<Tensor, TensorRepresentationError> {
+        if self.history.len() > way.rounds{
+            return Err(TensorRepresentationError::InfoSetNotFit {
+                info_set: format!("Reputation history of length {}", self.history.len()),
+                shape: Vec::from(way.desired_shape()),
+            });
+        }
+        let limit = self.rule.limit() as f32;
+        let action = |a: Option<ClassicAction>| match a{
+            Some(ClassicAction::Down) => 1.0,
+            Some(ClassicAction::Up) => 0.0,
+            None => -1.0,
+        };
+        let mut values = vec![-1.0f32; way.shape[0] as usize];
+        values[0] = match self.stage{
+            Stage::Choice => 0.0,
+            Stage::Play => 1.0,
+        };
+        values[1] = self.own_reputation as f32 / limit;
+        values[2] = self.partner_reputation as f32 / limit;
+        for (i, round) in self.history.iter().enumerate(){
+            let offset = ReputationConversion::CURRENT_VALUES + i * ReputationConversion::ROUND_VALUES;
+            values[offset] = round.accepted as u8 as f32;
+            values[offset + 1] = round.partner_accepted as u8 as f32;
+            values[offset + 2] = action(round.own_action);
+            values[offset + 3] = action(round.partner_action);
+            values[offset + 4] = round.own_reputation as f32 / limit;
+            values[offset + 5] = round.partner_reputation as f32 / limit;
+        }
+        Ok(Tensor::f_from_slice(&values[..])?)
+    }
+}
+
+/// Scripted behaviour towards partners.
+#[derive(Serialize, Copy, Clone, Debug, PartialEq, Eq, ValueEnum)]
+pub enum ReputationStrategy{
+    /// Accepts every partner and cooperates
+    Cooperator,
+    /// Accepts every partner and defects
+    Defector,
+    /// Accepts every partner, cooperates with those of good reputation
+    Discriminator,
+    /// Refuses partners of bad reputation and cooperates with accepted ones
+    Selector,
+}
+
+pub struct ReputationPolicy<ID: UsizeAgentId>{
+    strategy: ReputationStrategy,
+    _id: PhantomData<ID>,
+}
+
+impl<ID: UsizeAgentId> ReputationPolicy<ID>{
+    pub fn new(strategy: ReputationStrategy) -> Self{
+        Self{strategy, _id: PhantomData}
+    }
+}
+
+impl<ID: UsizeAgentId> Policy<ReputationDomain<ID>> for ReputationPolicy<ID>{
+    type InfoSetType = ReputationInfoSet<ID>;
+
+    fn select_action(&self, state: &Self::InfoSetType) -> Option<ClassicAction> {
+        let good = is_good(state.partner_reputation());
+        let down = match (state.stage(), self.strategy){
+            (Stage::Choice, ReputationStrategy::Selector) => good,
+            (Stage::Choice, _) => true,
+            (Stage::Play, ReputationStrategy::Defector) => false,
+            (Stage::Play, ReputationStrategy::Discriminator) => good,
+            (Stage::Play, _) => true,
+        };
+        match down{
+            true => Some(ClassicAction::Down),
+            false => Some(ClassicAction::Up),
+        }
+    }
+}
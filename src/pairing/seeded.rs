@@ -0,0 +1,191 @@
+use std::collections::HashMap;
+use std::fmt::{Display, Formatter};
+use std::marker::PhantomData;
+use std::sync::Arc;
+use log::debug;
+use rand::prelude::SliceRandom;
+use rand::rngs::StdRng;
+use rand::SeedableRng;
+use serde::Serialize;
+use amfiteatr_core::domain::Renew;
+use amfiteatr_core::env::{EnvironmentStateSequential, EnvironmentStateUniScore};
+use amfiteatr_classic::{AsymmetricRewardTableInt, Side};
+use amfiteatr_classic::domain::{ClassicAction, ClassicGameDomain, ClassicGameError, ClassicGameUpdate, EncounterReport, IntReward, UsizeAgentId};
+use amfiteatr_classic::env::{PairingVec, PlayerPairing};
+
+/// Pairing state playing like [`PairingState`](amfiteatr_classic::env::PairingState), but
+/// shuffling players with own generator, so the same seed gives the same pairings. Serialized
+/// fields are named like in classic state, so trajectories of both are read the same way.
+#[derive(Clone, Debug, Serialize)]
+pub struct SeededPairingState<ID: UsizeAgentId>{
+    actual_pairings: PairingVec<ID>,
+    previous_pairings: Vec<Arc<PairingVec<ID>>>,
+    target_rounds: usize,
+    indexes: Vec<usize>,
+    reward_table: AsymmetricRewardTableInt,
+    score_cache: Vec<IntReward>,
+    current_player_index: usize,
+    #[serde(skip)]
+    rng: StdRng,
+    #[serde(skip)]
+    _id: PhantomData<ID>,
+}
+
+impl<ID: UsizeAgentId> SeededPairingState<ID>{
+    /// Creates state with generator seeded from entropy.
+    pub fn new_even(players: usize, target_rounds: usize, reward_table: AsymmetricRewardTableInt) -> Result<Self, ClassicGameError<ID>>{
+        Self::new_even_with_rng(players, target_rounds, reward_table, StdRng::from_entropy())
+    }
+
+    pub fn new_even_with_rng(players: usize, target_rounds: usize, reward_table: AsymmetricRewardTableInt,
+                             rng: StdRng) -> Result<Self, ClassicGameError<ID>>{
+        if !players.is_multiple_of(2){
+            return Err(ClassicGameError::ExpectedEvenNumberOfPlayers(players as u32));
+        }
+        let mut state = Self{
+            actual_pairings: Vec::new(),
+            previous_pairings: Vec::with_capacity(target_rounds),
+            target_rounds,
+            indexes: (0..players).collect(),
+            reward_table,
+            score_cache: vec![0; players],
+            current_player_index: 0,
+            rng,
+            _id: PhantomData,
+        };
+        state.actual_pairings = state.shuffled_pairings();
+        Ok(state)
+    }
+
+    fn shuffled_pairings(&mut self) -> PairingVec<ID>{
+        self.indexes.shuffle(&mut self.rng);
+        debug!("Preparing new pairings for indexes: {:?}", self.indexes);
+        let mut pairings = vec![PlayerPairing{
+            paired_player: ID::make_from_usize(0),
+            taken_action: None,
+            side: Side::Left,
+        }; self.indexes.len()];
+        for pair in self.indexes.chunks_exact(2){
+            pairings[pair[0]] = PlayerPairing{paired_player: ID::make_from_usize(pair[1]), taken_action: None, side: Side::Left};
+            pairings[pair[1]] = PlayerPairing{paired_player: ID::make_from_usize(pair[0]), taken_action: None, side: Side::Right};
+        }
+        pairings
+    }
+
+    /// Pairings of current round, with actions of players that already acted.
+    pub fn actual_pairings(&self) -> &PairingVec<ID>{
+        &self.actual_pairings
+    }
+
+    /// Pairings with actions of finished rounds of episode.
+    pub fn previous_pairings(&self) -> &[Arc<PairingVec<ID>>]{
+        &self.previous_pairings[..]
+    }
+
+    pub fn reward_table(&self) -> &AsymmetricRewardTableInt{
+        &self.reward_table
+    }
+
+    pub fn players(&self) -> usize{
+        self.indexes.len()
+    }
+
+    pub fn is_round_clean(&self) -> bool{
+        self.current_player_index == 0
+    }
+
+    fn encounters(&self) -> HashMap<ID, EncounterReport<ID>>{
+        self.actual_pairings.iter().enumerate().filter_map(|(i, pairing)|{
+            Some((ID::make_from_usize(i), EncounterReport{
+                own_action: pairing.taken_action?,
+                other_player_action: self.actual_pairings[pairing.paired_player.as_usize()].taken_action?,
+                side: pairing.side,
+                other_id: pairing.paired_player,
+            }))
+        }).collect()
+    }
+}
+
+impl<ID: UsizeAgentId> Display for SeededPairingState<ID>{
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        for (r, round) in self.previous_pairings.iter().enumerate(){
+            writeln!(f, "Round: {r}:")?;
+            for (i, pairing) in round.iter().enumerate(){
+                let action = |a: Option<ClassicAction>| a.map_or(String::from("---"), |a| format!("{a:?}"));
+                writeln!(f, "\t{}\tpositioned: {:?}\tpaired with: {}\t;taken action: {}\tagainst: {}\t",
+                         i, pairing.side, pairing.paired_player, action(pairing.taken_action),
+                         action(round[pairing.paired_player.as_usize()].taken_action))?;
+            }
+        }
+        Ok(())
+    }
+}
+
+impl<ID: UsizeAgentId> EnvironmentStateSequential<ClassicGameDomain<ID>> for SeededPairingState<ID>{
+    type Updates = Vec<(ID, ClassicGameUpdate<ID>)>;
+
+    fn current_player(&self) -> Option<ID> {
+        match self.is_finished(){
+            true => None,
+            false => Some(ID::make_from_usize(self.current_player_index)),
+        }
+    }
+
+    fn is_finished(&self) -> bool {
+        self.previous_pairings.len() >= self.target_rounds
+    }
+
+    fn forward(&mut self, agent: ID, action: ClassicAction) -> Result<Self::Updates, ClassicGameError<ID>> {
+        let expected = self.current_player().ok_or(ClassicGameError::ActionAfterGameOver(agent))?;
+        if expected != agent{
+            return Err(ClassicGameError::GameViolatedOrder{acted: agent, expected: Some(expected)});
+        }
+        let index = agent.as_usize();
+        self.actual_pairings[index].taken_action = Some(action);
+        let pairing = self.actual_pairings[index];
+        let other = pairing.paired_player.as_usize();
+        if let Some(other_action) = self.actual_pairings[other].taken_action{
+            let (left, right) = match pairing.side{
+                Side::Left => (action, other_action),
+                Side::Right => (other_action, action),
+            };
+            let (left_reward, right_reward) = self.reward_table.rewards(left, right);
+            let (own, other_reward) = match pairing.side{
+                Side::Left => (left_reward, right_reward),
+                Side::Right => (right_reward, left_reward),
+            };
+            self.score_cache[index] += own;
+            self.score_cache[other] += other_reward;
+        }
+        self.current_player_index += 1;
+        if self.current_player_index < self.actual_pairings.len(){
+            return Ok(Vec::new());
+        }
+        let encounters = Arc::new(self.encounters());
+        let next = self.shuffled_pairings();
+        let finished = std::mem::replace(&mut self.actual_pairings, next);
+        self.previous_pairings.push(Arc::new(finished));
+        self.current_player_index = 0;
+        debug!("Finished round {}", self.previous_pairings.len());
+        let update = ClassicGameUpdate{
+            encounters,
+            pairing: (!self.is_finished()).then(|| Arc::new(self.actual_pairings.clone())),
+        };
+        Ok((0..self.actual_pairings.len()).map(|i| (ID::make_from_usize(i), update.clone())).collect())
+    }
+}
+
+impl<ID: UsizeAgentId> EnvironmentStateUniScore<ClassicGameDomain<ID>> for SeededPairingState<ID>{
+    fn state_score_of_player(&self, agent: &ID) -> IntReward {
+        self.score_cache[agent.as_usize()]
+    }
+}
+
+impl<ID: UsizeAgentId> Renew<()> for SeededPairingState<ID>{
+    fn renew_from(&mut self, _base: ()) {
+        self.score_cache.iter_mut().for_each(|s| *s = 0);
+        self.previous_pairings.clear();
+        self.current_player_index = 0;
+        self.actual_pairings = self.shuffled_pairings();
+    }
+}
@@ -1,170 +1,716 @@
 use std::cmp::Ordering;
+use std::error::Error;
+use std::ops::Range;
 use std::path::Path;
-use log::{debug, info};
+use log::debug;
+use plotters::chart::DualCoordChartContext;
+use plotters::coord::Shift;
+use plotters::coord::types::RangedCoordf32;
+use plotters::coord::ranged1d::ValueFormatter;
+use plotters::element::{Drawable, PointCollection};
 use plotters::prelude::*;
+use plotters::style::text_anchor::{HPos, Pos, VPos};
+use plotters_backend::{BackendCoord, DrawingErrorKind};
+use serde::Serialize;
+
+/// Way of drawing line connecting points of series.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub enum LineStyle {
+    #[default]
+    Solid,
+    Dashed,
+    Dotted,
+    /// Only markers are drawn.
+    NoLine,
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Marker {
+    Circle,
+    Cross,
+    Triangle,
+}
+
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub enum Axis {
+    #[default]
+    Primary,
+    /// Right side y axis, with own range and scale.
+    Secondary,
+}
+
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub enum LegendPosition {
+    UpperLeft,
+    UpperRight,
+    #[default]
+    LowerLeft,
+    LowerRight,
+    MiddleLeft,
+    MiddleRight,
+}
+
+impl From<LegendPosition> for SeriesLabelPosition {
+    fn from(value: LegendPosition) -> Self {
+        match value {
+            LegendPosition::UpperLeft => SeriesLabelPosition::UpperLeft,
+            LegendPosition::UpperRight => SeriesLabelPosition::UpperRight,
+            LegendPosition::LowerLeft => SeriesLabelPosition::LowerLeft,
+            LegendPosition::LowerRight => SeriesLabelPosition::LowerRight,
+            LegendPosition::MiddleLeft => SeriesLabelPosition::MiddleLeft,
+            LegendPosition::MiddleRight => SeriesLabelPosition::MiddleRight,
+        }
+    }
+}
+
+/// Output image format.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, clap::ValueEnum)]
+pub enum PlotFormat {
+    Svg,
+    Png,
+}
+
+impl PlotFormat {
+    pub fn extension(&self) -> &'static str {
+        match self {
+            PlotFormat::Svg => "svg",
+            PlotFormat::Png => "png",
+        }
+    }
+
+
+    /// Guesses format from file extension, defaulting to SVG.
+    pub fn from_path(file: &Path) -> Self {
+        match file.extension().and_then(|e| e.to_str()) {
+            Some(e) if e.eq_ignore_ascii_case("png") => PlotFormat::Png,
+            _ => PlotFormat::Svg,
+        }
+    }
+}
 
+#[derive(Clone, Debug)]
 pub struct PlotSeries {
     pub data: Vec<f32>,
     pub description: String,
     pub color: RGBColor,
+    pub line_style: LineStyle,
+    pub stroke_width: u32,
+    pub marker: Option<Marker>,
+    pub axis: Axis,
 }
 
-pub fn plot_payoffs(file: &Path, series_0: &PlotSeries) -> Result<(), Box<dyn std::error::Error>>{
-    let root  = SVGBackend::new(&file, (800, 600)).into_drawing_area();
-    root.fill(&WHITE)?;
+impl PlotSeries {
+    pub fn new(data: Vec<f32>, description: &str, color: RGBColor) -> Self {
+        Self {
+            data,
+            description: description.to_string(),
+            color,
+            line_style: LineStyle::Solid,
+            stroke_width: 1,
+            marker: None,
+            axis: Axis::Primary,
+        }
+    }
 
+    pub fn line_style(mut self, line_style: LineStyle) -> Self {
+        self.line_style = line_style;
+        self
+    }
 
-    let min = match series_0.data.iter().min_by(|a, b |{
-        a.partial_cmp(b).unwrap_or(Ordering::Equal)
-    }){
-        None => 0.0,
-        Some(n) if n < &0.0 => *n,
-        Some(_) => 0.0f32
-        //Some(n) => n
-    };
+    pub fn stroke_width(mut self, stroke_width: u32) -> Self {
+        self.stroke_width = stroke_width;
+        self
+    }
 
-    let max = match series_0.data.iter().max_by(|a, b |{
-        a.partial_cmp(b).unwrap_or(Ordering::Equal)
-    }){
-        None => 0.0,
-        Some(n) if n > &0.0 => *n,
-        Some(_) => 0.0f32
-    };
+    pub fn marker(mut self, marker: Marker) -> Self {
+        self.marker = Some(marker);
+        self
+    }
 
-    info!("Plotting globals: min = {}; max = {}", min, max);
+    pub fn axis(mut self, axis: Axis) -> Self {
+        self.axis = axis;
+        self
+    }
 
-    let mut chart = ChartBuilder::on(&root)
-        .caption("payoffs", ("sans-serif", 50).into_font())
-        .margin(5)
-        .x_label_area_size(30)
-        .y_label_area_size(30)
-        .build_cartesian_2d(0.0..series_0.data.len() as f32, min..max)?;
-
-    chart.configure_mesh().draw()?;
-
-    chart
-        .draw_series(LineSeries::new(
-            (0..series_0.data.len()).map(|x| (x as f32, series_0.data[x])),
-            &RED,
-        ))?
-        .label(series_0.description.as_str())
-        .legend(|(x, y)| PathElement::new(vec![(x, y), (x + 20, y)], &RED));
-
-    chart
-        .configure_series_labels()
-        .background_style(&WHITE.mix(0.8))
-        .border_style(&BLACK)
-        .draw()?;
+    fn shape_style(&self) -> ShapeStyle {
+        self.color.stroke_width(self.stroke_width)
+    }
+}
 
-    root.present()?;
+/// Polyline with [`LineStyle`] and optional [`Marker`] in every point. Dashes are laid out in
+/// image coordinates, so they look the same on linear and logarithmic axes.
+struct StyledLine<Coord> {
+    points: Vec<Coord>,
+    style: ShapeStyle,
+    line_style: LineStyle,
+    marker: Option<Marker>,
+    /// Legend sample, marker is drawn only in middle point.
+    legend: bool,
+}
 
-    Ok(())
+impl<'a, Coord> PointCollection<'a, Coord> for &'a StyledLine<Coord> {
+    type Point = &'a Coord;
+    type IntoIter = &'a [Coord];
+    fn point_iter(self) -> &'a [Coord] {
+        &self.points
+    }
 }
 
-pub fn plot_many_series(file: &Path, title: &str, series: &[PlotSeries], x_desc: &str, y_desc: &str) -> Result<(), Box<dyn std::error::Error>>{
-    let root  = SVGBackend::new(&file, (400, 300)).into_drawing_area();
-    root.fill(&WHITE)?;
-
-    let mut mins = Vec::with_capacity(series.len());
-    for s in series{
-        let min =  match s.data.iter().min_by(|a, b |{
-            a.partial_cmp(b).unwrap_or(Ordering::Equal)
-        }){
-            None => 0.0,
-            Some(n) if n < &0.0 => *n,
-            Some(_) => 0.0f32
-        };
-        mins.push(min)
-    }
-    let global_min = match mins.iter().min_by(|a, b |{
-            a.partial_cmp(b).unwrap_or(Ordering::Equal)
-    }){
-        None => 0.0,
-        Some(n) if n < &0.0 => *n,
-        Some(_) => 0.0f32
-    };
+impl<Coord, DB: DrawingBackend> Drawable<DB> for StyledLine<Coord> {
+    fn draw<I: Iterator<Item = BackendCoord>>(
+        &self,
+        points: I,
+        backend: &mut DB,
+        _: (u32, u32),
+    ) -> Result<(), DrawingErrorKind<DB::ErrorType>> {
+        let points: Vec<BackendCoord> = points.collect();
+        let width = self.style.stroke_width as f64;
+        match self.line_style {
+            LineStyle::NoLine => {},
+            LineStyle::Solid => backend.draw_path(points.iter().copied(), &self.style)?,
+            LineStyle::Dashed | LineStyle::Dotted => {
+                let (dash, gap) = match self.line_style {
+                    LineStyle::Dashed => (6.0 + 2.0 * width, 4.0),
+                    _ => (width, 3.0 + width),
+                };
+                // position in current dash + gap period, carried between segments
+                let mut phase = 0.0;
+                for w in points.windows(2) {
+                    let (x0, y0) = (w[0].0 as f64, w[0].1 as f64);
+                    let (dx, dy) = (w[1].0 as f64 - x0, w[1].1 as f64 - y0);
+                    let length = (dx * dx + dy * dy).sqrt();
+                    let at = |d: f64| ((x0 + dx * d / length).round() as i32, (y0 + dy * d / length).round() as i32);
+                    let mut d = 0.0;
+                    while d < length {
+                        let step = if phase < dash { dash - phase } else { dash + gap - phase };
+                        let end = (d + step).min(length);
+                        if phase < dash {
+                            backend.draw_line(at(d), at(end), &self.style)?;
+                        }
+                        phase = (phase + end - d) % (dash + gap);
+                        d = end;
+                    }
+                }
+            }
+        }
+        if let Some(marker) = self.marker {
+            let size = 3 + self.style.stroke_width as i32;
+            let markers = match self.legend {
+                true => &points[points.len() / 2..=points.len() / 2],
+                false => &points[..],
+            };
+            for &(x, y) in markers {
+                match marker {
+                    Marker::Circle => backend.draw_circle((x, y), size as u32, &self.style, true)?,
+                    Marker::Cross => {
+                        backend.draw_line((x - size, y - size), (x + size, y + size), &self.style)?;
+                        backend.draw_line((x - size, y + size), (x + size, y - size), &self.style)?;
+                    }
+                    Marker::Triangle => backend.fill_polygon(
+                        [(x, y - size), (x - size, y + size), (x + size, y + size)], &self.style)?,
+                }
+            }
+        }
+        Ok(())
+    }
+}
 
-    let mut maxes = Vec::with_capacity(series.len());
-    for s in series{
-        let max =  match s.data.iter().max_by(|a, b |{
-            a.partial_cmp(b).unwrap_or(Ordering::Equal)
-        }){
-            None => 0.0,
-            Some(n) if n > &0.0 => *n,
-            Some(_) => 0.0f32
-        };
-        debug!("Maximal value in series: {} is {}", s.description, max);
-        maxes.push(max)
-    }
-    let global_max = match maxes.iter().max_by(|a, b |{
-            a.partial_cmp(b).unwrap_or(Ordering::Equal)
-    }){
-        None => 0.0,
-        Some(n) if n > &0.0 => *n,
-        Some(_) => 0.0f32
-    };
+/// Configuration of one y axis.
+#[derive(Clone, Debug, Default)]
+pub struct AxisConfig {
+    pub description: String,
+    /// When not set range covers all data and `0`.
+    pub range: Option<Range<f32>>,
+    pub log_scale: bool,
+}
 
+/// Line chart builder. Series are given when drawing, so one configuration can be reused for
+/// many files or subplots.
+#[derive(Clone, Debug)]
+pub struct Plot {
+    title: String,
+    title_size: u32,
+    size: (u32, u32),
+    x_desc: String,
+    x_range: Option<Range<f32>>,
+    y: AxisConfig,
+    y2: AxisConfig,
+    legend: Option<LegendPosition>,
+    format: Option<PlotFormat>,
+}
 
-    debug!("Plotting globals: min = {}; max = {}", global_min, global_max);
+impl Default for Plot {
+    fn default() -> Self {
+        Self {
+            title: String::new(),
+            title_size: 30,
+            size: (400, 300),
+            x_desc: String::new(),
+            x_range: None,
+            y: AxisConfig::default(),
+            y2: AxisConfig::default(),
+            legend: Some(LegendPosition::default()),
+            format: None,
+        }
+    }
+}
 
-    /*
-    let max = match series_0.data..iter().chain(series_1.data..iter()).max_by(|a, b |{
-        a.partial_cmp(b).unwrap_or(Ordering::Equal)
-    }){
-        None => 0.0,
-        Some(n) if n > &0.0 => *n,
-        Some(_) => 0.0f32
-    };
+impl Plot {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn title(mut self, title: &str) -> Self {
+        self.title = title.to_string();
+        self
+    }
+
+    pub fn title_size(mut self, size: u32) -> Self {
+        self.title_size = size;
+        self
+    }
+
+    pub fn size(mut self, width: u32, height: u32) -> Self {
+        self.size = (width, height);
+        self
+    }
+
+    pub fn x_desc(mut self, desc: &str) -> Self {
+        self.x_desc = desc.to_string();
+        self
+    }
+
+    pub fn x_range(mut self, range: Range<f32>) -> Self {
+        self.x_range = Some(range);
+        self
+    }
+
+    pub fn y_desc(mut self, desc: &str) -> Self {
+        self.y.description = desc.to_string();
+        self
+    }
+
+    pub fn y_range(mut self, range: Range<f32>) -> Self {
+        self.y.range = Some(range);
+        self
+    }
+
+    pub fn y_log_scale(mut self, log_scale: bool) -> Self {
+        self.y.log_scale = log_scale;
+        self
+    }
+
+    /// Configures right side axis used by series set to [`Axis::Secondary`].
+    pub fn secondary_y(mut self, axis: AxisConfig) -> Self {
+        self.y2 = axis;
+        self
+    }
+
+    pub fn legend(mut self, position: LegendPosition) -> Self {
+        self.legend = Some(position);
+        self
+    }
+
+    pub fn hide_legend(mut self) -> Self {
+        self.legend = None;
+        self
+    }
+
+    /// Forces output format, otherwise it is guessed from file extension.
+    pub fn format(mut self, format: PlotFormat) -> Self {
+        self.format = Some(format);
+        self
+    }
+
+    pub fn draw(&self, file: &Path, series: &[PlotSeries]) -> Result<(), Box<dyn Error>> {
+        match self.format.unwrap_or_else(|| PlotFormat::from_path(file)) {
+            PlotFormat::Svg => {
+                let root = SVGBackend::new(file, self.size).into_drawing_area();
+                self.draw_on(&root, series)?;
+                root.present()?;
+            }
+            PlotFormat::Png => {
+                let root = BitMapBackend::new(file, self.size).into_drawing_area();
+                self.draw_on(&root, series)?;
+                root.present()?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Renders chart as SVG document in memory.
+    pub fn to_svg(&self, series: &[PlotSeries]) -> Result<String, Box<dyn Error>> {
+        let mut svg = String::new();
+        {
+            let root = SVGBackend::with_string(&mut svg, self.size).into_drawing_area();
+            self.draw_on(&root, series)?;
+            root.present()?;
+        }
+        Ok(svg)
+    }
+
+    /// Draws chart on given area, size and format set in builder are ignored.
+    pub fn draw_on<DB: DrawingBackend>(&self, area: &DrawingArea<DB, Shift>, series: &[PlotSeries]) -> Result<(), Box<dyn Error>>
+    where DB::ErrorType: 'static {
+        area.fill(&WHITE)?;
+        let x_range = self.x_range.clone().unwrap_or_else(|| {
+            0.0..series.iter().map(|s| s.data.len()).max().unwrap_or(0) as f32
+        });
+        let primary: Vec<&PlotSeries> = series.iter().filter(|s| s.axis == Axis::Primary).collect();
+        let secondary: Vec<&PlotSeries> = series.iter().filter(|s| s.axis == Axis::Secondary).collect();
+        let y_range = self.y.range.clone().unwrap_or_else(|| data_range(&primary[..], self.y.log_scale));
+
+        let mut builder = ChartBuilder::on(area);
+        builder.caption(&self.title, ("sans-serif", self.title_size).into_font())
+            .margin(5)
+            .x_label_area_size(40)
+            .y_label_area_size(40);
+        if !secondary.is_empty() {
+            builder.right_y_label_area_size(40);
+        }
+        match self.y.log_scale {
+            false => {
+                let chart = builder.build_cartesian_2d(x_range, y_range)?;
+                self.draw_chart(chart, &primary[..], &secondary[..])
+            }
+            true => {
+                let chart = builder.build_cartesian_2d(x_range, y_range.log_scale())?;
+                self.draw_chart(chart, &primary[..], &secondary[..])
+            }
+        }
+    }
+
+    fn draw_chart<'a, DB: DrawingBackend + 'a, Y>(
+        &self,
+        mut chart: ChartContext<'a, DB, Cartesian2d<RangedCoordf32, Y>>,
+        primary: &[&PlotSeries],
+        secondary: &[&PlotSeries]) -> Result<(), Box<dyn Error>>
+    where DB::ErrorType: 'static, Y: Ranged<ValueType = f32> + ValueFormatter<f32> {
+        if secondary.is_empty() {
+            self.draw_primary(&mut chart, primary)?;
+            return self.draw_legend(&mut chart);
+        }
+        // secondary coordinates must be set before drawing mesh, so right label area is not
+        // used by primary axis
+        let x_range = chart.x_range();
+        let y2_range = self.y2.range.clone().unwrap_or_else(|| data_range(secondary, self.y2.log_scale));
+        match self.y2.log_scale {
+            false => self.draw_secondary(chart.set_secondary_coord(x_range, y2_range), primary, secondary),
+            true => self.draw_secondary(chart.set_secondary_coord(x_range, y2_range.log_scale()), primary, secondary),
+        }
+    }
+
+    fn draw_primary<'a, DB: DrawingBackend + 'a, Y>(
+        &self,
+        chart: &mut ChartContext<'a, DB, Cartesian2d<RangedCoordf32, Y>>,
+        primary: &[&PlotSeries]) -> Result<(), Box<dyn Error>>
+    where DB::ErrorType: 'static, Y: Ranged<ValueType = f32> + ValueFormatter<f32> {
+        chart.configure_mesh()
+            .x_desc(&self.x_desc)
+            .y_desc(&self.y.description)
+            .disable_mesh()
+            .draw()?;
+        for s in primary {
+            let anno = chart.draw_series(std::iter::once(styled_line(s)))?;
+            anno.label(s.description.as_str());
+            let legend = legend_line(s);
+            anno.legend(move |(x, y)| legend((x, y)));
+        }
+        Ok(())
+    }
+
+    fn draw_secondary<'a, DB: DrawingBackend + 'a, Y, Y2>(
+        &self,
+        mut chart: DualCoordChartContext<'a, DB, Cartesian2d<RangedCoordf32, Y>, Cartesian2d<RangedCoordf32, Y2>>,
+        primary: &[&PlotSeries],
+        secondary: &[&PlotSeries]) -> Result<(), Box<dyn Error>>
+    where DB::ErrorType: 'static,
+        Y: Ranged<ValueType = f32> + ValueFormatter<f32>,
+        Y2: Ranged<ValueType = f32> + ValueFormatter<f32> {
+        self.draw_primary(&mut chart, primary)?;
+        chart.configure_secondary_axes()
+            .y_desc(&self.y2.description)
+            .draw()?;
+        for s in secondary {
+            let anno = chart.draw_secondary_series(std::iter::once(styled_line(s)))?;
+            anno.label(s.description.as_str());
+            let legend = legend_line(s);
+            anno.legend(move |(x, y)| legend((x, y)));
+        }
+        self.draw_legend(&mut chart)
+    }
+
+    fn draw_legend<'a, DB: DrawingBackend + 'a, CT: CoordTranslate>(&self, chart: &mut ChartContext<'a, DB, CT>) -> Result<(), Box<dyn Error>>
+    where DB::ErrorType: 'static {
+        if let Some(position) = self.legend {
+            chart.configure_series_labels()
+                .position(position.into())
+                .margin(5)
+                .border_style(BLACK)
+                .background_style(WHITE.mix(0.8))
+                .label_font(("sans-serif", 14))
+                .draw()?;
+        }
+        Ok(())
+    }
+}
+
+fn styled_line(s: &PlotSeries) -> StyledLine<(f32, f32)> {
+    StyledLine {
+        points: s.data.iter().enumerate().map(|(x, y)| (x as f32, *y)).collect(),
+        style: s.shape_style(),
+        line_style: s.line_style,
+        marker: s.marker,
+        legend: false,
+    }
+}
+
+fn legend_line(s: &PlotSeries) -> impl Fn(BackendCoord) -> StyledLine<BackendCoord> {
+    let (style, line_style, marker) = (s.shape_style(), s.line_style, s.marker);
+    move |(x, y)| StyledLine {
+        points: vec![(x, y), (x + 10, y), (x + 20, y)],
+        style,
+        line_style,
+        marker,
+        legend: true,
+    }
+}
+
+/// Range covering all values in series and `0` (for logarithmic scale only positive values).
+fn data_range(series: &[&PlotSeries], log_scale: bool) -> Range<f32> {
+    let values = series.iter().flat_map(|s| s.data.iter().copied())
+        .filter(|v| !log_scale || *v > 0.0);
+    let min = values.clone().min_by(|a, b| a.partial_cmp(b).unwrap_or(Ordering::Equal));
+    let max = values.max_by(|a, b| a.partial_cmp(b).unwrap_or(Ordering::Equal));
+    debug!("Plotting globals: min = {:?}; max = {:?}", min, max);
+    match log_scale {
+        false => min.unwrap_or(0.0).min(0.0)..max.unwrap_or(0.0).max(0.0),
+        true => {
+            let min = min.unwrap_or(1.0);
+            min..max.unwrap_or(1.0).max(min * 10.0)
+        }
+    }
+}
+
+pub fn plot_payoffs(file: &Path, series_0: &PlotSeries) -> Result<(), Box<dyn std::error::Error>>{
+    Plot::new()
+        .title(&series_0.description)
+        .title_size(50)
+        .size(800, 600)
+        .legend(LegendPosition::UpperLeft)
+        .draw(file, std::slice::from_ref(series_0))
+}
+
+pub fn plot_many_series(file: &Path, title: &str, series: &[PlotSeries], x_desc: &str, y_desc: &str) -> Result<(), Box<dyn std::error::Error>>{
+    Plot::new()
+        .title(title)
+        .x_desc(x_desc)
+        .y_desc(y_desc)
+        .draw(file, series)
+}
+
+/// Values in grid, rows are drawn from bottom to top. Each row of `values` has one entry per column.
+#[derive(Serialize, Clone, Debug)]
+pub struct HeatmapData {
+    pub row_labels: Vec<String>,
+    pub column_labels: Vec<String>,
+    pub values: Vec<Vec<f32>>,
+}
+
+impl HeatmapData {
+    /// Image size fitting all cells.
+    pub fn fitting_size(&self) -> (u32, u32) {
+        ((80 + 40 * self.column_labels.len() as u32).max(400), (100 + 40 * self.row_labels.len() as u32).max(300))
+    }
+}
 
-     */
+/// Draws heatmap of values in range `[0, 1]` using viridis color map, with value printed in every cell.
+pub fn plot_heatmap(file: &Path, title: &str, heatmap: &HeatmapData, x_desc: &str, y_desc: &str) -> Result<(), Box<dyn std::error::Error>>{
+    match PlotFormat::from_path(file) {
+        PlotFormat::Svg => {
+            let root = SVGBackend::new(file, heatmap.fitting_size()).into_drawing_area();
+            draw_heatmap_on(&root, title, heatmap, x_desc, y_desc)?;
+            root.present()?;
+        }
+        PlotFormat::Png => {
+            let root = BitMapBackend::new(file, heatmap.fitting_size()).into_drawing_area();
+            draw_heatmap_on(&root, title, heatmap, x_desc, y_desc)?;
+            root.present()?;
+        }
+    }
+    Ok(())
+}
 
+pub fn draw_heatmap_on<DB: DrawingBackend>(area: &DrawingArea<DB, Shift>, title: &str, heatmap: &HeatmapData, x_desc: &str, y_desc: &str) -> Result<(), Box<dyn std::error::Error>>
+where DB::ErrorType: 'static {
+    let rows = heatmap.row_labels.len();
+    let columns = heatmap.column_labels.len();
+    area.fill(&WHITE)?;
 
-    let mut chart = ChartBuilder::on(&root)
+    let mut chart = ChartBuilder::on(area)
         .caption(title, ("sans-serif", 30).into_font())
         .margin(5)
         .x_label_area_size(40)
-        .y_label_area_size(40)
-        .build_cartesian_2d(0.0..series[0].data.len() as f32, global_min..global_max)?;
+        .y_label_area_size(60)
+        .build_cartesian_2d((0..columns.saturating_sub(1)).into_segmented(), (0..rows.saturating_sub(1)).into_segmented())?;
 
+    let label = |labels: &[String], v: &SegmentValue<usize>| match v{
+        SegmentValue::CenterOf(i) => labels.get(*i).cloned().unwrap_or_default(),
+        _ => String::new()
+    };
     chart.configure_mesh()
+        .disable_mesh()
+        .x_labels(columns)
+        .y_labels(rows)
+        .x_label_formatter(&|v| label(&heatmap.column_labels, v))
+        .y_label_formatter(&|v| label(&heatmap.row_labels, v))
         .x_desc(x_desc)
         .y_desc(y_desc)
-        //.axis_desc_style()
-        .disable_mesh().draw()?;
+        .draw()?;
 
+    let cells = heatmap.values.iter().enumerate().flat_map(|(r, row)|{
+        row.iter().enumerate().map(move |(c, v)| (r, c, *v))
+    });
+    chart.draw_series(cells.clone().map(|(r, c, v)|{
+        Rectangle::new([
+            (SegmentValue::Exact(c), SegmentValue::Exact(r)),
+            (SegmentValue::Exact(c+1), SegmentValue::Exact(r+1))
+        ], ViridisRGB::get_color(v.clamp(0.0, 1.0)).filled())
+    }))?;
+    chart.draw_series(cells.map(|(r, c, v)|{
+        let color = if v < 0.5 {WHITE} else {BLACK};
+        Text::new(format!("{:.2}", v),
+                  (SegmentValue::CenterOf(c), SegmentValue::CenterOf(r)),
+                  ("sans-serif", 12).into_font().color(&color).pos(Pos::new(HPos::Center, VPos::Center)))
+    }))?;
 
+    Ok(())
+}
 
-    for s in series{
-        chart
-            .draw_series(LineSeries::new(
-                (0..s.data.len()).map(|x| (x as f32, s.data[x])),
-                &s.color,
-            ))?
-            .label(s.description.as_str())
-            .legend(|(x, y)| PathElement::new(vec![(x, y), (x + 20, y)], &s.color));
+/// Subplot of [`Dashboard`].
+pub enum Panel {
+    Lines {
+        plot: Plot,
+        series: Vec<PlotSeries>,
+    },
+    Heatmap {
+        title: String,
+        heatmap: HeatmapData,
+        x_desc: String,
+        y_desc: String,
+    },
+}
 
+impl Panel {
+    pub fn lines(plot: Plot, series: Vec<PlotSeries>) -> Self {
+        Panel::Lines { plot, series }
     }
-    chart
-        .configure_series_labels()
-        .position(SeriesLabelPosition::LowerLeft).margin(5)
-        //.legend_area_size(3)
-        .border_style(BLACK)
-        .background_style(WHITE.mix(0.8))
-        .label_font(("sans-serif", 14))
-        .draw()?;
-
 
-    /*
-    chart
-        .configure_series_labels()
-        .background_style(&WHITE.mix(0.8))
-        .border_style(&BLACK)
-        .draw()?;
+    pub fn heatmap(title: &str, heatmap: HeatmapData, x_desc: &str, y_desc: &str) -> Self {
+        Panel::Heatmap {
+            title: title.to_string(),
+            heatmap,
+            x_desc: x_desc.to_string(),
+            y_desc: y_desc.to_string(),
+        }
+    }
 
+    /// Panel has nothing to draw (no data in any series or heatmap without cells).
+    pub fn is_empty(&self) -> bool {
+        match self {
+            Panel::Lines { series, .. } => series.iter().all(|s| s.data.is_empty()),
+            Panel::Heatmap { heatmap, .. } => heatmap.values.iter().all(|row| row.is_empty()),
+        }
+    }
 
-     */
-    root.present()?;
+    pub fn draw_on<DB: DrawingBackend>(&self, area: &DrawingArea<DB, Shift>) -> Result<(), Box<dyn Error>>
+    where DB::ErrorType: 'static {
+        match self {
+            Panel::Lines { plot, series } => plot.draw_on(area, series),
+            Panel::Heatmap { title, heatmap, x_desc, y_desc } => draw_heatmap_on(area, title, heatmap, x_desc, y_desc),
+        }
+    }
+}
 
-    Ok(())
+/// Figure summarising whole run: panels are placed in grid, row by row, under common title.
+pub struct Dashboard {
+    title: String,
+    columns: usize,
+    panel_size: (u32, u32),
+    panels: Vec<Panel>,
+    format: Option<PlotFormat>,
 }
 
+impl Dashboard {
+    pub fn new(title: &str) -> Self {
+        Self {
+            title: title.to_string(),
+            columns: 2,
+            panel_size: (400, 300),
+            panels: Vec::new(),
+            format: None,
+        }
+    }
+
+    pub fn columns(mut self, columns: usize) -> Self {
+        self.columns = columns.max(1);
+        self
+    }
+
+    pub fn panel_size(mut self, width: u32, height: u32) -> Self {
+        self.panel_size = (width, height);
+        self
+    }
+
+    /// Adds panel, empty panels are skipped.
+    pub fn panel(mut self, panel: Panel) -> Self {
+        if !panel.is_empty() {
+            self.panels.push(panel);
+        }
+        self
+    }
+
+    /// Forces output format, otherwise it is guessed from file extension.
+    pub fn format(mut self, format: PlotFormat) -> Self {
+        self.format = Some(format);
+        self
+    }
+
+    /// Number of (rows, columns) in grid.
+    pub fn grid(&self) -> (usize, usize) {
+        let columns = self.columns.min(self.panels.len()).max(1);
+        (self.panels.len().div_ceil(columns).max(1), columns)
+    }
+
+    pub fn size(&self) -> (u32, u32) {
+        let (rows, columns) = self.grid();
+        (self.panel_size.0 * columns as u32, self.panel_size.1 * rows as u32 + 50)
+    }
+
+    pub fn draw(&self, file: &Path) -> Result<(), Box<dyn Error>> {
+        match self.format.unwrap_or_else(|| PlotFormat::from_path(file)) {
+            PlotFormat::Svg => {
+                let root = SVGBackend::new(file, self.size()).into_drawing_area();
+                self.draw_on(&root)?;
+                root.present()?;
+            }
+            PlotFormat::Png => {
+                let root = BitMapBackend::new(file, self.size()).into_drawing_area();
+                self.draw_on(&root)?;
+                root.present()?;
+            }
+        }
+        Ok(())
+    }
+
+    pub fn draw_on<DB: DrawingBackend>(&self, area: &DrawingArea<DB, Shift>) -> Result<(), Box<dyn Error>>
+    where DB::ErrorType: 'static {
+        area.fill(&WHITE)?;
+        let area = area.titled(&self.title, ("sans-serif", 40))?;
+        let cells = area.split_evenly(self.grid());
+        for (panel, cell) in self.panels.iter().zip(cells.iter()) {
+            panel.draw_on(cell)?;
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,96 @@
+use std::marker::PhantomData;
+use rand::{thread_rng, Rng};
+use clap::ValueEnum;
+use amfiteatr_core::agent::Policy;
+use amfiteatr_classic::agent::LocalHistoryInfoSet;
+use amfiteatr_classic::AsymmetricRewardTableInt;
+use amfiteatr_classic::domain::{ClassicAction, ClassicGameDomain, UsizeAgentId};
+use amfiteatr_classic::domain::ClassicAction::{Down, Up};
+use amfiteatr_rl::tch::{Device, nn, Tensor};
+use amfiteatr_rl::tch::nn::VarStore;
+use amfiteatr_rl::torch_net::{A2CNet, TensorA2C};
+use crate::guard::Fallback;
+use crate::probe::{KnownStrategy, RoundActions};
+
+/// Policy playing one of [`KnownStrategy`] against agent's own history of encounters
+/// (regardless of who was the opponent).
+pub struct KnownStrategyPolicy<ID: UsizeAgentId>{
+    strategy: KnownStrategy,
+    _id: PhantomData<ID>,
+}
+
+impl<ID: UsizeAgentId> KnownStrategyPolicy<ID>{
+    pub fn new(strategy: KnownStrategy) -> Self{
+        Self{
+            strategy,
+            _id: PhantomData,
+        }
+    }
+
+    pub fn strategy(&self) -> KnownStrategy{
+        self.strategy
+    }
+}
+
+impl<ID: UsizeAgentId> Policy<ClassicGameDomain<ID>> for KnownStrategyPolicy<ID>{
+    type InfoSetType = LocalHistoryInfoSet<ID>;
+
+    fn select_action(&self, state: &Self::InfoSetType) -> Option<ClassicAction>{
+        let history: Vec<RoundActions> = state.previous_encounters().iter()
+            .map(|report| (report.own_action, report.other_player_action))
+            .collect();
+        let p = self.strategy.cooperate_probability(&history);
+        match thread_rng().gen_bool(p.clamp(0.0, 1.0)){
+            true => Some(Down),
+            false => Some(Up),
+        }
+    }
+}
+
+/// Actor-critic network used by agents learning on local history, actor has output for each of
+/// `actions`. Checkpoints saved from network built here can be loaded only into network with
+/// the same `input_size` and `actions`.
+pub fn a2c_network(input_size: i64, actions: i64, device: Device) -> A2CNet{
+    A2CNet::new(VarStore::new(device), |path|{
+        let seq = nn::seq()
+            .add(nn::linear(path / "input", input_size, 512, Default::default()))
+            .add(nn::linear(path / "hidden1", 512, 512, Default::default()))
+            .add_fn(|xs| xs.tanh())
+            .add(nn::linear(path / "hidden2", 512, 256, Default::default()))
+            .add_fn(|xs| xs.tanh())
+            .add_fn(|xs|xs.relu());
+        let actor = nn::linear(path / "al", 256, actions, Default::default());
+        let critic =  nn::linear(path / "ac", 256, 1, Default::default());
+        {move |input: &Tensor|{
+            let xs = input.to_device(device).apply(&seq);
+            TensorA2C{critic: xs.apply(&critic), actor: xs.apply(&actor)}
+        }}
+    })
+}
+
+/// Replacement of classic game agent that timed out or disconnected.
+#[derive(ValueEnum, Copy, Clone, Debug, PartialEq, Eq)]
+pub enum FallbackKind{
+    /// Quit game in the name of agent, episode is aborted
+    Forfeit,
+    /// Cooperate in every remaining move
+    Cooperate,
+    /// Defect in every remaining move
+    Defect,
+    /// Play fallback strategy on agent's history in current game
+    Strategy,
+}
+
+/// Builds [`Fallback`] for classic game agents. `strategy` is used only by [`FallbackKind::Strategy`].
+pub fn classic_fallback<ID: UsizeAgentId>(kind: FallbackKind, strategy: KnownStrategy, reward_table: AsymmetricRewardTableInt)
+    -> Fallback<ClassicGameDomain<ID>, LocalHistoryInfoSet<ID>>{
+    match kind{
+        FallbackKind::Forfeit => Fallback::Forfeit,
+        FallbackKind::Cooperate => Fallback::DefaultAction(Down),
+        FallbackKind::Defect => Fallback::DefaultAction(Up),
+        FallbackKind::Strategy => Fallback::Policy {
+            policy: Box::new(KnownStrategyPolicy::new(strategy)),
+            info_set: Box::new(move |id| LocalHistoryInfoSet::new(*id, reward_table)),
+        },
+    }
+}
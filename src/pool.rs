@@ -0,0 +1,179 @@
+use std::panic::{self, AssertUnwindSafe};
+use std::sync::{Arc, Mutex, PoisonError};
+use std::sync::mpsc::{channel, Receiver, Sender};
+use std::thread::{self, JoinHandle};
+use log::warn;
+use amfiteatr_core::agent::{IdAgent, MultiEpisodeAutoAgentRewarded};
+use amfiteatr_core::domain::{DomainParameters, EnvironmentMessage};
+use amfiteatr_core::env::CommunicatingAdapterEnvironment;
+use amfiteatr_core::error::AmfiError;
+use crate::error::{EpisodeReport, Participant};
+
+type Outcome<DP> = thread::Result<Result<(), AmfiError<DP>>>;
+
+struct Worker<DP: DomainParameters>{
+    agent: DP::AgentId,
+    start: Sender<()>,
+    finished: Receiver<Outcome<DP>>,
+    handle: Option<JoinHandle<()>>,
+}
+
+/// Persistent threads running agents' episodes. Every agent gets its own thread once, because
+/// agents block waiting for environment, and the thread is woken up for every episode.
+/// Agents stay behind `Arc<Mutex<_>>`, so they can be inspected and trained between episodes.
+///
+/// Unlike general thread pool it does not bound number of threads nor share work between them,
+/// it only saves spawning threads for every episode. There are as many threads as added agents,
+/// for large populations without learning agents consider [`sync`](crate::sync) mode.
+pub struct AgentPool<DP: DomainParameters>{
+    workers: Vec<Worker<DP>>,
+}
+
+impl<DP: DomainParameters> Default for AgentPool<DP>{
+    fn default() -> Self {
+        Self{workers: Vec::new()}
+    }
+}
+
+impl<DP: DomainParameters> AgentPool<DP>{
+    pub fn new() -> Self{
+        Self::default()
+    }
+
+    /// Starts thread of agent, it waits until [`run_episode`](AgentPool::run_episode).
+    pub fn add<A>(&mut self, agent: Arc<Mutex<A>>)
+    where A: MultiEpisodeAutoAgentRewarded<DP, ()> + IdAgent<DP> + Send + 'static{
+        let id = agent.lock().unwrap_or_else(PoisonError::into_inner).id().clone();
+        let (start, start_rx) = channel::<()>();
+        let (finished_tx, finished) = channel();
+        let handle = thread::Builder::new()
+            .name(format!("agent-{id}"))
+            .spawn(move ||{
+                while start_rx.recv().is_ok(){
+                    let outcome = panic::catch_unwind(AssertUnwindSafe(||{
+                        let mut guard = agent.lock().unwrap_or_else(PoisonError::into_inner);
+                        guard.run_episode_rewarded(())
+                    }));
+                    agent.clear_poison();
+                    if finished_tx.send(outcome).is_err(){
+                        break;
+                    }
+                }
+            })
+            .expect("failed spawning agent thread");
+        self.workers.push(Worker{agent: id, start, finished, handle: Some(handle)});
+    }
+
+    pub fn len(&self) -> usize{
+        self.workers.len()
+    }
+
+    pub fn is_empty(&self) -> bool{
+        self.workers.is_empty()
+    }
+
+    /// Wakes all agents and runs episode of environment with `run` in current thread, then waits
+    /// for agents to finish. When environment fails or panics, agents that did not finish are
+    /// killed through environment, so they do not wait for the end of game.
+    pub fn run_episode<E, F>(&self, environment: &mut E, run: F) -> EpisodeReport<DP>
+    where E: CommunicatingAdapterEnvironment<DP>, F: FnOnce(&mut E) -> Result<(), AmfiError<DP>>{
+        let mut report = EpisodeReport::default();
+        let mut started = Vec::with_capacity(self.workers.len());
+        for worker in &self.workers{
+            match worker.start.send(()){
+                Ok(()) => started.push(worker),
+                Err(_) => report.note::<()>(Participant::Agent(worker.agent.clone()),
+                    Err(Box::new("agent thread is not running"))),
+            }
+        }
+        let outcome = panic::catch_unwind(AssertUnwindSafe(|| run(environment)));
+        let failed = !matches!(outcome, Ok(Ok(())));
+        report.note(Participant::Environment, outcome);
+        let mut outcomes: Vec<Option<Outcome<DP>>> = started.iter().map(|_| None).collect();
+        if failed{
+            for (worker, outcome) in started.iter().zip(outcomes.iter_mut()){
+                // agent that already finished would get kill in next episode
+                match worker.finished.try_recv(){
+                    Ok(finished) => *outcome = Some(finished),
+                    Err(_) => {
+                        if let Err(e) = environment.send(&worker.agent, EnvironmentMessage::Kill){
+                            warn!("Failed killing agent {} after failure of environment: {e}", worker.agent);
+                        }
+                    }
+                }
+            }
+        }
+        for (worker, outcome) in started.into_iter().zip(outcomes){
+            let outcome = outcome.unwrap_or_else(|| worker.finished.recv()
+                .unwrap_or_else(|_| Err(Box::new("agent thread stopped during episode"))));
+            report.note(Participant::Agent(worker.agent.clone()), outcome);
+        }
+        report
+    }
+}
+
+impl<DP: DomainParameters> Drop for AgentPool<DP>{
+    fn drop(&mut self) {
+        for worker in self.workers.iter_mut(){
+            // closing channel ends loop of worker
+            let (closed, _) = channel();
+            worker.start = closed;
+            if let Some(handle) = worker.handle.take(){
+                let _ = handle.join();
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests{
+    use std::sync::{Arc, Mutex};
+    use amfiteatr_classic::agent::LocalHistoryInfoSet;
+    use amfiteatr_classic::domain::{AgentNum, ClassicAction, ClassicGameDomainNumbered};
+    use amfiteatr_classic::policy::ClassicPureStrategy;
+    use amfiteatr_core::agent::AgentGen;
+    use amfiteatr_core::comm::{AgentMpscAdapter, EnvironmentMpscPort};
+    use amfiteatr_core::env::{AutoEnvironmentWithScores, ReseedEnvironment, TracingBasicEnvironment};
+    use amfiteatr_core::error::AmfiError;
+    use crate::games::GamePreset;
+    use crate::pairing::{GameState, new_game_state};
+    use crate::pairing::noise::Noise;
+    use super::AgentPool;
+
+    type D = ClassicGameDomainNumbered;
+    type Agent = AgentGen<D, ClassicPureStrategy<AgentNum, LocalHistoryInfoSet<AgentNum>>, AgentMpscAdapter<D>>;
+    type Environment = TracingBasicEnvironment<D, GameState<AgentNum>, EnvironmentMpscPort<D>>;
+
+    const PLAYERS: usize = 4;
+
+    fn pool_with_game() -> (AgentPool<D>, Environment){
+        let table = GamePreset::PrisonersDilemma.reward_table();
+        let state = new_game_state(PLAYERS, 5, table, Noise::new(0.0, 0.0), None).unwrap();
+        let mut port = EnvironmentMpscPort::new();
+        let mut pool = AgentPool::new();
+        for id in 0..PLAYERS as AgentNum{
+            let comm = port.register_agent(id).unwrap();
+            let agent: Agent = AgentGen::new(LocalHistoryInfoSet::new(id, table), comm, ClassicPureStrategy::new(ClassicAction::Down));
+            pool.add(Arc::new(Mutex::new(agent)));
+        }
+        (pool, TracingBasicEnvironment::new(state, port))
+    }
+
+    fn play(environment: &mut Environment) -> Result<(), AmfiError<D>>{
+        environment.reseed(());
+        environment.run_with_scores()
+    }
+
+    #[test]
+    fn agents_are_killed_when_environment_fails(){
+        let (pool, mut environment) = pool_with_game();
+        let report = pool.run_episode(&mut environment, |_| panic!("environment panicked before game"));
+        assert!(report.into_result(0).is_err());
+        let report = pool.run_episode(&mut environment, |_| Err(AmfiError::Custom(String::from("environment failed"))));
+        assert!(report.into_result(1).is_err());
+
+        // agents do not keep kill messages for next episode
+        pool.run_episode(&mut environment, play).into_result(2).unwrap();
+        pool.run_episode(&mut environment, play).into_result(3).unwrap();
+    }
+}
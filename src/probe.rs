@@ -0,0 +1,319 @@
+use std::collections::HashMap;
+use std::fmt::{Display, Formatter};
+use std::sync::Arc;
+use log::debug;
+use serde::Serialize;
+use amfiteatr_core::agent::{InformationSet, Policy};
+use amfiteatr_classic::agent::LocalHistoryInfoSet;
+use amfiteatr_classic::AsymmetricRewardTableInt;
+use amfiteatr_classic::domain::{ClassicAction, ClassicGameDomain, ClassicGameUpdate, EncounterReport, UsizeAgentId};
+use amfiteatr_classic::domain::ClassicAction::{Down, Up};
+use amfiteatr_classic::Side;
+use crate::plots::HeatmapData;
+
+/// Pair of actions played in one round: (own action, opponent's action).
+pub type RoundActions = (ClassicAction, ClassicAction);
+
+/// Well known strategies of iterated prisoners' dilemma that probed policies are compared against.
+/// `Down` is treated as cooperation and `Up` as defection.
+#[derive(Serialize, Copy, Clone, Debug, PartialEq, Eq, clap::ValueEnum)]
+pub enum KnownStrategy{
+    AllCooperate,
+    AllDefect,
+    TitForTat,
+    SuspiciousTitForTat,
+    TitForTwoTats,
+    Pavlov,
+    Grim,
+    Random,
+}
+
+impl KnownStrategy{
+    pub const ALL: [KnownStrategy; 8] = [
+        KnownStrategy::AllCooperate,
+        KnownStrategy::AllDefect,
+        KnownStrategy::TitForTat,
+        KnownStrategy::SuspiciousTitForTat,
+        KnownStrategy::TitForTwoTats,
+        KnownStrategy::Pavlov,
+        KnownStrategy::Grim,
+        KnownStrategy::Random,
+    ];
+
+    pub fn name(&self) -> &'static str{
+        match self{
+            KnownStrategy::AllCooperate => "AllC",
+            KnownStrategy::AllDefect => "AllD",
+            KnownStrategy::TitForTat => "TFT",
+            KnownStrategy::SuspiciousTitForTat => "STFT",
+            KnownStrategy::TitForTwoTats => "TF2T",
+            KnownStrategy::Pavlov => "Pavlov",
+            KnownStrategy::Grim => "Grim",
+            KnownStrategy::Random => "Random",
+        }
+    }
+
+    /// Probability that strategy cooperates after given history (oldest round first).
+    pub fn cooperate_probability(&self, history: &[RoundActions]) -> f64{
+        let coop = |b: bool| if b {1.0} else {0.0};
+        match self{
+            KnownStrategy::AllCooperate => 1.0,
+            KnownStrategy::AllDefect => 0.0,
+            KnownStrategy::TitForTat => match history.last(){
+                None => 1.0,
+                Some((_, other)) => coop(*other == Down),
+            },
+            KnownStrategy::SuspiciousTitForTat => match history.last(){
+                None => 0.0,
+                Some((_, other)) => coop(*other == Down),
+            },
+            KnownStrategy::TitForTwoTats => {
+                let n = history.len();
+                coop(n < 2 || history[n-1].1 == Down || history[n-2].1 == Down)
+            },
+            // win-stay, lose-shift: repeat own action if opponent cooperated, switch otherwise
+            KnownStrategy::Pavlov => match history.last(){
+                None => 1.0,
+                Some((own, other)) => coop(own == other),
+            },
+            KnownStrategy::Grim => coop(history.iter().all(|(_, other)| *other == Down)),
+            KnownStrategy::Random => 0.5,
+        }
+    }
+}
+
+impl Display for KnownStrategy{
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.name())
+    }
+}
+
+#[derive(thiserror::Error, Debug, Clone)]
+pub enum ProbeError{
+    #[error("Probed memory ({memory}) must be shorter than number of rounds ({rounds})")]
+    MemoryTooLong{
+        memory: usize,
+        rounds: usize,
+    },
+    #[error("Policy did not select action for history: {0}")]
+    NoAction(String),
+    #[error("Failed building probe information set: {0}")]
+    InfoSet(String),
+}
+
+/// Estimated cooperation probability of probed policy in one history context.
+#[derive(Serialize, Clone, Debug)]
+pub struct ResponseEntry{
+    /// Last rounds before decision, oldest first, written as own and opponent's action
+    /// (`C` - cooperate, `D` - defect), or `start` for first move.
+    pub context: String,
+    pub cooperate_probability: f64,
+    pub samples: usize,
+}
+
+#[derive(Serialize, Clone, Debug)]
+pub struct StrategyDistance{
+    pub strategy: KnownStrategy,
+    pub distance: f64,
+}
+
+#[derive(Serialize, Clone, Debug)]
+pub struct StrategyProbeReport{
+    pub memory: usize,
+    pub samples: usize,
+    pub responses: Vec<ResponseEntry>,
+    /// Distances to known strategies, nearest first.
+    pub distances: Vec<StrategyDistance>,
+}
+
+impl StrategyProbeReport{
+    pub fn nearest(&self) -> Option<&StrategyDistance>{
+        self.distances.first()
+    }
+}
+
+impl Display for StrategyProbeReport{
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        writeln!(f, "Memory-{} response table ({} samples per context):", self.memory, self.samples)?;
+        for r in &self.responses{
+            writeln!(f, "\t{:<16} P(C) = {:.3}", r.context, r.cooperate_probability)?;
+        }
+        match self.nearest(){
+            None => write!(f, "No strategies to compare."),
+            Some(n) => write!(f, "Nearest known strategy: {} (distance: {:.4})", n.strategy, n.distance)
+        }
+    }
+}
+
+fn action_symbol(action: ClassicAction) -> char{
+    match action{
+        Up => 'D',
+        Down => 'C'
+    }
+}
+
+fn context_name(context: &[RoundActions]) -> String{
+    if context.is_empty(){
+        return String::from("start")
+    }
+    context.iter().map(|(own, other)|{
+        format!("{}{}", action_symbol(*own), action_symbol(*other))
+    }).collect::<Vec<String>>().join("|")
+}
+
+/// All sequences of rounds of given length.
+fn all_contexts(length: usize) -> Vec<Vec<RoundActions>>{
+    let mut contexts = vec![Vec::new()];
+    for _ in 0..length{
+        contexts = contexts.into_iter().flat_map(|c|{
+            [(Down, Down), (Down, Up), (Up, Down), (Up, Up)].into_iter().map(move |round|{
+                let mut next = c.clone();
+                next.push(round);
+                next
+            })
+        }).collect();
+    }
+    contexts
+}
+
+/// Estimates memory-n response table of a policy by sampling its actions on scripted histories
+/// and finds nearest [`KnownStrategy`].
+///
+/// Contexts shorter than `memory` are probed as opening moves of the game.
+/// Full contexts are probed at every round the game allows, preceded by mutual cooperation.
+#[derive(Copy, Clone, Debug)]
+pub struct ResponseProbe{
+    pub memory: usize,
+    pub rounds: usize,
+    pub samples: usize,
+}
+
+impl ResponseProbe{
+    pub fn new(memory: usize, rounds: usize, samples: usize) -> Self{
+        Self{memory, rounds, samples}
+    }
+
+    fn info_set<ID: UsizeAgentId>(id: ID, reward_table: AsymmetricRewardTableInt, history: &[RoundActions])
+        -> Result<LocalHistoryInfoSet<ID>, ProbeError>{
+
+        let other_id = ID::make_from_usize(id.as_usize() + 1);
+        let mut info_set = LocalHistoryInfoSet::new(id, reward_table);
+        for (own, other) in history{
+            let report = EncounterReport{
+                own_action: *own,
+                other_player_action: *other,
+                side: Side::Left,
+                other_id,
+            };
+            let update = ClassicGameUpdate{
+                encounters: Arc::new(HashMap::from([(id, report)])),
+                pairing: None,
+            };
+            info_set.update(update).map_err(|e| ProbeError::InfoSet(format!("{e}")))?;
+        }
+        Ok(info_set)
+    }
+
+    pub fn probe<ID: UsizeAgentId, P: Policy<ClassicGameDomain<ID>, InfoSetType=LocalHistoryInfoSet<ID>>>(
+        &self, policy: &P, id: ID, reward_table: AsymmetricRewardTableInt)
+        -> Result<StrategyProbeReport, ProbeError>{
+
+        if self.memory >= self.rounds{
+            return Err(ProbeError::MemoryTooLong {memory: self.memory, rounds: self.rounds})
+        }
+        let mut responses = Vec::new();
+        let mut squared_errors = vec![0.0; KnownStrategy::ALL.len()];
+        for length in 0..=self.memory{
+            // opening contexts are probed only at their own round, full ones on every round left
+            let prefixes: Vec<usize> = match length == self.memory{
+                true => (0..self.rounds - self.memory).collect(),
+                false => vec![0],
+            };
+            for context in all_contexts(length){
+                let mut cooperations = 0;
+                let mut expected = vec![0.0; KnownStrategy::ALL.len()];
+                for i in 0..self.samples{
+                    let prefix = prefixes[i % prefixes.len()];
+                    let mut history = vec![(Down, Down); prefix];
+                    history.extend_from_slice(&context[..]);
+                    let info_set = Self::info_set(id, reward_table, &history[..])?;
+                    match policy.select_action(&info_set){
+                        Some(Down) => cooperations += 1,
+                        Some(Up) => {},
+                        None => return Err(ProbeError::NoAction(context_name(&context[..])))
+                    }
+                    for (k, s) in KnownStrategy::ALL.iter().enumerate(){
+                        expected[k] += s.cooperate_probability(&history[..]);
+                    }
+                }
+                let samples = self.samples.max(1) as f64;
+                let cooperate_probability = cooperations as f64 / samples;
+                for k in 0..expected.len(){
+                    let diff = cooperate_probability - expected[k] / samples;
+                    squared_errors[k] += diff * diff;
+                }
+                debug!("Probed context {}: P(C) = {}", context_name(&context[..]), cooperate_probability);
+                responses.push(ResponseEntry{
+                    context: context_name(&context[..]),
+                    cooperate_probability,
+                    samples: self.samples,
+                });
+            }
+        }
+        let mut distances: Vec<StrategyDistance> = KnownStrategy::ALL.iter().zip(squared_errors.iter())
+            .map(|(s, e)| StrategyDistance{
+                strategy: *s,
+                distance: (e / responses.len() as f64).sqrt(),
+            }).collect();
+        distances.sort_by(|a, b| a.distance.total_cmp(&b.distance));
+
+        Ok(StrategyProbeReport{
+            memory: self.memory,
+            samples: self.samples,
+            responses,
+            distances,
+        })
+    }
+
+    /// Cooperation probability for every combination of opponent's last `memory` actions (rows)
+    /// and round in which decision is made (columns). Probed policy is assumed to have cooperated
+    /// in all previous rounds, and all rounds before the context are mutual cooperation.
+    pub fn heatmap<ID: UsizeAgentId, P: Policy<ClassicGameDomain<ID>, InfoSetType=LocalHistoryInfoSet<ID>>>(
+        &self, policy: &P, id: ID, reward_table: AsymmetricRewardTableInt)
+        -> Result<HeatmapData, ProbeError>{
+
+        if self.memory >= self.rounds{
+            return Err(ProbeError::MemoryTooLong {memory: self.memory, rounds: self.rounds})
+        }
+        let contexts: Vec<Vec<ClassicAction>> = all_contexts(self.memory).into_iter()
+            .filter(|c| c.iter().all(|(own, _)| *own == Down))
+            .map(|c| c.into_iter().map(|(_, other)| other).collect())
+            .collect();
+        let mut values = Vec::with_capacity(contexts.len());
+        let mut row_labels = Vec::with_capacity(contexts.len());
+        for context in contexts{
+            let mut row = Vec::with_capacity(self.rounds - self.memory);
+            for round in self.memory..self.rounds{
+                let mut history = vec![(Down, Down); round - self.memory];
+                history.extend(context.iter().map(|other| (Down, *other)));
+                let info_set = Self::info_set(id, reward_table, &history[..])?;
+                let mut cooperations = 0;
+                for _ in 0..self.samples{
+                    match policy.select_action(&info_set){
+                        Some(Down) => cooperations += 1,
+                        Some(Up) => {},
+                        None => return Err(ProbeError::NoAction(context_name(&history[..])))
+                    }
+                }
+                row.push(cooperations as f32 / self.samples.max(1) as f32);
+            }
+            values.push(row);
+            row_labels.push(context.iter().map(|a| action_symbol(*a)).collect());
+        }
+        Ok(HeatmapData{
+            row_labels,
+            column_labels: (self.memory..self.rounds).map(|r| format!("{r}")).collect(),
+            values,
+        })
+    }
+}
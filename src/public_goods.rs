@@ -0,0 +1,552 @@
+use std::fmt::{Display, Formatter};
+use std::marker::PhantomData;
+use std::sync::Arc;
+use log::debug;
+use rand::prelude::SliceRandom;
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng, thread_rng};
+use serde::Serialize;
+use amfiteatr_core::agent::{EvaluatedInformationSet, InformationSet, Policy, PresentPossibleActions};
+use amfiteatr_core::domain::{Action, DomainParameters, Renew};
+use amfiteatr_core::env::{EnvironmentStateSequential, EnvironmentStateUniScore};
+use amfiteatr_core::error::{AmfiError, ConvertError};
+use amfiteatr_classic::domain::{AgentNum, UsizeAgentId};
+use amfiteatr_rl::error::TensorRepresentationError;
+use amfiteatr_rl::tch::Tensor;
+use amfiteatr_rl::tensor_data::{ActionTensor, ConversionToTensor, ConvertToTensor};
+
+/// Decision of agent in current stage of round. In contribution stage [`Cooperate`](PublicGoodsAction::Cooperate)
+/// puts endowment into common pool, in punishment stage it punishes every group member who withheld.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash, Serialize)]
+pub enum PublicGoodsAction{
+    Cooperate,
+    Defect,
+}
+
+impl Display for PublicGoodsAction{
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{self:?}")
+    }
+}
+
+impl Action for PublicGoodsAction{}
+
+impl ActionTensor for PublicGoodsAction{
+    fn to_tensor(&self) -> Tensor {
+        match self{
+            PublicGoodsAction::Defect => Tensor::from_slice(&[0.0f32]),
+            PublicGoodsAction::Cooperate => Tensor::from_slice(&[1.0f32]),
+        }
+    }
+
+    fn try_from_tensor(t: &Tensor) -> Result<Self, ConvertError> {
+        let v: Vec<i64> = Vec::try_from(t)
+            .map_err(|_| ConvertError::ActionDeserialize(format!("{t}")))?;
+        match v.first(){
+            Some(0) => Ok(PublicGoodsAction::Defect),
+            Some(1) => Ok(PublicGoodsAction::Cooperate),
+            _ => Err(ConvertError::ActionDeserialize(format!("{t}")))
+        }
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Serialize)]
+pub enum Stage{
+    Contribution,
+    Punishment,
+}
+
+/// Cost paid by punisher and fine paid by punished agent, for every punished group member.
+#[derive(Copy, Clone, Debug, Serialize)]
+pub struct Punishment{
+    pub cost: f32,
+    pub fine: f32,
+}
+
+/// Rules of public goods game. Contributions in group are multiplied by `factor` and split equally
+/// among all group members, including those who withheld.
+#[derive(Copy, Clone, Debug, Serialize)]
+pub struct PublicGoodsParams{
+    pub group_size: usize,
+    pub endowment: f32,
+    pub factor: f32,
+    pub punishment: Option<Punishment>,
+}
+
+impl PublicGoodsParams{
+    /// Payoff of contribution stage, when `contributors` of group members contributed.
+    pub fn contribution_payoff(&self, contributed: bool, contributors: usize) -> f32{
+        let kept = match contributed{
+            true => 0.0,
+            false => self.endowment,
+        };
+        kept + self.factor * self.endowment * contributors as f32 / self.group_size as f32
+    }
+
+    /// Payoff of punishment stage for agent punishing `punished` members and punished by `punishers`.
+    pub fn punishment_payoff(&self, punished: usize, punishers: usize) -> f32{
+        match self.punishment{
+            Some(p) => -p.cost * punished as f32 - p.fine * punishers as f32,
+            None => 0.0,
+        }
+    }
+}
+
+#[derive(thiserror::Error, Debug, Clone, PartialEq, Eq)]
+pub enum PublicGoodsError<ID: UsizeAgentId>{
+    #[error("Order in game was violated. Expected player: {expected:?} given: {acted:}")]
+    GameViolatedOrder{
+        acted: ID,
+        expected: Option<ID>
+    },
+    #[error("Player: {0} played after GameOver")]
+    ActionAfterGameOver(ID),
+    #[error("Population of {players} players can not be split in groups of {group_size}")]
+    IndivisiblePopulation{
+        players: usize,
+        group_size: usize,
+    },
+}
+
+#[derive(Clone, Debug, Serialize)]
+pub struct PublicGoodsDomain<ID: UsizeAgentId>{
+    _id: PhantomData<ID>
+}
+
+/// Decisions of all players in finished stage of round, indexed by player.
+#[derive(Clone, Debug, Serialize)]
+pub struct StageReport<ID: UsizeAgentId>{
+    pub stage: Stage,
+    pub groups: Vec<Vec<ID>>,
+    pub group_of: Vec<usize>,
+    pub decisions: Vec<PublicGoodsAction>,
+}
+
+impl<ID: UsizeAgentId> StageReport<ID>{
+    /// Number of members of agent's group (including agent) that cooperated.
+    pub fn cooperating_in_group(&self, agent: &ID) -> usize{
+        self.groups[self.group_of[agent.as_usize()]].iter()
+            .filter(|m| self.decisions[m.as_usize()] == PublicGoodsAction::Cooperate)
+            .count()
+    }
+
+    pub fn decision(&self, agent: &ID) -> PublicGoodsAction{
+        self.decisions[agent.as_usize()]
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct PublicGoodsUpdate<ID: UsizeAgentId>{
+    pub report: Arc<StageReport<ID>>,
+}
+
+impl<ID: UsizeAgentId> DomainParameters for PublicGoodsDomain<ID>{
+    type ActionType = PublicGoodsAction;
+    type GameErrorType = PublicGoodsError<ID>;
+    type UpdateType = PublicGoodsUpdate<ID>;
+    type AgentId = ID;
+    type UniversalReward = f32;
+}
+
+pub type PublicGoodsDomainNumbered = PublicGoodsDomain<AgentNum>;
+
+impl<ID: UsizeAgentId> From<PublicGoodsError<ID>> for AmfiError<PublicGoodsDomain<ID>>{
+    fn from(value: PublicGoodsError<ID>) -> Self {
+        AmfiError::Game(value)
+    }
+}
+
+/// State of n-player public goods game. Every round population is shuffled into groups, players
+/// decide on contribution in order of their ids and, if punishment is enabled, in the second stage
+/// decide whether to punish free riders of their group.
+#[derive(Clone, Debug, Serialize)]
+pub struct PublicGoodsState<ID: UsizeAgentId>{
+    params: PublicGoodsParams,
+    target_rounds: usize,
+    rounds_played: usize,
+    stage: Stage,
+    indexes: Vec<usize>,
+    groups: Vec<Vec<ID>>,
+    group_of: Vec<usize>,
+    contributions: Vec<PublicGoodsAction>,
+    decisions: Vec<PublicGoodsAction>,
+    score_cache: Vec<f32>,
+    current_player_index: usize,
+    #[serde(skip)]
+    rng: StdRng,
+}
+
+impl<ID: UsizeAgentId> PublicGoodsState<ID>{
+    pub fn new(players: usize, target_rounds: usize, params: PublicGoodsParams) -> Result<Self, PublicGoodsError<ID>>{
+        if params.group_size == 0 || !players.is_multiple_of(params.group_size){
+            return Err(PublicGoodsError::IndivisiblePopulation{players, group_size: params.group_size});
+        }
+        let mut state = Self{
+            params, target_rounds,
+            rounds_played: 0,
+            stage: Stage::Contribution,
+            indexes: (0..players).collect(),
+            groups: Vec::new(),
+            group_of: vec![0; players],
+            contributions: Vec::with_capacity(players),
+            decisions: Vec::with_capacity(players),
+            score_cache: vec![0.0; players],
+            current_player_index: 0,
+            rng: StdRng::from_entropy(),
+        };
+        state.prepare_groups();
+        Ok(state)
+    }
+
+    /// Replaces generator of groups, e.g. with seeded one, and groups players again with it.
+    pub fn with_rng(mut self, rng: StdRng) -> Self{
+        self.rng = rng;
+        self.indexes.sort_unstable();
+        self.prepare_groups();
+        self
+    }
+
+    fn prepare_groups(&mut self){
+        self.indexes.shuffle(&mut self.rng);
+        self.groups = self.indexes.chunks(self.params.group_size)
+            .map(|group| group.iter().map(|i| ID::make_from_usize(*i)).collect())
+            .collect();
+        for (g, group) in self.groups.iter().enumerate(){
+            for member in group{
+                self.group_of[member.as_usize()] = g;
+            }
+        }
+    }
+
+    pub fn params(&self) -> &PublicGoodsParams{
+        &self.params
+    }
+
+    pub fn rounds_played(&self) -> usize{
+        self.rounds_played
+    }
+
+    fn finish_stage(&mut self) -> Vec<(ID, PublicGoodsUpdate<ID>)>{
+        let report = StageReport{
+            stage: self.stage,
+            groups: self.groups.clone(),
+            group_of: self.group_of.clone(),
+            decisions: std::mem::take(&mut self.decisions),
+        };
+        for i in 0..self.score_cache.len(){
+            let id = ID::make_from_usize(i);
+            self.score_cache[i] += match self.stage{
+                Stage::Contribution => self.params.contribution_payoff(
+                    report.decision(&id) == PublicGoodsAction::Cooperate, report.cooperating_in_group(&id)),
+                Stage::Punishment => {
+                    let (punished, punishers) = punishment_counts(&report, &self.contributions, &id);
+                    self.params.punishment_payoff(punished, punishers)
+                },
+            };
+        }
+        self.current_player_index = 0;
+        match (self.stage, self.params.punishment){
+            (Stage::Contribution, Some(_)) => {
+                self.contributions = report.decisions.clone();
+                self.stage = Stage::Punishment;
+            },
+            _ => {
+                self.rounds_played += 1;
+                self.stage = Stage::Contribution;
+                self.prepare_groups();
+                debug!("Finished round {}", self.rounds_played);
+            }
+        }
+        let update = PublicGoodsUpdate{report: Arc::new(report)};
+        (0..self.score_cache.len()).map(|i| (ID::make_from_usize(i), update.clone())).collect()
+    }
+}
+
+/// Number of group members punished by agent and number of members punishing agent, given
+/// decisions of punishment stage and contributions of the same round.
+pub fn punishment_counts<ID: UsizeAgentId>(report: &StageReport<ID>, contributions: &[PublicGoodsAction], agent: &ID) -> (usize, usize){
+    let group = &report.groups[report.group_of[agent.as_usize()]];
+    let free_riders = group.iter()
+        .filter(|m| *m != agent && contributions[m.as_usize()] == PublicGoodsAction::Defect)
+        .count();
+    let punished = match report.decision(agent){
+        PublicGoodsAction::Cooperate => free_riders,
+        PublicGoodsAction::Defect => 0,
+    };
+    let punishers = match contributions[agent.as_usize()]{
+        PublicGoodsAction::Defect => group.iter()
+            .filter(|m| *m != agent && report.decision(m) == PublicGoodsAction::Cooperate)
+            .count(),
+        PublicGoodsAction::Cooperate => 0,
+    };
+    (punished, punishers)
+}
+
+impl<ID: UsizeAgentId> Display for PublicGoodsState<ID>{
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Rounds played: {}, scores: {:?}", self.rounds_played, self.score_cache)
+    }
+}
+
+impl<ID: UsizeAgentId> EnvironmentStateSequential<PublicGoodsDomain<ID>> for PublicGoodsState<ID>{
+    type Updates = Vec<(ID, PublicGoodsUpdate<ID>)>;
+
+    fn current_player(&self) -> Option<ID> {
+        match self.is_finished(){
+            true => None,
+            false => Some(ID::make_from_usize(self.current_player_index)),
+        }
+    }
+
+    fn is_finished(&self) -> bool {
+        self.rounds_played >= self.target_rounds
+    }
+
+    fn forward(&mut self, agent: ID, action: PublicGoodsAction) -> Result<Self::Updates, PublicGoodsError<ID>> {
+        let expected = self.current_player().ok_or(PublicGoodsError::ActionAfterGameOver(agent))?;
+        if expected != agent{
+            return Err(PublicGoodsError::GameViolatedOrder{acted: agent, expected: Some(expected)});
+        }
+        self.decisions.push(action);
+        self.current_player_index += 1;
+        match self.current_player_index < self.score_cache.len(){
+            true => Ok(Vec::new()),
+            false => Ok(self.finish_stage()),
+        }
+    }
+}
+
+impl<ID: UsizeAgentId> EnvironmentStateUniScore<PublicGoodsDomain<ID>> for PublicGoodsState<ID>{
+    fn state_score_of_player(&self, agent: &ID) -> f32 {
+        self.score_cache[agent.as_usize()]
+    }
+}
+
+impl<ID: UsizeAgentId> Renew<()> for PublicGoodsState<ID>{
+    fn renew_from(&mut self, _base: ()) {
+        self.score_cache.iter_mut().for_each(|s| *s = 0.0);
+        self.rounds_played = 0;
+        self.current_player_index = 0;
+        self.stage = Stage::Contribution;
+        self.decisions.clear();
+        self.contributions.clear();
+        self.prepare_groups();
+    }
+}
+
+/// What agent knows about one round.
+#[derive(Copy, Clone, Debug, Serialize)]
+pub struct RoundMemory{
+    pub contributed: bool,
+    /// Fraction of other group members that contributed
+    pub others_contributing: f32,
+    pub punished: Option<bool>,
+    /// Fraction of other group members that punished agent
+    pub others_punishing: Option<f32>,
+    pub payoff: f32,
+}
+
+/// Information set of agent remembering outcomes of its group in previous rounds.
+#[derive(Clone, Debug, Serialize)]
+pub struct PublicGoodsInfoSet<ID: UsizeAgentId>{
+    id: ID,
+    params: PublicGoodsParams,
+    stage: Stage,
+    rounds: Vec<RoundMemory>,
+    contributions: Vec<PublicGoodsAction>,
+    payoff: f32,
+}
+
+impl<ID: UsizeAgentId> PublicGoodsInfoSet<ID>{
+    pub fn new(id: ID, params: PublicGoodsParams) -> Self{
+        Self{id, params, stage: Stage::Contribution, rounds: Vec::new(), contributions: Vec::new(), payoff: 0.0}
+    }
+
+    /// Stage in which agent makes its next decision.
+    pub fn stage(&self) -> Stage{
+        self.stage
+    }
+
+    pub fn rounds(&self) -> &[RoundMemory]{
+        &self.rounds[..]
+    }
+
+    pub fn count_contributions(&self) -> usize{
+        self.rounds.iter().filter(|r| r.contributed).count()
+    }
+
+    pub fn count_punishments(&self) -> usize{
+        self.rounds.iter().filter(|r| r.punished == Some(true)).count()
+    }
+
+    /// Whether any other member of agent's group withheld contribution in current round.
+    pub fn sees_free_riders(&self) -> bool{
+        self.stage == Stage::Punishment
+            && self.rounds.last().map(|r| r.others_contributing < 1.0).unwrap_or(false)
+    }
+
+    fn others(&self) -> f32{
+        (self.params.group_size.max(2) - 1) as f32
+    }
+}
+
+impl<ID: UsizeAgentId> Display for PublicGoodsInfoSet<ID>{
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Agent: {}, rounds: {}, contributions: {}, payoff: {}", self.id, self.rounds.len(),
+            self.count_contributions(), self.payoff)
+    }
+}
+
+impl<ID: UsizeAgentId> InformationSet<PublicGoodsDomain<ID>> for PublicGoodsInfoSet<ID>{
+    fn agent_id(&self) -> &ID {
+        &self.id
+    }
+
+    fn is_action_valid(&self, _action: &PublicGoodsAction) -> bool {
+        true
+    }
+
+    fn update(&mut self, update: PublicGoodsUpdate<ID>) -> Result<(), PublicGoodsError<ID>> {
+        let report = &update.report;
+        match report.stage{
+            Stage::Contribution => {
+                let contributed = report.decision(&self.id) == PublicGoodsAction::Cooperate;
+                let cooperating = report.cooperating_in_group(&self.id);
+                let payoff = self.params.contribution_payoff(contributed, cooperating);
+                self.rounds.push(RoundMemory{
+                    contributed,
+                    others_contributing: (cooperating - contributed as usize) as f32 / self.others(),
+                    punished: None,
+                    others_punishing: None,
+                    payoff,
+                });
+                self.payoff += payoff;
+                self.contributions = report.decisions.clone();
+                if self.params.punishment.is_some(){
+                    self.stage = Stage::Punishment;
+                }
+            },
+            Stage::Punishment => {
+                let (punished, punishers) = punishment_counts(report, &self.contributions, &self.id);
+                let payoff = self.params.punishment_payoff(punished, punishers);
+                let others = self.others();
+                if let Some(round) = self.rounds.last_mut(){
+                    round.punished = Some(report.decision(&self.id) == PublicGoodsAction::Cooperate);
+                    round.others_punishing = Some(punishers as f32 / others);
+                    round.payoff += payoff;
+                }
+                self.payoff += payoff;
+                self.stage = Stage::Contribution;
+            }
+        }
+        Ok(())
+    }
+}
+
+impl<ID: UsizeAgentId> PresentPossibleActions<PublicGoodsDomain<ID>> for PublicGoodsInfoSet<ID>{
+    type ActionIteratorType = [PublicGoodsAction; 2];
+
+    fn available_actions(&self) -> Self::ActionIteratorType {
+        [PublicGoodsAction::Cooperate, PublicGoodsAction::Defect]
+    }
+}
+
+impl<ID: UsizeAgentId> EvaluatedInformationSet<PublicGoodsDomain<ID>> for PublicGoodsInfoSet<ID>{
+    type RewardType = f32;
+
+    fn current_subjective_score(&self) -> Self::RewardType {
+        self.payoff
+    }
+
+    fn penalty_for_illegal(&self) -> Self::RewardType {
+        -100.0
+    }
+}
+
+impl<ID: UsizeAgentId> Renew<()> for PublicGoodsInfoSet<ID>{
+    fn renew_from(&mut self, _base: ()) {
+        self.stage = Stage::Contribution;
+        self.rounds.clear();
+        self.contributions.clear();
+        self.payoff = 0.0;
+    }
+}
+
+/// Represents history as four values for every round (own contribution, fraction of contributing
+/// group members, own punishment, fraction of members punishing agent), `-1` for unknown, and
+/// stage of next decision.
+#[derive(Copy, Clone, Debug, Default)]
+pub struct PublicGoodsConversion{
+    rounds: usize,
+    shape: [i64; 1],
+}
+
+impl PublicGoodsConversion{
+    const ROUND_VALUES: usize = 4;
+
+    pub fn new(rounds: usize) -> Self{
+        Self{rounds, shape: [(rounds * Self::ROUND_VALUES + 1) as i64]}
+    }
+}
+
+impl ConversionToTensor for PublicGoodsConversion{
+    fn desired_shape(&self) -> &[i64] {
+        &self.shape[..]
+    }
+}
+
+impl<ID: UsizeAgentId> ConvertToTensor<PublicGoodsConversion> for PublicGoodsInfoSet<ID>{
+    fn try_to_tensor(&self, way: &PublicGoodsConversion) -> Result<Tensor, TensorRepresentationError> {
+        if self.rounds.len() > way.rounds{
+            return Err(TensorRepresentationError::InfoSetNotFit {
+                info_set: format!("Public goods history of length {}", self.rounds.len()),
+                shape: Vec::from(way.desired_shape()),
+            });
+        }
+        let mut values = vec![-1.0f32; way.shape[0] as usize];
+        for (i, round) in self.rounds.iter().enumerate(){
+            let offset = i * PublicGoodsConversion::ROUND_VALUES;
+            values[offset] = round.contributed as u8 as f32;
+            values[offset + 1] = round.others_contributing;
+            if let Some(punished) = round.punished{
+                values[offset + 2] = punished as u8 as f32;
+            }
+            if let Some(punishing) = round.others_punishing{
+                values[offset + 3] = punishing;
+            }
+        }
+        values[way.rounds * PublicGoodsConversion::ROUND_VALUES] = match self.stage{
+            Stage::Contribution => 0.0,
+            Stage::Punishment => 1.0,
+        };
+        Ok(Tensor::f_from_slice(&values[..])?)
+    }
+}
+
+/// Scripted behaviour: contributes with fixed probability and optionally punishes every free rider.
+pub struct PublicGoodsStrategy<ID: UsizeAgentId>{
+    contribution_probability: f64,
+    punish: bool,
+    _id: PhantomData<ID>,
+}
+
+impl<ID: UsizeAgentId> PublicGoodsStrategy<ID>{
+    pub fn new(contribution_probability: f64, punish: bool) -> Self{
+        Self{contribution_probability: contribution_probability.clamp(0.0, 1.0), punish, _id: PhantomData}
+    }
+}
+
+impl<ID: UsizeAgentId> Policy<PublicGoodsDomain<ID>> for PublicGoodsStrategy<ID>{
+    type InfoSetType = PublicGoodsInfoSet<ID>;
+
+    fn select_action(&self, state: &Self::InfoSetType) -> Option<PublicGoodsAction> {
+        let cooperate = match state.stage(){
+            Stage::Contribution => thread_rng().gen_bool(self.contribution_probability),
+            Stage::Punishment => self.punish && state.sees_free_riders(),
+        };
+        match cooperate{
+            true => Some(PublicGoodsAction::Cooperate),
+            false => Some(PublicGoodsAction::Defect),
+        }
+    }
+}
@@ -1,5 +1,8 @@
+use plotters::style::RGBColor;
 use serde::{Serialize};
 use amfiteatr_core::domain::DomainParameters;
+use amfiteatr_classic::domain::IntReward;
+use crate::plots::PlotSeries;
 
 #[derive(Serialize, Clone, Debug)]
 pub struct PayoffSeries<DP: DomainParameters>
@@ -23,4 +26,78 @@ pub struct MultiAgentPayoffSeries<DP: DomainParameters>
 where <DP as DomainParameters>::AgentId: Serialize,
     <DP as DomainParameters>::UniversalReward: Serialize,{
     pub agent_series: Vec<PayoffSeries<DP>>
-}
\ No newline at end of file
+}
+
+/// Average of entries, `None` when there are none.
+pub fn avg(entries: &[f32]) -> Option<f32>{
+    match entries.is_empty(){
+        true => None,
+        false => Some(entries.iter().sum::<f32>() / entries.len() as f32),
+    }
+}
+
+/// Universal reward that can be averaged as payoff.
+pub trait PayoffValue{
+    fn payoff(&self) -> f32;
+}
+
+impl PayoffValue for IntReward{
+    fn payoff(&self) -> f32{
+        *self as f32
+    }
+}
+
+impl PayoffValue for f32{
+    fn payoff(&self) -> f32{
+        *self
+    }
+}
+
+/// Average payoffs of learning agents and of every scripted group in test episodes, `None` for
+/// group without agents.
+#[derive(Clone, Debug, Default)]
+pub struct GroupPayoffs{
+    pub learning: Option<f32>,
+    pub scripted: Vec<Option<f32>>,
+}
+
+impl GroupPayoffs{
+    /// Payoffs of all groups, learners first.
+    pub fn iter(&self) -> impl Iterator<Item = Option<f32>> + '_{
+        std::iter::once(self.learning).chain(self.scripted.iter().copied())
+    }
+}
+
+/// Payoffs of groups noted after every evaluation, learners first.
+#[derive(Clone, Debug)]
+pub struct GroupPayoffHistory{
+    series: Vec<PayoffGroupSeries>,
+}
+
+impl GroupPayoffHistory{
+    pub fn new<'a>(names: impl IntoIterator<Item = &'a str>) -> Self{
+        Self{series: names.into_iter().map(|id| PayoffGroupSeries{id: id.to_string(), payoffs: Vec::new()}).collect()}
+    }
+
+    pub fn push(&mut self, payoffs: &GroupPayoffs){
+        for (series, payoff) in self.series.iter_mut().zip(payoffs.iter()){
+            series.payoffs.extend(payoff);
+        }
+    }
+
+    pub fn series(&self) -> &[PayoffGroupSeries]{
+        &self.series[..]
+    }
+
+    /// Lines of groups that were present in evaluations, colors are assigned in order of groups.
+    pub fn plot_series(&self, colors: &[RGBColor]) -> Vec<PlotSeries>{
+        self.series.iter().zip(colors.iter().cycle())
+            .filter(|(s, _)| !s.payoffs.is_empty())
+            .map(|(s, color)| PlotSeries::new(s.payoffs.clone(), &s.id, *color))
+            .collect()
+    }
+
+    pub fn into_series(self) -> Vec<PayoffGroupSeries>{
+        self.series
+    }
+}
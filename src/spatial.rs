@@ -0,0 +1,455 @@
+use std::collections::BTreeSet;
+use std::fmt::{Display, Formatter};
+use std::marker::PhantomData;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use clap::ValueEnum;
+use log::debug;
+use plotters::prelude::*;
+use rand::{Rng, SeedableRng};
+use rand::rngs::StdRng;
+use rand::prelude::SliceRandom;
+use serde::Serialize;
+use amfiteatr_core::agent::{EvaluatedInformationSet, InformationSet, Policy, PresentPossibleActions};
+use amfiteatr_core::domain::{DomainParameters, Renew};
+use amfiteatr_core::env::{EnvironmentStateSequential, EnvironmentStateUniScore};
+use amfiteatr_core::error::AmfiError;
+use amfiteatr_classic::{AsymmetricRewardTableInt, Side};
+use amfiteatr_classic::domain::{AgentNum, ClassicAction, UsizeAgentId};
+
+#[derive(thiserror::Error, Debug)]
+pub enum SpatialError{
+    #[error("Failed reading graph {path:?}: {source}")]
+    Io{
+        path: PathBuf,
+        source: std::io::Error,
+    },
+    #[error("Invalid edge in line {line}: {content:?}")]
+    Parse{
+        line: usize,
+        content: String,
+    },
+    #[error("Graph has no nodes")]
+    Empty,
+}
+
+/// Cells counted as neighbours on lattice.
+#[derive(Serialize, Copy, Clone, Debug, PartialEq, Eq, ValueEnum)]
+pub enum Neighbourhood{
+    /// Four orthogonal cells
+    VonNeumann,
+    /// Eight surrounding cells
+    Moore,
+}
+
+/// Nodes occupied by agents and their neighbours. Every topology has grid in which its nodes are
+/// drawn, lattice uses its own layout and graph nodes fill rows of square grid in order of ids.
+#[derive(Clone, Debug)]
+pub struct Topology{
+    neighbours: Vec<Vec<usize>>,
+    width: usize,
+}
+
+impl Topology{
+    pub fn lattice(width: usize, height: usize, neighbourhood: Neighbourhood, periodic: bool) -> Result<Self, SpatialError>{
+        if width == 0 || height == 0{
+            return Err(SpatialError::Empty);
+        }
+        let offsets: &[(i64, i64)] = match neighbourhood{
+            Neighbourhood::VonNeumann => &[(0, -1), (-1, 0), (1, 0), (0, 1)],
+            Neighbourhood::Moore => &[(-1, -1), (0, -1), (1, -1), (-1, 0), (1, 0), (-1, 1), (0, 1), (1, 1)],
+        };
+        let (w, h) = (width as i64, height as i64);
+        let neighbours = (0..width * height).map(|i|{
+            let (x, y) = ((i % width) as i64, (i / width) as i64);
+            let cells: BTreeSet<usize> = offsets.iter().filter_map(|(dx, dy)|{
+                let (nx, ny) = match periodic{
+                    true => ((x + dx).rem_euclid(w), (y + dy).rem_euclid(h)),
+                    false => (x + dx, y + dy),
+                };
+                ((0..w).contains(&nx) && (0..h).contains(&ny) && (nx, ny) != (x, y))
+                    .then_some((ny * w + nx) as usize)
+            }).collect();
+            cells.into_iter().collect()
+        }).collect();
+        Ok(Self{neighbours, width})
+    }
+
+    /// Reads undirected graph from file with one edge per line given as two node ids separated
+    /// by whitespace or comma. Empty lines and lines starting with `#` are skipped. Number of
+    /// nodes is one more than the greatest id.
+    pub fn from_edge_list(path: &Path) -> Result<Self, SpatialError>{
+        let content = std::fs::read_to_string(path)
+            .map_err(|source| SpatialError::Io{path: path.to_path_buf(), source})?;
+        let mut edges = Vec::new();
+        for (n, line) in content.lines().enumerate(){
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#'){
+                continue;
+            }
+            let ids: Vec<usize> = line.split(|c: char| c.is_whitespace() || c == ',')
+                .filter(|s| !s.is_empty())
+                .map(|s| s.parse())
+                .collect::<Result<_, _>>()
+                .map_err(|_| SpatialError::Parse{line: n + 1, content: line.to_string()})?;
+            match ids[..]{
+                [a, b] => edges.push((a, b)),
+                _ => return Err(SpatialError::Parse{line: n + 1, content: line.to_string()}),
+            }
+        }
+        let nodes = edges.iter().map(|(a, b)| a.max(b) + 1).max().ok_or(SpatialError::Empty)?;
+        let mut neighbours = vec![BTreeSet::new(); nodes];
+        for (a, b) in edges.into_iter().filter(|(a, b)| a != b){
+            neighbours[a].insert(b);
+            neighbours[b].insert(a);
+        }
+        let width = (nodes as f64).sqrt().ceil() as usize;
+        Ok(Self{neighbours: neighbours.into_iter().map(|n| n.into_iter().collect()).collect(), width})
+    }
+
+    pub fn size(&self) -> usize{
+        self.neighbours.len()
+    }
+
+    pub fn neighbours(&self, node: usize) -> &[usize]{
+        &self.neighbours[node][..]
+    }
+
+    /// Columns and rows of grid in which nodes are drawn.
+    pub fn grid(&self) -> (usize, usize){
+        (self.width, self.size().div_ceil(self.width))
+    }
+}
+
+/// How agents adopt strategies of neighbours after every generation.
+#[derive(Serialize, Copy, Clone, Debug, PartialEq, Eq, ValueEnum)]
+pub enum UpdateRule{
+    /// Copy strategy of most successful agent in neighbourhood, including self
+    BestNeighbour,
+    /// Copy strategy of random neighbour with probability given by Fermi function of payoff difference
+    Fermi,
+}
+
+
+#[derive(thiserror::Error, Debug, Clone, PartialEq, Eq)]
+pub enum SpatialGameError<ID: UsizeAgentId>{
+    #[error("Order in game was violated. Expected player: {expected:?} given: {acted:}")]
+    GameViolatedOrder{
+        acted: ID,
+        expected: Option<ID>
+    },
+    #[error("Player: {0} played after GameOver")]
+    ActionAfterGameOver(ID),
+}
+
+/// Game of agents placed in nodes of [`Topology`]. Every agent plays one action per generation,
+/// the same against all of its neighbours. Cooperation is `Down` and defection is `Up`.
+#[derive(Clone, Debug, Serialize)]
+pub struct SpatialDomain<ID: UsizeAgentId>{
+    _id: PhantomData<ID>
+}
+
+/// Actions of all agents in finished generation and payoffs they got from games with neighbours.
+#[derive(Clone, Debug, Serialize)]
+pub struct GenerationReport{
+    pub generation: usize,
+    pub actions: Vec<ClassicAction>,
+    pub payoffs: Vec<f32>,
+}
+
+impl GenerationReport{
+    pub fn cooperation(&self) -> f32{
+        self.actions.iter().filter(|a| **a == ClassicAction::Down).count() as f32 / self.actions.len() as f32
+    }
+
+    pub fn average_payoff(&self) -> f32{
+        self.payoffs.iter().sum::<f32>() / self.payoffs.len() as f32
+    }
+
+    /// Actions as string with `C` for cooperation and `D` for defection, in order of nodes.
+    pub fn snapshot(&self) -> String{
+        self.actions.iter().map(|a| match a{
+            ClassicAction::Down => 'C',
+            ClassicAction::Up => 'D',
+        }).collect()
+    }
+
+    /// Draws grid of agents, cooperators are blue and defectors red. Agents that changed action
+    /// since `previous` generation are green (new cooperators) and yellow (new defectors).
+    pub fn draw_frame(&self, topology: &Topology, previous: &GenerationReport, file: &Path, cell_size: u32) -> Result<(), Box<dyn std::error::Error>>{
+        let (columns, rows) = topology.grid();
+        let root = SVGBackend::new(file, (columns as u32 * cell_size, rows as u32 * cell_size)).into_drawing_area();
+        root.fill(&WHITE)?;
+        let cell = cell_size as i32;
+        for (node, (now, before)) in self.actions.iter().zip(previous.actions.iter()).enumerate(){
+            let color = match (before, now){
+                (ClassicAction::Down, ClassicAction::Down) => BLUE,
+                (ClassicAction::Up, ClassicAction::Up) => RED,
+                (ClassicAction::Up, ClassicAction::Down) => GREEN,
+                (ClassicAction::Down, ClassicAction::Up) => YELLOW,
+            };
+            let (x, y) = ((node % columns) as i32 * cell, (node / columns) as i32 * cell);
+            root.draw(&Rectangle::new([(x, y), (x + cell, y + cell)], color.filled()))?;
+        }
+        root.present()?;
+        Ok(())
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct SpatialUpdate{
+    pub report: Arc<GenerationReport>,
+}
+
+impl<ID: UsizeAgentId> DomainParameters for SpatialDomain<ID>{
+    type ActionType = ClassicAction;
+    type GameErrorType = SpatialGameError<ID>;
+    type UpdateType = SpatialUpdate;
+    type AgentId = ID;
+    type UniversalReward = f32;
+}
+
+pub type SpatialDomainNumbered = SpatialDomain<AgentNum>;
+
+impl<ID: UsizeAgentId> From<SpatialGameError<ID>> for AmfiError<SpatialDomain<ID>>{
+    fn from(value: SpatialGameError<ID>) -> Self {
+        AmfiError::Game(value)
+    }
+}
+
+/// State of spatial game. In every generation agents act in order of their ids and when all of
+/// them acted, every agent gets sum of payoffs from games with its neighbours. Agents evaluate
+/// table as left player, so asymmetric tables are seen from left side by all of them.
+#[derive(Clone, Debug)]
+pub struct SpatialState<ID: UsizeAgentId>{
+    topology: Arc<Topology>,
+    reward_table: AsymmetricRewardTableInt,
+    target_generations: usize,
+    actions: Vec<ClassicAction>,
+    generations: Vec<Arc<GenerationReport>>,
+    score_cache: Vec<f32>,
+    _id: PhantomData<ID>,
+}
+
+impl<ID: UsizeAgentId> SpatialState<ID>{
+    pub fn new(topology: Arc<Topology>, reward_table: AsymmetricRewardTableInt, target_generations: usize) -> Self{
+        Self{
+            actions: Vec::with_capacity(topology.size()),
+            generations: Vec::with_capacity(target_generations),
+            score_cache: vec![0.0; topology.size()],
+            topology, reward_table, target_generations,
+            _id: PhantomData,
+        }
+    }
+
+    pub fn topology(&self) -> &Topology{
+        &self.topology
+    }
+
+    /// Reports of finished generations of episode.
+    pub fn generations(&self) -> &[Arc<GenerationReport>]{
+        &self.generations[..]
+    }
+
+    fn finish_generation(&mut self) -> Vec<(ID, SpatialUpdate)>{
+        let actions = std::mem::take(&mut self.actions);
+        let payoffs: Vec<f32> = actions.iter().enumerate().map(|(node, own)|{
+            self.topology.neighbours(node).iter()
+                .map(|n| self.reward_table.reward_for_side(Side::Left, *own, actions[*n]) as f32)
+                .sum()
+        }).collect();
+        for (score, payoff) in self.score_cache.iter_mut().zip(payoffs.iter()){
+            *score += payoff;
+        }
+        let report = Arc::new(GenerationReport{generation: self.generations.len(), actions, payoffs});
+        self.generations.push(report.clone());
+        debug!("Finished generation {}", self.generations.len());
+        let update = SpatialUpdate{report};
+        (0..self.score_cache.len()).map(|i| (ID::make_from_usize(i), update.clone())).collect()
+    }
+}
+
+impl<ID: UsizeAgentId> Display for SpatialState<ID>{
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Generations played: {}", self.generations.len())?;
+        if let Some(last) = self.generations.last(){
+            write!(f, ", cooperation: {:.03}, average payoff: {:.03}", last.cooperation(), last.average_payoff())?;
+        }
+        Ok(())
+    }
+}
+
+impl<ID: UsizeAgentId> EnvironmentStateSequential<SpatialDomain<ID>> for SpatialState<ID>{
+    type Updates = Vec<(ID, SpatialUpdate)>;
+
+    fn current_player(&self) -> Option<ID> {
+        match self.is_finished(){
+            true => None,
+            false => Some(ID::make_from_usize(self.actions.len())),
+        }
+    }
+
+    fn is_finished(&self) -> bool {
+        self.generations.len() >= self.target_generations
+    }
+
+    fn forward(&mut self, agent: ID, action: ClassicAction) -> Result<Self::Updates, SpatialGameError<ID>> {
+        let expected = self.current_player().ok_or(SpatialGameError::ActionAfterGameOver(agent))?;
+        if expected != agent{
+            return Err(SpatialGameError::GameViolatedOrder{acted: agent, expected: Some(expected)});
+        }
+        self.actions.push(action);
+        match self.actions.len() < self.topology.size(){
+            true => Ok(Vec::new()),
+            false => Ok(self.finish_generation()),
+        }
+    }
+}
+
+impl<ID: UsizeAgentId> EnvironmentStateUniScore<SpatialDomain<ID>> for SpatialState<ID>{
+    fn state_score_of_player(&self, agent: &ID) -> f32 {
+        self.score_cache[agent.as_usize()]
+    }
+}
+
+impl<ID: UsizeAgentId> Renew<()> for SpatialState<ID>{
+    fn renew_from(&mut self, _base: ()) {
+        self.score_cache.iter_mut().for_each(|s| *s = 0.0);
+        self.actions.clear();
+        self.generations.clear();
+    }
+}
+
+/// Information set of agent knowing its neighbours and outcome of last generation.
+#[derive(Clone, Debug)]
+pub struct SpatialInfoSet<ID: UsizeAgentId>{
+    id: ID,
+    topology: Arc<Topology>,
+    last_generation: Option<Arc<GenerationReport>>,
+    payoff: f32,
+}
+
+impl<ID: UsizeAgentId> SpatialInfoSet<ID>{
+    pub fn new(id: ID, topology: Arc<Topology>) -> Self{
+        Self{id, topology, last_generation: None, payoff: 0.0}
+    }
+
+    pub fn neighbours(&self) -> &[usize]{
+        self.topology.neighbours(self.id.as_usize())
+    }
+
+    /// Report of last finished generation, `None` before the first one.
+    pub fn last_generation(&self) -> Option<&GenerationReport>{
+        self.last_generation.as_deref()
+    }
+}
+
+impl<ID: UsizeAgentId> Display for SpatialInfoSet<ID>{
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Agent: {}, neighbours: {}, payoff: {}", self.id, self.neighbours().len(), self.payoff)
+    }
+}
+
+impl<ID: UsizeAgentId> InformationSet<SpatialDomain<ID>> for SpatialInfoSet<ID>{
+    fn agent_id(&self) -> &ID {
+        &self.id
+    }
+
+    fn is_action_valid(&self, _action: &ClassicAction) -> bool {
+        true
+    }
+
+    fn update(&mut self, update: SpatialUpdate) -> Result<(), SpatialGameError<ID>> {
+        self.payoff += update.report.payoffs[self.id.as_usize()];
+        self.last_generation = Some(update.report);
+        Ok(())
+    }
+}
+
+impl<ID: UsizeAgentId> PresentPossibleActions<SpatialDomain<ID>> for SpatialInfoSet<ID>{
+    type ActionIteratorType = [ClassicAction; 2];
+
+    fn available_actions(&self) -> Self::ActionIteratorType {
+        [ClassicAction::Up, ClassicAction::Down]
+    }
+}
+
+impl<ID: UsizeAgentId> EvaluatedInformationSet<SpatialDomain<ID>> for SpatialInfoSet<ID>{
+    type RewardType = f32;
+
+    fn current_subjective_score(&self) -> Self::RewardType {
+        self.payoff
+    }
+
+    fn penalty_for_illegal(&self) -> Self::RewardType {
+        -100.0
+    }
+}
+
+impl<ID: UsizeAgentId> Renew<()> for SpatialInfoSet<ID>{
+    fn renew_from(&mut self, _base: ()) {
+        self.last_generation = None;
+        self.payoff = 0.0;
+    }
+}
+
+/// Imitating agent. In the first generation it cooperates with given probability, later it
+/// adopts action of neighbours according to [`UpdateRule`], using payoffs of last generation.
+/// [`SpatialState`] draws nothing at random, so seeding policies makes whole run repeatable.
+pub struct ImitationPolicy<ID: UsizeAgentId>{
+    rule: UpdateRule,
+    temperature: f32,
+    cooperation: f64,
+    rng: Mutex<StdRng>,
+    _id: PhantomData<ID>,
+}
+
+impl<ID: UsizeAgentId> ImitationPolicy<ID>{
+    /// Temperature is used only by [`UpdateRule::Fermi`].
+    pub fn new(rule: UpdateRule, temperature: f32, cooperation: f64) -> Self{
+        Self{rule, temperature, cooperation: cooperation.clamp(0.0, 1.0),
+            rng: Mutex::new(StdRng::from_entropy()), _id: PhantomData}
+    }
+
+    /// Replaces generator of random choices, e.g. with seeded one.
+    pub fn with_rng(mut self, rng: StdRng) -> Self{
+        self.rng = Mutex::new(rng);
+        self
+    }
+}
+
+impl<ID: UsizeAgentId> Policy<SpatialDomain<ID>> for ImitationPolicy<ID>{
+    type InfoSetType = SpatialInfoSet<ID>;
+
+    fn select_action(&self, state: &Self::InfoSetType) -> Option<ClassicAction> {
+        let mut rng = self.rng.lock().unwrap();
+        let report = match state.last_generation(){
+            Some(report) => report,
+            None => return match rng.gen_bool(self.cooperation){
+                true => Some(ClassicAction::Down),
+                false => Some(ClassicAction::Up),
+            },
+        };
+        let node = state.agent_id().as_usize();
+        let neighbours = state.neighbours();
+        let imitated = match self.rule{
+            UpdateRule::BestNeighbour => neighbours.iter().copied()
+                .fold(node, |best, n| match report.payoffs[n] > report.payoffs[best]{
+                    true => n,
+                    false => best,
+                }),
+            UpdateRule::Fermi => match neighbours.choose(&mut *rng){
+                Some(n) => {
+                    let difference = report.payoffs[*n] - report.payoffs[node];
+                    let probability = 1.0 / (1.0 + (-difference / self.temperature.max(f32::EPSILON)).exp());
+                    match rng.gen_bool(probability as f64){
+                        true => *n,
+                        false => node,
+                    }
+                },
+                None => node,
+            },
+        };
+        Some(report.actions[imitated])
+    }
+}
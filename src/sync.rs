@@ -0,0 +1,311 @@
+use std::collections::HashMap;
+use std::marker::PhantomData;
+use std::panic::{self, AssertUnwindSafe};
+use std::sync::{Arc, Mutex, MutexGuard, PoisonError};
+use log::{debug, error, warn};
+use amfiteatr_core::agent::{ActingAgent, EpisodeMemoryAgent, IdAgent, RewardedAgent, SelfEvaluatingAgent, StatefulAgent};
+use amfiteatr_core::domain::{DomainParameters, Reward};
+use amfiteatr_core::env::{EnvironmentStateSequential, ReseedEnvironment, ScoreEnvironment, StatefulEnvironment};
+use amfiteatr_core::error::{AmfiError, CommunicationError, ProtocolError};
+use amfiteatr_classic::domain::{ClassicGameDomain, UsizeAgentId};
+use amfiteatr_classic::env::PairingState;
+use crate::cheap_talk::{CheapTalkDomain, CheapTalkState};
+use crate::error::{EpisodeReport, Participant};
+use crate::matrix::{MatrixGameDomain, MatrixPairingState};
+use crate::pairing::noise::NoisyState;
+use crate::public_goods::{PublicGoodsDomain, PublicGoodsState};
+use crate::series::{avg, GroupPayoffs, PayoffValue};
+use crate::spatial::{SpatialDomain, SpatialState};
+
+/// Environment state sending updates to agents when episode starts, before anyone acts, e.g.
+/// partners proposed for the first round. They are delivered by [`run_episode_synchronous`],
+/// automatic environments of amfiteatr start episode with request for action.
+pub trait OpeningUpdates<DP: DomainParameters>{
+    fn opening_updates(&self) -> Vec<(DP::AgentId, DP::UpdateType)>{
+        Vec::new()
+    }
+}
+
+impl<ID: UsizeAgentId> OpeningUpdates<ClassicGameDomain<ID>> for PairingState<ID>{}
+impl<ID: UsizeAgentId, S> OpeningUpdates<ClassicGameDomain<ID>> for NoisyState<ID, S>{}
+impl<ID: UsizeAgentId> OpeningUpdates<MatrixGameDomain<ID>> for MatrixPairingState<ID>{}
+impl<ID: UsizeAgentId> OpeningUpdates<PublicGoodsDomain<ID>> for PublicGoodsState<ID>{}
+impl<ID: UsizeAgentId> OpeningUpdates<CheapTalkDomain<ID>> for CheapTalkState<ID>{}
+impl<ID: UsizeAgentId> OpeningUpdates<SpatialDomain<ID>> for SpatialState<ID>{}
+
+/// Agent stepped directly by [`run_episode_synchronous`] in thread of environment, without
+/// messages. Implemented for every agent that can be run automatically with episode memory.
+pub trait SteppedAgent<DP: DomainParameters>: Send{
+    fn agent_id(&self) -> DP::AgentId;
+    /// Prepares agent for new episode, like [`ReseedAgent::reseed`](amfiteatr_core::agent::ReseedAgent::reseed).
+    fn begin_episode(&mut self);
+    fn receive_update(&mut self, update: DP::UpdateType) -> Result<(), DP::GameErrorType>;
+    fn receive_reward(&mut self, reward: &DP::UniversalReward);
+    fn act(&mut self) -> Option<DP::ActionType>;
+    /// Called for agent whose action was refused by environment.
+    fn action_refused(&mut self);
+    /// Finalizes trajectory and stores episode.
+    fn end_episode(&mut self);
+}
+
+impl<DP: DomainParameters, A> SteppedAgent<DP> for A
+where A: EpisodeMemoryAgent<DP, ()> + ActingAgent<DP> + RewardedAgent<DP> + SelfEvaluatingAgent<DP> + IdAgent<DP> + Send{
+    fn agent_id(&self) -> DP::AgentId{
+        IdAgent::id(self).clone()
+    }
+
+    fn begin_episode(&mut self){
+        self.reseed(());
+    }
+
+    fn receive_update(&mut self, update: DP::UpdateType) -> Result<(), DP::GameErrorType>{
+        StatefulAgent::update(self, update)
+    }
+
+    fn receive_reward(&mut self, reward: &DP::UniversalReward){
+        self.current_universal_reward_add(reward);
+    }
+
+    fn act(&mut self) -> Option<DP::ActionType>{
+        self.take_action()
+    }
+
+    fn action_refused(&mut self){
+        self.add_explicit_assessment(&self.penalty_for_illegal_action());
+    }
+
+    fn end_episode(&mut self){
+        self.finalize();
+        self.store_episode();
+    }
+}
+
+/// Runs episode in current thread, asking agents for actions directly. Agents see the same
+/// sequence of updates, rewards and requests for action as when environment runs with scores
+/// and they run in own threads, so their trajectories are built the same way.
+///
+/// Environment and agents are reseeded with `()` before episode, then agents get
+/// [`OpeningUpdates`] of state. Agents store episode when game
+/// is finished, also when it finishes with illegal action (as automatic agents do).
+pub fn run_episode_synchronous<DP, E>(environment: &mut E, agents: &mut [&mut dyn SteppedAgent<DP>]) -> Result<(), AmfiError<DP>>
+where DP: DomainParameters,
+      E: ScoreEnvironment<DP> + ReseedEnvironment<DP, ()>,
+      <E as StatefulEnvironment<DP>>::State: OpeningUpdates<DP>{
+    environment.reseed(());
+    let index: HashMap<DP::AgentId, usize> = agents.iter().enumerate()
+        .map(|(i, agent)| (agent.agent_id(), i))
+        .collect();
+    let mut scores: Vec<DP::UniversalReward> = agents.iter().map(|_| DP::UniversalReward::neutral()).collect();
+    for agent in agents.iter_mut(){
+        agent.begin_episode();
+    }
+    let agent_index = |id: &DP::AgentId| index.get(id).copied()
+        .ok_or_else(|| AmfiError::Communication(CommunicationError::ConnectionToAgentNotFound(id.clone())));
+    for (agent, update) in environment.state().opening_updates(){
+        agents[agent_index(&agent)?].receive_update(update).map_err(AmfiError::Game)?;
+    }
+
+    while let Some(player) = environment.current_player(){
+        let i = agent_index(&player)?;
+        let action = agents[i].act()
+            .ok_or_else(|| AmfiError::Protocol(ProtocolError::NoPossibleAction(player.clone())))?;
+        debug!("Player {} performs action: {:#}", &player, &action);
+        match environment.process_action(&player, &action){
+            Ok(updates) => {
+                for (agent, update) in updates{
+                    agents[agent_index(&agent)?].receive_update(update).map_err(AmfiError::Game)?;
+                }
+                for (agent, score) in agents.iter_mut().zip(scores.iter_mut()){
+                    let actual = environment.actual_score_of_player(&agent.agent_id());
+                    let reward = actual.clone() - score.clone();
+                    *score = actual;
+                    agent.receive_reward(&reward);
+                }
+            },
+            Err(e) => {
+                error!("Action was refused or caused error in updating state: {e:}");
+                agents[i].action_refused();
+                agents.iter_mut().for_each(|a| a.end_episode());
+                return Err(AmfiError::GameA(e, player));
+            }
+        }
+        if environment.state().is_finished(){
+            break;
+        }
+    }
+    debug!("Game reached finished state");
+    agents.iter_mut().for_each(|a| a.end_episode());
+    Ok(())
+}
+
+/// Runs [`run_episode_synchronous`] noting its error or panic in report, so failed episode does
+/// not stop experiment.
+pub fn run_reported_episode<DP, E>(environment: &mut E, agents: &mut [&mut dyn SteppedAgent<DP>]) -> EpisodeReport<DP>
+where DP: DomainParameters,
+      E: ScoreEnvironment<DP> + ReseedEnvironment<DP, ()>,
+      <E as StatefulEnvironment<DP>>::State: OpeningUpdates<DP>{
+    let mut report = EpisodeReport::default();
+    report.note(Participant::Environment, panic::catch_unwind(AssertUnwindSafe(||{
+        run_episode_synchronous(environment, agents)
+    })));
+    report
+}
+
+/// Locks agents for synchronous episode. Agent poisoned by panic in earlier episode is used as it is.
+pub fn lock_agents<'a, A: 'a>(agents: impl IntoIterator<Item = &'a Arc<Mutex<A>>>) -> Vec<MutexGuard<'a, A>>{
+    agents.into_iter().map(|a| a.lock().unwrap_or_else(PoisonError::into_inner)).collect()
+}
+
+/// Scripted agents sharing the same behaviour.
+pub struct ScriptedGroup<A>{
+    pub name: &'static str,
+    pub agents: Vec<Arc<Mutex<A>>>,
+}
+
+/// Environment with learning agents and groups of scripted agents, all of them stepped in thread
+/// of environment. Agents stay behind `Arc<Mutex<_>>`, so they can be inspected between episodes.
+pub struct SynchronousModel<DP: DomainParameters, E, L, S>{
+    pub environment: E,
+    pub learning_agents: Vec<Arc<Mutex<L>>>,
+    pub scripted_groups: Vec<ScriptedGroup<S>>,
+    episode: usize,
+    _dp: PhantomData<DP>,
+}
+
+impl<DP, E, L, S> SynchronousModel<DP, E, L, S>
+where DP: DomainParameters,
+      E: ScoreEnvironment<DP> + ReseedEnvironment<DP, ()>,
+      <E as StatefulEnvironment<DP>>::State: OpeningUpdates<DP>,
+      L: SteppedAgent<DP>,
+      S: SteppedAgent<DP>{
+    pub fn new(environment: E, learning_agents: Vec<Arc<Mutex<L>>>, scripted_groups: Vec<ScriptedGroup<S>>) -> Self{
+        Self{environment, learning_agents, scripted_groups, episode: 0, _dp: PhantomData}
+    }
+
+    /// Runs one episode and returns whether it was completed. Failed episode is logged with
+    /// failures of its participants.
+    pub fn run_episode(&mut self) -> bool{
+        let mut learning = lock_agents(&self.learning_agents);
+        let mut scripted = lock_agents(self.scripted_groups.iter().flat_map(|g| g.agents.iter()));
+        let mut agents: Vec<&mut dyn SteppedAgent<DP>> = Vec::with_capacity(learning.len() + scripted.len());
+        agents.extend(learning.iter_mut().map(|a| &mut **a as &mut dyn SteppedAgent<DP>));
+        agents.extend(scripted.iter_mut().map(|a| &mut **a as &mut dyn SteppedAgent<DP>));
+        let report = run_reported_episode(&mut self.environment, &mut agents[..]);
+        let episode = self.episode;
+        self.episode += 1;
+        match report.into_result(episode){
+            Ok(()) => true,
+            Err(e) => {
+                warn!("{e}");
+                false
+            }
+        }
+    }
+
+    /// Runs test episodes and returns average payoffs of groups. After every completed episode
+    /// `inspect` is called for every learning agent, then its episodes are cleared, as test
+    /// episodes are not used for training.
+    pub fn evaluate(&mut self, episodes: usize, inspect: impl FnMut(&L)) -> GroupPayoffs
+    where L: RewardedAgent<DP> + EpisodeMemoryAgent<DP, ()>,
+          S: RewardedAgent<DP>,
+          DP::UniversalReward: PayoffValue{
+        self.evaluate_groups(episodes, inspect, |_, _| ())
+    }
+
+    /// Like [`evaluate`](SynchronousModel::evaluate), but also calls `inspect_scripted` with index
+    /// of group for every scripted agent after completed episode.
+    pub fn evaluate_groups(&mut self, episodes: usize, mut inspect: impl FnMut(&L), mut inspect_scripted: impl FnMut(usize, &S)) -> GroupPayoffs
+    where L: RewardedAgent<DP> + EpisodeMemoryAgent<DP, ()>,
+          S: RewardedAgent<DP>,
+          DP::UniversalReward: PayoffValue{
+        let mut learning_payoffs = Vec::new();
+        let mut scripted_payoffs = vec![Vec::new(); self.scripted_groups.len()];
+        for _ in 0..episodes{
+            if !self.run_episode(){
+                continue;
+            }
+            for agent in lock_agents(&self.learning_agents).iter_mut(){
+                learning_payoffs.push(agent.current_universal_score().payoff());
+                inspect(agent);
+                agent.clear_episodes();
+            }
+            for (g, (group, payoffs)) in self.scripted_groups.iter().zip(scripted_payoffs.iter_mut()).enumerate(){
+                for agent in lock_agents(&group.agents).iter(){
+                    payoffs.push(agent.current_universal_score().payoff());
+                    inspect_scripted(g, agent);
+                }
+            }
+        }
+        GroupPayoffs{
+            learning: avg(&learning_payoffs),
+            scripted: scripted_payoffs.iter().map(|p| avg(p)).collect(),
+        }
+    }
+
+    /// Calls `train` for every learning agent, e.g. to train its policy on stored episodes.
+    pub fn update_policies<Err>(&mut self, mut train: impl FnMut(&mut L) -> Result<(), Err>) -> Result<(), Err>{
+        lock_agents(&self.learning_agents).iter_mut().try_for_each(|agent| train(agent))
+    }
+}
+
+#[cfg(test)]
+mod tests{
+    use std::sync::{Arc, Mutex};
+    use amfiteatr_classic::agent::LocalHistoryInfoSet;
+    use amfiteatr_classic::domain::{AgentNum, ClassicAction, ClassicGameDomainNumbered};
+    use amfiteatr_classic::policy::ClassicPureStrategy;
+    use amfiteatr_core::agent::AgentGen;
+    use amfiteatr_core::comm::{AgentMpscAdapter, EnvironmentMpscPort};
+    use amfiteatr_core::env::{AutoEnvironmentWithScores, ReseedEnvironment, TracingBasicEnvironment, TracingEnvironment};
+    use crate::games::GamePreset;
+    use crate::pairing::{GameState, new_game_state_with_tables};
+    use crate::pairing::heterogeneous::RewardTables;
+    use crate::pairing::noise::Noise;
+    use crate::pool::AgentPool;
+    use super::{run_episode_synchronous, SteppedAgent};
+
+    type D = ClassicGameDomainNumbered;
+    type Agent = AgentGen<D, ClassicPureStrategy<AgentNum, LocalHistoryInfoSet<AgentNum>>, AgentMpscAdapter<D>>;
+    type Environment = TracingBasicEnvironment<D, GameState<AgentNum>, EnvironmentMpscPort<D>>;
+
+    const PLAYERS: usize = 6;
+
+    fn seeded_game() -> (Environment, Vec<Arc<Mutex<Agent>>>){
+        let table = GamePreset::PrisonersDilemma.reward_table();
+        let state = new_game_state_with_tables(10, RewardTables::uniform(PLAYERS, table),
+            Noise::new(0.2, 0.2), Some(0.9), Some(17)).unwrap();
+        let mut port = EnvironmentMpscPort::new();
+        let agents = (0..PLAYERS as AgentNum).map(|id|{
+            let action = match id % 2{
+                0 => ClassicAction::Up,
+                _ => ClassicAction::Down,
+            };
+            let comm = port.register_agent(id).unwrap();
+            let agent = AgentGen::new(LocalHistoryInfoSet::new(id, table), comm, ClassicPureStrategy::new(action));
+            Arc::new(Mutex::new(agent))
+        }).collect();
+        (TracingBasicEnvironment::new(state, port), agents)
+    }
+
+    #[test]
+    fn synchronous_episode_follows_threaded_one(){
+        let (mut threaded, agents) = seeded_game();
+        let mut pool = AgentPool::new();
+        agents.iter().for_each(|agent| pool.add(agent.clone()));
+        pool.run_episode(&mut threaded, |environment|{
+            environment.reseed(());
+            environment.run_with_scores()
+        }).into_result(0).unwrap();
+
+        let (mut synchronous, agents) = seeded_game();
+        let mut guards: Vec<_> = agents.iter().map(|a| a.lock().unwrap()).collect();
+        let mut stepped: Vec<&mut dyn SteppedAgent<D>> = guards.iter_mut()
+            .map(|a| &mut **a as &mut dyn SteppedAgent<D>)
+            .collect();
+        run_episode_synchronous(&mut synchronous, &mut stepped[..]).unwrap();
+
+        let trajectory = |environment: &Environment| serde_json::to_value(environment.trajectory()).unwrap();
+        assert!(!threaded.trajectory().list().is_empty());
+        assert_eq!(trajectory(&threaded), trajectory(&synchronous));
+    }
+}
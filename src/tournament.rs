@@ -0,0 +1,53 @@
+use std::fmt::{Display, Formatter};
+use serde::Serialize;
+use amfiteatr_core::env::{EnvironmentStateSequential, EnvironmentTrajectory};
+use amfiteatr_classic::domain::{ClassicAction, ClassicGameDomain, IntReward, UsizeAgentId};
+
+/// Result of one agent in tournament.
+#[derive(Serialize, Clone, Debug)]
+pub struct ScoreboardEntry{
+    pub agent: usize,
+    pub name: String,
+    pub score: IntReward,
+    pub cooperations: usize,
+    pub defections: usize,
+}
+
+/// Table of tournament results, ordered from the best score.
+#[derive(Serialize, Clone, Debug, Default)]
+pub struct Scoreboard{
+    pub rounds: usize,
+    pub entries: Vec<ScoreboardEntry>,
+}
+
+impl Scoreboard{
+    /// Builds scoreboard from trajectory of environment. `agents` lists id, name and final score
+    /// of every agent, actions are counted from trajectory.
+    pub fn new<ID: UsizeAgentId, S: EnvironmentStateSequential<ClassicGameDomain<ID>>>(
+        rounds: usize,
+        agents: impl IntoIterator<Item = (ID, String, IntReward)>,
+        trajectory: &EnvironmentTrajectory<ClassicGameDomain<ID>, S>) -> Self{
+
+        let mut entries: Vec<ScoreboardEntry> = agents.into_iter().map(|(id, name, score)|{
+            let actions = trajectory.list().iter().filter(|step| step.agent() == &id);
+            let (cooperations, defections) = actions.fold((0, 0), |(c, d), step| match step.action(){
+                ClassicAction::Down => (c + 1, d),
+                ClassicAction::Up => (c, d + 1),
+            });
+            ScoreboardEntry{ agent: id.as_usize(), name, score, cooperations, defections }
+        }).collect();
+        entries.sort_by(|a, b| b.score.cmp(&a.score).then(a.agent.cmp(&b.agent)));
+        Self{rounds, entries}
+    }
+}
+
+impl Display for Scoreboard{
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        writeln!(f, "{:>4} {:>6} {:<20} {:>8} {:>6} {:>6}", "rank", "agent", "name", "score", "coop", "defect")?;
+        for (rank, e) in self.entries.iter().enumerate(){
+            writeln!(f, "{:>4} {:>6} {:<20} {:>8} {:>6} {:>6}",
+                rank + 1, e.agent, e.name, e.score, e.cooperations, e.defections)?;
+        }
+        Ok(())
+    }
+}